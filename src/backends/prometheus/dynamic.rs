@@ -0,0 +1,412 @@
+//! Metrics created after registration.
+//!
+//! Integrations such as the `tracing` layer only learn metric names at
+//! runtime, long after the registry was set up and possibly while it is
+//! locked for rendering. [`DynamicMetrics`] is registered once as a
+//! prometheus-client `Collector` and creates labeled families on first use;
+//! they are exported on the next scrape with the registry's label value
+//! policy and cardinality limits applied.
+
+use super::family::LabeledFamily;
+use super::prometheus_backend::{
+    family_selected, validate_histogram_buckets, validate_prometheus_metric_name, ExemplarLabels,
+    PrometheusBackend, PrometheusError,
+};
+use crate::core::cardinality::CardinalityLimiter;
+use crate::core::labels::LabelValuePolicy;
+use crate::core::registry::{MetricBackend, RegisteredNames};
+use prometheus_client::collector::Collector;
+use prometheus_client::encoding::{DescriptorEncoder, EncodeMetric};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::exemplar::HistogramWithExemplars;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::MetricType;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, RwLock};
+
+/// Label set of a dynamic series, in the order the caller supplies it.
+pub type DynamicLabels = Vec<(String, String)>;
+
+/// A counter family created at runtime.
+pub type DynamicCounter = LabeledFamily<DynamicLabels, Counter<u64>>;
+
+/// A floating point gauge family created at runtime.
+pub type DynamicGauge = LabeledFamily<DynamicLabels, Gauge<f64, AtomicU64>>;
+
+/// A histogram family created at runtime; series keep exemplars.
+pub type DynamicHistogram = LabeledFamily<DynamicLabels, HistogramWithExemplars<ExemplarLabels>>;
+
+#[derive(Clone)]
+enum DynamicFamily {
+    Counter(DynamicCounter),
+    Gauge(DynamicGauge),
+    Histogram(DynamicHistogram),
+}
+
+impl DynamicFamily {
+    fn metric_type(&self) -> MetricType {
+        match self {
+            Self::Counter(_) => MetricType::Counter,
+            Self::Gauge(_) => MetricType::Gauge,
+            Self::Histogram(_) => MetricType::Histogram,
+        }
+    }
+}
+
+struct DynamicInner {
+    policy: Option<LabelValuePolicy>,
+    cardinality: CardinalityLimiter,
+    names: RegisteredNames,
+    families: RwLock<BTreeMap<String, (String, DynamicFamily)>>,
+}
+
+/// Families created by name at runtime and exported through one collector.
+///
+/// Obtain one with
+/// [`ObservabilityRegistry::dynamic_metrics`](crate::core::registry::ObservabilityRegistry::dynamic_metrics).
+/// Cloning is cheap; clones share the same families. A family can't take a
+/// name already registered on the registry, or created by another
+/// `DynamicMetrics` of the same registry.
+///
+/// # Example
+/// ```ignore
+/// let dynamic = registry.dynamic_metrics();
+/// let queries = dynamic.counter("db_queries", "Database queries")?;
+/// queries.get_or_create(&vec![("table".into(), "users".into())]).inc();
+/// ```
+#[derive(Clone)]
+pub struct DynamicMetrics {
+    inner: Arc<DynamicInner>,
+}
+
+impl fmt::Debug for DynamicMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynamicMetrics")
+            .field("families", &self.read().keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl DynamicMetrics {
+    pub(crate) fn new(
+        policy: Option<LabelValuePolicy>,
+        cardinality: CardinalityLimiter,
+        names: RegisteredNames,
+    ) -> Self {
+        Self {
+            inner: Arc::new(DynamicInner {
+                policy,
+                cardinality,
+                names,
+                families: RwLock::new(BTreeMap::new()),
+            }),
+        }
+    }
+
+    /// The counter family `name`, created on first use.
+    ///
+    /// Fails if the name is invalid, already used by a different metric
+    /// type, or registered on the registry. `help` is only used when the
+    /// family is created.
+    pub fn counter(&self, name: &str, help: &str) -> Result<DynamicCounter, PrometheusError> {
+        match self.get_or_insert(name, help, || {
+            Ok(DynamicFamily::Counter(LabeledFamily::default()))
+        })? {
+            DynamicFamily::Counter(family) => Ok(family),
+            other => Err(type_conflict(name, other.metric_type())),
+        }
+    }
+
+    /// The gauge family `name`, created on first use.
+    pub fn gauge(&self, name: &str, help: &str) -> Result<DynamicGauge, PrometheusError> {
+        match self.get_or_insert(name, help, || {
+            Ok(DynamicFamily::Gauge(LabeledFamily::default()))
+        })? {
+            DynamicFamily::Gauge(family) => Ok(family),
+            other => Err(type_conflict(name, other.metric_type())),
+        }
+    }
+
+    /// The histogram family `name`, created on first use with the buckets
+    /// returned by `buckets`.
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        buckets: impl FnOnce() -> Vec<f64>,
+    ) -> Result<DynamicHistogram, PrometheusError> {
+        match self.get_or_insert(name, help, || {
+            let buckets = buckets();
+            validate_histogram_buckets(&buckets)?;
+            Ok(DynamicFamily::Histogram(
+                LabeledFamily::new_with_constructor(move || {
+                    HistogramWithExemplars::new(buckets.iter().copied())
+                }),
+            ))
+        })? {
+            DynamicFamily::Histogram(family) => Ok(family),
+            other => Err(type_conflict(name, other.metric_type())),
+        }
+    }
+
+    /// Replace the help text of family `name`, if it exists.
+    ///
+    /// Returns false if no family of that name was created yet.
+    pub fn describe(&self, name: &str, help: &str) -> bool {
+        let mut families = self
+            .inner
+            .families
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match families.get_mut(name) {
+            Some((existing, _)) => {
+                *existing = format!("{}.", help);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names of the families created so far.
+    pub fn names(&self) -> Vec<String> {
+        self.read().keys().cloned().collect()
+    }
+
+    fn get_or_insert(
+        &self,
+        name: &str,
+        help: &str,
+        create: impl FnOnce() -> Result<DynamicFamily, PrometheusError>,
+    ) -> Result<DynamicFamily, PrometheusError> {
+        if let Some((_, family)) = self.read().get(name) {
+            return Ok(family.clone());
+        }
+
+        let mut families = self
+            .inner
+            .families
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((_, family)) = families.get(name) {
+            return Ok(family.clone());
+        }
+        validate_prometheus_metric_name(name)?;
+        if !self.inner.names.insert(name) {
+            return Err(PrometheusBackend::duplicate_name_error(name));
+        }
+        let prepared = create().and_then(|family| {
+            match &family {
+                DynamicFamily::Counter(f) => f.prepare_registration(
+                    name,
+                    self.inner.policy.as_ref(),
+                    &self.inner.cardinality,
+                )?,
+                DynamicFamily::Gauge(f) => f.prepare_registration(
+                    name,
+                    self.inner.policy.as_ref(),
+                    &self.inner.cardinality,
+                )?,
+                DynamicFamily::Histogram(f) => f.prepare_registration(
+                    name,
+                    self.inner.policy.as_ref(),
+                    &self.inner.cardinality,
+                )?,
+            }
+            Ok(family)
+        });
+        let family = prepared.inspect_err(|_| self.inner.names.remove(name))?;
+        families.insert(name.to_string(), (format!("{}.", help), family.clone()));
+        Ok(family)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, (String, DynamicFamily)>> {
+        self.inner
+            .families
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn type_conflict(name: &str, existing: MetricType) -> PrometheusError {
+    PrometheusError::RegistrationError(format!(
+        "metric {:?} is already registered as a {}",
+        name,
+        existing.as_str()
+    ))
+}
+
+/// Collector exporting every family of a [`DynamicMetrics`].
+#[derive(Debug)]
+pub(crate) struct DynamicCollector(pub(crate) DynamicMetrics);
+
+impl Collector for DynamicCollector {
+    fn encode(&self, mut encoder: DescriptorEncoder) -> Result<(), fmt::Error> {
+        let families = self.0.read().clone();
+        for (name, (help, family)) in &families {
+            if !family_selected(name, family.metric_type()) {
+                continue;
+            }
+            let metric_encoder =
+                encoder.encode_descriptor(name, help, None, family.metric_type())?;
+            match family {
+                DynamicFamily::Counter(f) => f.encode(metric_encoder)?,
+                DynamicFamily::Gauge(f) => f.encode(metric_encoder)?,
+                DynamicFamily::Histogram(f) => f.encode(metric_encoder)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::prometheus::prometheus_backend::PrometheusRegistry;
+    use crate::core::metrics::ExemplarHistogramTrait;
+
+    fn labels(items: &[(&str, &str)]) -> DynamicLabels {
+        items
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn families_created_after_registration_are_exported() {
+        let mut registry = PrometheusRegistry::new();
+        let dynamic = registry.dynamic_metrics();
+
+        dynamic
+            .counter("db_queries", "Database queries")
+            .unwrap()
+            .get_or_create(&labels(&[("table", "users")]))
+            .inc();
+        dynamic
+            .histogram("db_query_seconds", "Query latency", || vec![0.1, 1.0])
+            .unwrap()
+            .get_or_create(&labels(&[("table", "users")]))
+            .observe_with_exemplar(0.05, &[("trace_id", "abc")]);
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains("# HELP db_queries Database queries.\n"));
+        assert!(text.contains(r#"db_queries_total{table="users"} 1"#));
+        assert!(text.contains(
+            r#"db_query_seconds_bucket{le="0.1",table="users"} 1 # {trace_id="abc"} 0.05"#
+        ));
+    }
+
+    #[test]
+    fn const_labels_apply_to_dynamic_families() {
+        let mut registry = PrometheusRegistry::with_const_labels([("service", "api")]).unwrap();
+        registry
+            .dynamic_metrics()
+            .counter("requests", "Requests")
+            .unwrap()
+            .get_or_create(&labels(&[("route", "/")]))
+            .inc();
+
+        let output = registry.render().unwrap();
+        assert!(output
+            .as_str()
+            .unwrap()
+            .contains(r#"requests_total{service="api",route="/"} 1"#));
+    }
+
+    #[test]
+    fn gauges_and_descriptions() {
+        let mut registry = PrometheusRegistry::new();
+        let dynamic = registry.dynamic_metrics();
+        assert!(!dynamic.describe("pool_size", "Ignored"));
+
+        dynamic
+            .gauge("pool_size", "Pool size")
+            .unwrap()
+            .get_or_create(&labels(&[]))
+            .set(2.5);
+        assert!(dynamic.describe("pool_size", "Connections in the pool"));
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains("# HELP pool_size Connections in the pool.\n"));
+        assert!(text.contains("pool_size{} 2.5\n"));
+    }
+
+    #[test]
+    fn same_name_returns_the_same_family() {
+        let dynamic = DynamicMetrics::new(
+            None,
+            CardinalityLimiter::default(),
+            RegisteredNames::default(),
+        );
+        let a = dynamic.counter("requests", "Requests").unwrap();
+        let b = dynamic.counter("requests", "Ignored").unwrap();
+        a.get_or_create(&labels(&[])).inc();
+        assert_eq!(b.get_or_create(&labels(&[])).get(), 1);
+        assert_eq!(dynamic.names(), vec!["requests".to_string()]);
+    }
+
+    #[test]
+    fn type_conflicts_and_invalid_names_are_rejected() {
+        let dynamic = DynamicMetrics::new(
+            None,
+            CardinalityLimiter::default(),
+            RegisteredNames::default(),
+        );
+        dynamic.counter("requests", "Requests").unwrap();
+        assert!(matches!(
+            dynamic.histogram("requests", "Requests", Vec::new),
+            Err(PrometheusError::RegistrationError(_))
+        ));
+        assert!(matches!(
+            dynamic.counter("bad-name", "Bad"),
+            Err(PrometheusError::InvalidNamingConvention(_))
+        ));
+        assert!(dynamic.names().len() == 1);
+    }
+
+    #[test]
+    fn registered_names_are_not_reused() {
+        let mut registry = PrometheusRegistry::new();
+        registry.counter("requests", "Requests").unwrap();
+        let dynamic = registry.dynamic_metrics();
+        assert!(matches!(
+            dynamic.counter("requests", "Requests"),
+            Err(PrometheusError::RegistrationError(_))
+        ));
+
+        // Nor are names taken by another set of dynamic metrics.
+        dynamic.gauge("pool_size", "Connections").unwrap();
+        assert!(matches!(
+            registry.dynamic_metrics().gauge("pool_size", "Connections"),
+            Err(PrometheusError::RegistrationError(_))
+        ));
+        assert!(registry.registered_names().contains("pool_size"));
+    }
+
+    #[test]
+    fn registry_limits_apply_to_dynamic_families() {
+        use crate::core::cardinality::{CardinalityLimits, OverflowPolicy};
+
+        let mut registry = PrometheusRegistry::new().with_cardinality_limits(
+            CardinalityLimits::new()
+                .per_family(1)
+                .overflow(OverflowPolicy::Drop),
+        );
+        let requests = registry
+            .dynamic_metrics()
+            .counter("requests", "Requests")
+            .unwrap();
+        requests.get_or_create(&labels(&[("user", "a")])).inc();
+        requests.get_or_create(&labels(&[("user", "b")])).inc();
+
+        assert_eq!(requests.len(), 1);
+        let output = registry.render().unwrap();
+        assert!(output
+            .as_str()
+            .unwrap()
+            .contains(r#"observe_rs_cardinality_rejected_series_total{family="requests"} 1"#));
+    }
+}
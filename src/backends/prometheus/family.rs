@@ -0,0 +1,941 @@
+//! Managed labeled metric families.
+//!
+//! [`LabeledFamily`] plays the role of prometheus-client's `Family`, but
+//! checks every new label set before a series is created: label names must
+//! be valid Prometheus identifiers that don't collide with names reserved by
+//! the metric type, and label values must satisfy the family's
+//! [`LabelValuePolicy`], if any. Values are escaped for the exposition
+//! format so quotes or newlines can't corrupt scrape output.
+//!
+//! Label sets are only inspected the first time they are seen; afterwards
+//! `get_or_create` is a hash lookup like `Family::get_or_create`.
+//!
+//! Families can also be capped in size (see
+//! [`cardinality`](crate::core::cardinality)): once full, new label sets are
+//! dropped, folded into an `__overflow__` series, or replace the least
+//! recently used series.
+//!
+//! With [`LabeledFamily::with_ttl`], series nobody touched for the idle
+//! duration are removed when the family is scraped, so label values for
+//! long-gone pods or tenants stop being exported. Handles obtained before a
+//! series was removed (by expiry, eviction or [`LabeledFamily::remove`])
+//! keep working but are no longer exported; look the series up again with
+//! `get_or_create` to record into the live one.
+
+use super::labels::escape_label_value;
+use super::prometheus_backend::{validate_prometheus_label_name, PrometheusError};
+use crate::core::cardinality::{
+    Admission, CardinalityLimiter, FamilyLimiter, OverflowPolicy, OVERFLOW_LABEL_VALUE,
+};
+use crate::core::clock::{Clock, SystemClock};
+use crate::core::labels::LabelValuePolicy;
+use prometheus_client::encoding::{EncodeMetric, MetricEncoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+/// A label set that can key a [`LabeledFamily`].
+///
+/// The family validates and escapes each new label set from its
+/// `(name, value)` pairs. Implement [`to_pairs`](Self::to_pairs) by hand,
+/// or with [`label_set!`](crate::label_set) for structs whose fields
+/// implement `Display`. `Vec<(String, String)>` is a label set as is.
+pub trait LabelSet: Clone + Hash + Eq + Debug + Send + Sync + 'static {
+    /// The `(name, value)` pairs of the label set, in exposition order.
+    fn to_pairs(&self) -> Vec<(String, String)>;
+}
+
+impl LabelSet for Vec<(String, String)> {
+    fn to_pairs(&self) -> Vec<(String, String)> {
+        self.clone()
+    }
+}
+
+/// Implement [`LabelSet`] for a struct, with one label per listed field.
+///
+/// Label names are the field names and values their `Display` output.
+///
+/// # Example
+/// ```ignore
+/// #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+/// struct HttpLabels {
+///     method: String,
+///     status: u16,
+/// }
+/// observe_rs::label_set!(HttpLabels { method, status });
+/// ```
+#[macro_export]
+macro_rules! label_set {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::backends::prometheus::family::LabelSet for $type {
+            fn to_pairs(&self) -> ::std::vec::Vec<(::std::string::String, ::std::string::String)> {
+                ::std::vec![$((
+                    ::std::string::String::from(stringify!($field).trim_start_matches("r#")),
+                    ::std::string::ToString::to_string(&self.$field),
+                )),*]
+            }
+        }
+    };
+}
+
+/// Escaped `(name, value)` pairs identifying a series.
+type SeriesKey = Vec<(String, String)>;
+
+/// Label sets remembered per series before the lookup index is reset.
+const MAX_ALIASES_PER_SERIES: usize = 16;
+
+type Constructor<M> = Box<dyn Fn() -> M + Send + Sync>;
+
+struct Series<M> {
+    metric: M,
+    /// Tick of the last lookup, maintained only for LRU eviction.
+    last_used: AtomicU64,
+    /// Nanoseconds since the family's epoch at the last lookup, maintained
+    /// only when a TTL is set.
+    last_touched: AtomicU64,
+    /// Whether the series holds a slot of the cardinality limits. Only the
+    /// folded overflow series doesn't.
+    charged: bool,
+}
+
+struct FamilyState<L, M> {
+    /// Series keyed by their exposed label pairs.
+    series: HashMap<SeriesKey, Series<M>>,
+    /// Label sets already seen, mapped to the series they resolve to.
+    ///
+    /// Several label sets can map to one series when values are redacted
+    /// or folded.
+    index: HashMap<L, SeriesKey>,
+    /// Number of charged series.
+    charged: usize,
+    /// Label sets dropped by the limits, mapped to the detached metric they
+    /// record into, so each is only counted as rejected once.
+    dropped: HashMap<L, M>,
+    limiter: Option<FamilyLimiter>,
+    /// Idle duration after which a series is removed at scrape time.
+    ttl: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    epoch: Instant,
+}
+
+impl<L, M> FamilyState<L, M> {
+    /// Nanoseconds elapsed on the family's clock since its epoch.
+    fn elapsed(&self) -> u64 {
+        let elapsed = self.clock.now().saturating_duration_since(self.epoch);
+        u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX)
+    }
+
+    fn touch(&self, series: &Series<M>) {
+        if self.ttl.is_some() {
+            series.last_touched.store(self.elapsed(), Ordering::Relaxed);
+        }
+    }
+
+    /// Remove the series with `key`, returning its slot to the limiter.
+    fn remove_series(&mut self, key: &SeriesKey) -> bool {
+        let Some(series) = self.series.remove(key) else {
+            return false;
+        };
+        self.index.retain(|_, k| k != key);
+        if series.charged {
+            self.release(1);
+        }
+        true
+    }
+
+    /// Return `n` removed series' slots to the limiter.
+    fn release(&mut self, n: usize) {
+        self.charged -= n;
+        if let Some(limiter) = &self.limiter {
+            limiter.release_all(n);
+        }
+        // Dropped label sets may fit now.
+        self.dropped.clear();
+    }
+
+    /// Remove series untouched for longer than the TTL.
+    fn remove_expired(&mut self) -> usize {
+        let Some(ttl) = self.ttl else {
+            return 0;
+        };
+        let ttl = u64::try_from(ttl.as_nanos()).unwrap_or(u64::MAX);
+        let now = self.elapsed();
+        let before = self.series.len();
+        let mut released = 0;
+        self.series.retain(|_, series| {
+            let live = now.saturating_sub(series.last_touched.load(Ordering::Relaxed)) < ttl;
+            if !live && series.charged {
+                released += 1;
+            }
+            live
+        });
+        let removed = before - self.series.len();
+        if removed > 0 {
+            let series = &self.series;
+            self.index.retain(|_, key| series.contains_key(key));
+            self.release(released);
+        }
+        removed
+    }
+
+    fn tracks_lru(&self) -> bool {
+        self.limiter
+            .as_ref()
+            .is_some_and(|limiter| limiter.overflow() == OverflowPolicy::EvictLru)
+    }
+
+    /// Remove the least recently used charged series, returning its key.
+    fn evict_lru(&mut self) -> Option<SeriesKey> {
+        let victim = self
+            .series
+            .iter()
+            .filter(|(_, series)| series.charged)
+            .min_by_key(|(_, series)| series.last_used.load(Ordering::Relaxed))
+            .map(|(key, _)| key.clone())?;
+        // The victim's slot is handed to the new series, not released.
+        self.series.remove(&victim);
+        self.index.retain(|_, key| *key != victim);
+        self.charged -= 1;
+        Some(victim)
+    }
+}
+
+struct FamilyInner<L, M> {
+    constructor: Constructor<M>,
+    policy: OnceLock<LabelValuePolicy>,
+    /// Logical clock for LRU ordering.
+    ticks: AtomicU64,
+    state: RwLock<FamilyState<L, M>>,
+}
+
+/// A labeled metric family with label validation.
+///
+/// Cloning is cheap and clones share the same series, like `Family`.
+///
+/// # Example
+/// ```ignore
+/// use observe_rs::backends::prometheus::prometheus_backend::PrometheusRegistry;
+///
+/// #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+/// struct HttpLabels {
+///     method: String,
+///     status: u16,
+/// }
+/// observe_rs::label_set!(HttpLabels { method, status });
+///
+/// let mut registry = PrometheusRegistry::new();
+/// let requests = registry.labeled_counter::<HttpLabels>("http_requests", "HTTP requests")?;
+/// requests.get_or_create(&HttpLabels { method: "GET".into(), status: 200 }).inc();
+/// ```
+pub struct LabeledFamily<L, M> {
+    inner: Arc<FamilyInner<L, M>>,
+}
+
+impl<L, M> Clone for LabeledFamily<L, M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<L, M> Debug for LabeledFamily<L, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.read();
+        f.debug_struct("LabeledFamily")
+            .field("series", &state.series.len())
+            .field("policy", &self.inner.policy.get())
+            .field("limiter", &state.limiter)
+            .field("ttl", &state.ttl)
+            .finish()
+    }
+}
+
+impl<L: LabelSet, M: Default + 'static> Default for LabeledFamily<L, M> {
+    fn default() -> Self {
+        Self::new_with_constructor(M::default)
+    }
+}
+
+impl<L, M> LabeledFamily<L, M> {
+    fn read(&self) -> RwLockReadGuard<'_, FamilyState<L, M>> {
+        self.inner
+            .state
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, FamilyState<L, M>> {
+        self.inner
+            .state
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Number of series currently in the family.
+    pub fn len(&self) -> usize {
+        self.read().series.len()
+    }
+
+    /// Returns true if no series have been created yet.
+    pub fn is_empty(&self) -> bool {
+        self.read().series.is_empty()
+    }
+
+    /// The label value policy applied to new series, if any.
+    pub fn label_value_policy(&self) -> Option<&LabelValuePolicy> {
+        self.inner.policy.get()
+    }
+}
+
+impl<L: LabelSet, M> LabeledFamily<L, M> {
+    /// Create a family whose series are built by `constructor`.
+    pub fn new_with_constructor(constructor: impl Fn() -> M + Send + Sync + 'static) -> Self {
+        Self {
+            inner: Arc::new(FamilyInner {
+                constructor: Box::new(constructor),
+                policy: OnceLock::new(),
+                ticks: AtomicU64::new(0),
+                state: RwLock::new(FamilyState {
+                    series: HashMap::new(),
+                    index: HashMap::new(),
+                    charged: 0,
+                    dropped: HashMap::new(),
+                    limiter: None,
+                    ttl: None,
+                    clock: Arc::new(SystemClock),
+                    epoch: Instant::now(),
+                }),
+            }),
+        }
+    }
+
+    /// Apply `policy` to label values of series created from now on.
+    ///
+    /// A family has at most one policy; a policy set here takes precedence
+    /// over the registry's policy at registration.
+    pub fn with_label_value_policy(self, policy: LabelValuePolicy) -> Self {
+        let _ = self.inner.policy.set(policy);
+        self
+    }
+
+    /// Cap this family at `max_series` series.
+    ///
+    /// Overrides the registry's per-family limit and overflow policy; the
+    /// registry-wide limit still applies once the family is registered.
+    pub fn with_cardinality_limit(self, max_series: usize, overflow: OverflowPolicy) -> Self {
+        self.write().limiter = Some(FamilyLimiter::new(max_series, overflow));
+        self
+    }
+
+    /// Remove series that were not looked up for `idle`.
+    ///
+    /// Expired series are removed when the family is encoded (i.e. at
+    /// scrape time) or by [`remove_expired`](Self::remove_expired).
+    pub fn with_ttl(self, idle: Duration) -> Self {
+        self.write().ttl = Some(idle);
+        self
+    }
+
+    /// Use `clock` to measure series idle time (defaults to [`SystemClock`]).
+    ///
+    /// Call this before creating series; their idle time is measured from
+    /// the clock's current instant.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        {
+            let mut state = self.write();
+            state.epoch = clock.now();
+            state.clock = clock;
+        }
+        self
+    }
+
+    /// Remove series that have been idle for longer than the TTL, returning
+    /// how many were removed. Does nothing if no TTL is set.
+    pub fn remove_expired(&self) -> usize {
+        self.write().remove_expired()
+    }
+
+    fn tick(&self) -> u64 {
+        self.inner.ticks.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl<L: LabelSet, M: TypedMetric + Clone> LabeledFamily<L, M> {
+    /// Get the series for `labels`, creating it if needed.
+    ///
+    /// # Panics
+    /// Panics if `labels` has an invalid or reserved label name, or a value
+    /// rejected by the label value policy. Use
+    /// [`try_get_or_create`](Self::try_get_or_create) to handle this instead.
+    pub fn get_or_create(&self, labels: &L) -> M {
+        self.try_get_or_create(labels)
+            .unwrap_or_else(|e| panic!("invalid labels {:?}: {}", labels, e))
+    }
+
+    /// Get the series for `labels`, creating it if needed.
+    ///
+    /// Returns an error instead of creating a series whose labels are
+    /// invalid; nothing is recorded in that case.
+    pub fn try_get_or_create(&self, labels: &L) -> Result<M, PrometheusError> {
+        {
+            let state = self.read();
+            if let Some(series) = state
+                .index
+                .get(labels)
+                .and_then(|key| state.series.get(key))
+            {
+                if state.tracks_lru() {
+                    series.last_used.store(self.tick(), Ordering::Relaxed);
+                }
+                state.touch(series);
+                return Ok(series.metric.clone());
+            }
+            if let Some(metric) = state.dropped.get(labels) {
+                return Ok(metric.clone());
+            }
+        }
+
+        let mut key = self.series_key(labels)?;
+        let mut state = self.write();
+        if let Some(metric) = state.dropped.get(labels) {
+            return Ok(metric.clone());
+        }
+        let mut charged = false;
+        if !state.series.contains_key(&key) {
+            let admission = match &state.limiter {
+                Some(limiter) => limiter.try_admit(state.charged),
+                None => Admission::Admitted,
+            };
+            charged = true;
+            if admission != Admission::Admitted {
+                match self.overflow(&mut state, key) {
+                    Overflow::Detached(metric) => {
+                        let cap = state.series.len().max(1) * MAX_ALIASES_PER_SERIES;
+                        if state.dropped.len() >= cap {
+                            state.dropped.clear();
+                        }
+                        state.dropped.insert(labels.clone(), metric.clone());
+                        return Ok(metric);
+                    }
+                    Overflow::Use(overflow_key) => key = overflow_key,
+                    Overflow::Fold(overflow_key) => {
+                        key = overflow_key;
+                        charged = false;
+                    }
+                }
+            }
+        }
+
+        let now = state.ttl.map_or(0, |_| state.elapsed());
+        if charged {
+            state.charged += 1;
+        }
+        let series = state.series.entry(key.clone()).or_insert_with(|| Series {
+            metric: (self.inner.constructor)(),
+            last_used: AtomicU64::new(0),
+            last_touched: AtomicU64::new(0),
+            charged,
+        });
+        series.last_used.store(self.tick(), Ordering::Relaxed);
+        series.last_touched.store(now, Ordering::Relaxed);
+        let metric = series.metric.clone();
+        // Redaction and folding let unbounded raw values alias a few series;
+        // forget the aliases rather than let the index outgrow the family.
+        if state.index.len() >= state.series.len() * MAX_ALIASES_PER_SERIES {
+            state.index.clear();
+        }
+        state.index.insert(labels.clone(), key);
+        Ok(metric)
+    }
+
+    /// Remove the series for `labels`, returning true if it existed.
+    ///
+    /// Redacted or folded label sets remove the shared series they map to.
+    pub fn remove(&self, labels: &L) -> bool {
+        let key = self.read().index.get(labels).cloned();
+        let Some(key) = key.or_else(|| self.series_key(labels).ok()) else {
+            return false;
+        };
+        self.write().remove_series(&key)
+    }
+
+    /// Decide where a label set that didn't fit under the limits goes.
+    fn overflow(&self, state: &mut FamilyState<L, M>, key: SeriesKey) -> Overflow<M> {
+        let Some(limiter) = state.limiter.clone() else {
+            return Overflow::Use(key);
+        };
+        match limiter.overflow() {
+            OverflowPolicy::Drop => {
+                limiter.record_rejected();
+                Overflow::Detached((self.inner.constructor)())
+            }
+            OverflowPolicy::Fold => {
+                limiter.record_rejected();
+                Overflow::Fold(
+                    key.into_iter()
+                        .map(|(name, _)| (name, OVERFLOW_LABEL_VALUE.to_string()))
+                        .collect(),
+                )
+            }
+            OverflowPolicy::EvictLru => match state.evict_lru() {
+                // The evicted series' slot is reused, whichever limit was
+                // hit: on the registry limit, the family evicts its own
+                // series even if other families hold older ones.
+                Some(_) => {
+                    limiter.record_evicted();
+                    Overflow::Use(key)
+                }
+                None => {
+                    limiter.record_rejected();
+                    Overflow::Detached((self.inner.constructor)())
+                }
+            },
+        }
+    }
+
+    /// Validate `labels` and turn them into the escaped pairs of a series.
+    fn series_key(&self, labels: &L) -> Result<SeriesKey, PrometheusError> {
+        check_label_pairs(&labels.to_pairs(), M::TYPE, self.inner.policy.get())
+    }
+
+    /// Attach the registry's policy and limits (unless the family has its
+    /// own) and re-check series created before registration.
+    pub(crate) fn prepare_registration(
+        &self,
+        name: &str,
+        policy: Option<&LabelValuePolicy>,
+        cardinality: &CardinalityLimiter,
+    ) -> Result<(), PrometheusError> {
+        if let Some(policy) = policy {
+            let _ = self.inner.policy.set(policy.clone());
+        }
+        let mut state = self.write();
+        for labels in state.index.keys() {
+            self.series_key(labels)?;
+        }
+        let own = state.limiter.take();
+        // Registering again replaces the limiter; give back what the old
+        // one charged so the series aren't counted twice.
+        if let Some(own) = &own {
+            own.release_all(state.charged);
+        }
+        state.limiter = cardinality.family(
+            name,
+            own.as_ref().and_then(FamilyLimiter::max_series),
+            own.as_ref().map(FamilyLimiter::overflow),
+            state.charged,
+        );
+        Ok(())
+    }
+}
+
+enum Overflow<M> {
+    /// Record into a metric that is never exported.
+    Detached(M),
+    /// Record into the series with this key, in the evicted series' slot.
+    Use(SeriesKey),
+    /// Record into the uncharged overflow series with this key.
+    Fold(SeriesKey),
+}
+
+/// Validate label names and apply `policy` to values, returning the pairs
+/// with values escaped for exposition.
+pub(crate) fn check_label_pairs(
+    pairs: &[(String, String)],
+    metric_type: MetricType,
+    policy: Option<&LabelValuePolicy>,
+) -> Result<SeriesKey, PrometheusError> {
+    let mut seen = HashSet::with_capacity(pairs.len());
+    pairs
+        .iter()
+        .map(|(name, value)| {
+            validate_prometheus_label_name(name, metric_type)?;
+            if !seen.insert(name.as_str()) {
+                return Err(PrometheusError::InvalidLabelName(format!(
+                    "duplicate label name {:?}",
+                    name
+                )));
+            }
+            let value = match policy {
+                Some(policy) => policy
+                    .apply(name, value)
+                    .map_err(|v| PrometheusError::InvalidLabelValue(v.to_string()))?,
+                None => value.as_str().into(),
+            };
+            Ok((name.clone(), escape_label_value(&value).into_owned()))
+        })
+        .collect()
+}
+
+impl<L, M> TypedMetric for LabeledFamily<L, M>
+where
+    M: TypedMetric,
+{
+    const TYPE: MetricType = M::TYPE;
+}
+
+impl<L, M> EncodeMetric for LabeledFamily<L, M>
+where
+    M: EncodeMetric + TypedMetric,
+{
+    fn encode(&self, mut encoder: MetricEncoder) -> Result<(), fmt::Error> {
+        let mut expiring;
+        let reading;
+        let state: &FamilyState<L, M> = if self.read().ttl.is_some() {
+            expiring = self.write();
+            expiring.remove_expired();
+            &expiring
+        } else {
+            reading = self.read();
+            &reading
+        };
+        for (labels, series) in state.series.iter() {
+            let encoder = encoder.encode_family(labels)?;
+            series.metric.encode(encoder)?;
+        }
+        Ok(())
+    }
+
+    fn metric_type(&self) -> MetricType {
+        M::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::metrics::counter::Counter;
+    use prometheus_client::metrics::histogram::Histogram;
+    use prometheus_client::registry::Registry;
+
+    #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+    struct PathLabels {
+        path: String,
+    }
+    crate::label_set!(PathLabels { path });
+
+    #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+    struct BucketLabels {
+        le: String,
+    }
+    crate::label_set!(BucketLabels { le });
+
+    fn render(family: &LabeledFamily<PathLabels, Counter>) -> String {
+        let mut registry = Registry::default();
+        registry.register("requests", "Requests", family.clone());
+        let mut text = String::new();
+        encode(&mut text, &registry).unwrap();
+        text
+    }
+
+    #[test]
+    fn series_are_shared_between_lookups_and_clones() {
+        let family = LabeledFamily::<PathLabels, Counter>::default();
+        let labels = PathLabels { path: "/".into() };
+        family.get_or_create(&labels).inc();
+        family.clone().get_or_create(&labels).inc();
+        assert_eq!(family.get_or_create(&labels).get(), 2);
+        assert_eq!(family.len(), 1);
+    }
+
+    #[test]
+    fn label_values_are_escaped_in_output() {
+        let family = LabeledFamily::<PathLabels, Counter>::default();
+        family
+            .get_or_create(&PathLabels {
+                path: "a\"b\nc\\".into(),
+            })
+            .inc();
+        let text = render(&family);
+        assert!(
+            text.contains(r#"requests_total{path="a\"b\nc\\"} 1"#),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn le_is_reserved_on_histograms_only() {
+        let histograms = LabeledFamily::<BucketLabels, Histogram>::new_with_constructor(|| {
+            Histogram::new([1.0])
+        });
+        let result = histograms.try_get_or_create(&BucketLabels { le: "x".into() });
+        assert!(
+            matches!(result, Err(PrometheusError::InvalidLabelName(_))),
+            "expected InvalidLabelName, got {:?}",
+            result.map(|_| ())
+        );
+        assert!(histograms.is_empty());
+
+        let counters = LabeledFamily::<BucketLabels, Counter>::default();
+        assert!(counters
+            .try_get_or_create(&BucketLabels { le: "x".into() })
+            .is_ok());
+    }
+
+    #[test]
+    fn reserved_prefix_and_duplicates_rejected() {
+        let family = LabeledFamily::<Vec<(String, String)>, Counter>::default();
+        for labels in [
+            vec![("__name__".to_string(), "x".to_string())],
+            vec![("bad-name".to_string(), "x".to_string())],
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("a".to_string(), "2".to_string()),
+            ],
+        ] {
+            let result = family.try_get_or_create(&labels);
+            assert!(
+                matches!(result, Err(PrometheusError::InvalidLabelName(_))),
+                "expected InvalidLabelName for {:?}",
+                labels
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid labels")]
+    fn get_or_create_panics_on_policy_violation() {
+        let family = LabeledFamily::<PathLabels, Counter>::default()
+            .with_label_value_policy(LabelValuePolicy::new().max_length(4));
+        family.get_or_create(&PathLabels {
+            path: "/too/long".into(),
+        });
+    }
+
+    #[test]
+    fn redacted_values_share_one_series() {
+        let family = LabeledFamily::<PathLabels, Counter>::default()
+            .with_label_value_policy(LabelValuePolicy::new().redact("path"));
+        family
+            .get_or_create(&PathLabels { path: "/a".into() })
+            .inc();
+        family
+            .get_or_create(&PathLabels { path: "/b".into() })
+            .inc();
+        assert_eq!(family.len(), 1);
+        assert!(render(&family).contains(r#"requests_total{path="[REDACTED]"} 2"#));
+    }
+
+    fn path(p: &str) -> PathLabels {
+        PathLabels { path: p.into() }
+    }
+
+    #[test]
+    fn drop_policy_returns_detached_metric() {
+        let family = LabeledFamily::<PathLabels, Counter>::default()
+            .with_cardinality_limit(1, OverflowPolicy::Drop);
+        let limiter = CardinalityLimiter::default();
+        family
+            .prepare_registration("requests", None, &limiter)
+            .unwrap();
+
+        family.get_or_create(&path("/a")).inc();
+        family.get_or_create(&path("/b")).inc();
+        assert_eq!(family.len(), 1);
+        // A dropped label set is counted once and keeps its detached metric.
+        assert_eq!(family.get_or_create(&path("/b")).get(), 1);
+        assert_eq!(limiter.stats()[0].1.rejected(), 1);
+        assert!(!render(&family).contains("/b"));
+
+        // Once room is freed the label set gets its own series.
+        assert!(family.remove(&path("/a")));
+        family.get_or_create(&path("/b")).inc();
+        assert!(render(&family).contains(r#"requests_total{path="/b"} 1"#));
+    }
+
+    #[test]
+    fn fold_policy_uses_overflow_series() {
+        let family = LabeledFamily::<PathLabels, Counter>::default()
+            .with_cardinality_limit(1, OverflowPolicy::Fold);
+        family.get_or_create(&path("/a")).inc();
+        family.get_or_create(&path("/b")).inc();
+        family.get_or_create(&path("/c")).inc();
+
+        let text = render(&family);
+        assert!(text.contains(r#"requests_total{path="/a"} 1"#), "{}", text);
+        assert!(
+            text.contains(r#"requests_total{path="__overflow__"} 2"#),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn overflow_series_is_not_charged() {
+        use crate::core::cardinality::CardinalityLimits;
+
+        let limiter = CardinalityLimiter::new(
+            CardinalityLimits::new()
+                .per_family(2)
+                .per_registry(2)
+                .overflow(OverflowPolicy::Fold),
+        );
+        let family = LabeledFamily::<PathLabels, Counter>::default();
+        family
+            .prepare_registration("requests", None, &limiter)
+            .unwrap();
+        family.get_or_create(&path("/a"));
+        family.get_or_create(&path("/b"));
+        family.get_or_create(&path("/c"));
+        assert_eq!(family.len(), 3);
+        assert_eq!(limiter.series(), 2);
+
+        // Removing the overflow series returns no slot, and the family can
+        // still only hold two series of its own.
+        assert!(family.remove(&path(OVERFLOW_LABEL_VALUE)));
+        assert_eq!(limiter.series(), 2);
+        assert!(family.remove(&path("/a")));
+        assert_eq!(limiter.series(), 1);
+        family.get_or_create(&path("/d"));
+        family.get_or_create(&path("/e"));
+        let text = render(&family);
+        assert!(text.contains("/d") && !text.contains("/e"), "{}", text);
+        assert_eq!(limiter.series(), 2);
+    }
+
+    #[test]
+    fn registering_again_keeps_one_charge_and_one_stats_entry() {
+        use crate::core::cardinality::CardinalityLimits;
+
+        let limiter = CardinalityLimiter::new(CardinalityLimits::new().per_registry(10));
+        let family = LabeledFamily::<PathLabels, Counter>::default();
+        family
+            .prepare_registration("requests", None, &limiter)
+            .unwrap();
+        family.get_or_create(&path("/a"));
+        family.get_or_create(&path("/b"));
+        family
+            .prepare_registration("requests", None, &limiter)
+            .unwrap();
+
+        assert_eq!(limiter.series(), 2);
+        assert_eq!(limiter.stats().len(), 1);
+    }
+
+    #[test]
+    fn evict_lru_policy_replaces_least_recently_used() {
+        let family = LabeledFamily::<PathLabels, Counter>::default()
+            .with_cardinality_limit(2, OverflowPolicy::EvictLru);
+        family.get_or_create(&path("/a")).inc();
+        family.get_or_create(&path("/b")).inc();
+        // Touch /a so /b becomes the least recently used series.
+        family.get_or_create(&path("/a")).inc();
+        family.get_or_create(&path("/c")).inc();
+
+        let text = render(&family);
+        assert!(text.contains(r#"requests_total{path="/a"} 2"#), "{}", text);
+        assert!(text.contains(r#"requests_total{path="/c"} 1"#), "{}", text);
+        assert!(!text.contains("/b"), "{}", text);
+        // An evicted series starts over when it comes back.
+        assert_eq!(family.get_or_create(&path("/b")).get(), 0);
+    }
+
+    #[test]
+    fn registry_limit_is_shared_across_families() {
+        use crate::core::cardinality::CardinalityLimits;
+
+        let limiter = CardinalityLimiter::new(CardinalityLimits::new().per_registry(2));
+        let first = LabeledFamily::<PathLabels, Counter>::default();
+        let second = LabeledFamily::<PathLabels, Counter>::default();
+        first.prepare_registration("first", None, &limiter).unwrap();
+        second
+            .prepare_registration("second", None, &limiter)
+            .unwrap();
+
+        first.get_or_create(&path("/a"));
+        second.get_or_create(&path("/a"));
+        second.get_or_create(&path("/b"));
+        assert_eq!((first.len(), second.len()), (1, 1));
+        assert_eq!(limiter.series(), 2);
+    }
+
+    #[test]
+    fn idle_series_expire_at_scrape_time() {
+        use crate::core::clock::ManualClock;
+
+        let clock = ManualClock::new();
+        let family = LabeledFamily::<PathLabels, Counter>::default()
+            .with_clock(Arc::new(clock.clone()))
+            .with_ttl(Duration::from_secs(60));
+        family.get_or_create(&path("/old")).inc();
+        family.get_or_create(&path("/busy")).inc();
+
+        clock.advance(Duration::from_secs(45));
+        family.get_or_create(&path("/busy")).inc();
+        clock.advance(Duration::from_secs(30));
+
+        let text = render(&family);
+        assert!(!text.contains("/old"), "{}", text);
+        assert!(
+            text.contains(r#"requests_total{path="/busy"} 2"#),
+            "{}",
+            text
+        );
+
+        // A series that comes back starts from zero.
+        assert_eq!(family.get_or_create(&path("/old")).get(), 0);
+    }
+
+    #[test]
+    fn remove_expired_without_ttl_is_a_no_op() {
+        use crate::core::clock::ManualClock;
+
+        let clock = ManualClock::new();
+        let family =
+            LabeledFamily::<PathLabels, Counter>::default().with_clock(Arc::new(clock.clone()));
+        family.get_or_create(&path("/a"));
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(family.remove_expired(), 0);
+        assert_eq!(family.len(), 1);
+    }
+
+    #[test]
+    fn remove_drops_series_and_frees_its_slot() {
+        let family = LabeledFamily::<PathLabels, Counter>::default()
+            .with_cardinality_limit(1, OverflowPolicy::Drop);
+        let limiter = CardinalityLimiter::default();
+        family
+            .prepare_registration("requests", None, &limiter)
+            .unwrap();
+
+        family.get_or_create(&path("/a")).inc();
+        assert!(family.remove(&path("/a")));
+        assert!(!family.remove(&path("/a")));
+        assert!(family.is_empty());
+
+        family.get_or_create(&path("/b")).inc();
+        assert_eq!(family.len(), 1);
+        assert_eq!(limiter.stats()[0].1.rejected(), 0);
+        assert!(render(&family).contains(r#"requests_total{path="/b"} 1"#));
+    }
+
+    #[test]
+    fn expiry_releases_registry_budget() {
+        use crate::core::cardinality::CardinalityLimits;
+        use crate::core::clock::ManualClock;
+
+        let clock = ManualClock::new();
+        let limiter = CardinalityLimiter::new(CardinalityLimits::new().per_registry(1));
+        let family = LabeledFamily::<PathLabels, Counter>::default()
+            .with_clock(Arc::new(clock.clone()))
+            .with_ttl(Duration::from_secs(10));
+        family
+            .prepare_registration("requests", None, &limiter)
+            .unwrap();
+
+        family.get_or_create(&path("/a"));
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(family.remove_expired(), 1);
+        assert_eq!(limiter.series(), 0);
+
+        family.get_or_create(&path("/b"));
+        assert_eq!(family.len(), 1);
+    }
+}
@@ -0,0 +1,122 @@
+//! Label helpers for the Prometheus backend.
+
+use super::prometheus_backend::validate_prometheus_label_name;
+use prometheus_client::metrics::MetricType;
+use std::borrow::Cow;
+
+/// Escape a label value for the text exposition formats.
+///
+/// prometheus-client writes label values verbatim, so backslashes, double
+/// quotes and newlines must be escaped before they reach the encoder.
+pub fn escape_label_value(value: &str) -> Cow<'_, str> {
+    if !value.contains(['\\', '"', '\n']) {
+        return Cow::Borrowed(value);
+    }
+    let mut escaped = String::with_capacity(value.len() + 2);
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Maximum combined length, in characters, of an exemplar's label names and
+/// values allowed by OpenMetrics.
+pub const MAX_EXEMPLAR_LABEL_CHARS: usize = 128;
+
+/// Build the label set of an exemplar.
+///
+/// Returns `None`, so the sample is recorded without an exemplar, when there
+/// are no labels, a label name is invalid, or the labels exceed
+/// [`MAX_EXEMPLAR_LABEL_CHARS`]. Exemplars are recorded on the hot path, so
+/// bad exemplar labels are dropped rather than reported.
+pub(crate) fn exemplar_label_set(labels: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+    if labels.is_empty() {
+        return None;
+    }
+    let mut chars = 0;
+    for (name, value) in labels {
+        validate_prometheus_label_name(name, MetricType::Unknown).ok()?;
+        chars += name.chars().count() + value.chars().count();
+    }
+    if chars > MAX_EXEMPLAR_LABEL_CHARS {
+        return None;
+    }
+    Some(
+        labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), escape_label_value(value).into_owned()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::prometheus::family::LabelSet;
+
+    #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+    struct HttpLabels {
+        method: String,
+        status: u16,
+        r#type: &'static str,
+    }
+    crate::label_set!(HttpLabels {
+        method,
+        status,
+        r#type
+    });
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn label_set_macro_pairs_in_field_order() {
+        let labels = HttpLabels {
+            method: "GET".into(),
+            status: 200,
+            r#type: "api",
+        };
+        assert_eq!(
+            labels.to_pairs(),
+            pairs(&[("method", "GET"), ("status", "200"), ("type", "api")])
+        );
+    }
+
+    #[test]
+    fn exemplar_labels_are_escaped() {
+        assert_eq!(
+            exemplar_label_set(&[("trace_id", "a\"b")]),
+            Some(pairs(&[("trace_id", "a\\\"b")]))
+        );
+    }
+
+    #[test]
+    fn invalid_or_oversized_exemplar_labels_are_dropped() {
+        assert_eq!(exemplar_label_set(&[]), None);
+        assert_eq!(exemplar_label_set(&[("trace-id", "abc")]), None);
+        assert_eq!(exemplar_label_set(&[("__name__", "abc")]), None);
+
+        let value = "x".repeat(MAX_EXEMPLAR_LABEL_CHARS - 2);
+        assert!(exemplar_label_set(&[("id", &value)]).is_some());
+        let value = "x".repeat(MAX_EXEMPLAR_LABEL_CHARS - 1);
+        assert_eq!(exemplar_label_set(&[("id", &value)]), None);
+    }
+
+    #[test]
+    fn escaping_covers_backslash_quote_and_newline() {
+        assert_eq!(escape_label_value("plain"), "plain");
+        assert_eq!(
+            escape_label_value("a\\b\"c\nd"),
+            "a\\\\b\\\"c\\nd".to_string()
+        );
+    }
+}
@@ -1 +1,4 @@
+pub mod dynamic;
+pub mod family;
+pub mod labels;
 pub mod prometheus_backend;
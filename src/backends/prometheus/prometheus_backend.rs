@@ -4,18 +4,24 @@
 //!
 //! # Labeled Metrics
 //!
-//! For metrics with labels, use the `Family` type with a custom label struct:
+//! For metrics with labels, use prometheus-client's `Family` (aliased as
+//! [`LabeledCounter`], [`LabeledGauge`] and [`LabeledHistogram`]) with a label
+//! struct deriving `EncodeLabelSet`, or a [`LabeledFamily`] (aliased as
+//! [`ManagedCounter`], [`ManagedGauge`] and [`ManagedHistogram`]), which also
+//! validates label names and values before creating a series and supports
+//! cardinality limits and expiry (see [`family`](super::family)).
 //!
 //! ```ignore
-//! use observe_rs::backends::prometheus::{Family, EncodeLabelSet, Histogram};
+//! use observe_rs::backends::prometheus::{LabeledFamily, Histogram};
 //!
-//! #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+//! #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 //! struct HttpLabels {
 //!     method: String,
 //!     status: u16,
 //! }
+//! observe_rs::label_set!(HttpLabels { method, status });
 //!
-//! let latency: Family<HttpLabels, Histogram> = Family::new_with_constructor(|| {
+//! let latency: LabeledFamily<HttpLabels, Histogram> = LabeledFamily::new_with_constructor(|| {
 //!     Histogram::new([0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0].into_iter())
 //! });
 //!
@@ -26,15 +32,35 @@
 //! }).observe(0.042);
 //! ```
 
-use crate::core::metrics::{CounterTrait, GaugeTrait, HistogramTrait, Metric};
+use super::dynamic::DynamicCollector;
+use super::family::check_label_pairs;
+use super::labels::exemplar_label_set;
+use crate::core::cardinality::{CardinalityLimiter, CardinalityStats};
+#[cfg(feature = "filter")]
+use crate::core::filter::MetricFilter;
+use crate::core::metrics::{
+    CounterTrait, ExemplarCounterTrait, ExemplarHistogramTrait, GaugeTrait, HistogramTrait, Metric,
+};
 use crate::core::registry::{MetricBackend, ObservabilityRegistry};
+use crate::core::unit::Unit;
+use prometheus_client::collector::Collector;
+use prometheus_client::encoding::{DescriptorEncoder, EncodeMetric};
+use prometheus_client::metrics::counter::{Atomic, ConstCounter};
+use prometheus_client::metrics::exemplar::{CounterWithExemplar, HistogramWithExemplars};
+use prometheus_client::metrics::info::Info;
 use prometheus_client::metrics::{counter::Counter, gauge::Gauge, histogram::Histogram};
-use prometheus_client::registry::Registry;
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use prometheus_client::registry::{Metric as PrometheusMetric, Registry, Unit as PrometheusUnit};
+use std::borrow::Cow;
+use std::time::SystemTime;
 
 // Re-export key types for labeled metrics
 pub use prometheus_client::encoding::EncodeLabelSet;
 pub use prometheus_client::metrics::family::Family;
 
+pub use super::dynamic::DynamicMetrics;
+pub use super::family::{LabelSet, LabeledFamily};
+
 // ═══════════════════════════════════════════════════════════════════════════
 // CounterTrait implementation for prometheus-client Counter
 // ═══════════════════════════════════════════════════════════════════════════
@@ -93,6 +119,55 @@ impl HistogramTrait for Histogram {
     }
 }
 
+// ═══════════════════════════════════════════════════════════════════════════
+// Exemplar implementations for prometheus-client exemplar metrics
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// Label set stored with an exemplar.
+pub type ExemplarLabels = Vec<(String, String)>;
+
+// `CounterWithExemplar::inc_by` replaces the exemplar even when given none,
+// so increments without an exemplar go straight to the atomic to keep the
+// last one.
+impl CounterTrait for CounterWithExemplar<ExemplarLabels> {
+    fn inc(&self) {
+        CounterTrait::inc_by(self, 1);
+    }
+
+    fn inc_by(&self, value: u64) {
+        Atomic::inc_by(&*self.inner(), value);
+    }
+
+    fn get(&self) -> u64 {
+        CounterWithExemplar::get(self).0
+    }
+}
+
+impl ExemplarCounterTrait for CounterWithExemplar<ExemplarLabels> {
+    fn inc_by_with_exemplar(&self, value: u64, labels: &[(&str, &str)]) {
+        match exemplar_label_set(labels) {
+            Some(exemplar) => {
+                CounterWithExemplar::inc_by(self, value, Some(exemplar), Some(SystemTime::now()));
+            }
+            None => CounterTrait::inc_by(self, value),
+        }
+    }
+}
+
+impl HistogramTrait for HistogramWithExemplars<ExemplarLabels> {
+    fn observe(&self, value: f64) {
+        HistogramWithExemplars::observe(self, value, None, None);
+    }
+}
+
+impl ExemplarHistogramTrait for HistogramWithExemplars<ExemplarLabels> {
+    fn observe_with_exemplar(&self, value: f64, labels: &[(&str, &str)]) {
+        let exemplar = exemplar_label_set(labels);
+        let timestamp = exemplar.is_some().then(SystemTime::now);
+        HistogramWithExemplars::observe(self, value, exemplar, timestamp);
+    }
+}
+
 // ═══════════════════════════════════════════════════════════════════════════
 // MetricBackend implementation for Prometheus
 // ═══════════════════════════════════════════════════════════════════════════
@@ -110,6 +185,18 @@ pub enum PrometheusError {
     /// Histogram buckets invalid (e.g. not finite, negative, or unsorted).
     #[error("Invalid histogram buckets: {0}")]
     InvalidHistogramBuckets(String),
+
+    /// Unit invalid, or the metric name does not end with the unit suffix (e.g. `_seconds`).
+    #[error("Invalid unit: {0}")]
+    InvalidUnit(String),
+
+    /// Label name invalid, reserved (`__` prefix, `le` on histograms) or duplicated.
+    #[error("Invalid label name: {0}")]
+    InvalidLabelName(String),
+
+    /// Label value rejected by the label value policy.
+    #[error("Invalid label value: {0}")]
+    InvalidLabelValue(String),
 }
 
 /// First character of a Prometheus metric name: letter or underscore only.
@@ -123,7 +210,7 @@ fn is_valid_subsequent_char(c: char) -> bool {
 }
 
 /// Prometheus metric names must match `[a-zA-Z_][a-zA-Z0-9_]*` (non-empty).
-fn validate_prometheus_metric_name(name: &str) -> Result<(), PrometheusError> {
+pub(crate) fn validate_prometheus_metric_name(name: &str) -> Result<(), PrometheusError> {
     if name.is_empty() {
         return Err(PrometheusError::InvalidNamingConvention(
             "metric name cannot be empty".to_string(),
@@ -151,8 +238,52 @@ fn validate_prometheus_metric_name(name: &str) -> Result<(), PrometheusError> {
     Ok(())
 }
 
+/// Label names reserved by the exposition format for `metric_type`.
+///
+/// Histograms use `le` for their bucket bounds. Summaries would reserve
+/// `quantile`, but this backend does not produce summaries.
+fn reserved_label_names(metric_type: MetricType) -> &'static [&'static str] {
+    match metric_type {
+        MetricType::Histogram => &["le"],
+        _ => &[],
+    }
+}
+
+/// Prometheus label names must match `[a-zA-Z_][a-zA-Z0-9_]*`, must not start
+/// with `__` (reserved for internal use) and must not collide with a label
+/// the metric type generates itself.
+pub(crate) fn validate_prometheus_label_name(
+    name: &str,
+    metric_type: MetricType,
+) -> Result<(), PrometheusError> {
+    let mut chars = name.chars();
+    let first = chars.next().ok_or_else(|| {
+        PrometheusError::InvalidLabelName("label name cannot be empty".to_string())
+    })?;
+    if !is_valid_first_char(first) || !chars.all(is_valid_subsequent_char) {
+        return Err(PrometheusError::InvalidLabelName(format!(
+            "label name must match [a-zA-Z_][a-zA-Z0-9_]*, got {:?}",
+            name
+        )));
+    }
+    if name.starts_with("__") {
+        return Err(PrometheusError::InvalidLabelName(format!(
+            "label names starting with \"__\" are reserved, got {:?}",
+            name
+        )));
+    }
+    if reserved_label_names(metric_type).contains(&name) {
+        return Err(PrometheusError::InvalidLabelName(format!(
+            "label name {:?} is reserved for {} metrics",
+            name,
+            metric_type.as_str()
+        )));
+    }
+    Ok(())
+}
+
 /// Histogram buckets must be finite, non-negative, and strictly increasing.
-fn validate_histogram_buckets(buckets: &[f64]) -> Result<(), PrometheusError> {
+pub(crate) fn validate_histogram_buckets(buckets: &[f64]) -> Result<(), PrometheusError> {
     for (i, &b) in buckets.iter().enumerate() {
         if !b.is_finite() {
             return Err(PrometheusError::InvalidHistogramBuckets(format!(
@@ -178,6 +309,159 @@ fn validate_histogram_buckets(buckets: &[f64]) -> Result<(), PrometheusError> {
     Ok(())
 }
 
+/// Units must be lowercase `[a-z0-9_]` and the metric name must end with `_<unit>`.
+fn validate_unit(name: &str, unit: &Unit) -> Result<(), PrometheusError> {
+    let unit_str = unit.as_str();
+    if unit_str.is_empty()
+        || !unit_str
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        return Err(PrometheusError::InvalidUnit(format!(
+            "unit must be non-empty and only contain [a-z0-9_], got {:?}",
+            unit_str
+        )));
+    }
+    if !unit.matches_name(name) {
+        return Err(PrometheusError::InvalidUnit(format!(
+            "metric name {:?} must end with {:?} for unit {}",
+            name,
+            unit.suffix(),
+            unit
+        )));
+    }
+    Ok(())
+}
+
+fn to_prometheus_unit(unit: &Unit) -> PrometheusUnit {
+    match unit {
+        Unit::Seconds => PrometheusUnit::Seconds,
+        Unit::Bytes => PrometheusUnit::Bytes,
+        Unit::Ratio | Unit::Custom(_) => PrometheusUnit::Other(unit.as_str().to_string()),
+    }
+}
+
+/// Register `metric`, attaching `unit` metadata when present.
+///
+/// prometheus-client appends the unit to the name itself, so the already
+/// validated suffix is stripped first to keep the exposed name unchanged.
+fn register_with_optional_unit(
+    registry: &mut Registry,
+    name: &str,
+    help: &str,
+    unit: Option<&Unit>,
+    metric: impl PrometheusMetric,
+) -> Result<(), PrometheusError> {
+    match unit {
+        Some(unit) => {
+            validate_unit(name, unit)?;
+            let base = &name[..name.len() - unit.suffix().len()];
+            register_selectable(registry, base, help, Some(to_prometheus_unit(unit)), metric);
+        }
+        None => register_selectable(registry, name, help, None, metric),
+    }
+    Ok(())
+}
+
+/// Register `metric` so that filtered renders can skip it.
+///
+/// Registered as a collector, which decides whether the family is selected
+/// before encoding its descriptor. Like `Registry::register`, a full stop
+/// is appended to `help`.
+fn register_selectable(
+    registry: &mut Registry,
+    name: &str,
+    help: &str,
+    unit: Option<PrometheusUnit>,
+    metric: impl PrometheusMetric,
+) {
+    let exposed = match &unit {
+        Some(unit) => format!("{}_{}", name, unit.as_str()),
+        None => name.to_string(),
+    };
+    registry.register_collector(Box::new(SelectableFamily {
+        name: name.to_string(),
+        exposed,
+        help: format!("{}.", help),
+        unit,
+        metric,
+    }));
+}
+
+/// A registered metric that is only encoded when the render in progress
+/// selects it.
+#[derive(Debug)]
+struct SelectableFamily<M> {
+    name: String,
+    /// The family name as exposed, with the unit suffix.
+    exposed: String,
+    help: String,
+    unit: Option<PrometheusUnit>,
+    metric: M,
+}
+
+impl<M: PrometheusMetric> Collector for SelectableFamily<M> {
+    fn encode(&self, mut encoder: DescriptorEncoder) -> Result<(), std::fmt::Error> {
+        let metric_type = self.metric.metric_type();
+        if !family_selected(&self.exposed, metric_type) {
+            return Ok(());
+        }
+        let metric_encoder =
+            encoder.encode_descriptor(&self.name, &self.help, self.unit.as_ref(), metric_type)?;
+        self.metric.encode(metric_encoder)
+    }
+}
+
+#[cfg(feature = "filter")]
+thread_local! {
+    /// Filter of the render in progress on this thread.
+    ///
+    /// prometheus-client gives collectors no context while encoding, so
+    /// [`with_filter`] hands the filter to them through the rendering
+    /// thread.
+    static CURRENT_FILTER: std::cell::RefCell<Option<MetricFilter>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Run `render` with `filter` selecting the families encoded on this
+/// thread.
+#[cfg(feature = "filter")]
+pub(crate) fn with_filter<R>(filter: &MetricFilter, render: impl FnOnce() -> R) -> R {
+    /// Restores the previous filter, also when `render` panics.
+    struct Restore(Option<MetricFilter>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT_FILTER.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CURRENT_FILTER.with(|current| current.replace(Some(filter.clone())));
+    let _restore = Restore(previous);
+    render()
+}
+
+/// Whether the family `name` is part of the render in progress; outside
+/// a filtered render every family is.
+pub(crate) fn family_selected(name: &str, metric_type: MetricType) -> bool {
+    #[cfg(feature = "filter")]
+    {
+        use crate::core::snapshot::MetricKind;
+        CURRENT_FILTER.with(|current| {
+            current
+                .borrow()
+                .as_ref()
+                .is_none_or(|filter| filter.matches(name, MetricKind::parse(metric_type.as_str())))
+        })
+    }
+    #[cfg(not(feature = "filter"))]
+    {
+        let _ = (name, metric_type);
+        true
+    }
+}
+
 /// Prometheus backend marker type.
 ///
 /// Use this with `ObservabilityRegistry<PrometheusBackend>` to create
@@ -189,20 +473,39 @@ impl MetricBackend for PrometheusBackend {
     type Counter = Counter<u64>;
     type Gauge = Gauge<i64>;
     type Histogram = Histogram;
+    type ExemplarCounter = CounterWithExemplar<ExemplarLabels>;
+    type ExemplarHistogram = HistogramWithExemplars<ExemplarLabels>;
     type Error = PrometheusError;
 
     fn create_registry() -> Self::Registry {
         Registry::default()
     }
 
+    fn duplicate_name_error(name: &str) -> Self::Error {
+        PrometheusError::RegistrationError(format!("metric {:?} is already registered", name))
+    }
+
+    fn create_registry_with_labels(
+        labels: &[(String, String)],
+    ) -> Result<Self::Registry, Self::Error> {
+        // Constant labels end up on histograms too, so `le` is off limits.
+        let labels = check_label_pairs(labels, MetricType::Histogram, None)?;
+        Ok(Registry::with_labels(
+            labels
+                .into_iter()
+                .map(|(k, v)| (Cow::Owned(k), Cow::Owned(v))),
+        ))
+    }
+
     fn register_counter(
         registry: &mut Self::Registry,
         name: &str,
         help: &str,
+        unit: Option<&Unit>,
     ) -> Result<Self::Counter, Self::Error> {
         validate_prometheus_metric_name(name)?;
         let counter = Counter::default();
-        registry.register(name, help, counter.clone());
+        register_with_optional_unit(registry, name, help, unit, counter.clone())?;
         Ok(counter)
     }
 
@@ -210,10 +513,11 @@ impl MetricBackend for PrometheusBackend {
         registry: &mut Self::Registry,
         name: &str,
         help: &str,
+        unit: Option<&Unit>,
     ) -> Result<Self::Gauge, Self::Error> {
         validate_prometheus_metric_name(name)?;
         let gauge = Gauge::default();
-        registry.register(name, help, gauge.clone());
+        register_with_optional_unit(registry, name, help, unit, gauge.clone())?;
         Ok(gauge)
     }
 
@@ -222,13 +526,60 @@ impl MetricBackend for PrometheusBackend {
         name: &str,
         help: &str,
         buckets: Vec<f64>,
+        unit: Option<&Unit>,
     ) -> Result<Self::Histogram, Self::Error> {
         validate_prometheus_metric_name(name)?;
         validate_histogram_buckets(&buckets)?;
         let histogram = Histogram::new(buckets);
-        registry.register(name, help, histogram.clone());
+        register_with_optional_unit(registry, name, help, unit, histogram.clone())?;
+        Ok(histogram)
+    }
+
+    fn register_exemplar_counter(
+        registry: &mut Self::Registry,
+        name: &str,
+        help: &str,
+        unit: Option<&Unit>,
+    ) -> Result<Self::ExemplarCounter, Self::Error> {
+        validate_prometheus_metric_name(name)?;
+        let counter = CounterWithExemplar::default();
+        register_with_optional_unit(registry, name, help, unit, counter.clone())?;
+        Ok(counter)
+    }
+
+    fn register_exemplar_histogram(
+        registry: &mut Self::Registry,
+        name: &str,
+        help: &str,
+        buckets: Vec<f64>,
+        unit: Option<&Unit>,
+    ) -> Result<Self::ExemplarHistogram, Self::Error> {
+        validate_prometheus_metric_name(name)?;
+        validate_histogram_buckets(&buckets)?;
+        let histogram = HistogramWithExemplars::new(buckets.into_iter());
+        register_with_optional_unit(registry, name, help, unit, histogram.clone())?;
         Ok(histogram)
     }
+
+    /// Info metrics are exposed with the OpenMetrics `info` type: the family
+    /// is named without the `_info` suffix and its single sample with it,
+    /// e.g. `# TYPE target info` and `target_info{service="checkout"} 1`.
+    /// Names without the suffix get it on their sample.
+    fn register_info(
+        registry: &mut Self::Registry,
+        name: &str,
+        help: &str,
+        labels: &[(String, String)],
+    ) -> Result<(), Self::Error> {
+        validate_prometheus_metric_name(name)?;
+        let labels = check_label_pairs(labels, MetricType::Info, None)?;
+        let family = name
+            .strip_suffix("_info")
+            .filter(|family| !family.is_empty())
+            .unwrap_or(name);
+        register_selectable(registry, family, help, None, Info::new(labels));
+        Ok(())
+    }
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
@@ -263,6 +614,12 @@ pub type PrometheusCounter = Metric<Counter<u64>>;
 /// A Prometheus gauge metric with metadata.
 pub type PrometheusGauge = Metric<Gauge<i64>>;
 
+/// A Prometheus counter that keeps its latest exemplar.
+pub type PrometheusExemplarCounter = Metric<CounterWithExemplar<ExemplarLabels>>;
+
+/// A Prometheus histogram that keeps the latest exemplar of each bucket.
+pub type PrometheusExemplarHistogram = Metric<HistogramWithExemplars<ExemplarLabels>>;
+
 /// A Prometheus histogram metric with metadata.
 pub type PrometheusHistogram = Metric<Histogram>;
 
@@ -476,6 +833,218 @@ where
     Family::default()
 }
 
+/// A labeled counter family that validates its label sets (see
+/// [`LabeledFamily`]).
+pub type ManagedCounter<L> = LabeledFamily<L, Counter<u64>>;
+
+/// A labeled gauge family that validates its label sets.
+pub type ManagedGauge<L> = LabeledFamily<L, Gauge<i64>>;
+
+/// A labeled histogram family that validates its label sets.
+pub type ManagedHistogram<L> = LabeledFamily<L, Histogram>;
+
+/// A labeled counter family whose series keep their latest exemplar.
+pub type ManagedExemplarCounter<L> = LabeledFamily<L, CounterWithExemplar<ExemplarLabels>>;
+
+/// A labeled histogram family whose series keep the latest exemplar of each
+/// bucket.
+pub type ManagedExemplarHistogram<L> = LabeledFamily<L, HistogramWithExemplars<ExemplarLabels>>;
+
+/// Create a labeled counter family that validates its label sets.
+pub fn managed_counter<L: LabelSet>() -> ManagedCounter<L> {
+    LabeledFamily::default()
+}
+
+/// Create a labeled gauge family that validates its label sets.
+pub fn managed_gauge<L: LabelSet>() -> ManagedGauge<L> {
+    LabeledFamily::default()
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Registering labeled families
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// Name of the counter of label sets rejected by cardinality limits.
+pub const CARDINALITY_REJECTED_METRIC: &str = "observe_rs_cardinality_rejected_series";
+
+/// Name of the counter of series evicted by cardinality limits.
+pub const CARDINALITY_EVICTED_METRIC: &str = "observe_rs_cardinality_evicted_series";
+
+type StatReader = fn(&CardinalityStats) -> u64;
+
+/// Exposes a registry's [`CardinalityLimiter`] statistics, one series per
+/// limited family.
+#[derive(Debug)]
+struct CardinalityCollector(CardinalityLimiter);
+
+impl Collector for CardinalityCollector {
+    fn encode(&self, mut encoder: DescriptorEncoder) -> Result<(), std::fmt::Error> {
+        let stats = self.0.stats();
+        if stats.is_empty() {
+            return Ok(());
+        }
+        let counters: [(&str, &str, StatReader); 2] = [
+            (
+                CARDINALITY_REJECTED_METRIC,
+                "Label sets dropped or folded because a cardinality limit was reached",
+                CardinalityStats::rejected,
+            ),
+            (
+                CARDINALITY_EVICTED_METRIC,
+                "Series evicted to stay within a cardinality limit",
+                CardinalityStats::evicted,
+            ),
+        ];
+        for (name, help, value) in counters {
+            if !family_selected(name, MetricType::Counter) {
+                continue;
+            }
+            let mut metric_encoder =
+                encoder.encode_descriptor(name, help, None, MetricType::Counter)?;
+            for (family, family_stats) in &stats {
+                let labels = [("family", family.as_str())];
+                let counter = ConstCounter::new(value(family_stats));
+                counter.encode(metric_encoder.encode_family(&labels)?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ObservabilityRegistry<PrometheusBackend> {
+    /// Create a set of families that can be added after registration.
+    ///
+    /// The returned [`DynamicMetrics`] is exported by this registry; families
+    /// created through it get the registry's label value policy and
+    /// cardinality limits. Call this once per integration and keep the handle.
+    pub fn dynamic_metrics(&mut self) -> DynamicMetrics {
+        let dynamic = DynamicMetrics::new(
+            self.label_value_policy().cloned(),
+            self.cardinality().clone(),
+            self.registered_names().clone(),
+        );
+        self.register_cardinality_collector();
+        let collector = DynamicCollector(dynamic.clone());
+        self.inner_mut().register_collector(Box::new(collector));
+        dynamic
+    }
+
+    fn register_cardinality_collector(&mut self) {
+        if self.cardinality().mark_exported() {
+            let collector = CardinalityCollector(self.cardinality().clone());
+            self.inner_mut().register_collector(Box::new(collector));
+        }
+    }
+
+    /// Register a labeled family under `name`.
+    ///
+    /// The registry's [`LabelValuePolicy`](crate::core::labels::LabelValuePolicy)
+    /// is attached unless the family already has one, and any series created
+    /// before registration are checked against it. Label names are checked
+    /// when a label set is first seen, since a label struct only reveals its
+    /// names once encoded.
+    pub fn register_family<L, M>(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        family: &LabeledFamily<L, M>,
+    ) -> Result<(), PrometheusError>
+    where
+        L: LabelSet,
+        M: PrometheusMetric + TypedMetric + Clone,
+    {
+        let name = name.into();
+        validate_prometheus_metric_name(&name)?;
+        if !self.registered_names().insert(&name) {
+            return Err(PrometheusBackend::duplicate_name_error(&name));
+        }
+        if let Err(e) =
+            family.prepare_registration(&name, self.label_value_policy(), self.cardinality())
+        {
+            self.registered_names().remove(&name);
+            return Err(e);
+        }
+        self.register_cardinality_collector();
+        register_selectable(self.inner_mut(), &name, &help.into(), None, family.clone());
+        Ok(())
+    }
+
+    /// Create and register a labeled counter family.
+    pub fn labeled_counter<L: LabelSet>(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+    ) -> Result<ManagedCounter<L>, PrometheusError> {
+        let family = managed_counter();
+        self.register_family(name, help, &family)?;
+        Ok(family)
+    }
+
+    /// Create and register a labeled gauge family.
+    pub fn labeled_gauge<L: LabelSet>(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+    ) -> Result<ManagedGauge<L>, PrometheusError> {
+        let family = managed_gauge();
+        self.register_family(name, help, &family)?;
+        Ok(family)
+    }
+
+    /// Create and register a labeled histogram family with custom buckets.
+    pub fn labeled_histogram<L: LabelSet>(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        buckets: Vec<f64>,
+    ) -> Result<ManagedHistogram<L>, PrometheusError> {
+        validate_histogram_buckets(&buckets)?;
+        let family = LabeledFamily::new_with_constructor(move || Histogram::new(buckets.clone()));
+        self.register_family(name, help, &family)?;
+        Ok(family)
+    }
+
+    /// Create and register a labeled counter family whose series keep their
+    /// latest exemplar.
+    pub fn labeled_counter_with_exemplars<L: LabelSet>(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+    ) -> Result<ManagedExemplarCounter<L>, PrometheusError> {
+        let family = LabeledFamily::default();
+        self.register_family(name, help, &family)?;
+        Ok(family)
+    }
+
+    /// Create and register a labeled histogram family whose series keep the
+    /// latest exemplar of each bucket.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let latency = registry.labeled_histogram_with_exemplars::<HttpLabels>(
+    ///     "http_request_duration_seconds",
+    ///     "Request latency",
+    ///     DEFAULT_LATENCY_BUCKETS.to_vec(),
+    /// )?;
+    /// latency
+    ///     .get_or_create(&labels)
+    ///     .observe_with_exemplar(1.7, &[("trace_id", trace_id)]);
+    /// ```
+    pub fn labeled_histogram_with_exemplars<L: LabelSet>(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        buckets: Vec<f64>,
+    ) -> Result<ManagedExemplarHistogram<L>, PrometheusError> {
+        validate_histogram_buckets(&buckets)?;
+        let family = LabeledFamily::new_with_constructor(move || {
+            HistogramWithExemplars::new(buckets.iter().copied())
+        });
+        self.register_family(name, help, &family)?;
+        Ok(family)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -615,7 +1184,9 @@ mod tests {
         assert!(text.contains("42"));
 
         // Verify content type
-        assert!(output.content_type.contains("text/plain"));
+        assert!(output
+            .content_type
+            .starts_with("application/openmetrics-text"));
     }
 
     #[test]
@@ -902,4 +1473,481 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn const_labels_are_attached_to_every_series() {
+        let mut registry =
+            PrometheusRegistry::with_const_labels([("service", "checkout"), ("env", "prod")])
+                .unwrap();
+        registry.counter("requests", "Requests").unwrap().inc();
+        registry.gauge("in_flight", "In flight").unwrap().set(3);
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains(r#"requests_total{service="checkout",env="prod"} 1"#));
+        assert!(text.contains(r#"in_flight{service="checkout",env="prod"} 3"#));
+    }
+
+    #[test]
+    fn info_metric_renders_single_series_with_value_one() {
+        let mut registry = PrometheusRegistry::new();
+        registry
+            .info("target_info", "Target metadata", [("service", "checkout")])
+            .unwrap();
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains("# TYPE target info"));
+        assert!(text.contains(r#"target_info{service="checkout"} 1"#));
+    }
+
+    #[test]
+    fn info_metric_invalid_name_rejected() {
+        let mut registry = PrometheusRegistry::new();
+        let result = registry.info("bad-info", "help", [("k", "v")]);
+        assert!(
+            matches!(result, Err(PrometheusError::InvalidNamingConvention(_))),
+            "expected InvalidNamingConvention, got {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn unit_emitted_as_openmetrics_metadata() {
+        let mut registry = PrometheusRegistry::new();
+        let latency = registry
+            .histogram_with_unit(
+                "request_duration_seconds",
+                "Request latency",
+                vec![0.1, 1.0],
+                Unit::Seconds,
+            )
+            .unwrap();
+        latency.observe(0.2);
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains("# UNIT request_duration_seconds seconds"));
+        assert!(text.contains("request_duration_seconds_count 1"));
+        assert_eq!(latency.unit(), Some(&Unit::Seconds));
+    }
+
+    #[test]
+    fn typed_histogram_observes_durations() {
+        use crate::core::unit::Seconds;
+        use std::time::Duration;
+
+        let mut registry = PrometheusRegistry::new();
+        let latency = registry
+            .typed_histogram::<Seconds>("job_duration_seconds", "Job duration", vec![1.0, 5.0])
+            .unwrap();
+        latency.observe_duration(Duration::from_millis(1500));
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains("# UNIT job_duration_seconds seconds"));
+        assert!(text.contains("job_duration_seconds_sum 1.5"));
+    }
+
+    #[test]
+    fn counter_and_gauge_units_keep_exposed_name() {
+        let mut registry = PrometheusRegistry::new();
+        registry
+            .counter_with_unit("cpu_seconds", "CPU time", Unit::Seconds)
+            .unwrap()
+            .inc_by(3);
+        registry
+            .gauge_with_unit("heap_bytes", "Heap size", Unit::Bytes)
+            .unwrap()
+            .set(512);
+        registry
+            .gauge_with_unit("cache_hit_ratio", "Cache hit ratio", Unit::Ratio)
+            .unwrap();
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains("cpu_seconds_total 3"));
+        assert!(text.contains("heap_bytes 512"));
+        assert!(text.contains("# UNIT cache_hit_ratio ratio"));
+    }
+
+    #[test]
+    fn validation_unit_suffix_missing_rejected() {
+        let mut registry = PrometheusRegistry::new();
+        let result =
+            registry.histogram_with_unit("request_duration", "help", vec![1.0], Unit::Seconds);
+        assert!(
+            matches!(result, Err(PrometheusError::InvalidUnit(_))),
+            "expected InvalidUnit, got {:?}",
+            result
+        );
+
+        let result = registry.gauge_with_unit("payload_seconds", "help", Unit::Bytes);
+        assert!(
+            matches!(result, Err(PrometheusError::InvalidUnit(_))),
+            "expected InvalidUnit, got {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn validation_custom_unit_invalid_chars_rejected() {
+        let mut registry = PrometheusRegistry::new();
+        let result =
+            registry.gauge_with_unit("temp_Celsius", "help", Unit::Custom("Celsius".to_string()));
+        assert!(
+            matches!(result, Err(PrometheusError::InvalidUnit(_))),
+            "expected InvalidUnit, got {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn validation_const_label_names_rejected() {
+        for name in ["__reserved", "bad-name", "le", ""] {
+            let result = PrometheusRegistry::with_const_labels([(name, "x")]);
+            assert!(
+                matches!(result, Err(PrometheusError::InvalidLabelName(_))),
+                "expected InvalidLabelName for {:?}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn validation_info_label_names_rejected() {
+        let mut registry = PrometheusRegistry::new();
+        let result = registry.info("target_info", "help", [("__name__", "x")]);
+        assert!(
+            matches!(result, Err(PrometheusError::InvalidLabelName(_))),
+            "expected InvalidLabelName, got {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn registry_label_value_policy_applies_to_labeled_families() {
+        use crate::core::labels::LabelValuePolicy;
+
+        #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+        struct UserLabels {
+            tenant: String,
+            email: String,
+        }
+        crate::label_set!(UserLabels { tenant, email });
+
+        let mut registry = PrometheusRegistry::new().with_label_value_policy(
+            LabelValuePolicy::new()
+                .allowed_chars(|c| c.is_ascii_alphanumeric() || c == '@' || c == '.')
+                .redact("email"),
+        );
+        let logins = registry
+            .labeled_counter::<UserLabels>("logins", "Logins")
+            .unwrap();
+
+        logins
+            .get_or_create(&UserLabels {
+                tenant: "acme".into(),
+                email: "someone@example.com".into(),
+            })
+            .inc();
+        let result = logins.try_get_or_create(&UserLabels {
+            tenant: "ac me".into(),
+            email: "x".into(),
+        });
+        assert!(
+            matches!(result, Err(PrometheusError::InvalidLabelValue(_))),
+            "expected InvalidLabelValue, got {:?}",
+            result
+        );
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains(r#"logins_total{tenant="acme",email="[REDACTED]"} 1"#));
+        assert!(!text.contains("someone@example.com"));
+    }
+
+    #[cfg(feature = "filter")]
+    #[test]
+    fn filters_apply_to_the_current_render_only() {
+        let filter = MetricFilter::new().prefix("http_");
+        with_filter(&filter, || {
+            assert!(family_selected("http_requests", MetricType::Counter));
+            assert!(!family_selected("queue_depth", MetricType::Gauge));
+            // Other threads render unfiltered.
+            std::thread::spawn(|| assert!(family_selected("queue_depth", MetricType::Gauge)))
+                .join()
+                .unwrap();
+        });
+        assert!(family_selected("queue_depth", MetricType::Gauge));
+
+        let _ = std::panic::catch_unwind(|| with_filter(&filter, || panic!("render failed")));
+        assert!(family_selected("queue_depth", MetricType::Gauge));
+    }
+
+    #[cfg(feature = "filter")]
+    #[test]
+    fn filtered_renders_skip_families_before_encoding_them() {
+        use crate::core::filter::MetricFilter;
+        use prometheus_client::encoding::MetricEncoder;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        /// A gauge counting how often it is encoded.
+        #[derive(Debug, Clone, Default)]
+        struct Encodes(Arc<AtomicUsize>);
+
+        impl EncodeMetric for Encodes {
+            fn encode(&self, mut encoder: MetricEncoder) -> Result<(), std::fmt::Error> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                encoder.encode_gauge(&1i64)
+            }
+
+            fn metric_type(&self) -> MetricType {
+                MetricType::Gauge
+            }
+        }
+
+        let mut registry = PrometheusRegistry::new();
+        registry.counter("http_requests", "Requests").unwrap().inc();
+        registry
+            .dynamic_metrics()
+            .gauge("pool_size", "Connections")
+            .unwrap();
+        let encodes = Encodes::default();
+        register_selectable(
+            registry.inner_mut(),
+            "queue_depth",
+            "Items",
+            None,
+            encodes.clone(),
+        );
+
+        let filter = MetricFilter::new().name("http_requests_total");
+        let mut out = Vec::new();
+        registry.render_filtered_to(&filter, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text,
+            "# HELP http_requests Requests.\n# TYPE http_requests counter\nhttp_requests_total 1\n# EOF\n"
+        );
+        assert_eq!(encodes.0.load(Ordering::Relaxed), 0);
+
+        let text = registry.render().unwrap().as_str().unwrap().to_string();
+        assert!(text.contains("queue_depth 1\n") && text.contains("pool_size"));
+        assert_eq!(encodes.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(all(feature = "filter", feature = "relabel"))]
+    #[test]
+    fn filtered_renders_are_relabeled() {
+        use crate::core::filter::MetricFilter;
+        use crate::core::relabel::RelabelRules;
+
+        let mut registry = PrometheusRegistry::new()
+            .with_relabel_rules(RelabelRules::new().rename("http_(.*)", "web_$1").unwrap());
+        registry.counter("http_requests", "Requests").unwrap().inc();
+        registry.gauge("queue_depth", "Items").unwrap().set(1);
+
+        let mut out = Vec::new();
+        registry
+            .render_filtered_to(&MetricFilter::new().prefix("http_"), &mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("web_requests_total 1\n"), "{}", text);
+        assert!(!text.contains("queue_depth"));
+    }
+
+    #[test]
+    fn register_family_rechecks_existing_series() {
+        use crate::core::labels::LabelValuePolicy;
+
+        let family: ManagedGauge<Vec<(String, String)>> = LabeledFamily::default();
+        family
+            .get_or_create(&vec![("path".to_string(), "/a/very/long/path".to_string())])
+            .set(1);
+
+        let mut registry = PrometheusRegistry::new()
+            .with_label_value_policy(LabelValuePolicy::new().max_length(8));
+        let result = registry.register_family("paths", "Paths", &family);
+        assert!(
+            matches!(result, Err(PrometheusError::InvalidLabelValue(_))),
+            "expected InvalidLabelValue, got {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn registered_names_are_rejected_across_static_and_labeled_metrics() {
+        let mut registry = PrometheusRegistry::new();
+        registry.counter("reqs", "Requests").unwrap();
+        registry
+            .labeled_counter::<Vec<(String, String)>>("jobs", "Jobs")
+            .unwrap();
+
+        let duplicate = |result: Result<(), PrometheusError>| {
+            matches!(result, Err(PrometheusError::RegistrationError(_)))
+        };
+        assert!(duplicate(registry.counter("reqs", "Requests").map(drop)));
+        assert!(duplicate(registry.gauge("reqs", "Requests").map(drop)));
+        assert!(duplicate(
+            registry
+                .labeled_counter::<Vec<(String, String)>>("reqs", "Requests")
+                .map(drop)
+        ));
+        assert!(duplicate(
+            registry
+                .labeled_counter::<Vec<(String, String)>>("jobs", "Jobs")
+                .map(drop)
+        ));
+        assert!(duplicate(registry.counter("jobs", "Jobs").map(drop)));
+        assert!(duplicate(registry.info("jobs", "Jobs", [("a", "b")])));
+
+        // A failed registration doesn't take the name.
+        assert!(registry.counter("bad-name", "Bad").is_err());
+        assert!(!registry.registered_names().contains("bad-name"));
+
+        let text = registry.render().unwrap().as_str().unwrap().to_string();
+        assert_eq!(text.matches("# TYPE reqs counter").count(), 1, "{}", text);
+        assert_eq!(text.matches("# TYPE jobs counter").count(), 1, "{}", text);
+    }
+
+    #[test]
+    fn labeled_histogram_rejects_le_label() {
+        #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+        struct BadLabels {
+            le: String,
+        }
+        crate::label_set!(BadLabels { le });
+
+        let mut registry = PrometheusRegistry::new();
+        let latency = registry
+            .labeled_histogram::<BadLabels>("latency_seconds", "Latency", vec![0.1, 1.0])
+            .unwrap();
+        let result = latency.try_get_or_create(&BadLabels { le: "1".into() });
+        assert!(
+            matches!(result, Err(PrometheusError::InvalidLabelName(_))),
+            "expected InvalidLabelName, got {:?}",
+            result.map(|_| ())
+        );
+    }
+
+    #[test]
+    fn cardinality_self_metrics_count_rejected_series() {
+        use crate::core::cardinality::{CardinalityLimits, OverflowPolicy};
+
+        let mut registry = PrometheusRegistry::new().with_cardinality_limits(
+            CardinalityLimits::new()
+                .per_family(1)
+                .overflow(OverflowPolicy::Fold),
+        );
+        let requests = registry
+            .labeled_counter::<Vec<(String, String)>>("requests", "Requests")
+            .unwrap();
+        for user in ["alice", "bob", "carol"] {
+            requests
+                .get_or_create(&vec![("user".to_string(), user.to_string())])
+                .inc();
+        }
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains(r#"requests_total{user="alice"} 1"#));
+        assert!(text.contains(r#"requests_total{user="__overflow__"} 2"#));
+        assert!(
+            text.contains(r#"observe_rs_cardinality_rejected_series_total{family="requests"} 2"#)
+        );
+        assert!(
+            text.contains(r#"observe_rs_cardinality_evicted_series_total{family="requests"} 0"#)
+        );
+    }
+
+    #[test]
+    fn unlimited_registry_exposes_no_cardinality_metrics() {
+        let mut registry = PrometheusRegistry::new();
+        registry
+            .labeled_gauge::<Vec<(String, String)>>("pools", "Pools")
+            .unwrap();
+        let output = registry.render().unwrap();
+        assert!(!output.as_str().unwrap().contains("observe_rs_cardinality"));
+    }
+
+    #[test]
+    fn histogram_keeps_last_exemplar_per_bucket() {
+        let mut registry = PrometheusRegistry::new();
+        let latency = registry
+            .histogram_with_exemplars("latency_seconds", "Latency", vec![0.1, 1.0])
+            .unwrap();
+        latency.observe_with_exemplar(0.05, &[("trace_id", "first")]);
+        latency.observe_with_exemplar(0.07, &[("trace_id", "second")]);
+        latency.observe_with_exemplar(0.5, &[("trace_id", "slow")]);
+        latency.observe(0.6);
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(
+            text.contains(r#"latency_seconds_bucket{le="0.1"} 2 # {trace_id="second"} 0.07 "#),
+            "{}",
+            text
+        );
+        assert!(text.contains(r#"latency_seconds_bucket{le="1.0"} 4 # {trace_id="slow"} 0.5 "#));
+        assert!(!text.contains("first"));
+    }
+
+    #[test]
+    fn counter_exposes_latest_exemplar() {
+        let mut registry = PrometheusRegistry::new();
+        let orders = registry
+            .counter_with_exemplars("orders", "Orders placed")
+            .unwrap();
+        orders.inc_with_exemplar(&[("trace_id", "abc")]);
+        orders.inc_by_with_exemplar(2, &[("trace_id", "def")]);
+        orders.inc();
+
+        assert_eq!(orders.get_counter(), 4);
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(
+            text.contains(r#"orders_total 4 # {trace_id="def"} 2.0 "#),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn invalid_exemplar_labels_still_record_the_observation() {
+        let mut registry = PrometheusRegistry::new();
+        let latency = registry
+            .histogram_with_exemplars("latency_seconds", "Latency", vec![1.0])
+            .unwrap();
+        latency.observe_with_exemplar(0.5, &[("trace-id", "abc")]);
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(
+            text.contains("latency_seconds_bucket{le=\"1.0\"} 1\n"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn labeled_histogram_with_exemplars_escapes_exemplar_values() {
+        let mut registry = PrometheusRegistry::new();
+        let latency = registry
+            .labeled_histogram_with_exemplars::<Vec<(String, String)>>(
+                "http_latency_seconds",
+                "Latency",
+                vec![1.0],
+            )
+            .unwrap();
+        latency
+            .get_or_create(&vec![("method".to_string(), "GET".to_string())])
+            .observe_with_exemplar(0.2, &[("note", "a\"b")]);
+
+        let output = registry.render().unwrap();
+        assert!(output.as_str().unwrap().contains(
+            r#"http_latency_seconds_bucket{le="1.0",method="GET"} 1 # {note="a\"b"} 0.2 "#
+        ));
+    }
 }
@@ -0,0 +1,125 @@
+//! Build metadata exposed as an info metric.
+//!
+//! Use the [`build_info!`](crate::build_info) macro to capture the calling
+//! crate's version at compile time:
+//!
+//! ```ignore
+//! use observe_rs::build_info;
+//!
+//! let server = StandaloneServer::<PrometheusBackend>::builder()
+//!     .build_info(build_info!())
+//!     .build();
+//! ```
+
+/// Environment variable consulted for the git commit SHA.
+///
+/// It is read at compile time by [`build_info!`](crate::build_info) and,
+/// if unset there, at runtime as a fallback.
+pub const GIT_SHA_ENV: &str = "GIT_SHA";
+
+/// Value used for a build-info label whose source is unavailable.
+pub const UNKNOWN: &str = "unknown";
+
+/// Build metadata for the `build_info` metric.
+///
+/// Rendered as `build_info{version="1.2.3",git_sha="abc123"} 1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    version: String,
+    git_sha: String,
+}
+
+impl BuildInfo {
+    /// Create build info for the given version with an unknown git SHA.
+    pub fn new(version: impl Into<String>) -> Self {
+        Self {
+            version: version.into(),
+            git_sha: UNKNOWN.to_string(),
+        }
+    }
+
+    /// Create build info from a version and an optional compile-time git SHA.
+    ///
+    /// If `compile_time_sha` is `None`, the [`GIT_SHA_ENV`] variable is read
+    /// at runtime instead. This is what [`build_info!`](crate::build_info)
+    /// expands to.
+    pub fn from_parts(version: impl Into<String>, compile_time_sha: Option<&str>) -> Self {
+        let git_sha = compile_time_sha
+            .map(str::to_string)
+            .or_else(|| std::env::var(GIT_SHA_ENV).ok())
+            .filter(|sha| !sha.is_empty())
+            .unwrap_or_else(|| UNKNOWN.to_string());
+        Self {
+            version: version.into(),
+            git_sha,
+        }
+    }
+
+    /// Set the git commit SHA.
+    pub fn git_sha(mut self, sha: impl Into<String>) -> Self {
+        self.git_sha = sha.into();
+        self
+    }
+
+    /// Get the version label value.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Get the git SHA label value.
+    pub fn sha(&self) -> &str {
+        &self.git_sha
+    }
+
+    /// Label pairs for the info metric.
+    pub fn labels(&self) -> Vec<(String, String)> {
+        vec![
+            ("version".to_string(), self.version.clone()),
+            ("git_sha".to_string(), self.git_sha.clone()),
+        ]
+    }
+}
+
+/// Capture [`BuildInfo`] for the calling crate.
+///
+/// The version comes from the caller's `CARGO_PKG_VERSION`; the git SHA from
+/// the `GIT_SHA` environment variable at compile time (e.g. set in `build.rs`
+/// or CI), falling back to the runtime environment.
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::core::build_info::BuildInfo::from_parts(
+            env!("CARGO_PKG_VERSION"),
+            option_env!("GIT_SHA"),
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_info_labels_use_version_and_sha() {
+        let info = BuildInfo::new("1.2.3").git_sha("abc123");
+        assert_eq!(
+            info.labels(),
+            vec![
+                ("version".to_string(), "1.2.3".to_string()),
+                ("git_sha".to_string(), "abc123".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_parts_prefers_compile_time_sha() {
+        let info = BuildInfo::from_parts("0.1.0", Some("deadbeef"));
+        assert_eq!(info.sha(), "deadbeef");
+    }
+
+    #[test]
+    fn build_info_macro_captures_crate_version() {
+        let info = crate::build_info!();
+        assert_eq!(info.version(), env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -0,0 +1,404 @@
+//! Cardinality limits for labeled families.
+//!
+//! A single unbounded label value (a user id in a path, a request id) can
+//! create a new series per request. [`CardinalityLimits`] caps the number of
+//! series per family and per registry and picks what happens to label sets
+//! that don't fit ([`OverflowPolicy`]). Every family registered through a
+//! registry shares that registry's [`CardinalityLimiter`], which also counts
+//! rejected and evicted series so they can be exposed as self-metrics.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+use serde::Deserialize;
+
+/// Label value used for the series that absorbs folded label sets.
+pub const OVERFLOW_LABEL_VALUE: &str = "__overflow__";
+
+/// What to do with a new label set once a limit is reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    any(feature = "json-config", feature = "yaml-config"),
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum OverflowPolicy {
+    /// Don't create the series; updates go to a detached metric that is
+    /// never exported. Each dropped label set is counted as rejected once
+    /// until series are removed or expire.
+    #[default]
+    Drop,
+    /// Record into a single series whose label values are all
+    /// [`OVERFLOW_LABEL_VALUE`]. The overflow series does not count
+    /// against the limits.
+    Fold,
+    /// Evict the family's least recently used series to make room.
+    ///
+    /// Eviction is always within the family, also when the registry limit
+    /// was hit: the family gives up its own series even if other families
+    /// hold older ones. A family with no series to evict drops the label
+    /// set.
+    EvictLru,
+}
+
+/// Series caps for labeled families.
+///
+/// Configurable from code or, with `json-config`/`yaml-config`, as the
+/// `cardinality` section of a registry document:
+///
+/// ```yaml
+/// cardinality:
+///   max_series_per_family: 1000
+///   max_series_per_registry: 50000
+///   overflow: fold
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    any(feature = "json-config", feature = "yaml-config"),
+    derive(Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct CardinalityLimits {
+    /// Maximum number of series in one family.
+    pub max_series_per_family: Option<usize>,
+    /// Maximum number of series across all families of a registry.
+    pub max_series_per_registry: Option<usize>,
+    /// Behavior once a limit is reached.
+    pub overflow: OverflowPolicy,
+}
+
+impl CardinalityLimits {
+    /// No limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cap every family at `max` series.
+    pub fn per_family(mut self, max: usize) -> Self {
+        self.max_series_per_family = Some(max);
+        self
+    }
+
+    /// Cap the registry at `max` series across all families.
+    pub fn per_registry(mut self, max: usize) -> Self {
+        self.max_series_per_registry = Some(max);
+        self
+    }
+
+    /// Set the overflow behavior.
+    pub fn overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+}
+
+/// Counts of label sets a family could not give their own series.
+#[derive(Debug, Default)]
+pub struct CardinalityStats {
+    rejected: AtomicU64,
+    evicted: AtomicU64,
+}
+
+impl CardinalityStats {
+    /// Label sets dropped or folded because a limit was reached. A dropped
+    /// label set is counted again only after the family freed room.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Series evicted to make room for new label sets.
+    pub fn evicted(&self) -> u64 {
+        self.evicted.load(Ordering::Relaxed)
+    }
+}
+
+/// Outcome of asking a [`FamilyLimiter`] for room for a new series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// The series may be created.
+    Admitted,
+    /// The family is at its series cap.
+    FamilyFull,
+    /// The registry is at its series cap.
+    RegistryFull,
+}
+
+#[derive(Debug)]
+struct LimiterInner {
+    limits: CardinalityLimits,
+    series: AtomicUsize,
+    families: Mutex<Vec<(String, Arc<CardinalityStats>)>>,
+    exported: AtomicBool,
+}
+
+/// Registry-wide limiter shared by all families of one registry.
+///
+/// Cloning is cheap; clones share the series budget and statistics.
+#[derive(Debug, Clone)]
+pub struct CardinalityLimiter {
+    inner: Arc<LimiterInner>,
+}
+
+impl Default for CardinalityLimiter {
+    fn default() -> Self {
+        Self::new(CardinalityLimits::default())
+    }
+}
+
+impl CardinalityLimiter {
+    /// Create a limiter enforcing `limits`.
+    pub fn new(limits: CardinalityLimits) -> Self {
+        Self {
+            inner: Arc::new(LimiterInner {
+                limits,
+                series: AtomicUsize::new(0),
+                families: Mutex::new(Vec::new()),
+                exported: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// The limits this limiter enforces.
+    pub fn limits(&self) -> &CardinalityLimits {
+        &self.inner.limits
+    }
+
+    /// Number of series currently held by limited families.
+    pub fn series(&self) -> usize {
+        self.inner.series.load(Ordering::Relaxed)
+    }
+
+    /// Statistics for every limited family, by family name, one entry per
+    /// name.
+    pub fn stats(&self) -> Vec<(String, Arc<CardinalityStats>)> {
+        self.inner
+            .families
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Create the limiter for family `name`, which already holds `existing`
+    /// series.
+    ///
+    /// `max_series` and `overflow` override the registry defaults. Returns
+    /// `None` when no limit applies to the family. Limiters created for the
+    /// same name share their statistics; release the series charged by a
+    /// limiter this one replaces with [`FamilyLimiter::release_all`].
+    pub fn family(
+        &self,
+        name: &str,
+        max_series: Option<usize>,
+        overflow: Option<OverflowPolicy>,
+        existing: usize,
+    ) -> Option<FamilyLimiter> {
+        let limits = &self.inner.limits;
+        let max_series = max_series.or(limits.max_series_per_family);
+        if max_series.is_none() && limits.max_series_per_registry.is_none() {
+            return None;
+        }
+
+        let stats = {
+            let mut families = self
+                .inner
+                .families
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            match families.iter().find(|(family, _)| family == name) {
+                Some((_, stats)) => Arc::clone(stats),
+                None => {
+                    let stats = Arc::new(CardinalityStats::default());
+                    families.push((name.to_string(), Arc::clone(&stats)));
+                    stats
+                }
+            }
+        };
+        self.inner.series.fetch_add(existing, Ordering::Relaxed);
+
+        Some(FamilyLimiter {
+            max_series,
+            overflow: overflow.unwrap_or(limits.overflow),
+            registry: Some(self.clone()),
+            stats,
+        })
+    }
+
+    /// Returns true the first time it is called.
+    ///
+    /// Backends use this to register the limiter's self-metrics once.
+    pub fn mark_exported(&self) -> bool {
+        !self.inner.exported.swap(true, Ordering::Relaxed)
+    }
+
+    fn try_acquire(&self) -> bool {
+        let Some(max) = self.inner.limits.max_series_per_registry else {
+            self.inner.series.fetch_add(1, Ordering::Relaxed);
+            return true;
+        };
+        self.inner
+            .series
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, series: usize) {
+        let _ = self
+            .inner
+            .series
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                Some(n.saturating_sub(series))
+            });
+    }
+}
+
+/// Limiter for a single family.
+#[derive(Debug, Clone)]
+pub struct FamilyLimiter {
+    max_series: Option<usize>,
+    overflow: OverflowPolicy,
+    registry: Option<CardinalityLimiter>,
+    stats: Arc<CardinalityStats>,
+}
+
+impl FamilyLimiter {
+    /// A limiter for a family that is not (yet) attached to a registry.
+    pub fn new(max_series: usize, overflow: OverflowPolicy) -> Self {
+        Self {
+            max_series: Some(max_series),
+            overflow,
+            registry: None,
+            stats: Arc::default(),
+        }
+    }
+
+    /// The family's series cap, if any.
+    pub fn max_series(&self) -> Option<usize> {
+        self.max_series
+    }
+
+    /// The family's overflow behavior.
+    pub fn overflow(&self) -> OverflowPolicy {
+        self.overflow
+    }
+
+    /// The family's statistics.
+    pub fn stats(&self) -> &CardinalityStats {
+        &self.stats
+    }
+
+    /// Ask for room for a new series in a family holding `len` series.
+    ///
+    /// On [`Admission::Admitted`] the series is charged to the registry
+    /// budget; call [`release`](Self::release) when it is removed.
+    pub fn try_admit(&self, len: usize) -> Admission {
+        if self.max_series.is_some_and(|max| len >= max) {
+            return Admission::FamilyFull;
+        }
+        match &self.registry {
+            Some(registry) if !registry.try_acquire() => Admission::RegistryFull,
+            _ => Admission::Admitted,
+        }
+    }
+
+    /// Return a removed series' slot to the registry budget.
+    pub fn release(&self) {
+        self.release_all(1);
+    }
+
+    /// Return the slots of `series` series to the registry budget, e.g.
+    /// all of a family's series when its limiter is replaced.
+    pub fn release_all(&self, series: usize) {
+        if let Some(registry) = &self.registry {
+            registry.release(series);
+        }
+    }
+
+    /// Count a label set that was dropped or folded.
+    pub fn record_rejected(&self) {
+        self.stats.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a series evicted to make room.
+    pub fn record_evicted(&self) {
+        self.stats.evicted.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_limits_means_no_family_limiter() {
+        let limiter = CardinalityLimiter::default();
+        assert!(limiter.family("requests", None, None, 0).is_none());
+        assert!(limiter.stats().is_empty());
+    }
+
+    #[test]
+    fn family_cap_is_enforced() {
+        let limiter = CardinalityLimiter::new(CardinalityLimits::new().per_family(2));
+        let family = limiter.family("requests", None, None, 0).unwrap();
+        assert_eq!(family.try_admit(1), Admission::Admitted);
+        assert_eq!(family.try_admit(2), Admission::FamilyFull);
+    }
+
+    #[test]
+    fn registry_budget_is_shared_and_released() {
+        let limiter = CardinalityLimiter::new(CardinalityLimits::new().per_registry(2));
+        let a = limiter.family("a", None, None, 1).unwrap();
+        let b = limiter.family("b", None, None, 0).unwrap();
+        assert_eq!(b.try_admit(0), Admission::Admitted);
+        assert_eq!(a.try_admit(1), Admission::RegistryFull);
+        b.release();
+        assert_eq!(a.try_admit(1), Admission::Admitted);
+        assert_eq!(limiter.series(), 2);
+    }
+
+    #[test]
+    fn family_overrides_take_precedence() {
+        let limiter = CardinalityLimiter::new(
+            CardinalityLimits::new()
+                .per_family(10)
+                .overflow(OverflowPolicy::Fold),
+        );
+        let family = limiter
+            .family("a", Some(1), Some(OverflowPolicy::EvictLru), 0)
+            .unwrap();
+        assert_eq!(family.max_series(), Some(1));
+        assert_eq!(family.overflow(), OverflowPolicy::EvictLru);
+    }
+
+    #[test]
+    fn stats_are_reported_per_family() {
+        let limiter = CardinalityLimiter::new(CardinalityLimits::new().per_family(1));
+        let family = limiter.family("requests", None, None, 0).unwrap();
+        family.record_rejected();
+        family.record_rejected();
+        family.record_evicted();
+
+        let stats = limiter.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].0, "requests");
+        assert_eq!(stats[0].1.rejected(), 2);
+        assert_eq!(stats[0].1.evicted(), 1);
+    }
+
+    #[test]
+    fn replaced_family_limiters_share_stats_and_release_their_series() {
+        let limiter = CardinalityLimiter::new(CardinalityLimits::new().per_registry(10));
+        let first = limiter.family("requests", None, None, 3).unwrap();
+        first.record_rejected();
+        first.release_all(3);
+        let second = limiter.family("requests", None, None, 3).unwrap();
+        second.record_rejected();
+
+        assert_eq!(limiter.series(), 3);
+        let stats = limiter.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].1.rejected(), 2);
+    }
+}
@@ -3,6 +3,13 @@
 //! These types work for both JSON and YAML deserialization since they use
 //! the same serde Deserialize trait. The configuration format is format-agnostic.
 
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+use crate::core::cardinality::CardinalityLimits;
+#[cfg(all(
+    feature = "relabel",
+    any(feature = "json-config", feature = "yaml-config")
+))]
+use crate::core::relabel::RelabelRules;
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
 use serde::Deserialize;
 
@@ -40,3 +47,81 @@ fn default_histogram_buckets() -> Vec<f64> {
 /// This deserializes directly from an array format: `[{...}, {...}]`
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
 pub type RegistryConfig = Vec<MetricConfig>;
+
+/// Registry configuration with registry-wide settings.
+///
+/// Deserializes from either the plain metric array accepted by
+/// [`RegistryConfig`] or an object:
+///
+/// ```yaml
+/// metrics:
+///   - metric_type: Counter
+///     title: http_requests_total
+///     description: Total HTTP requests
+/// cardinality:
+///   max_series_per_family: 1000
+///   overflow: fold
+/// relabel:
+///   - action: hash
+///     target_label: user_id
+/// ```
+///
+/// The `relabel` section needs the `relabel` feature.
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "RegistryDocumentRepr")]
+pub struct RegistryDocument {
+    /// Metric definitions.
+    pub metrics: RegistryConfig,
+    /// Series caps for labeled families registered on the registry.
+    pub cardinality: CardinalityLimits,
+    /// Rules applied to everything the registry renders.
+    #[cfg(feature = "relabel")]
+    pub relabel: RelabelRules,
+}
+
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RegistryDocumentRepr {
+    Metrics(RegistryConfig),
+    Document(RegistryDocumentFields),
+}
+
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryDocumentFields {
+    #[serde(default)]
+    metrics: RegistryConfig,
+    #[serde(default)]
+    cardinality: CardinalityLimits,
+    #[cfg(feature = "relabel")]
+    #[serde(default)]
+    relabel: RelabelRules,
+}
+
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+impl From<RegistryDocumentRepr> for RegistryDocument {
+    fn from(repr: RegistryDocumentRepr) -> Self {
+        match repr {
+            RegistryDocumentRepr::Metrics(metrics) => Self::from(metrics),
+            RegistryDocumentRepr::Document(fields) => Self {
+                metrics: fields.metrics,
+                cardinality: fields.cardinality,
+                #[cfg(feature = "relabel")]
+                relabel: fields.relabel,
+            },
+        }
+    }
+}
+
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+impl From<RegistryConfig> for RegistryDocument {
+    fn from(metrics: RegistryConfig) -> Self {
+        Self {
+            metrics,
+            ..Self::default()
+        }
+    }
+}
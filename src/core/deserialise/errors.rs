@@ -43,7 +43,7 @@ pub enum DeserializeError {
     #[error("Feature not enabled: {0}")]
     FeatureNotEnabled(String),
 
-    /// Duplicate metric name (same type) in config.
+    /// Duplicate metric name in config, of the same or another type.
     #[error("Duplicate metric name: {0}")]
     DuplicateMetricName(String),
 }
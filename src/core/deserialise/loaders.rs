@@ -12,7 +12,7 @@ pub enum SupportedFileTypes {
 }
 
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
-use crate::core::deserialise::config::RegistryConfig;
+use crate::core::deserialise::config::{RegistryConfig, RegistryDocument};
 use crate::core::deserialise::errors::DeserializeError;
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
 use std::fs::File;
@@ -94,6 +94,65 @@ pub fn load_yaml_str(yaml: &str) -> Result<RegistryConfig, DeserializeError> {
     Ok(config)
 }
 
+/// Load a registry document (metrics plus registry-wide settings such as
+/// cardinality limits) from a JSON file.
+///
+/// The plain metric array accepted by [`load_json_file`] is also accepted.
+#[cfg(feature = "json-config")]
+pub fn load_json_document_file(
+    path: impl AsRef<std::path::Path>,
+) -> Result<RegistryDocument, DeserializeError> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let document: RegistryDocument = serde_json::from_reader(reader)?;
+    Ok(document)
+}
+
+/// Load a registry document from a JSON string.
+///
+/// # Example
+/// ```ignore
+/// let json = r#"{"metrics": [], "cardinality": {"max_series_per_family": 100}}"#;
+/// let document = load_json_document_str(json)?;
+/// ```
+#[cfg(feature = "json-config")]
+pub fn load_json_document_str(json: &str) -> Result<RegistryDocument, DeserializeError> {
+    let document: RegistryDocument = serde_json::from_str(json)?;
+    Ok(document)
+}
+
+/// Load a registry document (metrics plus registry-wide settings such as
+/// cardinality limits) from a YAML file.
+///
+/// The plain metric list accepted by [`load_yaml_file`] is also accepted.
+#[cfg(feature = "yaml-config")]
+pub fn load_yaml_document_file(
+    path: impl AsRef<std::path::Path>,
+) -> Result<RegistryDocument, DeserializeError> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let document: RegistryDocument = serde_yaml::from_reader(reader)?;
+    Ok(document)
+}
+
+/// Load a registry document from a YAML string.
+///
+/// # Example
+/// ```ignore
+/// let yaml = r#"
+/// metrics: []
+/// cardinality:
+///   max_series_per_registry: 10000
+///   overflow: evict_lru
+/// "#;
+/// let document = load_yaml_document_str(yaml)?;
+/// ```
+#[cfg(feature = "yaml-config")]
+pub fn load_yaml_document_str(yaml: &str) -> Result<RegistryDocument, DeserializeError> {
+    let document: RegistryDocument = serde_yaml::from_str(yaml)?;
+    Ok(document)
+}
+
 /// Returns allowed base directories: XDG_CONFIG_HOME (or ~/.config), current dir, and optional base.
 fn allowed_base_directories(extra_base: Option<&Path>) -> Result<Vec<PathBuf>, DeserializeError> {
     let mut bases = Vec::new();
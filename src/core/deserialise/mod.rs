@@ -13,7 +13,7 @@ pub mod loaders;
 pub mod registry;
 
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
-pub use config::{MetricConfig, RegistryConfig};
+pub use config::{MetricConfig, RegistryConfig, RegistryDocument};
 
 pub use errors::DeserializeError;
 
@@ -1,12 +1,12 @@
 //! Registry builder that creates a configured registry from metric definitions.
 
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
-use crate::core::deserialise::config::RegistryConfig;
+use crate::core::deserialise::config::{RegistryConfig, RegistryDocument};
 use crate::core::deserialise::errors::{BackendErrorExt, DeserializeError};
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
 use crate::core::metrics::Metric;
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
-use crate::core::registry::{MetricBackend, ObservabilityRegistry};
+use crate::core::registry::{MetricBackend, ObservabilityRegistry, RegisteredNames};
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
 use std::collections::hash_map::Entry;
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
@@ -29,14 +29,18 @@ pub struct ConfiguredRegistry<B: MetricBackend> {
     pub histograms: HashMap<String, Metric<B::Histogram>>,
 }
 
-/// If `title` is already in `map`, returns `DuplicateMetricName`. Otherwise calls `make(key)`,
-/// inserts the result, and returns `Ok(())`.
+/// If `title` is already in `map` or registered as another type, returns
+/// `DuplicateMetricName`. Otherwise calls `make(key)`, inserts the result, and returns `Ok(())`.
 #[cfg(any(feature = "json-config", feature = "yaml-config"))]
 fn register_unique_metric<V>(
     map: &mut HashMap<String, V>,
+    names: &RegisteredNames,
     title: String,
     make: impl FnOnce(&str) -> Result<V, DeserializeError>,
 ) -> Result<(), DeserializeError> {
+    if names.contains(&title) {
+        return Err(DeserializeError::DuplicateMetricName(title));
+    }
     match map.entry(title) {
         Entry::Occupied(o) => Err(DeserializeError::DuplicateMetricName(o.key().clone())),
         Entry::Vacant(e) => {
@@ -69,7 +73,20 @@ impl<B: MetricBackend> ConfiguredRegistry<B> {
     /// requests.inc();
     /// ```
     pub fn from_config(config: RegistryConfig) -> Result<Self, DeserializeError> {
-        let metrics = config;
+        Self::from_document(RegistryDocument::from(config))
+    }
+
+    /// Create a configured registry from a [`RegistryDocument`], applying its
+    /// registry-wide settings (e.g. cardinality limits, relabel rules) before registering
+    /// the metrics.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let document = load_yaml_document_file("metrics.yaml")?;
+    /// let configured = ConfiguredRegistry::<PrometheusBackend>::from_document(document)?;
+    /// ```
+    pub fn from_document(document: RegistryDocument) -> Result<Self, DeserializeError> {
+        let metrics = document.metrics;
 
         // Pre-count metrics by type to allocate HashMaps with appropriate capacity
         // This avoids rehashing as the HashMaps grow
@@ -80,10 +97,16 @@ impl<B: MetricBackend> ConfiguredRegistry<B> {
                 crate::core::deserialise::config::MetricConfig::Histogram { .. } => (c, g, h + 1),
             });
 
-        let mut registry = ObservabilityRegistry::<B>::new();
+        let mut registry =
+            ObservabilityRegistry::<B>::new().with_cardinality_limits(document.cardinality);
+        #[cfg(feature = "relabel")]
+        {
+            registry = registry.with_relabel_rules(document.relabel);
+        }
         let mut counters = HashMap::with_capacity(counter_count);
         let mut gauges = HashMap::with_capacity(gauge_count);
         let mut histograms = HashMap::with_capacity(histogram_count);
+        let names = registry.registered_names().clone();
 
         // Single-pass registration: deserialize and register all metrics
         for metric_config in metrics {
@@ -93,7 +116,7 @@ impl<B: MetricBackend> ConfiguredRegistry<B> {
                     description,
                     initial_value,
                 } => {
-                    register_unique_metric(&mut counters, title, |key| {
+                    register_unique_metric(&mut counters, &names, title, |key| {
                         let counter = registry
                             .counter(key, &description)
                             .map_err(|e| e.into_deserialize_error())?;
@@ -108,7 +131,7 @@ impl<B: MetricBackend> ConfiguredRegistry<B> {
                     description,
                     initial_value,
                 } => {
-                    register_unique_metric(&mut gauges, title, |key| {
+                    register_unique_metric(&mut gauges, &names, title, |key| {
                         let gauge = registry
                             .gauge(key, &description)
                             .map_err(|e| e.into_deserialize_error())?;
@@ -123,7 +146,7 @@ impl<B: MetricBackend> ConfiguredRegistry<B> {
                     description,
                     buckets,
                 } => {
-                    register_unique_metric(&mut histograms, title, |key| {
+                    register_unique_metric(&mut histograms, &names, title, |key| {
                         registry
                             .histogram_with_buckets(key, &description, buckets)
                             .map_err(|e| e.into_deserialize_error())
@@ -333,18 +356,17 @@ mod tests {
     }
 
     #[test]
-    fn from_config_same_name_different_types_allowed() {
+    fn from_config_same_name_different_types_returns_error() {
         let config: RegistryConfig = vec![
             counter_config("metric", "Counter", 0),
             gauge_config("metric", "Gauge", 0),
-            histogram_config("metric", "Histogram", vec![1.0]),
         ];
         let result = ConfiguredRegistry::<PrometheusBackend>::from_config(config);
-        assert!(result.is_ok());
-        let configured = result.unwrap();
-        assert!(configured.counters.contains_key("metric"));
-        assert!(configured.gauges.contains_key("metric"));
-        assert!(configured.histograms.contains_key("metric"));
+        match result {
+            Err(DeserializeError::DuplicateMetricName(name)) => assert_eq!(name, "metric"),
+            Err(e) => panic!("expected DuplicateMetricName, got error: {:?}", e),
+            Ok(_) => panic!("expected DuplicateMetricName, got Ok"),
+        }
     }
 
     #[test]
@@ -359,4 +381,91 @@ mod tests {
         let output = registry.render();
         assert!(output.is_ok());
     }
+
+    #[test]
+    fn from_document_applies_cardinality_limits() {
+        use crate::core::cardinality::OverflowPolicy;
+        use crate::core::deserialise::loaders::load_json_document_str;
+
+        let json = r#"{
+            "metrics": [{"metric_type": "Counter", "title": "jobs", "description": "Jobs"}],
+            "cardinality": {"max_series_per_family": 10, "overflow": "evict_lru"}
+        }"#;
+        let document = load_json_document_str(json).unwrap();
+        let configured = ConfiguredRegistry::<PrometheusBackend>::from_document(document).unwrap();
+
+        assert!(configured.counters.contains_key("jobs"));
+        let limits = configured.registry.cardinality().limits();
+        assert_eq!(limits.max_series_per_family, Some(10));
+        assert_eq!(limits.max_series_per_registry, None);
+        assert_eq!(limits.overflow, OverflowPolicy::EvictLru);
+    }
+
+    #[cfg(all(feature = "relabel", feature = "yaml-config"))]
+    #[test]
+    fn from_document_applies_relabel_rules() {
+        use crate::core::deserialise::loaders::load_yaml_document_str;
+
+        let yaml = r#"
+metrics:
+  - metric_type: Counter
+    title: legacy_jobs
+    description: Jobs
+relabel:
+  - action: rename
+    regex: "legacy_(.*)"
+    replacement: "app_$1"
+"#;
+        let document = load_yaml_document_str(yaml).unwrap();
+        let configured = ConfiguredRegistry::<PrometheusBackend>::from_document(document).unwrap();
+
+        assert_eq!(
+            configured.registry.relabel_rules().unwrap().rules().len(),
+            1
+        );
+        let rendered = configured.registry.render().unwrap();
+        let text = rendered.as_str().unwrap();
+        assert!(text.contains("# TYPE app_jobs counter\n"));
+        assert!(text.contains("app_jobs_total 0\n"));
+        assert!(!text.contains("legacy_"));
+    }
+
+    #[test]
+    fn document_accepts_plain_metric_array() {
+        use crate::core::deserialise::loaders::load_json_document_str;
+
+        let json = r#"[{"metric_type": "Gauge", "title": "level", "description": "Level"}]"#;
+        let document = load_json_document_str(json).unwrap();
+        assert_eq!(document.metrics.len(), 1);
+        assert_eq!(document.cardinality, Default::default());
+    }
+
+    #[test]
+    fn document_rejects_unknown_cardinality_fields() {
+        use crate::core::deserialise::loaders::load_json_document_str;
+
+        let json = r#"{"metrics": [], "cardinality": {"max_series": 10}}"#;
+        assert!(load_json_document_str(json).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "yaml-config")]
+    fn yaml_document_parses_cardinality_section() {
+        use crate::core::cardinality::OverflowPolicy;
+        use crate::core::deserialise::loaders::load_yaml_document_str;
+
+        let yaml = r#"
+metrics:
+  - metric_type: Counter
+    title: requests
+    description: Requests
+cardinality:
+  max_series_per_registry: 5000
+  overflow: fold
+"#;
+        let document = load_yaml_document_str(yaml).unwrap();
+        assert_eq!(document.metrics.len(), 1);
+        assert_eq!(document.cardinality.max_series_per_registry, Some(5000));
+        assert_eq!(document.cardinality.overflow, OverflowPolicy::Fold);
+    }
 }
@@ -0,0 +1,176 @@
+//! Metric family filters for partial scrapes.
+//!
+//! A [`MetricFilter`] selects families by exact name, name prefix or
+//! regex. It is passed to the backend's
+//! [`render_filtered_to`](super::renderer::MetricsRenderer::render_filtered_to),
+//! which checks it before encoding each family's descriptor, so a family
+//! is either encoded in full or skipped without being encoded at all.
+//!
+//! Families are matched on their registered names, before relabel rules
+//! rename anything. Only metrics registered through
+//! [`ObservabilityRegistry`](super::registry::ObservabilityRegistry) are
+//! filtered: anything registered on the backend registry directly (via
+//! `inner_mut()`) is always included.
+//!
+//! # Example
+//! ```ignore
+//! use observe_rs::core::filter::MetricFilter;
+//!
+//! let filter = MetricFilter::new()
+//!     .name("process_cpu_seconds_total")
+//!     .prefix("http_")
+//!     .regex("db_.*_seconds")?;
+//!
+//! let mut out = Vec::new();
+//! registry.render_filtered_to(&filter, &mut out)?;
+//! ```
+
+use regex::Regex;
+
+use super::snapshot::{MetricFamily, MetricKind};
+
+/// Errors from building a [`MetricFilter`].
+#[derive(Debug, thiserror::Error)]
+pub enum FilterError {
+    /// A pattern isn't a valid regex.
+    #[error("invalid metric name regex {pattern:?}: {source}")]
+    InvalidRegex {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+}
+
+/// Selects metric families by name.
+///
+/// A family matches when its name, or one of its sample names (e.g.
+/// `http_requests_total` for the counter `http_requests`), equals one of
+/// the [`name`](Self::name)s, starts with one of the
+/// [`prefix`](Self::prefix)es or matches one of the
+/// [`regex`](Self::regex)es in full. An empty filter matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct MetricFilter {
+    names: Vec<String>,
+    prefixes: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl MetricFilter {
+    /// Create a filter that matches everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match families with this name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Match families whose name starts with `prefix`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Match families whose name matches `pattern` in full.
+    pub fn regex(mut self, pattern: &str) -> Result<Self, FilterError> {
+        let regex = Regex::new(&format!("^(?:{})$", pattern)).map_err(|source| {
+            FilterError::InvalidRegex {
+                pattern: pattern.to_string(),
+                source,
+            }
+        })?;
+        self.patterns.push(regex);
+        Ok(self)
+    }
+
+    /// Build a filter from a URL query string.
+    ///
+    /// `name[]` (or `name`), `prefix` and `regex` may each be repeated;
+    /// other parameters are ignored.
+    pub fn from_query(query: &str) -> Result<Self, FilterError> {
+        let mut filter = Self::new();
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            filter = match key.as_ref() {
+                "name[]" | "name" => filter.name(value),
+                "prefix" => filter.prefix(value),
+                "regex" => filter.regex(&value)?,
+                _ => filter,
+            };
+        }
+        Ok(filter)
+    }
+
+    /// Whether the filter matches everything.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.prefixes.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Whether the family `name` of type `kind` is selected.
+    pub fn matches(&self, name: &str, kind: MetricKind) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let suffixed = kind
+            .suffixes()
+            .iter()
+            .map(|suffix| format!("{}{}", name, suffix));
+        std::iter::once(name.to_string())
+            .chain(suffixed)
+            .any(|candidate| self.matches_name(&candidate))
+    }
+
+    /// Whether `family` is selected.
+    pub fn matches_family(&self, family: &MetricFamily) -> bool {
+        self.matches(&family.name, family.kind)
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+            || self.prefixes.iter().any(|p| name.starts_with(p.as_str()))
+            || self.patterns.iter().any(|r| r.is_match(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_match_families_and_their_samples() {
+        let filter = MetricFilter::new().name("http_requests_total");
+        assert!(filter.matches("http_requests", MetricKind::Counter));
+        assert!(!filter.matches("http_requests", MetricKind::Gauge));
+        assert!(MetricFilter::new()
+            .name("queue_depth")
+            .matches("queue_depth", MetricKind::Gauge));
+        assert!(MetricFilter::new().matches("anything", MetricKind::Unknown));
+    }
+
+    #[test]
+    fn regexes_match_in_full() {
+        let filter = MetricFilter::new().regex("db_.*_seconds").unwrap();
+        assert!(filter.matches("db_query_seconds", MetricKind::Histogram));
+        assert!(!filter.matches("old_db_query_seconds", MetricKind::Histogram));
+        assert!(matches!(
+            MetricFilter::new().regex("(unclosed"),
+            Err(FilterError::InvalidRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn query_strings_are_parsed() {
+        let filter = MetricFilter::from_query(
+            "name[]=queue_depth&name%5B%5D=up&prefix=http_&regex=db_.%2A&debug=1",
+        )
+        .unwrap();
+        assert!(filter.matches("queue_depth", MetricKind::Gauge));
+        assert!(filter.matches("up", MetricKind::Gauge));
+        assert!(filter.matches("http_requests", MetricKind::Counter));
+        assert!(filter.matches("db_query_seconds", MetricKind::Histogram));
+        assert!(!filter.matches("process_cpu_seconds", MetricKind::Counter));
+        assert!(MetricFilter::from_query("").unwrap().is_empty());
+        assert!(MetricFilter::from_query("regex=%28").is_err());
+    }
+}
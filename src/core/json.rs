@@ -0,0 +1,527 @@
+//! JSON rendering of a registry.
+//!
+//! The output is an array of [`JsonMetricFamily`] values. These types are
+//! the schema: they serialise to the documented JSON and deserialise from
+//! it, so consumers written in Rust can reuse them.
+//!
+//! ```json
+//! [
+//!   {
+//!     "name": "http_request_duration_seconds",
+//!     "type": "histogram",
+//!     "help": "Request latency.",
+//!     "unit": "seconds",
+//!     "samples": [
+//!       {
+//!         "labels": { "route": "/" },
+//!         "count": 3,
+//!         "sum": 0.42,
+//!         "buckets": [ { "le": 0.1, "count": 2 }, { "le": "+Inf", "count": 3 } ]
+//!       }
+//!     ]
+//!   },
+//!   {
+//!     "name": "jobs",
+//!     "type": "counter",
+//!     "help": "Jobs processed.",
+//!     "samples": [ { "labels": { "queue": "mail" }, "value": 3, "created": 1700000000.0 } ]
+//!   }
+//! ]
+//! ```
+//!
+//! Histograms and summaries have one sample per label set, with `count`,
+//! `sum` and their `buckets` or `quantiles`; every other type has one
+//! sample per series with a `value`. Absent fields are omitted. JSON has no
+//! NaN or infinity, so those values are written as the strings `"NaN"`,
+//! `"+Inf"` and `"-Inf"`.
+
+#[cfg(feature = "filter")]
+use super::filter::MetricFilter;
+use super::registry::{MetricBackend, ObservabilityRegistry};
+use super::renderer::{MetricsRenderer, RenderToError, RenderedMetrics};
+use super::snapshot::{Exemplar, MetricFamily, MetricKind, Sample, Snapshot, SnapshotError};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+
+/// Content type of rendered JSON metrics.
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// A number that may be NaN or infinite.
+///
+/// Finite values are JSON numbers; NaN and infinities are the strings
+/// `"NaN"`, `"+Inf"` and `"-Inf"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JsonNumber(pub f64);
+
+impl Serialize for JsonNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            value if value.is_finite() => serializer.serialize_f64(value),
+            value if value.is_nan() => serializer.serialize_str("NaN"),
+            value if value > 0.0 => serializer.serialize_str("+Inf"),
+            _ => serializer.serialize_str("-Inf"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Number(f64),
+            String(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Number(value) => Ok(Self(value)),
+            Repr::String(value) => match value.as_str() {
+                "NaN" => Ok(Self(f64::NAN)),
+                "+Inf" | "Inf" => Ok(Self(f64::INFINITY)),
+                "-Inf" => Ok(Self(f64::NEG_INFINITY)),
+                _ => Err(serde::de::Error::custom(format!(
+                    "invalid number {:?}",
+                    value
+                ))),
+            },
+        }
+    }
+}
+
+impl From<f64> for JsonNumber {
+    fn from(value: f64) -> Self {
+        Self(value)
+    }
+}
+
+impl Serialize for MetricKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for MetricKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::parse(&String::deserialize(deserializer)?))
+    }
+}
+
+/// A metric family.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonMetricFamily {
+    /// Family name, without sample suffixes such as `_total`.
+    pub name: String,
+    /// Family type, as in OpenMetrics `# TYPE` lines (`counter`, `gauge`,
+    /// `histogram`, `gaugehistogram`, `summary`, `info`, `stateset`,
+    /// `unknown`).
+    #[serde(rename = "type")]
+    pub kind: MetricKind,
+    /// Help text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub help: Option<String>,
+    /// Unit, e.g. `seconds`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Series of the family.
+    pub samples: Vec<JsonSample>,
+}
+
+/// One series of a family.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JsonSample {
+    /// Labels, excluding `le` and `quantile`.
+    pub labels: BTreeMap<String, String>,
+    /// Value of a counter, gauge, info, stateset or untyped series.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<JsonNumber>,
+    /// Observation count of a histogram or summary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<JsonNumber>,
+    /// Sum of observations of a histogram or summary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sum: Option<JsonNumber>,
+    /// Cumulative histogram buckets, in exposition order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buckets: Option<Vec<JsonBucket>>,
+    /// Summary quantiles, in exposition order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantiles: Option<Vec<JsonQuantile>>,
+    /// When the series was created, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<f64>,
+    /// Explicit timestamp in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f64>,
+    /// Exemplar attached to the value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exemplar: Option<JsonExemplar>,
+}
+
+/// A cumulative histogram bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonBucket {
+    /// Upper bound (inclusive); the last bucket is `"+Inf"`.
+    pub le: JsonNumber,
+    /// Observations less than or equal to `le`.
+    pub count: JsonNumber,
+    /// Exemplar of an observation in this bucket.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exemplar: Option<JsonExemplar>,
+}
+
+/// A summary quantile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonQuantile {
+    /// Quantile rank, between 0 and 1.
+    pub quantile: JsonNumber,
+    /// Value at that rank.
+    pub value: JsonNumber,
+}
+
+/// An exemplar: a traced observation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonExemplar {
+    /// Exemplar labels, e.g. `trace_id`.
+    pub labels: BTreeMap<String, String>,
+    /// Observed value.
+    pub value: JsonNumber,
+    /// When it was observed, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f64>,
+}
+
+impl From<&Exemplar> for JsonExemplar {
+    fn from(exemplar: &Exemplar) -> Self {
+        Self {
+            labels: exemplar.labels.iter().cloned().collect(),
+            value: exemplar.value.into(),
+            timestamp: exemplar.timestamp,
+        }
+    }
+}
+
+impl JsonMetricFamily {
+    /// Convert every family of `snapshot`.
+    pub fn from_snapshot(snapshot: &Snapshot) -> Vec<Self> {
+        snapshot.families.iter().map(Self::from).collect()
+    }
+}
+
+impl From<&MetricFamily> for JsonMetricFamily {
+    fn from(family: &MetricFamily) -> Self {
+        let mut samples: Vec<JsonSample> = Vec::new();
+        // Position of each series' sample in `samples`, by its labels.
+        let mut index: HashMap<Vec<(&str, &str)>, usize> = HashMap::new();
+        for sample in &family.samples {
+            let suffix = sample.name.strip_prefix(family.name.as_str()).unwrap_or("");
+            let labels: Vec<(&str, &str)> = sample
+                .labels
+                .iter()
+                .filter(|(name, _)| !family.kind.is_bound(name))
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let position = *index.entry(labels).or_insert_with_key(|labels| {
+                samples.push(JsonSample {
+                    labels: labels
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), value.to_string()))
+                        .collect(),
+                    ..JsonSample::default()
+                });
+                samples.len() - 1
+            });
+            add_sample(family.kind, suffix, sample, &mut samples[position]);
+        }
+        Self {
+            name: family.name.clone(),
+            kind: family.kind,
+            help: family.help.clone(),
+            unit: family.unit.clone(),
+            samples,
+        }
+    }
+}
+
+fn add_sample(kind: MetricKind, suffix: &str, sample: &Sample, entry: &mut JsonSample) {
+    let value = JsonNumber(sample.value);
+    let bound = |name: &str| {
+        sample
+            .label(name)
+            .and_then(parse_bound)
+            .unwrap_or(JsonNumber(f64::NAN))
+    };
+    match (kind, suffix) {
+        (_, "_created") => entry.created = Some(sample.value),
+        (MetricKind::Histogram | MetricKind::GaugeHistogram, "_bucket") => {
+            entry.buckets.get_or_insert_with(Vec::new).push(JsonBucket {
+                le: bound("le"),
+                count: value,
+                exemplar: sample.exemplar.as_ref().map(JsonExemplar::from),
+            });
+        }
+        (MetricKind::Histogram | MetricKind::Summary, "_count")
+        | (MetricKind::GaugeHistogram, "_gcount") => entry.count = Some(value),
+        (MetricKind::Histogram | MetricKind::Summary, "_sum")
+        | (MetricKind::GaugeHistogram, "_gsum") => entry.sum = Some(value),
+        (MetricKind::Summary, _) => {
+            entry
+                .quantiles
+                .get_or_insert_with(Vec::new)
+                .push(JsonQuantile {
+                    quantile: bound("quantile"),
+                    value,
+                });
+        }
+        _ => {
+            entry.value = Some(value);
+            entry.timestamp = sample.timestamp;
+            entry.exemplar = sample.exemplar.as_ref().map(JsonExemplar::from);
+        }
+    }
+}
+
+fn parse_bound(bound: &str) -> Option<JsonNumber> {
+    match bound {
+        "+Inf" | "Inf" => Some(JsonNumber(f64::INFINITY)),
+        "-Inf" => Some(JsonNumber(f64::NEG_INFINITY)),
+        bound => bound.parse().ok().map(JsonNumber),
+    }
+}
+
+/// Error rendering JSON metrics.
+#[derive(Debug, thiserror::Error)]
+pub enum JsonRenderError {
+    /// The registry could not be snapshotted.
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
+
+    /// Serialisation failed.
+    #[error("failed to serialise metrics: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// JSON view of a registry.
+///
+/// # Example
+/// ```ignore
+/// let rendered = JsonMetrics::new(&registry).render()?;
+/// let families: Vec<JsonMetricFamily> = serde_json::from_slice(rendered.as_bytes())?;
+/// ```
+pub struct JsonMetrics<'a, B: MetricBackend> {
+    registry: &'a ObservabilityRegistry<B>,
+    #[cfg(feature = "filter")]
+    filter: Option<&'a MetricFilter>,
+}
+
+impl<'a, B: MetricBackend> JsonMetrics<'a, B> {
+    /// Render `registry` as JSON.
+    pub fn new(registry: &'a ObservabilityRegistry<B>) -> Self {
+        Self {
+            registry,
+            #[cfg(feature = "filter")]
+            filter: None,
+        }
+    }
+
+    /// Only render the families `filter` selects.
+    #[cfg(feature = "filter")]
+    pub fn filter(mut self, filter: &'a MetricFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    fn snapshot(&self) -> Result<Snapshot, SnapshotError>
+    where
+        <B::Registry as MetricsRenderer>::Error: Display,
+    {
+        #[cfg(feature = "filter")]
+        if let Some(filter) = self.filter {
+            return self.registry.snapshot_filtered(filter);
+        }
+        self.registry.snapshot()
+    }
+
+    fn for_each_family<F>(&self, emit: F) -> Result<(), RenderToError<SnapshotError>>
+    where
+        F: FnMut(MetricFamily) -> std::io::Result<()>,
+        <B::Registry as MetricsRenderer>::Error: Display,
+    {
+        #[cfg(feature = "filter")]
+        if let Some(filter) = self.filter {
+            return self.registry.for_each_family_filtered(filter, emit);
+        }
+        self.registry.for_each_family(emit)
+    }
+}
+
+impl<B> MetricsRenderer for JsonMetrics<'_, B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    type Error = JsonRenderError;
+
+    fn render(&self) -> Result<RenderedMetrics, Self::Error> {
+        let snapshot = self.snapshot()?;
+        let body = serde_json::to_vec(&JsonMetricFamily::from_snapshot(&snapshot))?;
+        Ok(RenderedMetrics::new(JSON_CONTENT_TYPE, body))
+    }
+
+    fn content_type(&self) -> Result<String, Self::Error> {
+        Ok(JSON_CONTENT_TYPE.to_string())
+    }
+
+    fn render_to<W: std::io::Write + ?Sized>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), RenderToError<Self::Error>> {
+        // Convert one family at a time so the registry is never held as a
+        // whole, only written out.
+        writer.write_all(b"[").map_err(RenderToError::Io)?;
+        let mut first = true;
+        self.for_each_family(|family| {
+            if !std::mem::take(&mut first) {
+                writer.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut *writer, &JsonMetricFamily::from(&family))?;
+            Ok(())
+        })
+        .map_err(|e| match e {
+            RenderToError::Render(e) => RenderToError::Render(e.into()),
+            RenderToError::Io(e) => RenderToError::Io(e),
+        })?;
+        writer.write_all(b"]").map_err(RenderToError::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn families(text: &str) -> Vec<JsonMetricFamily> {
+        JsonMetricFamily::from_snapshot(&Snapshot::parse(text).unwrap())
+    }
+
+    #[test]
+    fn counters_and_gauges_have_one_value_per_series() {
+        let families = families(
+            "\
+# TYPE jobs counter
+# HELP jobs Jobs processed.
+jobs_total{queue=\"mail\"} 3 # {trace_id=\"abc\"} 1.0 1700000000.5
+jobs_created{queue=\"mail\"} 1700000000.0
+# TYPE temperature gauge
+# UNIT temperature celsius
+temperature NaN
+# EOF
+",
+        );
+        assert_eq!(
+            serde_json::to_value(&families).unwrap(),
+            json!([
+                {
+                    "name": "jobs",
+                    "type": "counter",
+                    "help": "Jobs processed.",
+                    "samples": [{
+                        "labels": { "queue": "mail" },
+                        "value": 3.0,
+                        "created": 1700000000.0,
+                        "exemplar": {
+                            "labels": { "trace_id": "abc" },
+                            "value": 1.0,
+                            "timestamp": 1700000000.5
+                        }
+                    }]
+                },
+                {
+                    "name": "temperature",
+                    "type": "gauge",
+                    "unit": "celsius",
+                    "samples": [{ "labels": {}, "value": "NaN" }]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn histograms_and_summaries_group_buckets_and_quantiles() {
+        let families = families(
+            "\
+# TYPE latency histogram
+latency_bucket{route=\"/\",le=\"0.5\"} 2
+latency_bucket{route=\"/\",le=\"+Inf\"} 3
+latency_sum{route=\"/\"} 1.5
+latency_count{route=\"/\"} 3
+# TYPE rpc summary
+rpc{quantile=\"0.99\"} 0.2
+rpc_sum 4
+rpc_count 20
+",
+        );
+        assert_eq!(
+            serde_json::to_value(&families).unwrap(),
+            json!([
+                {
+                    "name": "latency",
+                    "type": "histogram",
+                    "samples": [{
+                        "labels": { "route": "/" },
+                        "count": 3.0,
+                        "sum": 1.5,
+                        "buckets": [
+                            { "le": 0.5, "count": 2.0 },
+                            { "le": "+Inf", "count": 3.0 }
+                        ]
+                    }]
+                },
+                {
+                    "name": "rpc",
+                    "type": "summary",
+                    "samples": [{
+                        "labels": {},
+                        "count": 20.0,
+                        "sum": 4.0,
+                        "quantiles": [{ "quantile": 0.99, "value": 0.2 }]
+                    }]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn schema_round_trips() {
+        let families = families(
+            "\
+# TYPE latency histogram
+latency_bucket{le=\"1\"} 1
+latency_bucket{le=\"+Inf\"} 1
+latency_sum 0.5
+latency_count 1
+# TYPE up gauge
+up -Inf 1700000000
+",
+        );
+        let json = serde_json::to_string(&families).unwrap();
+        let decoded: Vec<JsonMetricFamily> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, families);
+        assert!(serde_json::from_str::<JsonNumber>("\"infinite\"").is_err());
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn registry_renders_as_json() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+
+        let mut registry = ObservabilityRegistry::<PrometheusBackend>::new();
+        registry.counter("jobs", "Jobs").unwrap().inc_by(2);
+
+        let rendered = JsonMetrics::new(&registry).render().unwrap();
+        assert_eq!(rendered.content_type, JSON_CONTENT_TYPE);
+        let families: Vec<JsonMetricFamily> = serde_json::from_slice(rendered.as_bytes()).unwrap();
+        assert_eq!(families[0].name, "jobs");
+        assert_eq!(families[0].kind, MetricKind::Counter);
+        assert_eq!(families[0].samples[0].value, Some(JsonNumber(2.0)));
+    }
+}
@@ -0,0 +1,165 @@
+//! Label value policies.
+//!
+//! A [`LabelValuePolicy`] restricts what label values a labeled family will
+//! accept (maximum length, allowed characters) and which labels have their
+//! values redacted before they reach the exposition output. Violations are
+//! reported as errors so bad data fails loudly in tests instead of silently
+//! producing unusable series.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Replacement used for redacted label values unless configured otherwise.
+pub const DEFAULT_REDACTION: &str = "[REDACTED]";
+
+/// A label value rejected by a [`LabelValuePolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelValueViolation {
+    /// The label whose value was rejected.
+    pub label: String,
+    /// Why the value was rejected.
+    pub reason: String,
+}
+
+impl fmt::Display for LabelValueViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "label {:?}: {}", self.label, self.reason)
+    }
+}
+
+impl std::error::Error for LabelValueViolation {}
+
+/// Policy applied to label values when a new series is created.
+///
+/// # Example
+/// ```ignore
+/// use observe_rs::core::labels::LabelValuePolicy;
+///
+/// let policy = LabelValuePolicy::new()
+///     .max_length(64)
+///     .allowed_chars(|c| c.is_ascii_graphic())
+///     .redact("user_email");
+/// ```
+#[derive(Debug, Clone)]
+pub struct LabelValuePolicy {
+    max_length: Option<usize>,
+    allowed_chars: Option<fn(char) -> bool>,
+    redacted: HashSet<String>,
+    redaction: String,
+}
+
+impl Default for LabelValuePolicy {
+    fn default() -> Self {
+        Self {
+            max_length: None,
+            allowed_chars: None,
+            redacted: HashSet::new(),
+            redaction: DEFAULT_REDACTION.to_string(),
+        }
+    }
+}
+
+impl LabelValuePolicy {
+    /// Create a policy that accepts every value.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject values longer than `max` characters.
+    pub fn max_length(mut self, max: usize) -> Self {
+        self.max_length = Some(max);
+        self
+    }
+
+    /// Reject values containing characters for which `allowed` returns false.
+    pub fn allowed_chars(mut self, allowed: fn(char) -> bool) -> Self {
+        self.allowed_chars = Some(allowed);
+        self
+    }
+
+    /// Replace the value of `label` with the redaction text.
+    ///
+    /// Redacted values are not checked against the length or charset rules.
+    pub fn redact(mut self, label: impl Into<String>) -> Self {
+        self.redacted.insert(label.into());
+        self
+    }
+
+    /// Set the text that replaces redacted values (default `[REDACTED]`).
+    pub fn redaction_text(mut self, text: impl Into<String>) -> Self {
+        self.redaction = text.into();
+        self
+    }
+
+    /// Apply the policy to a single label value.
+    ///
+    /// Returns the value to expose (possibly redacted) or the violation.
+    pub fn apply<'a>(
+        &self,
+        label: &str,
+        value: &'a str,
+    ) -> Result<Cow<'a, str>, LabelValueViolation> {
+        if self.redacted.contains(label) {
+            return Ok(Cow::Owned(self.redaction.clone()));
+        }
+
+        if let Some(max) = self.max_length {
+            let len = value.chars().count();
+            if len > max {
+                return Err(LabelValueViolation {
+                    label: label.to_string(),
+                    reason: format!("value is {} characters long, maximum is {}", len, max),
+                });
+            }
+        }
+
+        if let Some(allowed) = self.allowed_chars {
+            if let Some(c) = value.chars().find(|&c| !allowed(c)) {
+                return Err(LabelValueViolation {
+                    label: label.to_string(),
+                    reason: format!("value {:?} contains disallowed character {:?}", value, c),
+                });
+            }
+        }
+
+        Ok(Cow::Borrowed(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy_accepts_everything() {
+        let policy = LabelValuePolicy::new();
+        assert_eq!(policy.apply("path", "/a b\n\"c\"").unwrap(), "/a b\n\"c\"");
+    }
+
+    #[test]
+    fn max_length_counts_characters() {
+        let policy = LabelValuePolicy::new().max_length(3);
+        assert!(policy.apply("k", "héé").is_ok());
+        let err = policy.apply("k", "abcd").unwrap_err();
+        assert_eq!(err.label, "k");
+    }
+
+    #[test]
+    fn allowed_chars_rejects_first_bad_char() {
+        let policy = LabelValuePolicy::new().allowed_chars(|c| c.is_ascii_alphanumeric());
+        assert!(policy.apply("method", "GET").is_ok());
+        let err = policy.apply("method", "GET /x").unwrap_err();
+        assert!(err.reason.contains("' '"), "{}", err);
+    }
+
+    #[test]
+    fn redaction_replaces_value_and_skips_checks() {
+        let policy = LabelValuePolicy::new()
+            .max_length(4)
+            .redact("email")
+            .redaction_text("***");
+        assert_eq!(policy.apply("email", "someone@example.com").unwrap(), "***");
+        assert!(policy.apply("other", "someone@example.com").is_err());
+    }
+}
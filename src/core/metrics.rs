@@ -3,6 +3,11 @@
 //! These traits define the interface for metrics that any backend
 //! (Prometheus, OpenTelemetry, StatsD, etc.) can implement.
 
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use super::unit::{Bytes, Ratio, Seconds, Unit, UnitMarker, Unitless};
+
 /// A monotonically increasing counter.
 ///
 /// Counters are used for values that only go up, such as:
@@ -81,15 +86,71 @@ pub trait HistogramTrait: Clone + Send + Sync + 'static {
     fn observe(&self, value: f64);
 }
 
-/// A metric with metadata (name and description).
+/// A counter that can attach an exemplar to an increment.
+///
+/// Exemplars link a sample to data outside the metric set, usually a trace:
+/// the backend keeps the most recent exemplar and exposes it alongside the
+/// counter value.
+///
+/// # Example
+/// ```ignore
+/// counter.inc_with_exemplar(&[("trace_id", "4bf92f3577b34da6")]);
+/// ```
+pub trait ExemplarCounterTrait: CounterTrait {
+    /// Increment the counter by a specific value, recording `labels` as the
+    /// exemplar.
+    fn inc_by_with_exemplar(&self, value: u64, labels: &[(&str, &str)]);
+
+    /// Increment the counter by 1, recording `labels` as the exemplar.
+    fn inc_with_exemplar(&self, labels: &[(&str, &str)]) {
+        self.inc_by_with_exemplar(1, labels);
+    }
+}
+
+/// A histogram that can attach an exemplar to an observation.
+///
+/// The backend keeps the most recent exemplar per bucket, so a latency
+/// spike in the upper buckets points at a trace that actually was slow.
+///
+/// # Example
+/// ```ignore
+/// histogram.observe_with_exemplar(1.7, &[("trace_id", "4bf92f3577b34da6")]);
+/// ```
+pub trait ExemplarHistogramTrait: HistogramTrait {
+    /// Record an observation, recording `labels` as the exemplar of the
+    /// bucket it falls into.
+    fn observe_with_exemplar(&self, value: f64, labels: &[(&str, &str)]);
+}
+
+/// A metric with metadata (name, description and optional unit).
 ///
 /// This is a generic wrapper that works with any metric type
 /// implementing the appropriate trait.
+///
+/// The second type parameter is a type-level unit marker (see
+/// [`crate::core::unit`]). It defaults to [`Unitless`], which keeps the
+/// plain `observe(f64)` API. Typed handles only expose unit-aware operations:
+///
+/// ```compile_fail
+/// use observe_rs::core::metrics::{HistogramTrait, Metric};
+/// use observe_rs::core::unit::Seconds;
+///
+/// #[derive(Clone)]
+/// struct H;
+/// impl HistogramTrait for H {
+///     fn observe(&self, _value: f64) {}
+/// }
+///
+/// let latency = Metric::<H, Seconds>::new_typed("latency_seconds", "Latency", H);
+/// latency.observe_bytes(1024); // error: no `observe_bytes` on a seconds histogram
+/// ```
 #[derive(Debug)]
-pub struct Metric<T> {
+pub struct Metric<T, U = Unitless> {
     inner: T,
     name: String,
     description: String,
+    unit: Option<Unit>,
+    _unit: PhantomData<fn() -> U>,
 }
 
 impl<T> Metric<T> {
@@ -99,9 +160,32 @@ impl<T> Metric<T> {
             inner,
             name: name.into(),
             description: description.into(),
+            unit: None,
+            _unit: PhantomData,
+        }
+    }
+
+    /// Attach a runtime unit to the metric metadata.
+    pub fn with_unit(mut self, unit: Unit) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+}
+
+impl<T, U: UnitMarker> Metric<T, U> {
+    /// Create a new metric whose unit is fixed by the type-level marker `U`.
+    pub fn new_typed(name: impl Into<String>, description: impl Into<String>, inner: T) -> Self {
+        Self {
+            inner,
+            name: name.into(),
+            description: description.into(),
+            unit: U::unit(),
+            _unit: PhantomData,
         }
     }
+}
 
+impl<T, U> Metric<T, U> {
     /// Get the metric name.
     pub fn name(&self) -> &str {
         &self.name
@@ -112,6 +196,11 @@ impl<T> Metric<T> {
         &self.description
     }
 
+    /// Get the metric unit, if any.
+    pub fn unit(&self) -> Option<&Unit> {
+        self.unit.as_ref()
+    }
+
     /// Access the underlying metric.
     pub fn inner(&self) -> &T {
         &self.inner
@@ -122,7 +211,7 @@ impl<T> Metric<T> {
 // Counter operations - delegated to inner type
 // ═══════════════════════════════════════════════════════════════════════════
 
-impl<T: CounterTrait> Metric<T> {
+impl<T: CounterTrait, U> Metric<T, U> {
     /// Increment the counter by 1.
     pub fn inc(&self) {
         self.inner.inc();
@@ -139,11 +228,43 @@ impl<T: CounterTrait> Metric<T> {
     }
 }
 
+impl<T: ExemplarCounterTrait, U> Metric<T, U> {
+    /// Increment the counter by 1, recording `labels` as the exemplar.
+    pub fn inc_with_exemplar(&self, labels: &[(&str, &str)]) {
+        self.inner.inc_with_exemplar(labels);
+    }
+
+    /// Increment the counter by a specific value, recording `labels` as the
+    /// exemplar.
+    pub fn inc_by_with_exemplar(&self, value: u64, labels: &[(&str, &str)]) {
+        self.inner.inc_by_with_exemplar(value, labels);
+    }
+}
+
+#[cfg(feature = "tracing-integration")]
+impl<T: ExemplarCounterTrait, U> Metric<T, U> {
+    /// Increment the counter by 1, taking the exemplar from the current
+    /// `tracing` span.
+    ///
+    /// Increments without an exemplar when there is no current span.
+    pub fn inc_with_span_exemplar(&self) {
+        self.inc_by_with_span_exemplar(1);
+    }
+
+    /// Increment the counter by a specific value, taking the exemplar from
+    /// the current `tracing` span.
+    pub fn inc_by_with_span_exemplar(&self, value: u64) {
+        let labels = crate::tracing_integration::current_span_exemplar();
+        self.inner
+            .inc_by_with_exemplar(value, &crate::tracing_integration::as_pairs(&labels));
+    }
+}
+
 // ═══════════════════════════════════════════════════════════════════════════
 // Gauge operations - delegated to inner type
 // ═══════════════════════════════════════════════════════════════════════════
 
-impl<T: GaugeTrait> Metric<T> {
+impl<T: GaugeTrait, U> Metric<T, U> {
     /// Set the gauge to a specific value.
     pub fn set(&self, value: i64) {
         self.inner.set(value);
@@ -186,6 +307,68 @@ impl<T: HistogramTrait> Metric<T> {
     }
 }
 
+impl<T: ExemplarHistogramTrait> Metric<T> {
+    /// Record an observation, recording `labels` as the exemplar.
+    pub fn observe_with_exemplar(&self, value: f64, labels: &[(&str, &str)]) {
+        self.inner.observe_with_exemplar(value, labels);
+    }
+}
+
+impl<T: ExemplarHistogramTrait> Metric<T, Seconds> {
+    /// Record a duration, in seconds, recording `labels` as the exemplar.
+    pub fn observe_duration_with_exemplar(&self, duration: Duration, labels: &[(&str, &str)]) {
+        self.inner
+            .observe_with_exemplar(duration.as_secs_f64(), labels);
+    }
+}
+
+#[cfg(feature = "tracing-integration")]
+impl<T: ExemplarHistogramTrait> Metric<T> {
+    /// Record an observation, taking the exemplar from the current
+    /// `tracing` span.
+    ///
+    /// Records without an exemplar when there is no current span.
+    pub fn observe_with_span_exemplar(&self, value: f64) {
+        let labels = crate::tracing_integration::current_span_exemplar();
+        self.inner
+            .observe_with_exemplar(value, &crate::tracing_integration::as_pairs(&labels));
+    }
+}
+
+#[cfg(feature = "tracing-integration")]
+impl<T: ExemplarHistogramTrait> Metric<T, Seconds> {
+    /// Record a duration, in seconds, taking the exemplar from the current
+    /// `tracing` span.
+    pub fn observe_duration_with_span_exemplar(&self, duration: Duration) {
+        let labels = crate::tracing_integration::current_span_exemplar();
+        self.inner.observe_with_exemplar(
+            duration.as_secs_f64(),
+            &crate::tracing_integration::as_pairs(&labels),
+        );
+    }
+}
+
+impl<T: HistogramTrait> Metric<T, Seconds> {
+    /// Record a duration, in seconds.
+    pub fn observe_duration(&self, duration: Duration) {
+        self.inner.observe(duration.as_secs_f64());
+    }
+}
+
+impl<T: HistogramTrait> Metric<T, Bytes> {
+    /// Record a size, in bytes.
+    pub fn observe_bytes(&self, bytes: u64) {
+        self.inner.observe(bytes as f64);
+    }
+}
+
+impl<T: HistogramTrait> Metric<T, Ratio> {
+    /// Record a ratio, usually between 0 and 1.
+    pub fn observe_ratio(&self, ratio: f64) {
+        self.inner.observe(ratio);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -227,4 +410,77 @@ mod tests {
         counter.inc_by(10);
         assert_eq!(counter.get_counter(), 11);
     }
+
+    #[derive(Clone, Default)]
+    struct TestHistogram(std::sync::Arc<std::sync::Mutex<Vec<f64>>>);
+
+    impl HistogramTrait for TestHistogram {
+        fn observe(&self, value: f64) {
+            self.0.lock().unwrap().push(value);
+        }
+    }
+
+    #[test]
+    fn test_typed_seconds_histogram_observes_durations() {
+        let inner = TestHistogram::default();
+        let latency = Metric::<_, Seconds>::new_typed("latency_seconds", "Latency", inner.clone());
+
+        latency.observe_duration(Duration::from_millis(250));
+
+        assert_eq!(latency.unit(), Some(&Unit::Seconds));
+        assert_eq!(*inner.0.lock().unwrap(), vec![0.25]);
+    }
+
+    #[test]
+    fn test_typed_bytes_histogram_observes_byte_counts() {
+        let inner = TestHistogram::default();
+        let size = Metric::<_, Bytes>::new_typed("payload_bytes", "Payload", inner.clone());
+
+        size.observe_bytes(1024);
+
+        assert_eq!(size.unit(), Some(&Unit::Bytes));
+        assert_eq!(*inner.0.lock().unwrap(), vec![1024.0]);
+    }
+
+    #[derive(Clone, Default)]
+    struct TestExemplarHistogram(std::sync::Arc<std::sync::Mutex<Vec<(f64, String)>>>);
+
+    impl HistogramTrait for TestExemplarHistogram {
+        fn observe(&self, value: f64) {
+            self.0.lock().unwrap().push((value, String::new()));
+        }
+    }
+
+    impl ExemplarHistogramTrait for TestExemplarHistogram {
+        fn observe_with_exemplar(&self, value: f64, labels: &[(&str, &str)]) {
+            let exemplar = labels
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(",");
+            self.0.lock().unwrap().push((value, exemplar));
+        }
+    }
+
+    #[test]
+    fn test_exemplar_observations_are_delegated() {
+        let inner = TestExemplarHistogram::default();
+        let latency = Metric::<_, Seconds>::new_typed("latency_seconds", "Latency", inner.clone());
+
+        latency.observe_duration_with_exemplar(Duration::from_secs(2), &[("trace_id", "abc")]);
+
+        assert_eq!(
+            *inner.0.lock().unwrap(),
+            vec![(2.0, "trace_id=abc".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_untyped_metric_with_runtime_unit() {
+        let metric = Metric::new("temp_celsius", "Temperature", TestHistogram::default())
+            .with_unit(Unit::Custom("celsius".into()));
+
+        metric.observe(21.5);
+        assert_eq!(metric.unit().map(Unit::as_str), Some("celsius"));
+    }
 }
@@ -3,11 +3,47 @@
 //! This module contains backend-agnostic abstractions that any metric
 //! system can implement.
 
+pub mod build_info;
+pub mod cardinality;
+pub mod clock;
 pub mod deserialise;
+#[cfg(feature = "filter")]
+pub mod filter;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod labels;
 pub mod metrics;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
 pub mod registry;
+#[cfg(feature = "relabel")]
+pub mod relabel;
 pub mod renderer;
+pub mod snapshot;
+pub mod unit;
 
-pub use metrics::{CounterTrait, GaugeTrait, HistogramTrait, Metric};
+pub use build_info::BuildInfo;
+pub use cardinality::{CardinalityLimits, OverflowPolicy};
+pub use clock::{Clock, ManualClock, SystemClock};
+#[cfg(feature = "filter")]
+pub use filter::{FilterError, MetricFilter};
+#[cfg(feature = "json")]
+pub use json::{
+    JsonBucket, JsonExemplar, JsonMetricFamily, JsonMetrics, JsonNumber, JsonQuantile,
+    JsonRenderError, JsonSample, JSON_CONTENT_TYPE,
+};
+pub use labels::LabelValuePolicy;
+pub use metrics::{
+    CounterTrait, ExemplarCounterTrait, ExemplarHistogramTrait, GaugeTrait, HistogramTrait, Metric,
+};
+#[cfg(feature = "protobuf")]
+pub use protobuf::{ProtobufMetrics, PROTOBUF_CONTENT_TYPE};
 pub use registry::{MetricBackend, ObservabilityRegistry, DEFAULT_LATENCY_BUCKETS};
-pub use renderer::{MetricsRenderer, RenderedMetrics};
+#[cfg(feature = "relabel")]
+pub use relabel::{RelabelError, RelabelRegex, RelabelRule, RelabelRules};
+pub use renderer::{MetricsRenderer, RenderToError, RenderedMetrics, OPENMETRICS_CONTENT_TYPE};
+pub use snapshot::{
+    MetricFamily, MetricKind, Sample, SampleLabels, Snapshot, SnapshotError,
+    PROMETHEUS_TEXT_CONTENT_TYPE,
+};
+pub use unit::Unit;
@@ -3,8 +3,20 @@
 //! This module provides a unified interface for creating, registering,
 //! and rendering metrics across different backends.
 
-use super::metrics::{CounterTrait, GaugeTrait, HistogramTrait, Metric};
-use super::renderer::{MetricsRenderer, RenderedMetrics};
+use super::cardinality::{CardinalityLimiter, CardinalityLimits};
+#[cfg(feature = "filter")]
+use super::filter::MetricFilter;
+use super::labels::LabelValuePolicy;
+use super::metrics::{
+    CounterTrait, ExemplarCounterTrait, ExemplarHistogramTrait, GaugeTrait, HistogramTrait, Metric,
+};
+#[cfg(feature = "relabel")]
+use super::relabel::{RelabelRules, RelabelWriter};
+use super::renderer::{MetricsRenderer, RenderToError, RenderedMetrics};
+use super::snapshot::{FamilyStream, MetricFamily, Snapshot, SnapshotError};
+use super::unit::{Unit, UnitMarker};
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
 
 /// Default histogram buckets for latency measurements (in seconds).
 /// These are suitable for most HTTP request latency tracking.
@@ -16,6 +28,9 @@ pub const DEFAULT_LATENCY_BUCKETS: &[f64] = &[
 ///
 /// Each backend (Prometheus, OTLP, etc.) implements this trait to specify
 /// its concrete types for registry and metrics.
+///
+/// Registration functions receive the metric's optional [`Unit`]; backends
+/// expose it in their own way (Prometheus emits `# UNIT` metadata).
 pub trait MetricBackend: Send + Sync + 'static {
     /// The registry type for this backend
     type Registry: MetricsRenderer + Send + Sync;
@@ -29,17 +44,39 @@ pub trait MetricBackend: Send + Sync + 'static {
     /// The histogram type for this backend
     type Histogram: HistogramTrait;
 
+    /// The counter type that keeps an exemplar
+    type ExemplarCounter: ExemplarCounterTrait;
+
+    /// The histogram type that keeps an exemplar per bucket
+    type ExemplarHistogram: ExemplarHistogramTrait;
+
     /// Error type for registration failures
     type Error: std::error::Error + Send + Sync;
 
     /// Create a new registry
     fn create_registry() -> Self::Registry;
 
+    /// Create a new registry whose series all carry the given constant labels
+    ///
+    /// Fails if a label name is not valid for the backend. The default falls
+    /// back to [`create_registry`](Self::create_registry) and ignores
+    /// `labels`, for backends without constant label support.
+    fn create_registry_with_labels(
+        labels: &[(String, String)],
+    ) -> Result<Self::Registry, Self::Error> {
+        let _ = labels;
+        Ok(Self::create_registry())
+    }
+
+    /// The error for registering a second metric called `name`
+    fn duplicate_name_error(name: &str) -> Self::Error;
+
     /// Create and register a counter
     fn register_counter(
         registry: &mut Self::Registry,
         name: &str,
         help: &str,
+        unit: Option<&Unit>,
     ) -> Result<Self::Counter, Self::Error>;
 
     /// Create and register a gauge
@@ -47,6 +84,7 @@ pub trait MetricBackend: Send + Sync + 'static {
         registry: &mut Self::Registry,
         name: &str,
         help: &str,
+        unit: Option<&Unit>,
     ) -> Result<Self::Gauge, Self::Error>;
 
     /// Create and register a histogram with custom buckets
@@ -55,7 +93,76 @@ pub trait MetricBackend: Send + Sync + 'static {
         name: &str,
         help: &str,
         buckets: Vec<f64>,
+        unit: Option<&Unit>,
     ) -> Result<Self::Histogram, Self::Error>;
+
+    /// Create and register a counter that keeps its latest exemplar
+    fn register_exemplar_counter(
+        registry: &mut Self::Registry,
+        name: &str,
+        help: &str,
+        unit: Option<&Unit>,
+    ) -> Result<Self::ExemplarCounter, Self::Error>;
+
+    /// Create and register a histogram that keeps the latest exemplar of
+    /// each bucket
+    fn register_exemplar_histogram(
+        registry: &mut Self::Registry,
+        name: &str,
+        help: &str,
+        buckets: Vec<f64>,
+        unit: Option<&Unit>,
+    ) -> Result<Self::ExemplarHistogram, Self::Error>;
+
+    /// Create and register an info metric: a single series with value `1`
+    /// that carries `labels` (e.g. `build_info{version="1.2.3"} 1`)
+    ///
+    /// The default registers nothing, for backends without info metrics.
+    fn register_info(
+        registry: &mut Self::Registry,
+        name: &str,
+        help: &str,
+        labels: &[(String, String)],
+    ) -> Result<(), Self::Error> {
+        let _ = (registry, name, help, labels);
+        Ok(())
+    }
+}
+
+/// Names of the metrics registered through an [`ObservabilityRegistry`].
+///
+/// Shared with integrations that add families after setup, such as dynamic
+/// metrics, so they can refuse names that are already taken. Metrics
+/// registered on the backend registry directly (via
+/// [`inner_mut`](ObservabilityRegistry::inner_mut)) are not tracked.
+/// Cloning is cheap; clones share the same names.
+#[derive(Debug, Clone, Default)]
+pub struct RegisteredNames(Arc<RwLock<BTreeSet<String>>>);
+
+impl RegisteredNames {
+    /// Whether `name` is taken.
+    pub fn contains(&self, name: &str) -> bool {
+        self.0
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .contains(name)
+    }
+
+    /// Record `name`, returning false if it was already taken.
+    pub(crate) fn insert(&self, name: &str) -> bool {
+        self.0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(name.to_string())
+    }
+
+    /// Give back a name whose registration failed.
+    pub(crate) fn remove(&self, name: &str) {
+        self.0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(name);
+    }
 }
 
 /// A wrapper around a metric backend's registry.
@@ -81,6 +188,11 @@ pub trait MetricBackend: Send + Sync + 'static {
 /// ```
 pub struct ObservabilityRegistry<B: MetricBackend> {
     inner: B::Registry,
+    label_policy: Option<LabelValuePolicy>,
+    cardinality: CardinalityLimiter,
+    names: RegisteredNames,
+    #[cfg(feature = "relabel")]
+    relabel: Option<RelabelRules>,
 }
 
 impl<B: MetricBackend> ObservabilityRegistry<B> {
@@ -88,9 +200,116 @@ impl<B: MetricBackend> ObservabilityRegistry<B> {
     pub fn new() -> Self {
         Self {
             inner: B::create_registry(),
+            label_policy: None,
+            cardinality: CardinalityLimiter::default(),
+            names: RegisteredNames::default(),
+            #[cfg(feature = "relabel")]
+            relabel: None,
         }
     }
 
+    /// Create a new registry that attaches constant labels to every series.
+    ///
+    /// Useful for labels such as `service`, `version`, `instance` or `env`
+    /// that should be present on everything the process exposes, without
+    /// repeating them at every registration call.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let registry = ObservabilityRegistry::<PrometheusBackend>::with_const_labels([
+    ///     ("service", "checkout"),
+    ///     ("env", "prod"),
+    /// ])?;
+    /// ```
+    pub fn with_const_labels<K, V>(
+        labels: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<Self, B::Error>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let labels: Vec<(String, String)> = labels
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        Ok(Self {
+            inner: B::create_registry_with_labels(&labels)?,
+            label_policy: None,
+            cardinality: CardinalityLimiter::default(),
+            names: RegisteredNames::default(),
+            #[cfg(feature = "relabel")]
+            relabel: None,
+        })
+    }
+
+    /// Apply a label value policy to labeled families registered from now on.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let registry = ObservabilityRegistry::<PrometheusBackend>::new()
+    ///     .with_label_value_policy(LabelValuePolicy::new().max_length(128).redact("user_id"));
+    /// ```
+    pub fn with_label_value_policy(mut self, policy: LabelValuePolicy) -> Self {
+        self.label_policy = Some(policy);
+        self
+    }
+
+    /// The label value policy for labeled families, if any.
+    pub fn label_value_policy(&self) -> Option<&LabelValuePolicy> {
+        self.label_policy.as_ref()
+    }
+
+    /// Cap the number of series of labeled families registered from now on.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let registry = ObservabilityRegistry::<PrometheusBackend>::new().with_cardinality_limits(
+    ///     CardinalityLimits::new()
+    ///         .per_family(1_000)
+    ///         .per_registry(50_000)
+    ///         .overflow(OverflowPolicy::Fold),
+    /// );
+    /// ```
+    pub fn with_cardinality_limits(mut self, limits: CardinalityLimits) -> Self {
+        self.cardinality = CardinalityLimiter::new(limits);
+        self
+    }
+
+    /// The limiter shared by this registry's labeled families.
+    pub fn cardinality(&self) -> &CardinalityLimiter {
+        &self.cardinality
+    }
+
+    /// Names of the metrics registered through this registry.
+    pub fn registered_names(&self) -> &RegisteredNames {
+        &self.names
+    }
+
+    /// Relabel everything the registry renders with `rules`.
+    ///
+    /// The rules apply to [`render`](Self::render), [`snapshot`](Self::snapshot)
+    /// and everything built on them, so scrapes and exporters agree.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let registry = ObservabilityRegistry::<PrometheusBackend>::new().with_relabel_rules(
+    ///     RelabelRules::new()
+    ///         .drop_series(["path"], "/health.*")?
+    ///         .hash("user_id")?,
+    /// );
+    /// ```
+    #[cfg(feature = "relabel")]
+    pub fn with_relabel_rules(mut self, rules: RelabelRules) -> Self {
+        self.relabel = (!rules.is_empty()).then_some(rules);
+        self
+    }
+
+    /// The relabel rules applied at render time, if any.
+    #[cfg(feature = "relabel")]
+    pub fn relabel_rules(&self) -> Option<&RelabelRules> {
+        self.relabel.as_ref()
+    }
+
     /// Create and register a counter.
     pub fn counter(
         &mut self,
@@ -99,10 +318,30 @@ impl<B: MetricBackend> ObservabilityRegistry<B> {
     ) -> Result<Metric<B::Counter>, B::Error> {
         let name = name.into();
         let help = help.into();
-        let counter = B::register_counter(&mut self.inner, &name, &help)?;
+        let counter = self.register_named(&name, |registry| {
+            B::register_counter(registry, &name, &help, None)
+        })?;
         Ok(Metric::new(name, help, counter))
     }
 
+    /// Create and register a counter with a unit.
+    ///
+    /// The name must end with the unit suffix, e.g. `cpu_seconds` for
+    /// [`Unit::Seconds`].
+    pub fn counter_with_unit(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        unit: Unit,
+    ) -> Result<Metric<B::Counter>, B::Error> {
+        let name = name.into();
+        let help = help.into();
+        let counter = self.register_named(&name, |registry| {
+            B::register_counter(registry, &name, &help, Some(&unit))
+        })?;
+        Ok(Metric::new(name, help, counter).with_unit(unit))
+    }
+
     /// Create and register a gauge.
     pub fn gauge(
         &mut self,
@@ -111,10 +350,30 @@ impl<B: MetricBackend> ObservabilityRegistry<B> {
     ) -> Result<Metric<B::Gauge>, B::Error> {
         let name = name.into();
         let help = help.into();
-        let gauge = B::register_gauge(&mut self.inner, &name, &help)?;
+        let gauge = self.register_named(&name, |registry| {
+            B::register_gauge(registry, &name, &help, None)
+        })?;
         Ok(Metric::new(name, help, gauge))
     }
 
+    /// Create and register a gauge with a unit.
+    ///
+    /// The name must end with the unit suffix, e.g. `memory_usage_bytes` for
+    /// [`Unit::Bytes`].
+    pub fn gauge_with_unit(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        unit: Unit,
+    ) -> Result<Metric<B::Gauge>, B::Error> {
+        let name = name.into();
+        let help = help.into();
+        let gauge = self.register_named(&name, |registry| {
+            B::register_gauge(registry, &name, &help, Some(&unit))
+        })?;
+        Ok(Metric::new(name, help, gauge).with_unit(unit))
+    }
+
     /// Create and register a histogram with default latency buckets.
     pub fn histogram(
         &mut self,
@@ -133,21 +392,287 @@ impl<B: MetricBackend> ObservabilityRegistry<B> {
     ) -> Result<Metric<B::Histogram>, B::Error> {
         let name = name.into();
         let help = help.into();
-        let histogram = B::register_histogram(&mut self.inner, &name, &help, buckets)?;
+        let histogram = self.register_named(&name, |registry| {
+            B::register_histogram(registry, &name, &help, buckets, None)
+        })?;
+        Ok(Metric::new(name, help, histogram))
+    }
+
+    /// Create and register a histogram with custom buckets and a unit.
+    ///
+    /// The name must end with the unit suffix, e.g. `request_duration_seconds`
+    /// for [`Unit::Seconds`].
+    pub fn histogram_with_unit(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        buckets: Vec<f64>,
+        unit: Unit,
+    ) -> Result<Metric<B::Histogram>, B::Error> {
+        let name = name.into();
+        let help = help.into();
+        let histogram = self.register_named(&name, |registry| {
+            B::register_histogram(registry, &name, &help, buckets, Some(&unit))
+        })?;
+        Ok(Metric::new(name, help, histogram).with_unit(unit))
+    }
+
+    /// Create and register a histogram whose unit is fixed at the type level.
+    ///
+    /// The returned handle only exposes operations for that unit, e.g.
+    /// `observe_duration` for [`Seconds`](crate::core::unit::Seconds).
+    ///
+    /// # Example
+    /// ```ignore
+    /// use observe_rs::core::unit::Seconds;
+    ///
+    /// let latency = registry.typed_histogram::<Seconds>(
+    ///     "request_duration_seconds",
+    ///     "Request latency",
+    ///     DEFAULT_LATENCY_BUCKETS.to_vec(),
+    /// )?;
+    /// latency.observe_duration(started.elapsed());
+    /// ```
+    pub fn typed_histogram<U: UnitMarker>(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        buckets: Vec<f64>,
+    ) -> Result<Metric<B::Histogram, U>, B::Error> {
+        let name = name.into();
+        let help = help.into();
+        let unit = U::unit();
+        let histogram = self.register_named(&name, |registry| {
+            B::register_histogram(registry, &name, &help, buckets, unit.as_ref())
+        })?;
+        Ok(Metric::new_typed(name, help, histogram))
+    }
+
+    /// Create and register a counter that keeps its latest exemplar.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let orders = registry.counter_with_exemplars("orders_total", "Orders placed")?;
+    /// orders.inc_with_exemplar(&[("trace_id", trace_id)]);
+    /// ```
+    pub fn counter_with_exemplars(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+    ) -> Result<Metric<B::ExemplarCounter>, B::Error> {
+        let name = name.into();
+        let help = help.into();
+        let counter = self.register_named(&name, |registry| {
+            B::register_exemplar_counter(registry, &name, &help, None)
+        })?;
+        Ok(Metric::new(name, help, counter))
+    }
+
+    /// Create and register a histogram that keeps the latest exemplar of
+    /// each bucket.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let latency = registry.histogram_with_exemplars(
+    ///     "request_duration_seconds",
+    ///     "Request latency",
+    ///     DEFAULT_LATENCY_BUCKETS.to_vec(),
+    /// )?;
+    /// latency.observe_with_exemplar(1.7, &[("trace_id", trace_id)]);
+    /// ```
+    pub fn histogram_with_exemplars(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        buckets: Vec<f64>,
+    ) -> Result<Metric<B::ExemplarHistogram>, B::Error> {
+        let name = name.into();
+        let help = help.into();
+        let histogram = self.register_named(&name, |registry| {
+            B::register_exemplar_histogram(registry, &name, &help, buckets, None)
+        })?;
         Ok(Metric::new(name, help, histogram))
     }
 
+    /// Create and register a histogram whose unit is fixed at the type level
+    /// and that keeps the latest exemplar of each bucket.
+    ///
+    /// See [`typed_histogram`](Self::typed_histogram).
+    pub fn typed_histogram_with_exemplars<U: UnitMarker>(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        buckets: Vec<f64>,
+    ) -> Result<Metric<B::ExemplarHistogram, U>, B::Error> {
+        let name = name.into();
+        let help = help.into();
+        let unit = U::unit();
+        let histogram = self.register_named(&name, |registry| {
+            B::register_exemplar_histogram(registry, &name, &help, buckets, unit.as_ref())
+        })?;
+        Ok(Metric::new_typed(name, help, histogram))
+    }
+
+    /// Create and register an info metric.
+    ///
+    /// Info metrics expose static key/value metadata as a single series with
+    /// value `1`, e.g. `target_info{service="checkout",env="prod"} 1`.
+    pub fn info<K, V>(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        labels: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), B::Error>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let labels: Vec<(String, String)> = labels
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        let name = name.into();
+        let help = help.into();
+        self.register_named(&name, |registry| {
+            B::register_info(registry, &name, &help, &labels)
+        })
+    }
+
+    /// Reserve `name` and run `register`, giving the name back if it fails.
+    fn register_named<T>(
+        &mut self,
+        name: &str,
+        register: impl FnOnce(&mut B::Registry) -> Result<T, B::Error>,
+    ) -> Result<T, B::Error> {
+        if !self.names.insert(name) {
+            return Err(B::duplicate_name_error(name));
+        }
+        let registered = register(&mut self.inner);
+        if registered.is_err() {
+            self.names.remove(name);
+        }
+        registered
+    }
+
     /// Render the metrics in the backend's format.
     pub fn render(&self) -> Result<RenderedMetrics, <B::Registry as MetricsRenderer>::Error> {
+        #[cfg(feature = "relabel")]
+        if let Some(rules) = &self.relabel {
+            let mut rendered = self.inner.render()?;
+            rendered.body = rules.apply_to_text(&rendered.body);
+            return Ok(rendered);
+        }
         self.inner.render()
     }
 
+    /// Write the metrics in the backend's format to `writer` as they are
+    /// encoded, without building the whole output in memory.
+    pub fn render_to<W: std::io::Write + ?Sized>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), RenderToError<<B::Registry as MetricsRenderer>::Error>> {
+        #[cfg(feature = "relabel")]
+        if let Some(rules) = &self.relabel {
+            let mut relabeled = RelabelWriter::new(writer, rules);
+            self.inner.render_to(&mut relabeled)?;
+            return relabeled.finish().map_err(RenderToError::Io);
+        }
+        self.inner.render_to(writer)
+    }
+
+    /// Write the families `filter` selects to `writer` as they are encoded.
+    ///
+    /// The backend skips the other families before encoding them; see
+    /// [`filter`](super::filter) for how names are matched.
+    #[cfg(feature = "filter")]
+    pub fn render_filtered_to<W: std::io::Write + ?Sized>(
+        &self,
+        filter: &MetricFilter,
+        writer: &mut W,
+    ) -> Result<(), RenderToError<<B::Registry as MetricsRenderer>::Error>> {
+        if filter.is_empty() {
+            return self.render_to(writer);
+        }
+        #[cfg(feature = "relabel")]
+        if let Some(rules) = &self.relabel {
+            let mut relabeled = RelabelWriter::new(writer, rules);
+            self.inner.render_filtered_to(filter, &mut relabeled)?;
+            return relabeled.finish().map_err(RenderToError::Io);
+        }
+        self.inner.render_filtered_to(filter, writer)
+    }
+
+    /// Take a snapshot of every metric in the registry.
+    ///
+    /// Used by push exporters and alternative output formats.
+    pub fn snapshot(&self) -> Result<Snapshot, SnapshotError>
+    where
+        <B::Registry as MetricsRenderer>::Error: std::fmt::Display,
+    {
+        let rendered = self
+            .render()
+            .map_err(|e| SnapshotError::Render(e.to_string()))?;
+        let text = rendered
+            .as_str()
+            .map_err(|e| SnapshotError::Render(e.to_string()))?;
+        Snapshot::parse(text)
+    }
+
+    /// Take a snapshot of the families `filter` selects.
+    #[cfg(feature = "filter")]
+    pub fn snapshot_filtered(&self, filter: &MetricFilter) -> Result<Snapshot, SnapshotError>
+    where
+        <B::Registry as MetricsRenderer>::Error: std::fmt::Display,
+    {
+        let mut text = Vec::new();
+        self.render_filtered_to(filter, &mut text)
+            .map_err(|e| SnapshotError::Render(e.to_string()))?;
+        let text = std::str::from_utf8(&text).map_err(|e| SnapshotError::Render(e.to_string()))?;
+        Snapshot::parse(text)
+    }
+
+    /// Parse the registry's output one family at a time, passing each to
+    /// `emit` as soon as it is complete.
+    ///
+    /// Unlike [`snapshot`](Self::snapshot), only one family is held in
+    /// memory, so alternative output formats can be streamed. Errors from
+    /// `emit` are returned as [`RenderToError::Io`].
+    pub fn for_each_family<F>(&self, emit: F) -> Result<(), RenderToError<SnapshotError>>
+    where
+        F: FnMut(MetricFamily) -> std::io::Result<()>,
+        <B::Registry as MetricsRenderer>::Error: std::fmt::Display,
+    {
+        let mut stream = FamilyStream::new(emit);
+        let rendered = self.render_to(&mut stream);
+        stream.finish(rendered)
+    }
+
+    /// [`for_each_family`](Self::for_each_family) over the families
+    /// `filter` selects.
+    #[cfg(feature = "filter")]
+    pub fn for_each_family_filtered<F>(
+        &self,
+        filter: &MetricFilter,
+        emit: F,
+    ) -> Result<(), RenderToError<SnapshotError>>
+    where
+        F: FnMut(MetricFamily) -> std::io::Result<()>,
+        <B::Registry as MetricsRenderer>::Error: std::fmt::Display,
+    {
+        let mut stream = FamilyStream::new(emit);
+        let rendered = self.render_filtered_to(filter, &mut stream);
+        stream.finish(rendered)
+    }
+
     /// Get a reference to the underlying registry.
     pub fn inner(&self) -> &B::Registry {
         &self.inner
     }
 
     /// Get a mutable reference to the underlying registry.
+    ///
+    /// Metrics registered here directly bypass this registry's name, label
+    /// and cardinality checks, and filtered renders always include them.
     pub fn inner_mut(&mut self) -> &mut B::Registry {
         &mut self.inner
     }
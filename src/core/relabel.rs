@@ -0,0 +1,1171 @@
+//! Render-time relabeling, the in-process equivalent of Prometheus
+//! `metric_relabel_configs`.
+//!
+//! [`RelabelRules`] rename families, drop or keep series by label value,
+//! rewrite labels, hash or redact label values and map or drop label names.
+//! A registry with rules applies them to everything it renders, so the
+//! scrape endpoint, snapshots and every exporter see the same output.
+//!
+//! Rules run in order against each series. A series has a name (`__name__`)
+//! and its labels; `__name__` is the *family* name, e.g. `http_requests`
+//! for the samples `http_requests_total` and `http_requests_created`.
+//! Renaming a family keeps its sample suffixes; a family renamed to an
+//! invalid name, or to one another family already has, is dropped. The `le` and `quantile`
+//! labels of histograms and summaries are never changed, and no other
+//! label is mapped onto them.
+//!
+//! # Example
+//! ```ignore
+//! use observe_rs::core::relabel::RelabelRules;
+//!
+//! let rules = RelabelRules::new()
+//!     .rename("legacy_(.*)", "app_$1")?
+//!     .drop_series(["path"], "/health.*")?
+//!     .hash("user_id")?
+//!     .redact("email")?
+//!     .label_map("k8s_(.+)", "$1")?
+//!     .label_drop("tmp_.*")?;
+//!
+//! let registry = ObservabilityRegistry::<PrometheusBackend>::new().with_relabel_rules(rules);
+//! ```
+//!
+//! With `json-config`/`yaml-config`, the same rules are the `relabel`
+//! section of a registry document:
+//!
+//! ```yaml
+//! relabel:
+//!   - action: rename
+//!     regex: "legacy_(.*)"
+//!     replacement: "app_$1"
+//!   - action: drop
+//!     source_labels: [path]
+//!     regex: "/health.*"
+//!   - action: hash
+//!     target_label: user_id
+//!     salt: "per-deployment secret"
+//!   - action: redact
+//!     target_label: email
+//!   - action: labelmap
+//!     regex: "k8s_(.+)"
+//!     replacement: "$1"
+//!   - action: labeldrop
+//!     regex: "tmp_.*"
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use regex::Regex;
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+use serde::{Deserialize, Deserializer};
+use sha2::Sha256;
+
+use super::labels::DEFAULT_REDACTION;
+use super::snapshot::{parse_labels, MetricKind, SampleLabels};
+
+/// The pseudo-label holding a series' family name.
+pub const NAME_LABEL: &str = "__name__";
+
+/// Default separator between the values of `source_labels`.
+pub const DEFAULT_SEPARATOR: &str = ";";
+
+/// Errors from building [`RelabelRules`].
+#[derive(Debug, thiserror::Error)]
+pub enum RelabelError {
+    /// A pattern isn't a valid regex.
+    #[error("invalid relabel regex {pattern:?}: {source}")]
+    InvalidRegex {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+
+    /// A rule can't be applied as configured.
+    #[error("invalid relabel rule: {0}")]
+    InvalidRule(String),
+}
+
+/// A regex matched against the whole input, as in Prometheus.
+#[derive(Clone)]
+pub struct RelabelRegex {
+    pattern: String,
+    regex: Regex,
+}
+
+impl RelabelRegex {
+    /// Compile `pattern`, anchored at both ends.
+    pub fn new(pattern: &str) -> Result<Self, RelabelError> {
+        let regex = Regex::new(&format!("^(?:{})$", pattern)).map_err(|source| {
+            RelabelError::InvalidRegex {
+                pattern: pattern.to_string(),
+                source,
+            }
+        })?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+
+    /// The pattern as written.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    fn is_match(&self, input: &str) -> bool {
+        self.regex.is_match(input)
+    }
+
+    /// `replacement` with `$1`/`${name}` expanded, if `input` matches.
+    fn replace(&self, input: &str, replacement: &str) -> Option<String> {
+        let captures = self.regex.captures(input)?;
+        let mut out = String::new();
+        captures.expand(replacement, &mut out);
+        Some(out)
+    }
+}
+
+impl fmt::Debug for RelabelRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RelabelRegex").field(&self.pattern).finish()
+    }
+}
+
+impl PartialEq for RelabelRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+impl<'de> Deserialize<'de> for RelabelRegex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Self::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+/// One relabeling step.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    any(feature = "json-config", feature = "yaml-config"),
+    derive(Deserialize),
+    serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)
+)]
+pub enum RelabelRule {
+    /// Rename families whose name matches `regex` to `replacement`.
+    Rename {
+        regex: RelabelRegex,
+        replacement: String,
+    },
+    /// Drop series whose joined `source_labels` match `regex`.
+    Drop {
+        source_labels: Vec<String>,
+        #[cfg_attr(
+            any(feature = "json-config", feature = "yaml-config"),
+            serde(default = "default_separator")
+        )]
+        separator: String,
+        regex: RelabelRegex,
+    },
+    /// Drop series whose joined `source_labels` don't match `regex`.
+    Keep {
+        source_labels: Vec<String>,
+        #[cfg_attr(
+            any(feature = "json-config", feature = "yaml-config"),
+            serde(default = "default_separator")
+        )]
+        separator: String,
+        regex: RelabelRegex,
+    },
+    /// Set `target_label` to `replacement` when the joined `source_labels`
+    /// match `regex`; an empty result removes the label.
+    Replace {
+        source_labels: Vec<String>,
+        #[cfg_attr(
+            any(feature = "json-config", feature = "yaml-config"),
+            serde(default = "default_separator")
+        )]
+        separator: String,
+        regex: RelabelRegex,
+        target_label: String,
+        replacement: String,
+    },
+    /// Replace the value of `target_label` with its HMAC-SHA256 keyed by
+    /// `salt`, truncated to 16 hex digits.
+    Hash {
+        target_label: String,
+        #[cfg_attr(any(feature = "json-config", feature = "yaml-config"), serde(default))]
+        salt: String,
+    },
+    /// Replace the value of `target_label` with `replacement`.
+    Redact {
+        target_label: String,
+        #[cfg_attr(
+            any(feature = "json-config", feature = "yaml-config"),
+            serde(default = "default_redaction")
+        )]
+        replacement: String,
+    },
+    /// Rename labels whose name matches `regex` to `replacement`, unless
+    /// the new name is a protected label.
+    #[cfg_attr(
+        any(feature = "json-config", feature = "yaml-config"),
+        serde(rename = "labelmap")
+    )]
+    LabelMap {
+        regex: RelabelRegex,
+        replacement: String,
+    },
+    /// Remove labels whose name matches `regex`.
+    #[cfg_attr(
+        any(feature = "json-config", feature = "yaml-config"),
+        serde(rename = "labeldrop")
+    )]
+    LabelDrop { regex: RelabelRegex },
+}
+
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+fn default_separator() -> String {
+    DEFAULT_SEPARATOR.to_string()
+}
+
+#[cfg(any(feature = "json-config", feature = "yaml-config"))]
+fn default_redaction() -> String {
+    DEFAULT_REDACTION.to_string()
+}
+
+impl RelabelRule {
+    /// Apply a hash, redact, labelmap or labeldrop rule to `labels`,
+    /// leaving labels for which `is_protected` is true alone. Other rules
+    /// do nothing.
+    fn rewrite_labels(&self, labels: &mut SampleLabels, is_protected: &dyn Fn(&str) -> bool) {
+        match self {
+            Self::Hash { target_label, salt } => {
+                if let Some((_, value)) = labels
+                    .iter_mut()
+                    .find(|(label, _)| label == target_label && !is_protected(label))
+                {
+                    *value = keyed_hash(salt, value);
+                }
+            }
+            Self::Redact {
+                target_label,
+                replacement,
+            } => {
+                if let Some((_, value)) = labels
+                    .iter_mut()
+                    .find(|(label, _)| label == target_label && !is_protected(label))
+                {
+                    value.clone_from(replacement);
+                }
+            }
+            Self::LabelMap { regex, replacement } => {
+                let mut mapped: SampleLabels = Vec::with_capacity(labels.len());
+                for (label, value) in labels.drain(..) {
+                    let label = match regex.replace(&label, replacement) {
+                        Some(renamed) if !is_protected(&label) && !is_protected(&renamed) => {
+                            renamed
+                        }
+                        _ => label,
+                    };
+                    mapped.retain(|(existing, _)| *existing != label);
+                    mapped.push((label, value));
+                }
+                *labels = mapped;
+            }
+            Self::LabelDrop { regex } => {
+                labels.retain(|(label, _)| is_protected(label) || !regex.is_match(label));
+            }
+            Self::Rename { .. } | Self::Drop { .. } | Self::Keep { .. } | Self::Replace { .. } => {}
+        }
+    }
+
+    fn validate(&self) -> Result<(), RelabelError> {
+        if let Self::Rename { replacement, .. } = self {
+            // Expansions are checked per family at render time.
+            if !replacement.contains('$') && !is_valid_metric_name(replacement) {
+                return Err(RelabelError::InvalidRule(format!(
+                    "{:?} is not a valid metric name",
+                    replacement
+                )));
+            }
+            return Ok(());
+        }
+        let target = match self {
+            Self::Replace { target_label, .. }
+            | Self::Hash { target_label, .. }
+            | Self::Redact { target_label, .. } => target_label,
+            _ => return Ok(()),
+        };
+        if target == NAME_LABEL {
+            return Err(RelabelError::InvalidRule(format!(
+                "{} can't be the target label; use a rename rule",
+                NAME_LABEL
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// An ordered list of [`RelabelRule`]s.
+///
+/// Clones share the [`rejected_samples`](Self::rejected_samples) count.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    any(feature = "json-config", feature = "yaml-config"),
+    derive(Deserialize),
+    serde(try_from = "Vec<RelabelRule>")
+)]
+pub struct RelabelRules {
+    rules: Vec<RelabelRule>,
+    rejected: Arc<AtomicU64>,
+}
+
+impl PartialEq for RelabelRules {
+    fn eq(&self, other: &Self) -> bool {
+        self.rules == other.rules
+    }
+}
+
+impl TryFrom<Vec<RelabelRule>> for RelabelRules {
+    type Error = RelabelError;
+
+    fn try_from(rules: Vec<RelabelRule>) -> Result<Self, Self::Error> {
+        rules
+            .into_iter()
+            .try_fold(Self::new(), |rules, rule| rules.push(rule))
+    }
+}
+
+impl RelabelRules {
+    /// No rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a rule.
+    pub fn push(mut self, rule: RelabelRule) -> Result<Self, RelabelError> {
+        rule.validate()?;
+        self.rules.push(rule);
+        Ok(self)
+    }
+
+    /// Rename families whose name matches `regex` to `replacement`
+    /// (`$1`, `${name}` refer to capture groups).
+    ///
+    /// Fails if `replacement` has no capture references and isn't a valid
+    /// metric name. Families whose expanded name is invalid, or already
+    /// taken by another family, are dropped when rendered and counted in
+    /// [`rejected_samples`](Self::rejected_samples).
+    pub fn rename(self, regex: &str, replacement: impl Into<String>) -> Result<Self, RelabelError> {
+        self.push(RelabelRule::Rename {
+            regex: RelabelRegex::new(regex)?,
+            replacement: replacement.into(),
+        })
+    }
+
+    /// Drop series whose `source_labels`, joined with `;`, match `regex`.
+    pub fn drop_series<I, S>(self, source_labels: I, regex: &str) -> Result<Self, RelabelError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.push(RelabelRule::Drop {
+            source_labels: source_labels.into_iter().map(Into::into).collect(),
+            separator: DEFAULT_SEPARATOR.to_string(),
+            regex: RelabelRegex::new(regex)?,
+        })
+    }
+
+    /// Keep only series whose `source_labels`, joined with `;`, match
+    /// `regex`.
+    pub fn keep_series<I, S>(self, source_labels: I, regex: &str) -> Result<Self, RelabelError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.push(RelabelRule::Keep {
+            source_labels: source_labels.into_iter().map(Into::into).collect(),
+            separator: DEFAULT_SEPARATOR.to_string(),
+            regex: RelabelRegex::new(regex)?,
+        })
+    }
+
+    /// Set `target_label` to `replacement` where the `source_labels`,
+    /// joined with `;`, match `regex`.
+    pub fn replace<I, S>(
+        self,
+        source_labels: I,
+        regex: &str,
+        target_label: impl Into<String>,
+        replacement: impl Into<String>,
+    ) -> Result<Self, RelabelError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.push(RelabelRule::Replace {
+            source_labels: source_labels.into_iter().map(Into::into).collect(),
+            separator: DEFAULT_SEPARATOR.to_string(),
+            regex: RelabelRegex::new(regex)?,
+            target_label: target_label.into(),
+            replacement: replacement.into(),
+        })
+    }
+
+    /// Replace the value of `label` with an unsalted hash.
+    ///
+    /// Anyone can hash candidate values and compare, so guessable values
+    /// (user IDs, emails) can be recovered by a dictionary attack; use
+    /// [`hash_with_salt`](Self::hash_with_salt) with a secret salt to
+    /// pseudonymise them. Fails if `label` is `__name__`.
+    pub fn hash(self, label: impl Into<String>) -> Result<Self, RelabelError> {
+        self.hash_with_salt(label, "")
+    }
+
+    /// Replace the value of `label` with its HMAC-SHA256 keyed by `salt`.
+    ///
+    /// Without the salt, values can't be recovered by hashing candidates,
+    /// so keep it secret. Fails if `label` is `__name__`.
+    pub fn hash_with_salt(
+        self,
+        label: impl Into<String>,
+        salt: impl Into<String>,
+    ) -> Result<Self, RelabelError> {
+        self.push(RelabelRule::Hash {
+            target_label: label.into(),
+            salt: salt.into(),
+        })
+    }
+
+    /// Replace the value of `label` with `[REDACTED]`.
+    ///
+    /// Fails if `label` is `__name__`.
+    pub fn redact(self, label: impl Into<String>) -> Result<Self, RelabelError> {
+        self.redact_with(label, DEFAULT_REDACTION)
+    }
+
+    /// Replace the value of `label` with `replacement`.
+    ///
+    /// Fails if `label` is `__name__`.
+    pub fn redact_with(
+        self,
+        label: impl Into<String>,
+        replacement: impl Into<String>,
+    ) -> Result<Self, RelabelError> {
+        self.push(RelabelRule::Redact {
+            target_label: label.into(),
+            replacement: replacement.into(),
+        })
+    }
+
+    /// Rename labels whose name matches `regex` to `replacement`.
+    pub fn label_map(
+        self,
+        regex: &str,
+        replacement: impl Into<String>,
+    ) -> Result<Self, RelabelError> {
+        self.push(RelabelRule::LabelMap {
+            regex: RelabelRegex::new(regex)?,
+            replacement: replacement.into(),
+        })
+    }
+
+    /// Remove labels whose name matches `regex`.
+    pub fn label_drop(self, regex: &str) -> Result<Self, RelabelError> {
+        self.push(RelabelRule::LabelDrop {
+            regex: RelabelRegex::new(regex)?,
+        })
+    }
+
+    /// The rules, in order.
+    pub fn rules(&self) -> &[RelabelRule] {
+        &self.rules
+    }
+
+    /// Whether there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Samples dropped so far because their family was renamed to an
+    /// invalid name or to the name of another family.
+    pub fn rejected_samples(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// The name of family `name` after its rename rules.
+    pub fn family_name(&self, name: &str) -> String {
+        let mut name = name.to_string();
+        for rule in &self.rules {
+            if let RelabelRule::Rename { regex, replacement } = rule {
+                if let Some(renamed) = regex.replace(&name, replacement) {
+                    name = renamed;
+                }
+            }
+        }
+        name
+    }
+
+    /// Apply the rules to one series of family `name` and type `kind`,
+    /// returning its new family name, or `None` if it was dropped.
+    pub fn apply(&self, name: &str, kind: MetricKind, labels: &mut SampleLabels) -> Option<String> {
+        let protected = protected_label(kind);
+        let is_protected = |label: &str| Some(label) == protected;
+        let mut name = name.to_string();
+
+        for rule in &self.rules {
+            match rule {
+                RelabelRule::Rename { regex, replacement } => {
+                    if let Some(renamed) = regex.replace(&name, replacement) {
+                        name = renamed;
+                    }
+                }
+                RelabelRule::Drop {
+                    source_labels,
+                    separator,
+                    regex,
+                } => {
+                    if regex.is_match(&joined(&name, labels, source_labels, separator)) {
+                        return None;
+                    }
+                }
+                RelabelRule::Keep {
+                    source_labels,
+                    separator,
+                    regex,
+                } => {
+                    if !regex.is_match(&joined(&name, labels, source_labels, separator)) {
+                        return None;
+                    }
+                }
+                RelabelRule::Replace {
+                    source_labels,
+                    separator,
+                    regex,
+                    target_label,
+                    replacement,
+                } => {
+                    if is_protected(target_label) {
+                        continue;
+                    }
+                    let source = joined(&name, labels, source_labels, separator);
+                    if let Some(value) = regex.replace(&source, replacement) {
+                        labels.retain(|(label, _)| label != target_label);
+                        if !value.is_empty() {
+                            labels.push((target_label.clone(), value));
+                        }
+                    }
+                }
+                rule => rule.rewrite_labels(labels, &is_protected),
+            }
+        }
+        Some(name)
+    }
+
+    /// Apply the label rules (hash, redact, labelmap, labeldrop) to an
+    /// exemplar's labels, so values hidden from series don't leak through
+    /// exemplars.
+    pub fn apply_to_exemplar(&self, labels: &mut SampleLabels) {
+        for rule in &self.rules {
+            rule.rewrite_labels(labels, &|_| false);
+        }
+    }
+
+    /// Apply the rules to text exposition output.
+    pub(crate) fn apply_to_text(&self, text: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(text.len());
+        let mut writer = RelabelWriter::new(&mut out, self);
+        io::Write::write_all(&mut writer, text).expect("a Vec grows as needed");
+        writer.finish().expect("a Vec grows as needed");
+        out
+    }
+}
+
+/// The label the exposition format reserves for `kind`'s samples.
+fn protected_label(kind: MetricKind) -> Option<&'static str> {
+    match kind {
+        MetricKind::Histogram | MetricKind::GaugeHistogram => Some("le"),
+        MetricKind::Summary => Some("quantile"),
+        _ => None,
+    }
+}
+
+/// Whether `name` matches the metric name grammar `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+fn is_valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// The values of `source_labels` joined with `separator`; missing labels
+/// are empty.
+fn joined(name: &str, labels: &SampleLabels, source_labels: &[String], separator: &str) -> String {
+    source_labels
+        .iter()
+        .map(|source| {
+            if source == NAME_LABEL {
+                return name;
+            }
+            labels
+                .iter()
+                .find(|(label, _)| label == source)
+                .map_or("", |(_, value)| value.as_str())
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// HMAC-SHA256 of `value` keyed by `salt`, as its first 16 hex digits.
+fn keyed_hash(salt: &str, value: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(salt.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(value.as_bytes());
+    mac.finalize().into_bytes()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Applies [`RelabelRules`] to text exposition as it is written.
+///
+/// Descriptor lines get the renamed family name; sample lines get the
+/// relabeled name and labels, and their exemplar the relabeled labels
+/// (see [`RelabelRules::apply_to_exemplar`]), with the value and timestamp
+/// passed through untouched. Sample lines that don't parse are dropped,
+/// since the rules can't be checked against them.
+///
+/// A family renamed to an invalid name, or to a name an earlier family
+/// already has, is dropped along with its samples.
+pub(crate) struct RelabelWriter<'a, W: io::Write + ?Sized> {
+    inner: &'a mut W,
+    rules: &'a RelabelRules,
+    /// Incomplete line carried over from the previous write.
+    line: Vec<u8>,
+    /// Name and type of the family being written.
+    family: String,
+    kind: MetricKind,
+    /// The family each output name was given to.
+    claimed: HashMap<String, String>,
+}
+
+impl<'a, W: io::Write + ?Sized> RelabelWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W, rules: &'a RelabelRules) -> Self {
+        Self {
+            inner,
+            rules,
+            line: Vec::new(),
+            family: String::new(),
+            kind: MetricKind::Unknown,
+            claimed: HashMap::new(),
+        }
+    }
+
+    /// Write out a trailing line without a newline.
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        self.line_done(&line)
+    }
+
+    fn line_done(&mut self, line: &[u8]) -> io::Result<()> {
+        let Ok(text) = std::str::from_utf8(line) else {
+            return Ok(());
+        };
+        match self.relabel_line(text) {
+            Some(Some(relabeled)) => self.inner.write_all(relabeled.as_bytes()),
+            Some(None) => Ok(()),
+            None => self.inner.write_all(line),
+        }
+    }
+
+    /// The relabeled line, `Some(None)` to drop it, or `None` to keep it
+    /// as it is.
+    fn relabel_line(&mut self, line: &str) -> Option<Option<String>> {
+        if let Some(comment) = line.strip_prefix("# ") {
+            let mut parts = comment.splitn(3, ' ');
+            let keyword = parts.next()?;
+            if !matches!(keyword, "HELP" | "TYPE" | "UNIT") {
+                return None;
+            }
+            let name = parts.next()?.trim_end();
+            let rest = parts.next();
+            if name != self.family {
+                self.family = name.to_string();
+                self.kind = MetricKind::Unknown;
+            }
+            if keyword == "TYPE" {
+                self.kind = MetricKind::parse(rest.unwrap_or("").trim());
+            }
+            let renamed = self.rules.family_name(name);
+            if !claim(&mut self.claimed, name, &renamed) {
+                return Some(None);
+            }
+            return Some(Some(match rest {
+                Some(rest) => format!("# {} {} {}", keyword, renamed, rest),
+                None => format!("# {} {}\n", keyword, renamed),
+            }));
+        }
+        if line.starts_with('#') || line.trim().is_empty() {
+            return None;
+        }
+        Some(self.relabel_sample(line))
+    }
+
+    /// The relabeled sample line, or `None` to drop it.
+    fn relabel_sample(&mut self, line: &str) -> Option<String> {
+        let name_end = line.find(['{', ' '])?;
+        let sample = &line[..name_end];
+        let (mut labels, rest) = if line[name_end..].starts_with('{') {
+            parse_labels(&line[name_end..]).ok()?
+        } else {
+            (Vec::new(), &line[name_end..])
+        };
+        let (family, kind, suffix) = match sample.strip_prefix(self.family.as_str()) {
+            Some(suffix) if suffix.is_empty() || self.kind.suffixes().contains(&suffix) => {
+                (self.family.as_str(), self.kind, suffix)
+            }
+            _ => (sample, MetricKind::Unknown, ""),
+        };
+        let renamed = self.rules.apply(family, kind, &mut labels)?;
+        if !claim(&mut self.claimed, family, &renamed) {
+            self.rules.rejected.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let mut out = String::with_capacity(line.len());
+        out.push_str(&renamed);
+        out.push_str(suffix);
+        if !labels.is_empty() {
+            push_labels(&mut out, &labels);
+        }
+        match rest.find(" # {") {
+            Some(start) => {
+                // Skip ` # ` to the exemplar's label set.
+                let (mut exemplar, after) = parse_labels(&rest[start + 3..]).ok()?;
+                self.rules.apply_to_exemplar(&mut exemplar);
+                out.push_str(&rest[..start + 3]);
+                push_labels(&mut out, &exemplar);
+                out.push_str(after);
+            }
+            None => out.push_str(rest),
+        }
+        Some(out)
+    }
+}
+
+/// Whether family `name` may be written as `renamed`: the name is valid
+/// and no other family in `claimed` was written under it.
+fn claim(claimed: &mut HashMap<String, String>, name: &str, renamed: &str) -> bool {
+    if !is_valid_metric_name(renamed) {
+        return false;
+    }
+    match claimed.get(renamed) {
+        Some(owner) => owner == name,
+        None => {
+            claimed.insert(renamed.to_string(), name.to_string());
+            true
+        }
+    }
+}
+
+/// Append `{name="value",...}` to `out`.
+fn push_labels(out: &mut String, labels: &SampleLabels) {
+    out.push('{');
+    for (i, (label, value)) in labels.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(label);
+        out.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    out.push('}');
+}
+
+impl<W: io::Write + ?Sized> io::Write for RelabelWriter<'_, W> {
+    fn write(&mut self, mut data: &[u8]) -> io::Result<usize> {
+        let written = data.len();
+        while let Some(end) = data.iter().position(|&b| b == b'\n') {
+            let (head, rest) = data.split_at(end + 1);
+            if self.line.is_empty() {
+                self.line_done(head)?;
+            } else {
+                let mut line = std::mem::take(&mut self.line);
+                line.extend_from_slice(head);
+                self.line_done(&line)?;
+            }
+            data = rest;
+        }
+        self.line.extend_from_slice(data);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const TEXT: &str = "\
+# HELP legacy_requests Requests served.
+# TYPE legacy_requests counter
+legacy_requests_total{path=\"/\",user_id=\"alice\"} 3 # {trace_id=\"abc\",user_id=\"alice\"} 1.0
+legacy_requests_total{path=\"/healthz\",user_id=\"bob\"} 9
+legacy_requests_total{path=\"/\",user_id=\"carol 4
+legacy_requests_created{path=\"/\",user_id=\"alice\"} 1.7e9
+# HELP rpc_seconds Call latency.
+# TYPE rpc_seconds histogram
+# UNIT rpc_seconds seconds
+rpc_seconds_bucket{k8s_pod=\"api-0\",le=\"0.5\"} 1
+rpc_seconds_bucket{k8s_pod=\"api-0\",le=\"+Inf\"} 2
+rpc_seconds_sum{k8s_pod=\"api-0\"} 0.75
+rpc_seconds_count{k8s_pod=\"api-0\"} 2
+# EOF
+";
+
+    /// Relabel `TEXT`, writing it in pieces of `step` bytes.
+    fn relabeled(rules: &RelabelRules, step: usize) -> String {
+        let mut out = Vec::new();
+        let mut writer = RelabelWriter::new(&mut out, rules);
+        for piece in TEXT.as_bytes().chunks(step) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> SampleLabels {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn text_is_relabeled_as_it_streams() {
+        let rules = RelabelRules::new()
+            .rename("legacy_(.*)", "app_$1")
+            .unwrap()
+            .drop_series(["path"], "/health.*")
+            .unwrap()
+            .redact("user_id")
+            .unwrap()
+            .label_map("k8s_(.+)", "$1")
+            .unwrap();
+        let expected = "\
+# HELP app_requests Requests served.
+# TYPE app_requests counter
+app_requests_total{path=\"/\",user_id=\"[REDACTED]\"} 3 # {trace_id=\"abc\",user_id=\"[REDACTED]\"} 1.0
+app_requests_created{path=\"/\",user_id=\"[REDACTED]\"} 1.7e9
+# HELP rpc_seconds Call latency.
+# TYPE rpc_seconds histogram
+# UNIT rpc_seconds seconds
+rpc_seconds_bucket{pod=\"api-0\",le=\"0.5\"} 1
+rpc_seconds_bucket{pod=\"api-0\",le=\"+Inf\"} 2
+rpc_seconds_sum{pod=\"api-0\"} 0.75
+rpc_seconds_count{pod=\"api-0\"} 2
+# EOF
+";
+        for step in [1, 7, TEXT.len()] {
+            assert_eq!(relabeled(&rules, step), expected);
+        }
+        // Without rules only the sample that doesn't parse is left out.
+        assert_eq!(
+            relabeled(&RelabelRules::new(), 5),
+            TEXT.replace("legacy_requests_total{path=\"/\",user_id=\"carol 4\n", "")
+        );
+        assert_eq!(
+            String::from_utf8(rules.apply_to_text(TEXT.as_bytes())).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn later_rules_see_the_output_of_earlier_ones() {
+        let series = || labels(&[("user_id", "alice"), ("path", "/")]);
+
+        // Dropping on the raw value matches; after redaction it no longer can.
+        let drop_then_redact = RelabelRules::new()
+            .drop_series(["user_id"], "alice")
+            .unwrap()
+            .redact("user_id")
+            .unwrap();
+        assert_eq!(
+            drop_then_redact.apply("requests", MetricKind::Counter, &mut series()),
+            None
+        );
+        let redact_then_drop = RelabelRules::new()
+            .redact("user_id")
+            .unwrap()
+            .drop_series(["user_id"], "alice")
+            .unwrap();
+        let mut kept = series();
+        assert_eq!(
+            redact_then_drop.apply("requests", MetricKind::Counter, &mut kept),
+            Some("requests".to_string())
+        );
+        assert_eq!(kept, labels(&[("user_id", "[REDACTED]"), ("path", "/")]));
+
+        // A label renamed by labelmap is only visible under its new name.
+        let map_then_drop = RelabelRules::new()
+            .label_map("user_(.*)", "account_$1")
+            .unwrap()
+            .label_drop("user_id")
+            .unwrap();
+        let mut mapped = series();
+        map_then_drop.apply("requests", MetricKind::Counter, &mut mapped);
+        assert_eq!(mapped, labels(&[("account_id", "alice"), ("path", "/")]));
+        let drop_then_map = RelabelRules::new()
+            .label_drop("user_id")
+            .unwrap()
+            .label_map("user_(.*)", "account_$1")
+            .unwrap();
+        let mut dropped = series();
+        drop_then_map.apply("requests", MetricKind::Counter, &mut dropped);
+        assert_eq!(dropped, labels(&[("path", "/")]));
+
+        // `__name__` is the name as of the rule that reads it.
+        let rename_then_keep = RelabelRules::new()
+            .rename("legacy_(.*)", "app_$1")
+            .unwrap()
+            .keep_series([NAME_LABEL], "app_.*")
+            .unwrap();
+        assert_eq!(
+            rename_then_keep.apply("legacy_jobs", MetricKind::Counter, &mut series()),
+            Some("app_jobs".to_string())
+        );
+        let keep_then_rename = RelabelRules::new()
+            .keep_series([NAME_LABEL], "app_.*")
+            .unwrap()
+            .rename("legacy_(.*)", "app_$1")
+            .unwrap();
+        assert_eq!(
+            keep_then_rename.apply("legacy_jobs", MetricKind::Counter, &mut series()),
+            None
+        );
+
+        // Hashing first means the replacement copies the hash, not the value.
+        let hash_then_copy = RelabelRules::new().hash("user_id").unwrap().replace(
+            ["user_id"],
+            "(.*)",
+            "account",
+            "$1",
+        );
+        let mut hashed = series();
+        hash_then_copy
+            .unwrap()
+            .apply("requests", MetricKind::Counter, &mut hashed);
+        assert_eq!(hashed[0].1, hashed[2].1);
+        assert_ne!(hashed[0].1, "alice");
+    }
+
+    #[test]
+    fn replace_joins_sources_and_removes_empty_results() {
+        let rules = RelabelRules::new()
+            .replace(["method", "path"], "(GET|POST);/api/(.*)", "route", "$1 $2")
+            .unwrap()
+            .replace(["path"], "/internal/.*", "path", "")
+            .unwrap();
+
+        let mut api = labels(&[("method", "GET"), ("path", "/api/users")]);
+        rules.apply("requests", MetricKind::Counter, &mut api);
+        assert_eq!(
+            api,
+            labels(&[
+                ("method", "GET"),
+                ("path", "/api/users"),
+                ("route", "GET users")
+            ])
+        );
+
+        let mut internal = labels(&[("method", "PUT"), ("path", "/internal/x")]);
+        rules.apply("requests", MetricKind::Counter, &mut internal);
+        assert_eq!(internal, labels(&[("method", "PUT")]));
+
+        assert!(matches!(
+            RelabelRules::new().replace(["path"], ".*", NAME_LABEL, "x"),
+            Err(RelabelError::InvalidRule(_))
+        ));
+        assert!(matches!(
+            RelabelRules::new().redact(NAME_LABEL),
+            Err(RelabelError::InvalidRule(_))
+        ));
+        assert!(matches!(
+            RelabelRules::new().hash_with_salt(NAME_LABEL, "pepper"),
+            Err(RelabelError::InvalidRule(_))
+        ));
+        assert!(matches!(
+            RelabelRules::new().rename("(unclosed", "x"),
+            Err(RelabelError::InvalidRegex { .. })
+        ));
+    }
+
+    #[test]
+    fn renames_to_invalid_or_taken_names_are_dropped() {
+        assert!(matches!(
+            RelabelRules::new().rename("lat", "lat-ency!"),
+            Err(RelabelError::InvalidRule(_))
+        ));
+
+        // The first family keeps a contested name; the second is dropped.
+        let rules = RelabelRules::new()
+            .rename("legacy_requests", "rpc_seconds")
+            .unwrap();
+        let expected = "\
+# HELP rpc_seconds Requests served.
+# TYPE rpc_seconds counter
+rpc_seconds_total{path=\"/\",user_id=\"alice\"} 3 # {trace_id=\"abc\",user_id=\"alice\"} 1.0
+rpc_seconds_total{path=\"/healthz\",user_id=\"bob\"} 9
+rpc_seconds_created{path=\"/\",user_id=\"alice\"} 1.7e9
+# EOF
+";
+        assert_eq!(relabeled(&rules, 64), expected);
+        assert_eq!(rules.rejected_samples(), 4);
+
+        let rules = RelabelRules::new().rename("legacy_(.*)", "$1-x").unwrap();
+        let text = relabeled(&rules, 64);
+        assert!(!text.contains("requests"), "{}", text);
+        assert!(text.contains("rpc_seconds_count{k8s_pod=\"api-0\"} 2"));
+        assert_eq!(rules.rejected_samples(), 3);
+    }
+
+    #[test]
+    fn hashes_are_stable_and_salted() {
+        let hash = |rules: &RelabelRules| {
+            let mut series = labels(&[("user_id", "alice")]);
+            rules.apply("requests", MetricKind::Counter, &mut series);
+            series.remove(0).1
+        };
+        let unsalted = hash(&RelabelRules::new().hash("user_id").unwrap());
+        assert_eq!(unsalted.len(), 16);
+        assert_eq!(
+            unsalted,
+            hash(&RelabelRules::new().hash("user_id").unwrap())
+        );
+        let salted = hash(
+            &RelabelRules::new()
+                .hash_with_salt("user_id", "pepper")
+                .unwrap(),
+        );
+        assert_ne!(unsalted, salted);
+        // HMAC-SHA256("pepper", "alice"), truncated.
+        assert_eq!(salted, "f2f95d059a71b4aa");
+    }
+
+    #[test]
+    fn bucket_and_quantile_labels_are_protected() {
+        let rules = RelabelRules::new()
+            .label_drop("le|quantile")
+            .unwrap()
+            .redact("le")
+            .unwrap()
+            .redact("quantile")
+            .unwrap();
+
+        let mut bucket = labels(&[("le", "0.5")]);
+        rules.apply("rpc_seconds", MetricKind::Histogram, &mut bucket);
+        assert_eq!(bucket, labels(&[("le", "0.5")]));
+
+        let mut quantile = labels(&[("quantile", "0.99")]);
+        rules.apply("rpc_seconds", MetricKind::Summary, &mut quantile);
+        assert_eq!(quantile, labels(&[("quantile", "0.99")]));
+
+        let mut gauge = labels(&[("le", "x")]);
+        rules.apply("level", MetricKind::Gauge, &mut gauge);
+        assert!(gauge.is_empty());
+    }
+
+    #[test]
+    fn labels_are_not_mapped_onto_protected_labels() {
+        let rules = RelabelRules::new().label_map("bucket_(le)", "$1").unwrap();
+
+        let mut bucket = labels(&[("bucket_le", "x"), ("le", "0.5")]);
+        rules.apply("rpc_seconds", MetricKind::Histogram, &mut bucket);
+        assert_eq!(bucket, labels(&[("bucket_le", "x"), ("le", "0.5")]));
+
+        let mut gauge = labels(&[("bucket_le", "x")]);
+        rules.apply("level", MetricKind::Gauge, &mut gauge);
+        assert_eq!(gauge, labels(&[("le", "x")]));
+    }
+
+    #[cfg(feature = "yaml-config")]
+    #[test]
+    fn rules_deserialize_in_order() {
+        let yaml = r#"
+- action: labelmap
+  regex: "k8s_(.+)"
+  replacement: "$1"
+- action: drop
+  source_labels: [pod, path]
+  separator: "@"
+  regex: "api-0@/"
+- action: hash
+  target_label: user_id
+  salt: pepper
+- action: redact
+  target_label: email
+"#;
+        let rules: RelabelRules = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            rules,
+            RelabelRules::new()
+                .label_map("k8s_(.+)", "$1")
+                .unwrap()
+                .push(RelabelRule::Drop {
+                    source_labels: vec!["pod".into(), "path".into()],
+                    separator: "@".into(),
+                    regex: RelabelRegex::new("api-0@/").unwrap(),
+                })
+                .unwrap()
+                .hash_with_salt("user_id", "pepper")
+                .unwrap()
+                .redact("email")
+                .unwrap()
+        );
+
+        for invalid in [
+            "- action: drop\n  source_labels: [a]\n  regex: \"(\"",
+            "- action: redact\n  target_label: __name__",
+            "- action: explode\n  target_label: a",
+            "- action: hash\n  target_label: a\n  typo: 1",
+        ] {
+            assert!(serde_yaml::from_str::<RelabelRules>(invalid).is_err());
+        }
+    }
+
+    #[cfg(feature = "json-config")]
+    #[test]
+    fn rules_deserialize_from_json() {
+        let json = r#"[
+            {"action": "rename", "regex": "old_(.*)", "replacement": "new_$1"},
+            {"action": "keep", "source_labels": ["env"], "regex": "prod"},
+            {"action": "labeldrop", "regex": "tmp_.*"}
+        ]"#;
+        let rules: RelabelRules = serde_json::from_str(json).unwrap();
+        assert_eq!(rules.rules().len(), 3);
+        assert!(matches!(
+            &rules.rules()[1],
+            RelabelRule::Keep { separator, .. } if separator == DEFAULT_SEPARATOR
+        ));
+        assert_eq!(rules.family_name("old_jobs"), "new_jobs");
+    }
+}
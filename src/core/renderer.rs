@@ -1,5 +1,14 @@
 //! Metrics rendering for different output formats.
 
+use std::io;
+
+#[cfg(feature = "filter")]
+use super::filter::MetricFilter;
+
+/// Content type of OpenMetrics text, as rendered by the Prometheus backend.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
 /// Trait for registries that can render their metrics.
 pub trait MetricsRenderer {
     /// Error type for rendering failures.
@@ -7,6 +16,54 @@ pub trait MetricsRenderer {
 
     /// Render metrics in the appropriate format (Prometheus text, JSON, etc.)
     fn render(&self) -> Result<RenderedMetrics, Self::Error>;
+
+    /// The content type of the output.
+    ///
+    /// The default renders once to find out; renderers with a fixed
+    /// content type override it.
+    fn content_type(&self) -> Result<String, Self::Error> {
+        Ok(self.render()?.content_type)
+    }
+
+    /// Write the rendered metrics to `writer` as they are encoded.
+    ///
+    /// The writer sees many small writes, so wrap files and sockets in a
+    /// [`BufWriter`](std::io::BufWriter). The default renders into memory
+    /// first; renderers that can encode incrementally override it so the
+    /// whole output is never held at once.
+    fn render_to<W: io::Write + ?Sized>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), RenderToError<Self::Error>> {
+        let rendered = self.render().map_err(RenderToError::Render)?;
+        writer.write_all(&rendered.body).map_err(RenderToError::Io)
+    }
+
+    /// Write only the families `filter` selects to `writer`.
+    ///
+    /// The default ignores the filter and writes everything; renderers
+    /// that can skip families before encoding them override it.
+    #[cfg(feature = "filter")]
+    fn render_filtered_to<W: io::Write + ?Sized>(
+        &self,
+        filter: &MetricFilter,
+        writer: &mut W,
+    ) -> Result<(), RenderToError<Self::Error>> {
+        let _ = filter;
+        self.render_to(writer)
+    }
+}
+
+/// Error from [`MetricsRenderer::render_to`].
+#[derive(Debug, thiserror::Error)]
+pub enum RenderToError<E> {
+    /// The metrics could not be encoded.
+    #[error("failed to render metrics: {0}")]
+    Render(E),
+
+    /// The writer failed.
+    #[error("failed to write metrics: {0}")]
+    Io(#[source] io::Error),
 }
 
 /// Wrapper for rendered metrics with content type.
@@ -14,7 +71,7 @@ pub trait MetricsRenderer {
 /// This struct holds the serialized metrics output along with
 /// the appropriate HTTP Content-Type header.
 pub struct RenderedMetrics {
-    /// The MIME content type (e.g., "text/plain; version=0.0.4")
+    /// The MIME content type (e.g., "application/openmetrics-text; version=1.0.0")
     pub content_type: String,
     /// The raw bytes of the rendered output
     pub body: Vec<u8>,
@@ -57,9 +114,112 @@ impl MetricsRenderer for prometheus_client::registry::Registry {
         let mut buffer = String::new();
         prometheus_client::encoding::text::encode(&mut buffer, self)?;
 
+        // The encoder writes OpenMetrics (`# EOF`, exemplars), which the
+        // classic 0.0.4 text parser rejects once exemplars are present.
         Ok(RenderedMetrics::new(
-            "text/plain; version=0.0.4; charset=utf-8",
+            OPENMETRICS_CONTENT_TYPE,
             buffer.into_bytes(),
         ))
     }
+
+    fn content_type(&self) -> Result<String, Self::Error> {
+        Ok(OPENMETRICS_CONTENT_TYPE.to_string())
+    }
+
+    fn render_to<W: io::Write + ?Sized>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), RenderToError<Self::Error>> {
+        let mut adapter = FmtWriter {
+            inner: writer,
+            error: None,
+        };
+        prometheus_client::encoding::text::encode(&mut adapter, self).map_err(|e| {
+            match adapter.error.take() {
+                Some(io_error) => RenderToError::Io(io_error),
+                None => RenderToError::Render(e),
+            }
+        })
+    }
+
+    #[cfg(feature = "filter")]
+    fn render_filtered_to<W: io::Write + ?Sized>(
+        &self,
+        filter: &MetricFilter,
+        writer: &mut W,
+    ) -> Result<(), RenderToError<Self::Error>> {
+        crate::backends::prometheus::prometheus_backend::with_filter(filter, || {
+            self.render_to(writer)
+        })
+    }
+}
+
+/// Passes the text encoder's output straight to an `io::Write`, keeping
+/// the I/O error that `fmt::Error` can't carry.
+#[cfg(feature = "prometheus")]
+struct FmtWriter<'a, W: io::Write + ?Sized> {
+    inner: &'a mut W,
+    error: Option<io::Error>,
+}
+
+#[cfg(feature = "prometheus")]
+impl<W: io::Write + ?Sized> std::fmt::Write for FmtWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            std::fmt::Error
+        })
+    }
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+    use super::*;
+    use prometheus_client::metrics::counter::Counter;
+    use prometheus_client::registry::Registry;
+
+    fn registry() -> Registry {
+        let mut registry = Registry::default();
+        for i in 0..50 {
+            let counter = Counter::<u64>::default();
+            counter.inc_by(i);
+            registry.register(format!("jobs_{}", i), "Jobs processed", counter);
+        }
+        registry
+    }
+
+    #[test]
+    fn render_to_writes_what_render_returns() {
+        let registry = registry();
+        let mut streamed = Vec::new();
+        registry.render_to(&mut streamed).unwrap();
+
+        assert_eq!(streamed, registry.render().unwrap().into_bytes());
+        assert_eq!(
+            registry.content_type().unwrap(),
+            registry.render().unwrap().content_type
+        );
+    }
+
+    #[test]
+    fn render_to_stops_at_the_first_write_error() {
+        struct Full(usize);
+        impl io::Write for Full {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.0 + buf.len() > 100 {
+                    return Err(io::Error::new(io::ErrorKind::StorageFull, "full"));
+                }
+                self.0 += buf.len();
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let error = registry().render_to(&mut Full(0)).unwrap_err();
+        assert!(
+            matches!(error, RenderToError::Io(ref e) if e.kind() == io::ErrorKind::StorageFull)
+        );
+    }
 }
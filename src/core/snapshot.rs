@@ -0,0 +1,724 @@
+//! Point-in-time copies of a registry's metrics.
+//!
+//! Push exporters and alternative output formats need the metric values as
+//! data rather than as exposition text. A [`Snapshot`] is built by parsing a
+//! registry's rendered output (OpenMetrics or Prometheus text), so it works
+//! for any backend that renders one of those formats.
+
+use std::fmt::{self, Write};
+use std::io;
+use std::time::SystemTime;
+
+use super::renderer::RenderToError;
+
+/// Content type of [`Snapshot::to_prometheus_text`].
+pub const PROMETHEUS_TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Error building a [`Snapshot`].
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    /// The registry could not be rendered.
+    #[error("failed to render registry: {0}")]
+    Render(String),
+
+    /// The rendered output is not valid exposition text.
+    #[error("invalid exposition text at line {line}: {message}")]
+    Parse {
+        /// 1-based line number.
+        line: usize,
+        /// What was wrong with the line.
+        message: String,
+    },
+}
+
+/// The type of a metric family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    /// Monotonic counter (`_total`, `_created` samples).
+    Counter,
+    /// Value that can go up and down.
+    Gauge,
+    /// Bucketed distribution (`_bucket`, `_sum`, `_count` samples).
+    Histogram,
+    /// Histogram of gauge values (`_gsum`, `_gcount` samples).
+    GaugeHistogram,
+    /// Quantile summary.
+    Summary,
+    /// Static key/value metadata (`_info` samples).
+    Info,
+    /// Set of boolean states.
+    StateSet,
+    /// Type not declared.
+    Unknown,
+}
+
+impl MetricKind {
+    /// The name used in `# TYPE` lines.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Histogram => "histogram",
+            Self::GaugeHistogram => "gaugehistogram",
+            Self::Summary => "summary",
+            Self::Info => "info",
+            Self::StateSet => "stateset",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    pub(crate) fn parse(name: &str) -> Self {
+        match name {
+            "counter" => Self::Counter,
+            "gauge" => Self::Gauge,
+            "histogram" => Self::Histogram,
+            "gaugehistogram" => Self::GaugeHistogram,
+            "summary" => Self::Summary,
+            "info" => Self::Info,
+            "stateset" => Self::StateSet,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Suffixes this kind adds to sample names.
+    pub(crate) fn suffixes(&self) -> &'static [&'static str] {
+        match self {
+            Self::Counter => &["_total", "_created"],
+            Self::Histogram => &["_bucket", "_sum", "_count", "_created"],
+            Self::GaugeHistogram => &["_bucket", "_gsum", "_gcount"],
+            Self::Summary => &["_sum", "_count", "_created"],
+            Self::Info => &["_info"],
+            _ => &[],
+        }
+    }
+
+    /// Whether `label` is the bucket bound or quantile of a sample of this
+    /// kind, rather than part of its series identity.
+    #[cfg_attr(not(any(feature = "json", feature = "protobuf")), allow(dead_code))]
+    pub(crate) fn is_bound(&self, label: &str) -> bool {
+        match self {
+            Self::Histogram | Self::GaugeHistogram => label == "le",
+            Self::Summary => label == "quantile",
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Label pairs in exposition order, unescaped.
+pub type SampleLabels = Vec<(String, String)>;
+
+/// An exemplar attached to a sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    /// Exemplar labels, unescaped.
+    pub labels: SampleLabels,
+    /// Observed value.
+    pub value: f64,
+    /// Seconds since the Unix epoch, if recorded.
+    pub timestamp: Option<f64>,
+}
+
+/// A single sample line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// Full sample name, including suffixes such as `_total` or `_bucket`.
+    pub name: String,
+    /// Labels in exposition order, unescaped.
+    pub labels: SampleLabels,
+    /// Sample value.
+    pub value: f64,
+    /// Explicit timestamp, if the exposition carried one.
+    pub timestamp: Option<f64>,
+    /// Exemplar, if any.
+    pub exemplar: Option<Exemplar>,
+}
+
+impl Sample {
+    /// The value of label `name`.
+    pub fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(label, _)| label == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A metric family: metadata plus its samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricFamily {
+    /// Family name, without sample suffixes.
+    pub name: String,
+    /// Help text, if declared.
+    pub help: Option<String>,
+    /// Unit, if declared.
+    pub unit: Option<String>,
+    /// Family type.
+    pub kind: MetricKind,
+    /// Samples in exposition order.
+    pub samples: Vec<Sample>,
+}
+
+impl MetricFamily {
+    fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            help: None,
+            unit: None,
+            kind: MetricKind::Unknown,
+            samples: Vec::new(),
+        }
+    }
+
+    fn owns(&self, sample: &str) -> bool {
+        sample == self.name
+            || sample
+                .strip_prefix(self.name.as_str())
+                .is_some_and(|suffix| self.kind.suffixes().contains(&suffix))
+    }
+}
+
+/// Parses exposition text a line at a time, completing each family when
+/// the next one starts.
+#[derive(Default)]
+struct FamilyParser {
+    current: Option<MetricFamily>,
+    /// Lines seen so far, for error messages.
+    lines: usize,
+    /// Whether `# EOF` was seen.
+    done: bool,
+}
+
+impl FamilyParser {
+    /// Parse `line`, returning the family it completes, if any.
+    fn line(&mut self, line: &str) -> Result<Option<MetricFamily>, SnapshotError> {
+        self.lines += 1;
+        let line = line.trim_end();
+        if self.done || line.is_empty() {
+            return Ok(None);
+        }
+        if line == "# EOF" {
+            self.done = true;
+            return Ok(None);
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut parts = comment.trim_start().splitn(3, ' ');
+            let (Some(keyword), Some(name)) = (parts.next(), parts.next()) else {
+                return Ok(None);
+            };
+            let rest = parts.next().unwrap_or("");
+            if !matches!(keyword, "HELP" | "TYPE" | "UNIT") {
+                return Ok(None);
+            }
+            let completed = self.start_unless(|family| family.name == name, name);
+            let family = self.current.as_mut().expect("just started");
+            match keyword {
+                "HELP" => family.help = Some(unescape(rest, false)),
+                "TYPE" => family.kind = MetricKind::parse(rest.trim()),
+                _ => family.unit = Some(rest.trim().to_string()).filter(|u| !u.is_empty()),
+            }
+            return Ok(completed);
+        }
+
+        let sample = parse_sample(line).map_err(|message| SnapshotError::Parse {
+            line: self.lines,
+            message: message.to_string(),
+        })?;
+        let completed = self.start_unless(|family| family.owns(&sample.name), &sample.name);
+        self.current
+            .as_mut()
+            .expect("just started")
+            .samples
+            .push(sample);
+        Ok(completed)
+    }
+
+    /// Start a family called `name` unless the current one is `same`,
+    /// returning the one it replaces.
+    fn start_unless(
+        &mut self,
+        same: impl FnOnce(&MetricFamily) -> bool,
+        name: &str,
+    ) -> Option<MetricFamily> {
+        if self.current.as_ref().is_some_and(same) {
+            return None;
+        }
+        self.current.replace(MetricFamily::new(name))
+    }
+
+    /// The last family, once there are no more lines.
+    fn finish(self) -> Option<MetricFamily> {
+        self.current
+    }
+}
+
+/// Parses exposition text as it is written and hands each family to `emit`
+/// once it is complete, so only one family is held at a time.
+pub(crate) struct FamilyStream<F> {
+    parser: FamilyParser,
+    /// The current line, up to the last write.
+    partial: Vec<u8>,
+    emit: F,
+    /// Why the text stopped parsing; writes fail after this is set.
+    parse_error: Option<SnapshotError>,
+}
+
+impl<F: FnMut(MetricFamily) -> io::Result<()>> FamilyStream<F> {
+    pub(crate) fn new(emit: F) -> Self {
+        Self {
+            parser: FamilyParser::default(),
+            partial: Vec::new(),
+            emit,
+            parse_error: None,
+        }
+    }
+
+    fn parse_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let parsed = std::str::from_utf8(line)
+            .map_err(|e| SnapshotError::Parse {
+                line: self.parser.lines + 1,
+                message: e.to_string(),
+            })
+            .and_then(|line| self.parser.line(line));
+        match parsed {
+            Ok(Some(family)) => (self.emit)(family),
+            Ok(None) => Ok(()),
+            Err(e) => {
+                let error = io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+                self.parse_error = Some(e);
+                Err(error)
+            }
+        }
+    }
+
+    /// End the stream after the render that wrote to it returned
+    /// `rendered`, emitting the last family.
+    ///
+    /// Failures of `emit` are returned as [`RenderToError::Io`]; render
+    /// and parse failures as [`RenderToError::Render`].
+    pub(crate) fn finish<E: fmt::Display>(
+        mut self,
+        rendered: Result<(), RenderToError<E>>,
+    ) -> Result<(), RenderToError<SnapshotError>> {
+        let ended = match rendered {
+            Ok(()) => {
+                let partial = std::mem::take(&mut self.partial);
+                self.parse_line(&partial)
+            }
+            Err(RenderToError::Render(e)) => {
+                return Err(RenderToError::Render(SnapshotError::Render(e.to_string())))
+            }
+            Err(RenderToError::Io(e)) => Err(e),
+        };
+        if let Err(e) = ended {
+            return Err(match self.parse_error {
+                Some(parse_error) => RenderToError::Render(parse_error),
+                None => RenderToError::Io(e),
+            });
+        }
+        match self.parser.finish() {
+            Some(family) => (self.emit)(family).map_err(RenderToError::Io),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<F: FnMut(MetricFamily) -> io::Result<()>> io::Write for FamilyStream<F> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.parse_error.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid exposition text",
+            ));
+        }
+        let mut rest = data;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            self.partial.extend_from_slice(&rest[..end]);
+            rest = &rest[end + 1..];
+            let line = std::mem::take(&mut self.partial);
+            self.parse_line(&line)?;
+            // Reuse the line's allocation for the next one.
+            self.partial = line;
+            self.partial.clear();
+        }
+        self.partial.extend_from_slice(rest);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// All metric families of a registry at one point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// When the snapshot was taken.
+    pub timestamp: SystemTime,
+    /// Families in exposition order.
+    pub families: Vec<MetricFamily>,
+}
+
+impl Snapshot {
+    /// Parse OpenMetrics or Prometheus text exposition, stamped with the
+    /// current time.
+    ///
+    /// # Example
+    /// ```
+    /// use observe_rs::core::snapshot::Snapshot;
+    ///
+    /// let snapshot = Snapshot::parse("# TYPE jobs counter\njobs_total{queue=\"a\"} 3\n# EOF\n").unwrap();
+    /// let jobs = snapshot.family("jobs").unwrap();
+    /// assert_eq!(jobs.samples[0].label("queue"), Some("a"));
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, SnapshotError> {
+        let mut parser = FamilyParser::default();
+        let mut families = Vec::new();
+        for line in text.lines() {
+            families.extend(parser.line(line)?);
+        }
+        families.extend(parser.finish());
+
+        Ok(Self {
+            timestamp: SystemTime::now(),
+            families,
+        })
+    }
+
+    /// The family called `name`.
+    pub fn family(&self, name: &str) -> Option<&MetricFamily> {
+        self.families.iter().find(|family| family.name == name)
+    }
+
+    /// Total number of samples across all families.
+    pub fn sample_count(&self) -> usize {
+        self.families
+            .iter()
+            .map(|family| family.samples.len())
+            .sum()
+    }
+
+    /// Iterate over every sample with its family.
+    pub fn samples(&self) -> impl Iterator<Item = (&MetricFamily, &Sample)> {
+        self.families
+            .iter()
+            .flat_map(|family| family.samples.iter().map(move |sample| (family, sample)))
+    }
+
+    /// Encode in the Prometheus text exposition format, version 0.0.4.
+    ///
+    /// This is the format the Pushgateway and node_exporter's textfile
+    /// collector accept. Compared with OpenMetrics, counter families are
+    /// named after their `_total` samples, info and stateset families become
+    /// gauges, `_created` samples, units and exemplars are dropped, and
+    /// timestamps are in milliseconds.
+    pub fn to_prometheus_text(&self) -> String {
+        let mut out = String::new();
+        for family in &self.families {
+            let (name, kind) = match family.kind {
+                MetricKind::Counter if !family.name.ends_with("_total") => {
+                    (format!("{}_total", family.name), "counter")
+                }
+                MetricKind::Counter => (family.name.clone(), "counter"),
+                MetricKind::Gauge | MetricKind::StateSet => (family.name.clone(), "gauge"),
+                MetricKind::Info => (format!("{}_info", family.name), "gauge"),
+                MetricKind::Histogram => (family.name.clone(), "histogram"),
+                MetricKind::Summary => (family.name.clone(), "summary"),
+                MetricKind::GaugeHistogram | MetricKind::Unknown => {
+                    (family.name.clone(), "untyped")
+                }
+            };
+            if let Some(help) = &family.help {
+                let help = help.replace('\\', "\\\\").replace('\n', "\\n");
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+            }
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+
+            for sample in &family.samples {
+                if sample.name.ends_with("_created") && family.kind != MetricKind::Unknown {
+                    continue;
+                }
+                out.push_str(&sample.name);
+                if !sample.labels.is_empty() {
+                    out.push('{');
+                    for (i, (label, value)) in sample.labels.iter().enumerate() {
+                        if i > 0 {
+                            out.push(',');
+                        }
+                        let value = value
+                            .replace('\\', "\\\\")
+                            .replace('"', "\\\"")
+                            .replace('\n', "\\n");
+                        let _ = write!(out, "{}=\"{}\"", label, value);
+                    }
+                    out.push('}');
+                }
+                out.push(' ');
+                out.push_str(&format_value(sample.value));
+                if let Some(timestamp) = sample.timestamp {
+                    let _ = write!(out, " {}", (timestamp * 1000.0).round() as i64);
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Parse `name{labels} value [timestamp] [# {labels} value [timestamp]]`.
+fn parse_sample(line: &str) -> Result<Sample, &'static str> {
+    let name_end = line.find(['{', ' ']).ok_or("missing sample value")?;
+    let name = &line[..name_end];
+    if name.is_empty() {
+        return Err("missing metric name");
+    }
+    let mut rest = &line[name_end..];
+    let labels = if rest.starts_with('{') {
+        let (labels, after) = parse_labels(rest)?;
+        rest = after;
+        labels
+    } else {
+        Vec::new()
+    };
+
+    let (sample_part, exemplar_part) = match rest.find(" # ") {
+        Some(at) => (&rest[..at], Some(&rest[at + 3..])),
+        None => (rest, None),
+    };
+    let mut tokens = sample_part.split_whitespace();
+    let value = parse_float(tokens.next().ok_or("missing sample value")?)?;
+    let timestamp = tokens.next().map(parse_float).transpose()?;
+    if tokens.next().is_some() {
+        return Err("unexpected trailing data");
+    }
+
+    let exemplar = exemplar_part
+        .map(|part| -> Result<Exemplar, &'static str> {
+            let part = part.trim_start();
+            if !part.starts_with('{') {
+                return Err("exemplar must start with a label set");
+            }
+            let (labels, after) = parse_labels(part)?;
+            let mut tokens = after.split_whitespace();
+            let value = parse_float(tokens.next().ok_or("missing exemplar value")?)?;
+            let timestamp = tokens.next().map(parse_float).transpose()?;
+            Ok(Exemplar {
+                labels,
+                value,
+                timestamp,
+            })
+        })
+        .transpose()?;
+
+    Ok(Sample {
+        name: name.to_string(),
+        labels,
+        value,
+        timestamp,
+        exemplar,
+    })
+}
+
+/// Parse a `{name="value",...}` label set, returning the labels and the
+/// remaining input.
+pub(crate) fn parse_labels(input: &str) -> Result<(SampleLabels, &str), &'static str> {
+    let mut labels = Vec::new();
+    let mut rest = input.strip_prefix('{').ok_or("expected '{'")?;
+    loop {
+        rest = rest.trim_start_matches([' ', ',']);
+        if let Some(after) = rest.strip_prefix('}') {
+            return Ok((labels, after));
+        }
+        let eq = rest.find('=').ok_or("expected '=' in label set")?;
+        let name = rest[..eq].trim();
+        rest = rest[eq + 1..]
+            .strip_prefix('"')
+            .ok_or("label value must be quoted")?;
+
+        let mut end = None;
+        let mut escaped = false;
+        for (i, c) in rest.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    end = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = end.ok_or("unterminated label value")?;
+        labels.push((name.to_string(), unescape(&rest[..end], true)));
+        rest = &rest[end + 1..];
+    }
+}
+
+fn parse_float(token: &str) -> Result<f64, &'static str> {
+    token.parse().map_err(|_| "invalid number")
+}
+
+/// Undo exposition escaping (`\\`, `\n`, and `\"` inside label values).
+fn unescape(value: &str, quotes: bool) -> String {
+    if !value.contains('\\') {
+        return value.to_string();
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some('"') if quotes => out.push('"'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"# HELP http_requests Requests served.
+# TYPE http_requests counter
+http_requests_total{method="GET",path="/a \"b\"\\c"} 7
+# HELP latency_seconds Latency.
+# TYPE latency_seconds histogram
+# UNIT latency_seconds seconds
+latency_seconds_sum 1.5
+latency_seconds_count 3
+latency_seconds_bucket{le="0.5"} 2 # {trace_id="abc"} 0.2 1700000000.5
+latency_seconds_bucket{le="+Inf"} 3
+untyped_thing 4 1700000000
+# EOF
+"#;
+
+    #[test]
+    fn families_samples_and_metadata_are_parsed() {
+        let snapshot = Snapshot::parse(TEXT).unwrap();
+        assert_eq!(snapshot.families.len(), 3);
+        assert_eq!(snapshot.sample_count(), 6);
+
+        let requests = snapshot.family("http_requests").unwrap();
+        assert_eq!(requests.kind, MetricKind::Counter);
+        assert_eq!(requests.help.as_deref(), Some("Requests served."));
+        assert_eq!(requests.samples[0].name, "http_requests_total");
+        assert_eq!(requests.samples[0].label("path"), Some("/a \"b\"\\c"));
+        assert_eq!(requests.samples[0].value, 7.0);
+
+        let latency = snapshot.family("latency_seconds").unwrap();
+        assert_eq!(latency.kind, MetricKind::Histogram);
+        assert_eq!(latency.unit.as_deref(), Some("seconds"));
+        assert_eq!(latency.samples[3].value, 3.0);
+        assert_eq!(latency.samples[3].label("le"), Some("+Inf"));
+    }
+
+    #[test]
+    fn exemplars_and_timestamps_are_parsed() {
+        let snapshot = Snapshot::parse(TEXT).unwrap();
+        let bucket = &snapshot.family("latency_seconds").unwrap().samples[2];
+        let exemplar = bucket.exemplar.as_ref().unwrap();
+        assert_eq!(exemplar.labels, vec![("trace_id".into(), "abc".into())]);
+        assert_eq!(exemplar.value, 0.2);
+        assert_eq!(exemplar.timestamp, Some(1700000000.5));
+
+        let untyped = snapshot.family("untyped_thing").unwrap();
+        assert_eq!(untyped.kind, MetricKind::Unknown);
+        assert_eq!(untyped.samples[0].timestamp, Some(1700000000.0));
+    }
+
+    #[test]
+    fn special_values_are_parsed() {
+        let snapshot = Snapshot::parse("a +Inf\nb -Inf\nc NaN\n").unwrap();
+        assert_eq!(snapshot.families[0].samples[0].value, f64::INFINITY);
+        assert_eq!(snapshot.families[1].samples[0].value, f64::NEG_INFINITY);
+        assert!(snapshot.families[2].samples[0].value.is_nan());
+    }
+
+    #[test]
+    fn prometheus_text_drops_openmetrics_only_syntax() {
+        let text = "# HELP jobs Jobs\\n done.\n# TYPE jobs counter\njobs_total{q=\"a\\\"b\"} 3\njobs_created 1700000000.0\n\
+                    # TYPE build info\nbuild_info{version=\"1\"} 1\n";
+        let snapshot = Snapshot::parse(&format!("{}{}", text, TEXT)).unwrap();
+        let encoded = snapshot.to_prometheus_text();
+
+        assert!(encoded.starts_with(
+            "# HELP jobs_total Jobs\\n done.\n# TYPE jobs_total counter\njobs_total{q=\"a\\\"b\"} 3\n"
+        ));
+        assert!(!encoded.contains("_created"));
+        assert!(encoded.contains("# TYPE build_info gauge\nbuild_info{version=\"1\"} 1\n"));
+        assert!(encoded.contains("latency_seconds_bucket{le=\"0.5\"} 2\n"));
+        assert!(encoded.contains("latency_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(encoded.contains("untyped_thing 4 1700000000000\n"));
+        assert!(
+            !encoded.contains("# EOF")
+                && !encoded.contains("# UNIT")
+                && !encoded.contains("trace_id")
+        );
+
+        // The output parses back to the same values.
+        let reparsed = Snapshot::parse(&encoded).unwrap();
+        assert_eq!(reparsed.sample_count(), snapshot.sample_count() - 1);
+    }
+
+    #[test]
+    fn malformed_lines_report_their_line_number() {
+        let err = Snapshot::parse("# TYPE a gauge\na{x=\"1} 2\n").unwrap_err();
+        assert!(
+            matches!(err, SnapshotError::Parse { line: 2, .. }),
+            "{}",
+            err
+        );
+        assert!(Snapshot::parse("a not_a_number\n").is_err());
+    }
+
+    #[test]
+    fn streamed_families_match_the_snapshot() {
+        use std::io::Write;
+
+        let mut families = Vec::new();
+        let mut stream = FamilyStream::new(|family| {
+            families.push(family);
+            Ok(())
+        });
+        // Split lines across writes.
+        for piece in TEXT.as_bytes().chunks(7) {
+            stream.write_all(piece).unwrap();
+        }
+        stream.finish::<fmt::Error>(Ok(())).unwrap();
+        assert_eq!(families, Snapshot::parse(TEXT).unwrap().families);
+
+        let mut stream = FamilyStream::new(|_| Ok(()));
+        let written = stream.write_all(b"# TYPE a gauge\na{x=\"1} 2\n");
+        assert!(matches!(
+            stream.finish(written.map_err(RenderToError::<fmt::Error>::Io)),
+            Err(RenderToError::Render(SnapshotError::Parse { line: 2, .. }))
+        ));
+    }
+}
@@ -0,0 +1,124 @@
+//! Units of measurement for metrics.
+//!
+//! A unit can be attached at runtime ([`Unit`]) or at the type level via a
+//! marker such as [`Seconds`] or [`Bytes`]. Typed handles like
+//! `Metric<Histogram, Seconds>` only expose operations that make sense for
+//! their unit, so recording a raw byte count into a latency histogram is a
+//! compile error rather than a silently wrong dashboard.
+
+use std::fmt;
+
+/// Unit of measurement attached to a metric.
+///
+/// By convention the metric name ends with the unit as a suffix, e.g.
+/// `request_duration_seconds` or `response_size_bytes`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// Durations, in seconds.
+    Seconds,
+    /// Sizes, in bytes.
+    Bytes,
+    /// Dimensionless ratios, usually between 0 and 1.
+    Ratio,
+    /// Any other unit, e.g. `"celsius"` or `"requests"`.
+    Custom(String),
+}
+
+impl Unit {
+    /// The unit as it appears in metric names and metadata.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Unit::Seconds => "seconds",
+            Unit::Bytes => "bytes",
+            Unit::Ratio => "ratio",
+            Unit::Custom(unit) => unit.as_str(),
+        }
+    }
+
+    /// The suffix a metric name carrying this unit is expected to end with.
+    pub fn suffix(&self) -> String {
+        format!("_{}", self.as_str())
+    }
+
+    /// Returns true if `name` follows the unit suffix convention.
+    pub fn matches_name(&self, name: &str) -> bool {
+        let suffix = self.suffix();
+        name.len() > suffix.len() && name.ends_with(&suffix)
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Type-level unit marker for [`Metric`](crate::core::metrics::Metric).
+pub trait UnitMarker: Send + Sync + 'static {
+    /// The runtime unit this marker stands for, if any.
+    fn unit() -> Option<Unit>;
+}
+
+/// Marker for metrics without a type-level unit (the default).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Unitless;
+
+/// Marker for metrics measured in seconds.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Seconds;
+
+/// Marker for metrics measured in bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bytes;
+
+/// Marker for dimensionless ratio metrics.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ratio;
+
+impl UnitMarker for Unitless {
+    fn unit() -> Option<Unit> {
+        None
+    }
+}
+
+impl UnitMarker for Seconds {
+    fn unit() -> Option<Unit> {
+        Some(Unit::Seconds)
+    }
+}
+
+impl UnitMarker for Bytes {
+    fn unit() -> Option<Unit> {
+        Some(Unit::Bytes)
+    }
+}
+
+impl UnitMarker for Ratio {
+    fn unit() -> Option<Unit> {
+        Some(Unit::Ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_suffix_convention() {
+        assert!(Unit::Seconds.matches_name("request_duration_seconds"));
+        assert!(Unit::Bytes.matches_name("response_size_bytes"));
+        assert!(Unit::Custom("celsius".into()).matches_name("room_temp_celsius"));
+        assert!(!Unit::Seconds.matches_name("request_duration"));
+        assert!(!Unit::Seconds.matches_name("request_duration_ms"));
+        // The suffix alone is not a name.
+        assert!(!Unit::Seconds.matches_name("_seconds"));
+    }
+
+    #[test]
+    fn markers_map_to_runtime_units() {
+        assert_eq!(Unitless::unit(), None);
+        assert_eq!(Seconds::unit(), Some(Unit::Seconds));
+        assert_eq!(Bytes::unit(), Some(Unit::Bytes));
+        assert_eq!(Ratio::unit(), Some(Unit::Ratio));
+    }
+}
@@ -0,0 +1,478 @@
+//! Graphite exporter.
+//!
+//! Flattens snapshots into dotted Graphite paths and sends them to carbon
+//! over TCP, using either the plaintext protocol (port 2003) or the pickle
+//! protocol (port 2004):
+//!
+//! ```ignore
+//! let exporter = GraphiteExporter::builder("carbon:2003")
+//!     .prefix("myapp.web01")
+//!     .path_labels(["method", "status"])
+//!     .build()?;
+//! ExportScheduler::new(exporter, server.registry()).spawn().await?;
+//! // myapp.web01.http_requests_total.GET.200 42 1700000000
+//! ```
+//!
+//! Each sample's path is the prefix, the sample name, then its label values.
+//! By default every label becomes a path component, ordered by label name.
+//! [`GraphiteBuilder::path_labels`] picks the labels (and their order) that
+//! go into the path; with [`GraphiteBuilder::tags`] the remaining labels are
+//! sent as Graphite tags (`path;name=value`) instead of being appended.
+//!
+//! The connection is kept open between exports. A connection closed by
+//! carbon is detected before writing and re-established, and a failed write
+//! drops the connection so the scheduler's retry reconnects.
+
+use super::{ExportError, Exporter};
+use crate::core::snapshot::{Sample, Snapshot};
+use std::fmt::Write as _;
+use std::io;
+use std::time::UNIX_EPOCH;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+/// Most datapoints sent in one pickle message.
+///
+/// carbon rejects pickle messages over 1 MiB by default.
+pub const PICKLE_BATCH_SIZE: usize = 500;
+
+/// Error configuring a [`GraphiteExporter`].
+#[derive(Debug, thiserror::Error)]
+pub enum GraphiteError {
+    /// The address is not `host:port`.
+    #[error("invalid carbon address {0:?}: expected host:port")]
+    InvalidAddress(String),
+
+    /// The prefix or a label name cannot be used in a Graphite path.
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+}
+
+/// Wire protocol used to send datapoints to carbon.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GraphiteProtocol {
+    /// `path value timestamp\n` lines (carbon's line receiver).
+    #[default]
+    Plaintext,
+    /// Length-prefixed Python pickles (carbon's pickle receiver).
+    Pickle,
+}
+
+/// A datapoint ready to be sent to carbon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphiteSample {
+    /// Dotted path, including any `;name=value` tags.
+    pub path: String,
+    /// Sample value.
+    pub value: f64,
+    /// Unix timestamp in seconds.
+    pub timestamp: u64,
+}
+
+/// Builder for [`GraphiteExporter`].
+#[derive(Debug, Clone)]
+pub struct GraphiteBuilder {
+    address: String,
+    protocol: GraphiteProtocol,
+    prefix: Option<String>,
+    path_labels: Option<Vec<String>>,
+    tags: bool,
+}
+
+impl GraphiteBuilder {
+    /// Set the wire protocol. Default: [`GraphiteProtocol::Plaintext`].
+    pub fn protocol(mut self, protocol: GraphiteProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Prepend `prefix` (e.g. `myapp.web01`) to every path.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Use the values of these labels, in this order, as path components
+    /// after the metric name. Default: all labels, ordered by name.
+    ///
+    /// Labels not listed are sent as tags if [`tags`](Self::tags) is
+    /// enabled, and appended to the path (ordered by name) otherwise. A
+    /// listed label missing from a sample is skipped.
+    pub fn path_labels<I, S>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.path_labels = Some(labels.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Send labels not used in the path as Graphite tags
+    /// (`path;name=value`), which needs carbon 1.1 or later. Default:
+    /// disabled.
+    pub fn tags(mut self, enabled: bool) -> Self {
+        self.tags = enabled;
+        self
+    }
+
+    /// Validate the configuration and build the exporter.
+    ///
+    /// The address is resolved on every connect, not here.
+    pub fn build(self) -> Result<GraphiteExporter, GraphiteError> {
+        let valid_address = self
+            .address
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+        if !valid_address {
+            return Err(GraphiteError::InvalidAddress(self.address));
+        }
+        let prefix = match self.prefix {
+            Some(prefix) => {
+                let prefix = prefix.trim_matches('.');
+                let valid = !prefix.is_empty()
+                    && prefix
+                        .split('.')
+                        .all(|node| !node.is_empty() && sanitize_node(node) == node);
+                if !valid {
+                    return Err(GraphiteError::InvalidConfig(format!(
+                        "invalid path prefix {:?}",
+                        prefix
+                    )));
+                }
+                Some(prefix.to_string())
+            }
+            None => None,
+        };
+        Ok(GraphiteExporter {
+            address: self.address,
+            protocol: self.protocol,
+            prefix,
+            path_labels: self.path_labels,
+            tags: self.tags,
+            connection: Mutex::new(None),
+        })
+    }
+}
+
+/// [`Exporter`] that sends snapshots to carbon over TCP.
+///
+/// Its self-metrics are named `observe_rs_export_graphite_*`.
+#[derive(Debug)]
+pub struct GraphiteExporter {
+    address: String,
+    protocol: GraphiteProtocol,
+    prefix: Option<String>,
+    path_labels: Option<Vec<String>>,
+    tags: bool,
+    connection: Mutex<Option<TcpStream>>,
+}
+
+impl GraphiteExporter {
+    /// Start configuring an exporter sending to carbon at `address`
+    /// (`host:port`).
+    pub fn builder(address: impl Into<String>) -> GraphiteBuilder {
+        GraphiteBuilder {
+            address: address.into(),
+            protocol: GraphiteProtocol::default(),
+            prefix: None,
+            path_labels: None,
+            tags: false,
+        }
+    }
+
+    /// The carbon address.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Flatten `snapshot` into Graphite datapoints.
+    ///
+    /// `_created` samples and non-finite values are skipped.
+    pub fn samples(&self, snapshot: &Snapshot) -> Vec<GraphiteSample> {
+        let default_timestamp = snapshot
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        snapshot
+            .samples()
+            .filter(|(family, sample)| {
+                sample.name != format!("{}_created", family.name) && sample.value.is_finite()
+            })
+            .map(|(_, sample)| GraphiteSample {
+                path: self.path(sample),
+                value: sample.value,
+                timestamp: sample
+                    .timestamp
+                    .map(|seconds| seconds.max(0.0) as u64)
+                    .unwrap_or(default_timestamp),
+            })
+            .collect()
+    }
+
+    fn path(&self, sample: &Sample) -> String {
+        let mut path = String::new();
+        if let Some(prefix) = &self.prefix {
+            path.push_str(prefix);
+            path.push('.');
+        }
+        path.push_str(&sanitize_node(&sample.name));
+
+        let mut rest: Vec<&(String, String)> = sample
+            .labels
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        rest.sort();
+        if let Some(path_labels) = &self.path_labels {
+            for name in path_labels {
+                if let Some(index) = rest.iter().position(|(label, _)| label == name) {
+                    let (_, value) = rest.remove(index);
+                    path.push('.');
+                    path.push_str(&sanitize_node(value));
+                }
+            }
+        }
+        if self.tags {
+            for (name, value) in rest {
+                let _ = write!(path, ";{}={}", sanitize_tag(name), sanitize_tag(value));
+            }
+        } else {
+            for (_, value) in rest {
+                path.push('.');
+                path.push_str(&sanitize_node(value));
+            }
+        }
+        path
+    }
+
+    /// The messages `samples` are sent as.
+    fn encode(&self, samples: &[GraphiteSample]) -> Vec<Vec<u8>> {
+        match self.protocol {
+            GraphiteProtocol::Plaintext => {
+                let mut text = String::new();
+                for sample in samples {
+                    let _ = writeln!(
+                        text,
+                        "{} {} {}",
+                        sample.path, sample.value, sample.timestamp
+                    );
+                }
+                vec![text.into_bytes()]
+            }
+            GraphiteProtocol::Pickle => samples.chunks(PICKLE_BATCH_SIZE).map(pickle).collect(),
+        }
+    }
+
+    /// Write `messages`, reconnecting once if the open connection turns out
+    /// to be dead.
+    async fn send(&self, messages: &[Vec<u8>]) -> Result<(), ExportError> {
+        let mut connection = self.connection.lock().await;
+        // Taken for the duration of the write: if the export times out
+        // mid-write, the half-written connection is dropped with it.
+        let reused = connection.take().filter(is_open);
+        let fresh = reused.is_none();
+        let mut stream = match reused {
+            Some(stream) => stream,
+            None => self.connect().await?,
+        };
+        if let Err(error) = write_all(&mut stream, messages).await {
+            if fresh {
+                return Err(ExportError::transient(error));
+            }
+            // carbon may have closed the connection since the check.
+            stream = self.connect().await?;
+            write_all(&mut stream, messages)
+                .await
+                .map_err(ExportError::transient)?;
+        }
+        *connection = Some(stream);
+        Ok(())
+    }
+
+    async fn connect(&self) -> Result<TcpStream, ExportError> {
+        let stream = TcpStream::connect(&self.address)
+            .await
+            .map_err(|e| ExportError::transient(format!("connect to {}: {}", self.address, e)))?;
+        let _ = stream.set_nodelay(true);
+        Ok(stream)
+    }
+}
+
+impl Exporter for GraphiteExporter {
+    fn name(&self) -> &str {
+        "graphite"
+    }
+
+    async fn export(&self, snapshot: &Snapshot) -> Result<(), ExportError> {
+        let samples = self.samples(snapshot);
+        if samples.is_empty() {
+            return Ok(());
+        }
+        self.send(&self.encode(&samples)).await
+    }
+
+    async fn shutdown(&self) -> Result<(), ExportError> {
+        if let Some(mut stream) = self.connection.lock().await.take() {
+            let _ = stream.shutdown().await;
+        }
+        Ok(())
+    }
+}
+
+/// Whether carbon still has `stream` open. carbon never writes, so a
+/// readable stream means end-of-file or an error.
+fn is_open(stream: &TcpStream) -> bool {
+    let mut buf = [0u8; 1];
+    matches!(stream.try_read(&mut buf), Err(e) if e.kind() == io::ErrorKind::WouldBlock)
+}
+
+async fn write_all(stream: &mut TcpStream, messages: &[Vec<u8>]) -> io::Result<()> {
+    for message in messages {
+        stream.write_all(message).await?;
+    }
+    stream.flush().await
+}
+
+/// Replace characters that would split or break a path node.
+fn sanitize_node(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | ':' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Replace characters Graphite doesn't allow in tag names and values.
+fn sanitize_tag(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            ';' | '!' | '^' | '=' | '~' => '_',
+            c if c.is_whitespace() || c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Encode `samples` as a length-prefixed pickle (protocol 2) of
+/// `[(path, (timestamp, value)), ...]`, as carbon's pickle receiver
+/// expects.
+fn pickle(samples: &[GraphiteSample]) -> Vec<u8> {
+    let mut body = vec![0x80, 2, b']', b'('];
+    for sample in samples {
+        body.push(b'X');
+        body.extend_from_slice(&(sample.path.len() as u32).to_le_bytes());
+        body.extend_from_slice(sample.path.as_bytes());
+        body.push(b'G');
+        body.extend_from_slice(&(sample.timestamp as f64).to_be_bytes());
+        body.push(b'G');
+        body.extend_from_slice(&sample.value.to_be_bytes());
+        body.extend_from_slice(&[0x86, 0x86]);
+    }
+    body.extend_from_slice(b"e.");
+
+    let mut message = (body.len() as u32).to_be_bytes().to_vec();
+    message.extend_from_slice(&body);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn snapshot() -> Snapshot {
+        let mut snapshot = Snapshot::parse(
+            "\
+# TYPE http_requests counter
+http_requests_total{method=\"GET\",status=\"200\",host=\"web.01\"} 42
+http_requests_created{method=\"GET\",status=\"200\",host=\"web.01\"} 1600000000
+# TYPE up gauge
+up{zone=\"\"} 1 1700000100.5
+up{zone=\"b\"} NaN
+",
+        )
+        .unwrap();
+        snapshot.timestamp = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        snapshot
+    }
+
+    fn paths(exporter: GraphiteBuilder) -> Vec<(String, f64, u64)> {
+        exporter
+            .build()
+            .unwrap()
+            .samples(&snapshot())
+            .into_iter()
+            .map(|sample| (sample.path, sample.value, sample.timestamp))
+            .collect()
+    }
+
+    #[test]
+    fn labels_become_path_components_ordered_by_name() {
+        assert_eq!(
+            paths(GraphiteExporter::builder("carbon:2003").prefix("app.")),
+            vec![
+                (
+                    "app.http_requests_total.web_01.GET.200".to_string(),
+                    42.0,
+                    1_700_000_000
+                ),
+                ("app.up".to_string(), 1.0, 1_700_000_100),
+            ]
+        );
+    }
+
+    #[test]
+    fn path_labels_pick_the_order_and_the_rest_can_be_tags() {
+        let builder = GraphiteExporter::builder("carbon:2003").path_labels(["method", "status"]);
+        assert_eq!(
+            paths(builder.clone())[0].0,
+            "http_requests_total.GET.200.web_01"
+        );
+        assert_eq!(
+            paths(builder.tags(true))[0].0,
+            "http_requests_total.GET.200;host=web.01"
+        );
+    }
+
+    #[test]
+    fn invalid_configuration_is_rejected() {
+        for address in ["carbon", ":2003", "carbon:port"] {
+            assert!(matches!(
+                GraphiteExporter::builder(address).build(),
+                Err(GraphiteError::InvalidAddress(_))
+            ));
+        }
+        assert!(matches!(
+            GraphiteExporter::builder("carbon:2003")
+                .prefix("my app")
+                .build(),
+            Err(GraphiteError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn pickle_matches_python_encoding() {
+        // pickle.dumps([("a.b", (1700000000.0, 1.5))], protocol=2), with
+        // the path as unicode.
+        let message = pickle(&[GraphiteSample {
+            path: "a.b".to_string(),
+            value: 1.5,
+            timestamp: 1_700_000_000,
+        }]);
+        let mut expected = vec![
+            0x80, 2, b']', b'(', b'X', 3, 0, 0, 0, b'a', b'.', b'b', b'G',
+        ];
+        expected.extend_from_slice(&1_700_000_000f64.to_be_bytes());
+        expected.push(b'G');
+        expected.extend_from_slice(&1.5f64.to_be_bytes());
+        expected.extend_from_slice(&[0x86, 0x86, b'e', b'.']);
+
+        assert_eq!(&message[..4], &(expected.len() as u32).to_be_bytes());
+        assert_eq!(&message[4..], expected.as_slice());
+    }
+}
@@ -0,0 +1,300 @@
+//! HTTP client shared by the HTTP push exporters.
+//!
+//! Plain `http://` URLs need no certificates; `https://` URLs use rustls with
+//! the platform's native root certificates, loaded when the client is built.
+
+use super::{BoxError, ExportError};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{HeaderName, HeaderValue, AUTHORIZATION, USER_AGENT};
+use hyper::{HeaderMap, Method, Request, StatusCode, Uri};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use std::sync::Arc;
+
+/// `User-Agent` sent by the push exporters.
+const USER_AGENT_VALUE: &str = concat!("observe-rs/", env!("CARGO_PKG_VERSION"));
+
+/// Longest response body excerpt included in export errors.
+const MAX_ERROR_BODY: usize = 256;
+
+/// Error building an HTTP push client.
+#[derive(Debug, thiserror::Error)]
+pub enum HttpClientError {
+    /// The URL could not be parsed or has an unsupported scheme.
+    #[error("invalid URL {url:?}: {message}")]
+    InvalidUrl { url: String, message: String },
+
+    /// A configured header name or value is not valid HTTP.
+    #[error("invalid header {name:?}: {message}")]
+    InvalidHeader { name: String, message: String },
+
+    /// A configured option is out of range.
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    /// The TLS configuration could not be built (e.g. no root certificates).
+    #[error("failed to set up TLS: {0}")]
+    Tls(#[source] std::io::Error),
+}
+
+/// Credentials sent in the `Authorization` header.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) enum HttpAuth {
+    Basic {
+        username: String,
+        password: String,
+    },
+    #[cfg_attr(
+        not(any(feature = "remote-write", feature = "pushgateway")),
+        allow(dead_code)
+    )]
+    Bearer(String),
+    /// InfluxDB API token (`Authorization: Token <token>`).
+    #[cfg(feature = "influx")]
+    Token(String),
+}
+
+impl HttpAuth {
+    fn header_value(&self) -> Result<HeaderValue, HttpClientError> {
+        let value = match self {
+            Self::Basic { username, password } => {
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{}:{}", username, password))
+                )
+            }
+            Self::Bearer(token) => format!("Bearer {}", token),
+            #[cfg(feature = "influx")]
+            Self::Token(token) => format!("Token {}", token),
+        };
+        let mut value =
+            HeaderValue::from_str(&value).map_err(|e| HttpClientError::InvalidHeader {
+                name: AUTHORIZATION.to_string(),
+                message: e.to_string(),
+            })?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+}
+
+// Credentials stay out of logs and error messages.
+impl std::fmt::Debug for HttpAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+            Self::Bearer(_) => f.debug_tuple("Bearer").field(&"<redacted>").finish(),
+            #[cfg(feature = "influx")]
+            Self::Token(_) => f.debug_tuple("Token").field(&"<redacted>").finish(),
+        }
+    }
+}
+
+/// Parse a push target URL, accepting only `http` and `https`.
+pub(crate) fn parse_url(url: &str) -> Result<Uri, HttpClientError> {
+    let invalid = |message: String| HttpClientError::InvalidUrl {
+        url: url.to_string(),
+        message,
+    };
+    let uri: Uri = url
+        .parse()
+        .map_err(|e: hyper::http::uri::InvalidUri| invalid(e.to_string()))?;
+    match uri.scheme_str() {
+        Some("http") | Some("https") => {}
+        _ => return Err(invalid("expected an http:// or https:// URL".to_string())),
+    }
+    if uri.host().is_none() {
+        return Err(invalid("missing host".to_string()));
+    }
+    Ok(uri)
+}
+
+/// Client for one push target: connection pool, auth and extra headers.
+#[derive(Clone)]
+pub(crate) struct HttpClient {
+    client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    headers: Arc<HeaderMap>,
+}
+
+impl std::fmt::Debug for HttpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpClient")
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpClient {
+    /// Build a client for `url`, sending `auth` and `headers` with every
+    /// request.
+    pub(crate) fn new(
+        url: &Uri,
+        auth: Option<&HttpAuth>,
+        headers: &[(String, String)],
+    ) -> Result<Self, HttpClientError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let connector = if url.scheme_str() == Some("https") {
+            HttpsConnectorBuilder::new()
+                .with_provider_and_native_roots(provider)
+                .map_err(HttpClientError::Tls)?
+        } else {
+            let config = rustls::ClientConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .map_err(|e| HttpClientError::Tls(std::io::Error::other(e)))?
+                .with_root_certificates(rustls::RootCertStore::empty())
+                .with_no_client_auth();
+            HttpsConnectorBuilder::new().with_tls_config(config)
+        }
+        .https_or_http()
+        .enable_http1()
+        .build();
+
+        let mut header_map = HeaderMap::new();
+        header_map.insert(USER_AGENT, HeaderValue::from_static(USER_AGENT_VALUE));
+        for (name, value) in headers {
+            let invalid = |message: String| HttpClientError::InvalidHeader {
+                name: name.clone(),
+                message,
+            };
+            let header_name =
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(e.to_string()))?;
+            let header_value = HeaderValue::from_str(value).map_err(|e| invalid(e.to_string()))?;
+            header_map.insert(header_name, header_value);
+        }
+        if let Some(auth) = auth {
+            header_map.insert(AUTHORIZATION, auth.header_value()?);
+        }
+
+        Ok(Self {
+            client: Client::builder(TokioExecutor::new()).build(connector),
+            headers: Arc::new(header_map),
+        })
+    }
+
+    /// Send a request and map the response status to an export result.
+    ///
+    /// Connection failures, `429 Too Many Requests` and `5xx` responses are
+    /// transient; any other non-`2xx` status is permanent.
+    pub(crate) async fn send(
+        &self,
+        method: Method,
+        url: &Uri,
+        headers: &[(HeaderName, &'static str)],
+        body: Bytes,
+    ) -> Result<(), ExportError> {
+        let mut request = Request::builder().method(method.clone()).uri(url.clone());
+        if let Some(request_headers) = request.headers_mut() {
+            request_headers.extend(self.headers.iter().map(|(k, v)| (k.clone(), v.clone())));
+            for (name, value) in headers {
+                request_headers.insert(name.clone(), HeaderValue::from_static(value));
+            }
+        }
+        let request = request
+            .body(Full::new(body))
+            .map_err(ExportError::permanent)?;
+
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|e| ExportError::transient(format!("{} {}: {}", method, url, e)))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        // The body is only read for the error message; failing to read it
+        // doesn't change how the status is classified.
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map(|collected| collected.to_bytes())
+            .unwrap_or_default();
+        Err(status_error(&method, url, status, &body))
+    }
+}
+
+fn status_error(method: &Method, url: &Uri, status: StatusCode, body: &[u8]) -> ExportError {
+    let body = String::from_utf8_lossy(body);
+    let body = body.trim();
+    let excerpt: String = body.chars().take(MAX_ERROR_BODY).collect();
+    let error: BoxError = if excerpt.is_empty() {
+        format!("{} {}: {}", method, url, status).into()
+    } else {
+        format!("{} {}: {}: {}", method, url, status, excerpt).into()
+    };
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        ExportError::Transient(error)
+    } else {
+        ExportError::Permanent(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_codes_are_classified_for_retry() {
+        let url: Uri = "http://localhost/push".parse().unwrap();
+        let retryable = |status: u16| {
+            status_error(
+                &Method::POST,
+                &url,
+                StatusCode::from_u16(status).unwrap(),
+                b"",
+            )
+            .is_retryable()
+        };
+        assert!(retryable(429));
+        assert!(retryable(500));
+        assert!(retryable(503));
+        assert!(!retryable(400));
+        assert!(!retryable(401));
+        assert!(!retryable(404));
+    }
+
+    #[test]
+    fn error_messages_include_a_body_excerpt() {
+        let url: Uri = "http://localhost/push".parse().unwrap();
+        let body = "x".repeat(1000);
+        let error = status_error(&Method::PUT, &url, StatusCode::BAD_REQUEST, body.as_bytes());
+        let message = error.to_string();
+        assert!(message.contains("PUT http://localhost/push: 400 Bad Request: xxx"));
+        assert!(message.len() < 400);
+    }
+
+    #[test]
+    fn urls_must_be_http_with_a_host() {
+        assert!(parse_url("http://localhost:9091/api/v1/write").is_ok());
+        assert!(parse_url("https://mimir.example.com/api/v1/push").is_ok());
+        assert!(matches!(
+            parse_url("ftp://localhost/"),
+            Err(HttpClientError::InvalidUrl { .. })
+        ));
+        assert!(parse_url("/api/v1/write").is_err());
+    }
+
+    #[test]
+    fn auth_headers_are_encoded_and_redacted() {
+        let basic = HttpAuth::Basic {
+            username: "user".into(),
+            password: "pass".into(),
+        };
+        assert_eq!(basic.header_value().unwrap(), "Basic dXNlcjpwYXNz");
+        assert!(basic.header_value().unwrap().is_sensitive());
+        assert!(!format!("{:?}", basic).contains("pass\""));
+
+        let bearer = HttpAuth::Bearer("secret".into());
+        assert_eq!(bearer.header_value().unwrap(), "Bearer secret");
+        assert!(!format!("{:?}", bearer).contains("secret"));
+    }
+}
@@ -0,0 +1,468 @@
+//! InfluxDB line protocol rendering and export.
+//!
+//! [`LineProtocol`] renders a registry as line protocol (one point per
+//! series, with labels as tags) and [`InfluxExporter`] writes it to an
+//! InfluxDB 2.x `/api/v2/write` endpoint:
+//!
+//! ```ignore
+//! let exporter = InfluxExporter::builder("http://influxdb:8086", "iot", "metrics")
+//!     .token(token)
+//!     .build()?;
+//! ExportScheduler::new(exporter, server.registry()).spawn().await?;
+//! ```
+//!
+//! Fields follow Telegraf's Prometheus input (`metric_version = 1`):
+//!
+//! | Family | Fields |
+//! |--------|--------|
+//! | counter | `counter` |
+//! | gauge, stateset | `gauge` |
+//! | info | `info` |
+//! | untyped | `value` |
+//! | histogram | one field per bucket bound (`0.5`, `+Inf`), `sum`, `count` |
+//! | summary | one field per quantile, `sum`, `count` |
+//!
+//! Line protocol has no NaN or infinite field values; such samples are
+//! skipped.
+
+use super::http::{parse_url, HttpAuth, HttpClient, HttpClientError};
+use super::{ExportError, Exporter};
+use crate::core::registry::{MetricBackend, ObservabilityRegistry};
+use crate::core::renderer::{MetricsRenderer, RenderedMetrics};
+use crate::core::snapshot::{
+    MetricFamily, MetricKind, Sample, SampleLabels, Snapshot, SnapshotError,
+};
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hyper::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use hyper::{Method, Uri};
+use std::collections::HashMap;
+use std::fmt::{Display, Write as _};
+use std::io::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Content type of rendered line protocol.
+pub const LINE_PROTOCOL_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+/// Line protocol view of a registry.
+///
+/// # Example
+/// ```ignore
+/// let rendered = LineProtocol::new(&registry).render()?;
+/// // jobs,queue=default counter=3 1700000000000000000
+/// ```
+pub struct LineProtocol<'a, B: MetricBackend> {
+    registry: &'a ObservabilityRegistry<B>,
+}
+
+impl<'a, B: MetricBackend> LineProtocol<'a, B> {
+    /// Render `registry` as line protocol.
+    pub fn new(registry: &'a ObservabilityRegistry<B>) -> Self {
+        Self { registry }
+    }
+}
+
+impl<B> MetricsRenderer for LineProtocol<'_, B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    type Error = SnapshotError;
+
+    fn render(&self) -> Result<RenderedMetrics, Self::Error> {
+        let snapshot = self.registry.snapshot()?;
+        Ok(RenderedMetrics::new(
+            LINE_PROTOCOL_CONTENT_TYPE,
+            encode_line_protocol(&snapshot).into_bytes(),
+        ))
+    }
+
+    fn content_type(&self) -> Result<String, Self::Error> {
+        Ok(LINE_PROTOCOL_CONTENT_TYPE.to_string())
+    }
+}
+
+/// Encode a snapshot as line protocol with nanosecond timestamps.
+///
+/// Samples without a timestamp get the snapshot's.
+pub fn encode_line_protocol(snapshot: &Snapshot) -> String {
+    let default_timestamp = nanos(snapshot.timestamp);
+    let mut out = String::new();
+    for family in &snapshot.families {
+        for point in points(family) {
+            if point.fields.is_empty() {
+                continue;
+            }
+            out.push_str(&escape(&family.name, &[',', ' ']));
+            let mut tags: Vec<&(String, String)> = point
+                .tags
+                .iter()
+                .filter(|(_, value)| !value.is_empty())
+                .collect();
+            tags.sort();
+            for (name, value) in tags {
+                let _ = write!(
+                    out,
+                    ",{}={}",
+                    escape(name, &[',', '=', ' ']),
+                    escape(value, &[',', '=', ' '])
+                );
+            }
+            for (i, (name, value)) in point.fields.iter().enumerate() {
+                let separator = if i == 0 { ' ' } else { ',' };
+                let _ = write!(
+                    out,
+                    "{}{}={}",
+                    separator,
+                    escape(name, &[',', '=', ' ']),
+                    value
+                );
+            }
+            let timestamp = point
+                .timestamp
+                .map(|seconds| (seconds * 1e9).round() as i128)
+                .unwrap_or(default_timestamp);
+            let _ = writeln!(out, " {}", timestamp);
+        }
+    }
+    out
+}
+
+/// One line: the tags shared by a group of samples and their fields.
+struct Point {
+    tags: SampleLabels,
+    fields: Vec<(String, f64)>,
+    timestamp: Option<f64>,
+}
+
+/// Group a family's samples into points.
+///
+/// Histogram and summary samples that differ only in `le` / `quantile`
+/// (and in their suffix) belong to the same point.
+fn points(family: &MetricFamily) -> Vec<Point> {
+    let mut points: Vec<Point> = Vec::new();
+    // Position of each point in `points`, which keeps the samples' order.
+    let mut index: HashMap<SampleLabels, usize> = HashMap::new();
+    for sample in &family.samples {
+        let Some((tags, field)) = field_of(family, sample) else {
+            continue;
+        };
+        if !sample.value.is_finite() {
+            continue;
+        }
+        match index.get(&tags) {
+            Some(&i) => points[i].fields.push((field, sample.value)),
+            None => {
+                index.insert(tags.clone(), points.len());
+                points.push(Point {
+                    tags,
+                    fields: vec![(field, sample.value)],
+                    timestamp: sample.timestamp,
+                });
+            }
+        }
+    }
+    points
+}
+
+/// The point tags and field name of a sample, or `None` to skip it.
+fn field_of(family: &MetricFamily, sample: &Sample) -> Option<(SampleLabels, String)> {
+    let suffix = sample.name.strip_prefix(family.name.as_str()).unwrap_or("");
+    if suffix == "_created" {
+        return None;
+    }
+    let without = |label: &str| -> SampleLabels {
+        sample
+            .labels
+            .iter()
+            .filter(|(name, _)| name != label)
+            .cloned()
+            .collect()
+    };
+    let field = match family.kind {
+        MetricKind::Counter => "counter",
+        MetricKind::Gauge | MetricKind::StateSet => "gauge",
+        MetricKind::Info => "info",
+        MetricKind::Unknown => "value",
+        MetricKind::Histogram | MetricKind::GaugeHistogram | MetricKind::Summary => {
+            let bound = if family.kind == MetricKind::Summary {
+                "quantile"
+            } else {
+                "le"
+            };
+            return match suffix {
+                "_sum" | "_gsum" => Some((sample.labels.clone(), "sum".to_string())),
+                "_count" | "_gcount" => Some((sample.labels.clone(), "count".to_string())),
+                _ => Some((without(bound), sample.label(bound)?.to_string())),
+            };
+        }
+    };
+    Some((sample.labels.clone(), field.to_string()))
+}
+
+/// Backslash-escape `special` characters; newlines, which line protocol
+/// can't carry, become `\n`.
+fn escape(value: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\n' {
+            out.push_str("\\n");
+            continue;
+        }
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn nanos(time: SystemTime) -> i128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as i128)
+        .unwrap_or(0)
+}
+
+/// Builder for [`InfluxExporter`].
+#[derive(Debug, Clone)]
+pub struct InfluxBuilder {
+    url: String,
+    org: String,
+    bucket: String,
+    auth: Option<HttpAuth>,
+    headers: Vec<(String, String)>,
+    gzip: bool,
+}
+
+impl InfluxBuilder {
+    /// Authenticate with an InfluxDB API token. Replaces any basic auth.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(HttpAuth::Token(token.into()));
+        self
+    }
+
+    /// Authenticate with HTTP basic auth (e.g. behind a reverse proxy).
+    /// Replaces any API token.
+    pub fn basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some(HttpAuth::Basic {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Send an extra header with every request.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Gzip request bodies. Default: enabled.
+    pub fn gzip(mut self, enabled: bool) -> Self {
+        self.gzip = enabled;
+        self
+    }
+
+    /// Validate the configuration and build the exporter.
+    pub fn build(self) -> Result<InfluxExporter, HttpClientError> {
+        for (name, value) in [("org", &self.org), ("bucket", &self.bucket)] {
+            if value.is_empty() {
+                return Err(HttpClientError::InvalidConfig(format!(
+                    "InfluxDB {} must not be empty",
+                    name
+                )));
+            }
+        }
+        let base = parse_url(&self.url)?;
+        let url = parse_url(&format!(
+            "{}://{}{}/api/v2/write?org={}&bucket={}&precision=ns",
+            base.scheme_str().unwrap_or("http"),
+            base.authority().map(|a| a.as_str()).unwrap_or_default(),
+            base.path().trim_end_matches('/'),
+            query_encode(&self.org),
+            query_encode(&self.bucket)
+        ))?;
+        let client = HttpClient::new(&url, self.auth.as_ref(), &self.headers)?;
+        Ok(InfluxExporter {
+            client,
+            url,
+            gzip: self.gzip,
+        })
+    }
+}
+
+fn query_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            let _ = write!(out, "%{:02X}", byte);
+        }
+    }
+    out
+}
+
+/// [`Exporter`] that writes snapshots to InfluxDB 2.x as line protocol.
+///
+/// Its self-metrics are named `observe_rs_export_influx_*`.
+#[derive(Debug)]
+pub struct InfluxExporter {
+    client: HttpClient,
+    url: Uri,
+    gzip: bool,
+}
+
+impl InfluxExporter {
+    /// Start configuring an exporter writing to `bucket` of `org` on the
+    /// InfluxDB server at `url` (e.g. `http://influxdb:8086`).
+    pub fn builder(
+        url: impl Into<String>,
+        org: impl Into<String>,
+        bucket: impl Into<String>,
+    ) -> InfluxBuilder {
+        InfluxBuilder {
+            url: url.into(),
+            org: org.into(),
+            bucket: bucket.into(),
+            auth: None,
+            headers: Vec::new(),
+            gzip: true,
+        }
+    }
+
+    /// The write endpoint, including the query string.
+    pub fn url(&self) -> &Uri {
+        &self.url
+    }
+}
+
+impl Exporter for InfluxExporter {
+    fn name(&self) -> &str {
+        "influx"
+    }
+
+    async fn export(&self, snapshot: &Snapshot) -> Result<(), ExportError> {
+        let body = encode_line_protocol(snapshot);
+        if body.is_empty() {
+            return Ok(());
+        }
+        if !self.gzip {
+            return self
+                .client
+                .send(
+                    Method::POST,
+                    &self.url,
+                    &[(CONTENT_TYPE, LINE_PROTOCOL_CONTENT_TYPE)],
+                    Bytes::from(body),
+                )
+                .await;
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(body.as_bytes())
+            .map_err(ExportError::permanent)?;
+        let compressed = encoder.finish().map_err(ExportError::permanent)?;
+        self.client
+            .send(
+                Method::POST,
+                &self.url,
+                &[
+                    (CONTENT_TYPE, LINE_PROTOCOL_CONTENT_TYPE),
+                    (CONTENT_ENCODING, "gzip"),
+                ],
+                Bytes::from(compressed),
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn snapshot(text: &str) -> Snapshot {
+        let mut snapshot = Snapshot::parse(text).unwrap();
+        snapshot.timestamp = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        snapshot
+    }
+
+    #[test]
+    fn counters_and_gauges_become_fields_with_label_tags() {
+        let text = "\
+# TYPE jobs counter
+jobs_total{queue=\"mail\",host=\"a\"} 3
+jobs_created{queue=\"mail\",host=\"a\"} 1600000000
+# TYPE temperature gauge
+temperature{room=\"\"} 21.5
+untyped 1 1700000000.5
+";
+        assert_eq!(
+            encode_line_protocol(&snapshot(text)),
+            "jobs,host=a,queue=mail counter=3 1700000000000000000\n\
+             temperature gauge=21.5 1700000000000000000\n\
+             untyped value=1 1700000000500000000\n"
+        );
+    }
+
+    #[test]
+    fn histograms_become_one_point_with_bucket_fields() {
+        let text = "\
+# TYPE latency histogram
+latency_sum{route=\"/\"} 1.5
+latency_count{route=\"/\"} 3
+latency_bucket{route=\"/\",le=\"0.5\"} 2
+latency_bucket{route=\"/\",le=\"+Inf\"} 3
+latency_sum{route=\"/a\"} 0
+latency_count{route=\"/a\"} 0
+latency_bucket{route=\"/a\",le=\"0.5\"} 0
+latency_bucket{route=\"/a\",le=\"+Inf\"} 0
+";
+        assert_eq!(
+            encode_line_protocol(&snapshot(text)),
+            "latency,route=/ sum=1.5,count=3,0.5=2,+Inf=3 1700000000000000000\n\
+             latency,route=/a sum=0,count=0,0.5=0,+Inf=0 1700000000000000000\n"
+        );
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        let text = "# TYPE a gauge\na{path=\"x y,z=1\",note=\"two\\nlines\"} 1\nb NaN\n";
+        assert_eq!(
+            encode_line_protocol(&snapshot(text)),
+            "a,note=two\\nlines,path=x\\ y\\,z\\=1 gauge=1 1700000000000000000\n"
+        );
+        assert_eq!(escape("cpu load,total", &[',', ' ']), "cpu\\ load\\,total");
+    }
+
+    #[test]
+    fn write_url_carries_org_bucket_and_precision() {
+        let exporter = InfluxExporter::builder("http://influxdb:8086/", "my org", "metrics")
+            .build()
+            .unwrap();
+        assert_eq!(
+            exporter.url().to_string(),
+            "http://influxdb:8086/api/v2/write?org=my%20org&bucket=metrics&precision=ns"
+        );
+        assert!(matches!(
+            InfluxExporter::builder("http://influxdb:8086", "", "metrics").build(),
+            Err(HttpClientError::InvalidConfig(_))
+        ));
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn registry_renders_as_line_protocol() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+
+        let mut registry = ObservabilityRegistry::<PrometheusBackend>::new();
+        registry.counter("jobs", "Jobs").unwrap().inc_by(2);
+
+        let rendered = LineProtocol::new(&registry).render().unwrap();
+        assert_eq!(rendered.content_type, LINE_PROTOCOL_CONTENT_TYPE);
+        let body = rendered.as_str().unwrap();
+        assert!(body.starts_with("jobs counter=2 "), "{}", body);
+    }
+}
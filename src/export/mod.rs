@@ -0,0 +1,131 @@
+//! Push-based export.
+//!
+//! Backends that push instead of being scraped (remote-write, Pushgateway,
+//! OTLP, StatsD, ...) all need the same loop: snapshot the registry on an
+//! interval, send it, retry transient failures with jittered backoff, and
+//! flush once more on shutdown. An [`Exporter`] only implements the "send"
+//! step; [`ExportScheduler`] runs the loop on tokio.
+//!
+//! # Example
+//! ```ignore
+//! use observe_rs::export::{ExportConfig, ExportScheduler};
+//!
+//! let handle = ExportScheduler::new(my_exporter, server.registry())
+//!     .with_config(ExportConfig::default().interval(Duration::from_secs(30)))
+//!     .spawn()
+//!     .await?;
+//! // ...
+//! handle.shutdown().await; // final flush
+//! ```
+
+// `http-client` is only useful together with an exporter feature.
+#[cfg(feature = "graphite")]
+pub mod graphite;
+#[cfg(feature = "http-client")]
+#[cfg_attr(
+    not(any(feature = "remote-write", feature = "pushgateway", feature = "influx")),
+    allow(dead_code)
+)]
+mod http;
+#[cfg(feature = "influx")]
+pub mod influx;
+#[cfg(feature = "pushgateway")]
+pub mod pushgateway;
+#[cfg(feature = "remote-write")]
+pub mod remote_write;
+pub mod scheduler;
+#[cfg(feature = "textfile")]
+pub mod textfile;
+
+#[cfg(feature = "graphite")]
+pub use graphite::{GraphiteError, GraphiteExporter, GraphiteProtocol};
+#[cfg(feature = "http-client")]
+pub use http::HttpClientError;
+#[cfg(feature = "influx")]
+pub use influx::{InfluxBuilder, InfluxExporter, LineProtocol};
+#[cfg(feature = "pushgateway")]
+pub use pushgateway::{
+    delete_from_gateway, push_add_to_gateway, push_to_gateway, PushError, PushGuard, PushMethod,
+    Pushgateway,
+};
+#[cfg(feature = "remote-write")]
+pub use remote_write::{RemoteWriteBuilder, RemoteWriteExporter};
+pub use scheduler::{ExportConfig, ExportHandle, ExportScheduler, SharedRegistry, SpawnError};
+#[cfg(feature = "textfile")]
+pub use textfile::{TextfileError, TextfileExporter, TextfileGuard};
+
+use crate::core::snapshot::{Snapshot, SnapshotError};
+use std::error::Error;
+use std::future::Future;
+use std::time::Duration;
+
+/// Boxed error from an exporter's transport.
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Error from a single export attempt.
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    /// A failure worth retrying (connection errors, HTTP 5xx or 429).
+    #[error("transient export failure: {0}")]
+    Transient(BoxError),
+
+    /// A failure that will not go away by retrying (bad request, auth).
+    #[error("permanent export failure: {0}")]
+    Permanent(BoxError),
+
+    /// The attempt did not finish within the configured timeout.
+    #[error("export timed out after {0:?}")]
+    Timeout(Duration),
+
+    /// The registry could not be snapshotted.
+    #[error("failed to snapshot registry: {0}")]
+    Snapshot(#[from] SnapshotError),
+}
+
+impl ExportError {
+    /// A failure worth retrying.
+    pub fn transient(error: impl Into<BoxError>) -> Self {
+        Self::Transient(error.into())
+    }
+
+    /// A failure that should not be retried.
+    pub fn permanent(error: impl Into<BoxError>) -> Self {
+        Self::Permanent(error.into())
+    }
+
+    /// Whether the scheduler should retry after this error.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Transient(_) | Self::Timeout(_))
+    }
+}
+
+/// A destination for registry snapshots.
+///
+/// # Example
+/// ```ignore
+/// struct StdoutExporter;
+///
+/// impl Exporter for StdoutExporter {
+///     fn name(&self) -> &str {
+///         "stdout"
+///     }
+///
+///     async fn export(&self, snapshot: &Snapshot) -> Result<(), ExportError> {
+///         println!("{} samples", snapshot.sample_count());
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait Exporter: Send + Sync + 'static {
+    /// Short snake_case name, used in the scheduler's self-metric names
+    /// (`observe_rs_export_<name>_failures_total`, ...).
+    fn name(&self) -> &str;
+
+    /// Send one snapshot.
+    fn export(&self, snapshot: &Snapshot) -> impl Future<Output = Result<(), ExportError>> + Send;
+
+    /// Release resources after the final flush.
+    fn shutdown(&self) -> impl Future<Output = Result<(), ExportError>> + Send {
+        async { Ok(()) }
+    }
+}
@@ -0,0 +1,478 @@
+//! Pushgateway client for batch jobs.
+//!
+//! Short-lived jobs finish before Prometheus scrapes them, so they push their
+//! metrics to a [Pushgateway](https://github.com/prometheus/pushgateway)
+//! instead. Metrics are grouped by job name plus optional grouping labels;
+//! each push replaces ([`PushMethod::Put`]) or merges into
+//! ([`PushMethod::Post`]) that group, and [`delete_from_gateway`] removes it.
+//!
+//! ```ignore
+//! use observe_rs::export::pushgateway::push_to_gateway;
+//!
+//! run_backup(&registry)?;
+//! push_to_gateway("http://pushgateway:9091", "backup", &[("instance", "db-1")], &registry).await?;
+//! ```
+//!
+//! For a job whose `main` may return early, [`Pushgateway::push_on_exit`]
+//! returns a [`PushGuard`] that pushes when it is dropped.
+
+use super::http::{parse_url, HttpAuth, HttpClient, HttpClientError};
+use super::{ExportError, SharedRegistry};
+use crate::core::registry::{MetricBackend, ObservabilityRegistry};
+use crate::core::renderer::MetricsRenderer;
+use crate::core::snapshot::PROMETHEUS_TEXT_CONTENT_TYPE;
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use bytes::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Method, Uri};
+use std::fmt::{self, Display};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default timeout of a push.
+pub const DEFAULT_PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How a push combines with metrics already in the group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PushMethod {
+    /// Replace all metrics of the group (HTTP `PUT`).
+    #[default]
+    Put,
+    /// Replace only metrics with the same names (HTTP `POST`).
+    Post,
+}
+
+impl PushMethod {
+    fn http_method(self) -> Method {
+        match self {
+            Self::Put => Method::PUT,
+            Self::Post => Method::POST,
+        }
+    }
+}
+
+/// Error pushing to or deleting from a Pushgateway.
+#[derive(Debug, thiserror::Error)]
+pub enum PushError {
+    /// The client could not be configured.
+    #[error(transparent)]
+    Client(#[from] HttpClientError),
+
+    /// The job name or a grouping label is not usable.
+    #[error("invalid grouping key: {0}")]
+    InvalidGrouping(String),
+
+    /// The registry could not be rendered, or the request failed.
+    #[error(transparent)]
+    Export(#[from] ExportError),
+}
+
+/// Builder for [`Pushgateway`].
+#[derive(Debug, Clone)]
+pub struct PushgatewayBuilder {
+    url: String,
+    auth: Option<HttpAuth>,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl PushgatewayBuilder {
+    /// Authenticate with HTTP basic auth. Replaces any bearer token.
+    pub fn basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some(HttpAuth::Basic {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Authenticate with a bearer token. Replaces any basic auth.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(HttpAuth::Bearer(token.into()));
+        self
+    }
+
+    /// Send an extra header with every request.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the timeout of each push or delete. Default: 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Validate the configuration and build the client.
+    pub fn build(self) -> Result<Pushgateway, HttpClientError> {
+        let url = parse_url(&self.url)?;
+        let client = HttpClient::new(&url, self.auth.as_ref(), &self.headers)?;
+        Ok(Pushgateway {
+            url,
+            client,
+            config: Arc::new(self),
+        })
+    }
+}
+
+/// Client for one Pushgateway.
+#[derive(Debug, Clone)]
+pub struct Pushgateway {
+    url: Uri,
+    client: HttpClient,
+    config: Arc<PushgatewayBuilder>,
+}
+
+impl Pushgateway {
+    /// Start configuring a client for the Pushgateway at `url`
+    /// (e.g. `http://pushgateway:9091`).
+    pub fn builder(url: impl Into<String>) -> PushgatewayBuilder {
+        PushgatewayBuilder {
+            url: url.into(),
+            auth: None,
+            headers: Vec::new(),
+            timeout: DEFAULT_PUSH_TIMEOUT,
+        }
+    }
+
+    /// Push the registry's metrics to the group of `job` and `grouping`.
+    pub async fn push<B>(
+        &self,
+        method: PushMethod,
+        job: &str,
+        grouping: &[(&str, &str)],
+        registry: &ObservabilityRegistry<B>,
+    ) -> Result<(), PushError>
+    where
+        B: MetricBackend,
+        <B::Registry as MetricsRenderer>::Error: Display,
+    {
+        let url = self.group_url(job, grouping)?;
+        let body = registry
+            .snapshot()
+            .map_err(ExportError::from)?
+            .to_prometheus_text();
+        self.send(method.http_method(), url, Bytes::from(body))
+            .await
+    }
+
+    /// Delete all metrics of the group of `job` and `grouping`.
+    pub async fn delete(&self, job: &str, grouping: &[(&str, &str)]) -> Result<(), PushError> {
+        let url = self.group_url(job, grouping)?;
+        self.send(Method::DELETE, url, Bytes::new()).await
+    }
+
+    /// Return a guard that pushes `registry` with `method` when dropped,
+    /// typically at the end of the job's `main`.
+    pub fn push_on_exit<B>(
+        &self,
+        method: PushMethod,
+        job: impl Into<String>,
+        grouping: &[(&str, &str)],
+        registry: SharedRegistry<B>,
+    ) -> PushGuard<B>
+    where
+        B: MetricBackend,
+        <B::Registry as MetricsRenderer>::Error: Display,
+    {
+        PushGuard {
+            gateway: self.clone(),
+            method,
+            job: job.into(),
+            grouping: grouping
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            registry,
+            on_error: None,
+            armed: true,
+        }
+    }
+
+    async fn send(&self, method: Method, url: Uri, body: Bytes) -> Result<(), PushError> {
+        let timeout = self.config.timeout;
+        let request = self.client.send(
+            method,
+            &url,
+            &[(CONTENT_TYPE, PROMETHEUS_TEXT_CONTENT_TYPE)],
+            body,
+        );
+        tokio::time::timeout(timeout, request)
+            .await
+            .unwrap_or(Err(ExportError::Timeout(timeout)))
+            .map_err(PushError::from)
+    }
+
+    /// `<url>/metrics/job/<job>{/<label>/<value>}`.
+    fn group_url(&self, job: &str, grouping: &[(&str, &str)]) -> Result<Uri, PushError> {
+        if job.is_empty() {
+            return Err(PushError::InvalidGrouping(
+                "job name must not be empty".into(),
+            ));
+        }
+        let mut path = format!(
+            "{}/metrics/{}",
+            self.url.path().trim_end_matches('/'),
+            path_segment("job", job)
+        );
+        for (name, value) in grouping {
+            validate_grouping_label(name)?;
+            path.push('/');
+            path.push_str(&path_segment(name, value));
+        }
+
+        let mut parts = self.url.clone().into_parts();
+        parts.path_and_query = Some(
+            path.parse()
+                .map_err(|e| PushError::InvalidGrouping(format!("{}: {}", path, e)))?,
+        );
+        Uri::from_parts(parts).map_err(|e| PushError::InvalidGrouping(e.to_string()))
+    }
+}
+
+fn validate_grouping_label(name: &str) -> Result<(), PushError> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid || name == "job" || name.starts_with("__") {
+        return Err(PushError::InvalidGrouping(format!(
+            "invalid grouping label name {:?}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// `<label>/<value>`, switching to `<label>@base64/<value>` for values the
+/// Pushgateway can't take in a plain path segment (empty or containing `/`).
+fn path_segment(label: &str, value: &str) -> String {
+    if value.is_empty() {
+        return format!("{}@base64/=", label);
+    }
+    if value.contains('/') {
+        return format!("{}@base64/{}", label, URL_SAFE.encode(value));
+    }
+    format!("{}/{}", label, percent_encode(value))
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters.
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+/// Push with PUT semantics using a default [`Pushgateway`] client.
+pub async fn push_to_gateway<B>(
+    url: &str,
+    job: &str,
+    grouping: &[(&str, &str)],
+    registry: &ObservabilityRegistry<B>,
+) -> Result<(), PushError>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    Pushgateway::builder(url)
+        .build()?
+        .push(PushMethod::Put, job, grouping, registry)
+        .await
+}
+
+/// Push with POST semantics using a default [`Pushgateway`] client.
+pub async fn push_add_to_gateway<B>(
+    url: &str,
+    job: &str,
+    grouping: &[(&str, &str)],
+    registry: &ObservabilityRegistry<B>,
+) -> Result<(), PushError>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    Pushgateway::builder(url)
+        .build()?
+        .push(PushMethod::Post, job, grouping, registry)
+        .await
+}
+
+/// Delete a group using a default [`Pushgateway`] client.
+pub async fn delete_from_gateway(
+    url: &str,
+    job: &str,
+    grouping: &[(&str, &str)],
+) -> Result<(), PushError> {
+    Pushgateway::builder(url)
+        .build()?
+        .delete(job, grouping)
+        .await
+}
+
+type ErrorCallback = Box<dyn Fn(&PushError) + Send + Sync>;
+
+/// Pushes a registry when dropped. Created by [`Pushgateway::push_on_exit`].
+///
+/// Dropping the guard blocks the current thread until the push completes
+/// (or times out); the push runs on a helper thread with its own runtime,
+/// so this works both inside and outside tokio. The guard also pushes when
+/// dropped during a panic, so the Pushgateway sees how far a failed job got.
+/// Errors are passed to the [`on_error`](Self::on_error) callback; use
+/// [`finish`](Self::finish) to push and get the result instead.
+pub struct PushGuard<B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    gateway: Pushgateway,
+    method: PushMethod,
+    job: String,
+    grouping: Vec<(String, String)>,
+    registry: SharedRegistry<B>,
+    on_error: Option<ErrorCallback>,
+    armed: bool,
+}
+
+impl<B> PushGuard<B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    /// Call `callback` if the push on drop fails.
+    pub fn on_error(mut self, callback: impl Fn(&PushError) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(Box::new(callback));
+        self
+    }
+
+    /// Push now, keeping the guard armed.
+    pub async fn push(&self) -> Result<(), PushError> {
+        Self::push_with(&self.gateway, self).await
+    }
+
+    /// Push now and disarm the guard.
+    pub async fn finish(mut self) -> Result<(), PushError> {
+        self.armed = false;
+        self.push().await
+    }
+
+    /// Disarm the guard without pushing.
+    pub fn disarm(mut self) {
+        self.armed = false;
+    }
+
+    async fn push_with(gateway: &Pushgateway, guard: &Self) -> Result<(), PushError> {
+        let grouping: Vec<(&str, &str)> = guard
+            .grouping
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let registry = guard.registry.read().await;
+        gateway
+            .push(guard.method, &guard.job, &grouping, &registry)
+            .await
+    }
+
+    fn push_blocking(&self) -> Result<(), PushError> {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map_err(ExportError::permanent)?;
+                    // The client's connection pool belongs to the caller's
+                    // runtime, so the helper thread uses a fresh client.
+                    let gateway = PushgatewayBuilder::clone(&self.gateway.config).build()?;
+                    runtime.block_on(Self::push_with(&gateway, self))
+                })
+                .join()
+                .unwrap_or_else(|_| Err(ExportError::permanent("push thread panicked").into()))
+        })
+    }
+}
+
+impl<B> fmt::Debug for PushGuard<B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PushGuard")
+            .field("method", &self.method)
+            .field("job", &self.job)
+            .field("grouping", &self.grouping)
+            .field("armed", &self.armed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B> Drop for PushGuard<B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        if let Err(error) = self.push_blocking() {
+            if let Some(callback) = &self.on_error {
+                callback(&error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gateway(url: &str) -> Pushgateway {
+        Pushgateway::builder(url).build().unwrap()
+    }
+
+    #[test]
+    fn grouping_labels_are_encoded_into_the_path() {
+        let gateway = gateway("http://pushgateway:9091");
+        let url = gateway
+            .group_url("backup", &[("instance", "db 1"), ("env", "prod")])
+            .unwrap();
+        assert_eq!(
+            url.to_string(),
+            "http://pushgateway:9091/metrics/job/backup/instance/db%201/env/prod"
+        );
+    }
+
+    #[test]
+    fn values_with_slashes_or_empty_values_use_base64() {
+        let gateway = gateway("http://pushgateway:9091/prefix/");
+        let url = gateway
+            .group_url("nightly/backup", &[("path", "/var/tmp"), ("shard", "")])
+            .unwrap();
+        assert_eq!(
+            url.to_string(),
+            "http://pushgateway:9091/prefix/metrics/job@base64/bmlnaHRseS9iYWNrdXA=/path@base64/L3Zhci90bXA=/shard@base64/="
+        );
+    }
+
+    #[test]
+    fn invalid_grouping_keys_are_rejected() {
+        let gateway = gateway("http://pushgateway:9091");
+        assert!(matches!(
+            gateway.group_url("", &[]),
+            Err(PushError::InvalidGrouping(_))
+        ));
+        assert!(gateway.group_url("job", &[("job", "x")]).is_err());
+        assert!(gateway.group_url("job", &[("bad-name", "x")]).is_err());
+        assert!(gateway.group_url("job", &[("__meta", "x")]).is_err());
+    }
+}
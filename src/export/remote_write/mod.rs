@@ -0,0 +1,422 @@
+//! Prometheus remote-write v1 exporter.
+//!
+//! Encodes each snapshot as snappy-compressed protobuf
+//! [`WriteRequest`](proto::WriteRequest)s and POSTs them to a remote-write
+//! endpoint (Prometheus with `--web.enable-remote-write-receiver`, Mimir,
+//! Thanos receive, VictoriaMetrics, ...). Run it with an
+//! [`ExportScheduler`](super::ExportScheduler):
+//!
+//! ```ignore
+//! let exporter = RemoteWriteExporter::builder("https://mimir.example.com/api/v1/push")
+//!     .bearer_token(token)
+//!     .external_label("cluster", "eu-west-1")
+//!     .build()?;
+//! let handle = ExportScheduler::new(exporter, server.registry()).spawn().await?;
+//! ```
+//!
+//! Large snapshots are split into several requests of at most
+//! [`max_series_per_request`](RemoteWriteBuilder::max_series_per_request)
+//! series. If a later request fails, the scheduler retries the whole
+//! snapshot; receivers ignore the resent samples since they carry the same
+//! timestamps and values.
+
+pub mod proto;
+
+use super::http::{parse_url, HttpAuth, HttpClient, HttpClientError};
+use super::{ExportError, Exporter};
+use crate::core::snapshot::{MetricKind, SampleLabels, Snapshot};
+use bytes::Bytes;
+use hyper::header::{HeaderName, CONTENT_ENCODING, CONTENT_TYPE};
+use hyper::{Method, Uri};
+use prost::Message;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default maximum number of series per request, matching Prometheus'
+/// `max_samples_per_send`.
+pub const DEFAULT_MAX_SERIES_PER_REQUEST: usize = 2000;
+
+/// Protocol version header value for remote-write v1.
+const REMOTE_WRITE_VERSION: &str = "0.1.0";
+
+const REMOTE_WRITE_VERSION_HEADER: HeaderName =
+    HeaderName::from_static("x-prometheus-remote-write-version");
+
+/// Builder for [`RemoteWriteExporter`].
+#[derive(Debug, Clone)]
+pub struct RemoteWriteBuilder {
+    url: String,
+    auth: Option<HttpAuth>,
+    headers: Vec<(String, String)>,
+    external_labels: Vec<(String, String)>,
+    max_series_per_request: usize,
+}
+
+impl RemoteWriteBuilder {
+    fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            auth: None,
+            headers: Vec::new(),
+            external_labels: Vec::new(),
+            max_series_per_request: DEFAULT_MAX_SERIES_PER_REQUEST,
+        }
+    }
+
+    /// Authenticate with HTTP basic auth. Replaces any bearer token.
+    pub fn basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some(HttpAuth::Basic {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Authenticate with a bearer token. Replaces any basic auth.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(HttpAuth::Bearer(token.into()));
+        self
+    }
+
+    /// Send an extra header with every request (e.g. `X-Scope-OrgID`).
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Add a label to every series that doesn't already have it, like
+    /// Prometheus' `external_labels`.
+    pub fn external_label(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        self.external_labels
+            .retain(|(existing, _)| *existing != name);
+        self.external_labels.push((name, value.into()));
+        self
+    }
+
+    /// Split snapshots into requests of at most `max` series.
+    pub fn max_series_per_request(mut self, max: usize) -> Self {
+        self.max_series_per_request = max;
+        self
+    }
+
+    /// Validate the configuration and build the exporter.
+    pub fn build(self) -> Result<RemoteWriteExporter, HttpClientError> {
+        let url = parse_url(&self.url)?;
+        if self.max_series_per_request == 0 {
+            return Err(HttpClientError::InvalidConfig(
+                "max_series_per_request must be at least 1".to_string(),
+            ));
+        }
+        for (name, value) in &self.external_labels {
+            validate_external_label(name, value)?;
+        }
+        let client = HttpClient::new(&url, self.auth.as_ref(), &self.headers)?;
+        Ok(RemoteWriteExporter {
+            client,
+            url,
+            external_labels: self.external_labels,
+            max_series_per_request: self.max_series_per_request,
+        })
+    }
+}
+
+fn validate_external_label(name: &str, value: &str) -> Result<(), HttpClientError> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__");
+    if !valid {
+        return Err(HttpClientError::InvalidConfig(format!(
+            "invalid external label name {:?}",
+            name
+        )));
+    }
+    if value.is_empty() {
+        return Err(HttpClientError::InvalidConfig(format!(
+            "external label {:?} has an empty value",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// [`Exporter`] that sends snapshots to a Prometheus remote-write endpoint.
+///
+/// Its self-metrics are named `observe_rs_export_remote_write_*`.
+#[derive(Debug)]
+pub struct RemoteWriteExporter {
+    client: HttpClient,
+    url: Uri,
+    external_labels: Vec<(String, String)>,
+    max_series_per_request: usize,
+}
+
+impl RemoteWriteExporter {
+    /// Start configuring an exporter for the remote-write endpoint `url`.
+    pub fn builder(url: impl Into<String>) -> RemoteWriteBuilder {
+        RemoteWriteBuilder::new(url)
+    }
+
+    /// The endpoint URL.
+    pub fn url(&self) -> &Uri {
+        &self.url
+    }
+
+    /// Convert a snapshot into the (uncompressed) requests that would be
+    /// sent for it.
+    ///
+    /// Samples without an explicit timestamp get the snapshot's. Metadata
+    /// for every family is sent with the first request.
+    pub fn write_requests(&self, snapshot: &Snapshot) -> Vec<proto::WriteRequest> {
+        let default_timestamp = millis(snapshot.timestamp);
+
+        let metadata = snapshot
+            .families
+            .iter()
+            .map(|family| proto::MetricMetadata {
+                r#type: metric_type(family.kind) as i32,
+                metric_family_name: family.name.clone(),
+                help: family.help.clone().unwrap_or_default(),
+                unit: family.unit.clone().unwrap_or_default(),
+            })
+            .collect();
+
+        let series: Vec<proto::TimeSeries> = snapshot
+            .samples()
+            .map(|(_, sample)| {
+                let timestamp = sample
+                    .timestamp
+                    .map(seconds_to_millis)
+                    .unwrap_or(default_timestamp);
+                let exemplars = sample
+                    .exemplar
+                    .iter()
+                    .map(|exemplar| proto::Exemplar {
+                        labels: to_labels(&exemplar.labels),
+                        value: exemplar.value,
+                        timestamp: exemplar
+                            .timestamp
+                            .map(seconds_to_millis)
+                            .unwrap_or(timestamp),
+                    })
+                    .collect();
+                proto::TimeSeries {
+                    labels: self.series_labels(&sample.name, &sample.labels),
+                    samples: vec![proto::Sample {
+                        value: sample.value,
+                        timestamp,
+                    }],
+                    exemplars,
+                }
+            })
+            .collect();
+
+        let mut requests: Vec<proto::WriteRequest> = series
+            .chunks(self.max_series_per_request)
+            .map(|chunk| proto::WriteRequest {
+                timeseries: chunk.to_vec(),
+                metadata: Vec::new(),
+            })
+            .collect();
+        match requests.first_mut() {
+            Some(first) => first.metadata = metadata,
+            None => requests.push(proto::WriteRequest {
+                timeseries: Vec::new(),
+                metadata,
+            }),
+        }
+        requests
+    }
+
+    /// `__name__`, the sample's labels and any missing external labels,
+    /// sorted by name as remote-write requires.
+    fn series_labels(&self, name: &str, labels: &SampleLabels) -> Vec<proto::Label> {
+        let mut result = Vec::with_capacity(labels.len() + self.external_labels.len() + 1);
+        result.push(proto::Label {
+            name: "__name__".to_string(),
+            value: name.to_string(),
+        });
+        result.extend(to_labels(labels));
+        for (name, value) in &self.external_labels {
+            if !labels.iter().any(|(existing, _)| existing == name) {
+                result.push(proto::Label {
+                    name: name.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
+    }
+}
+
+impl Exporter for RemoteWriteExporter {
+    fn name(&self) -> &str {
+        "remote_write"
+    }
+
+    async fn export(&self, snapshot: &Snapshot) -> Result<(), ExportError> {
+        for request in self.write_requests(snapshot) {
+            if request.timeseries.is_empty() && request.metadata.is_empty() {
+                continue;
+            }
+            let body = snap::raw::Encoder::new()
+                .compress_vec(&request.encode_to_vec())
+                .map_err(ExportError::permanent)?;
+            self.client
+                .send(
+                    Method::POST,
+                    &self.url,
+                    &[
+                        (CONTENT_TYPE, "application/x-protobuf"),
+                        (CONTENT_ENCODING, "snappy"),
+                        (REMOTE_WRITE_VERSION_HEADER, REMOTE_WRITE_VERSION),
+                    ],
+                    Bytes::from(body),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn to_labels(labels: &SampleLabels) -> Vec<proto::Label> {
+    labels
+        .iter()
+        .map(|(name, value)| proto::Label {
+            name: name.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+fn metric_type(kind: MetricKind) -> proto::MetricType {
+    match kind {
+        MetricKind::Counter => proto::MetricType::Counter,
+        MetricKind::Gauge => proto::MetricType::Gauge,
+        MetricKind::Histogram => proto::MetricType::Histogram,
+        MetricKind::GaugeHistogram => proto::MetricType::GaugeHistogram,
+        MetricKind::Summary => proto::MetricType::Summary,
+        MetricKind::Info => proto::MetricType::Info,
+        MetricKind::StateSet => proto::MetricType::StateSet,
+        MetricKind::Unknown => proto::MetricType::Unknown,
+    }
+}
+
+fn millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn seconds_to_millis(seconds: f64) -> i64 {
+    (seconds * 1000.0).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+# HELP jobs Jobs processed.
+# TYPE jobs counter
+jobs_total{queue=\"default\"} 3
+jobs_total{queue=\"mail\",cluster=\"local\"} 1
+# HELP temperature Temperature.
+# TYPE temperature gauge
+# UNIT temperature celsius
+temperature 21.5 1700000000.5
+# EOF
+";
+
+    fn snapshot() -> Snapshot {
+        let mut snapshot = Snapshot::parse(TEXT).unwrap();
+        snapshot.timestamp = UNIX_EPOCH + std::time::Duration::from_secs(1_800_000_000);
+        snapshot
+    }
+
+    fn labels(series: &proto::TimeSeries) -> Vec<(&str, &str)> {
+        series
+            .labels
+            .iter()
+            .map(|label| (label.name.as_str(), label.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn series_carry_sorted_labels_and_external_labels() {
+        let exporter = RemoteWriteExporter::builder("http://localhost/api/v1/write")
+            .external_label("cluster", "eu")
+            .external_label("replica", "a")
+            .build()
+            .unwrap();
+        let requests = exporter.write_requests(&snapshot());
+        assert_eq!(requests.len(), 1);
+        let series = &requests[0].timeseries;
+        assert_eq!(series.len(), 3);
+
+        assert_eq!(
+            labels(&series[0]),
+            vec![
+                ("__name__", "jobs_total"),
+                ("cluster", "eu"),
+                ("queue", "default"),
+                ("replica", "a")
+            ]
+        );
+        // A label already on the series wins over the external label.
+        assert!(labels(&series[1]).contains(&("cluster", "local")));
+        assert!(!labels(&series[1]).contains(&("cluster", "eu")));
+
+        assert_eq!(series[0].samples[0].value, 3.0);
+        assert_eq!(series[0].samples[0].timestamp, 1_800_000_000_000);
+        assert_eq!(series[2].samples[0].timestamp, 1_700_000_000_500);
+    }
+
+    #[test]
+    fn metadata_is_sent_with_the_first_batch() {
+        let exporter = RemoteWriteExporter::builder("http://localhost/api/v1/write")
+            .max_series_per_request(2)
+            .build()
+            .unwrap();
+        let requests = exporter.write_requests(&snapshot());
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].timeseries.len(), 2);
+        assert_eq!(requests[1].timeseries.len(), 1);
+        assert!(requests[1].metadata.is_empty());
+
+        let metadata = &requests[0].metadata;
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata[0].r#type, proto::MetricType::Counter as i32);
+        assert_eq!(metadata[0].metric_family_name, "jobs");
+        assert_eq!(metadata[1].unit, "celsius");
+    }
+
+    #[test]
+    fn invalid_configuration_is_rejected() {
+        let build = |builder: RemoteWriteBuilder| builder.build().unwrap_err();
+        let url = "http://localhost/api/v1/write";
+        assert!(matches!(
+            build(RemoteWriteExporter::builder(url).max_series_per_request(0)),
+            HttpClientError::InvalidConfig(_)
+        ));
+        assert!(matches!(
+            build(RemoteWriteExporter::builder(url).external_label("__tenant", "a")),
+            HttpClientError::InvalidConfig(_)
+        ));
+        assert!(matches!(
+            build(RemoteWriteExporter::builder(url).external_label("env", "")),
+            HttpClientError::InvalidConfig(_)
+        ));
+        assert!(matches!(
+            build(RemoteWriteExporter::builder(url).header("bad header", "x")),
+            HttpClientError::InvalidHeader { .. }
+        ));
+        assert!(matches!(
+            build(RemoteWriteExporter::builder("localhost:9090")),
+            HttpClientError::InvalidUrl { .. }
+        ));
+    }
+}
@@ -0,0 +1,493 @@
+//! Interval-driven export loop with retry, backoff and a final flush.
+
+use super::{ExportError, Exporter};
+use crate::core::metrics::Metric;
+use crate::core::registry::{MetricBackend, ObservabilityRegistry};
+use crate::core::renderer::MetricsRenderer;
+use crate::core::unit::Unit;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{oneshot, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+
+/// A registry shared between the application, the HTTP server and exporters.
+///
+/// This is the type returned by
+/// [`StandaloneServer::registry`](crate::http::standalone::StandaloneServer::registry).
+pub type SharedRegistry<B> = Arc<RwLock<ObservabilityRegistry<B>>>;
+
+/// Buckets for the export duration self-metric, in seconds.
+const EXPORT_DURATION_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Error starting an [`ExportScheduler`].
+#[derive(Debug, thiserror::Error)]
+pub enum SpawnError<E> {
+    /// The [`ExportConfig`] can't be used, e.g. a zero interval.
+    #[error("invalid export config: {0}")]
+    InvalidConfig(String),
+
+    /// The self-metrics could not be registered.
+    #[error("failed to register export self-metrics: {0}")]
+    Register(E),
+}
+
+/// Timing and retry settings of an [`ExportScheduler`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportConfig {
+    /// Time between exports.
+    pub interval: Duration,
+    /// Maximum duration of one export attempt.
+    pub timeout: Duration,
+    /// Retries after the first failed attempt; the batch is dropped after that.
+    pub max_retries: u32,
+    /// Backoff before the first retry; doubles with every retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff.
+    pub max_backoff: Duration,
+    /// Random spread applied to each backoff, as a fraction (0.2 = ±20%).
+    pub jitter: f64,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            timeout: Duration::from_secs(10),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl ExportConfig {
+    /// Set the time between exports; must not be zero.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the maximum duration of one export attempt.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the number of retries after a failed attempt.
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Set the initial and maximum backoff.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Set the backoff jitter, clamped to `0.0..=1.0`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Backoff before retry number `retry` (0-based), without jitter.
+    fn base_backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/// Self-metrics published by a running scheduler.
+struct SelfMetrics<B: MetricBackend> {
+    duration: Metric<B::Histogram>,
+    failures: Metric<B::Counter>,
+    dropped: Metric<B::Counter>,
+}
+
+impl<B: MetricBackend> SelfMetrics<B> {
+    fn register(registry: &mut ObservabilityRegistry<B>, exporter: &str) -> Result<Self, B::Error> {
+        let prefix = format!("observe_rs_export_{}", exporter);
+        Ok(Self {
+            duration: registry.histogram_with_unit(
+                format!("{}_duration_seconds", prefix),
+                format!("Duration of {} export attempts", exporter),
+                EXPORT_DURATION_BUCKETS.to_vec(),
+                Unit::Seconds,
+            )?,
+            failures: registry.counter(
+                format!("{}_failures", prefix),
+                format!("Failed {} export attempts", exporter),
+            )?,
+            dropped: registry.counter(
+                format!("{}_dropped_batches", prefix),
+                format!("Snapshots {} gave up on after retrying", exporter),
+            )?,
+        })
+    }
+}
+
+/// Runs an [`Exporter`] on an interval.
+///
+/// Each tick takes a snapshot of the registry and hands it to the exporter.
+/// Transient failures and timeouts are retried with exponential, jittered
+/// backoff up to [`ExportConfig::max_retries`] times; after that, or on a
+/// permanent failure, the snapshot is dropped. Shutting down through the
+/// [`ExportHandle`] exports one final snapshot.
+///
+/// The scheduler registers three self-metrics, named after
+/// [`Exporter::name`]:
+///
+/// - `observe_rs_export_<name>_duration_seconds`: duration of each attempt
+/// - `observe_rs_export_<name>_failures_total`: failed attempts
+/// - `observe_rs_export_<name>_dropped_batches_total`: snapshots given up on
+pub struct ExportScheduler<E: Exporter, B: MetricBackend> {
+    exporter: Arc<E>,
+    registry: SharedRegistry<B>,
+    config: ExportConfig,
+    metrics: Option<Arc<SelfMetrics<B>>>,
+    rng: Arc<AtomicU64>,
+}
+
+impl<E, B> ExportScheduler<E, B>
+where
+    E: Exporter,
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    /// Create a scheduler exporting snapshots of `registry` through
+    /// `exporter`, with the default [`ExportConfig`].
+    pub fn new(exporter: E, registry: SharedRegistry<B>) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0x9E37_79B9_7F4A_7C15);
+        Self {
+            exporter: Arc::new(exporter),
+            registry,
+            config: ExportConfig::default(),
+            metrics: None,
+            rng: Arc::new(AtomicU64::new(seed | 1)),
+        }
+    }
+
+    /// Replace the timing and retry settings.
+    pub fn with_config(mut self, config: ExportConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The exporter.
+    pub fn exporter(&self) -> &E {
+        &self.exporter
+    }
+
+    /// Export one snapshot now, with retries.
+    ///
+    /// Returns the last error if the snapshot was dropped.
+    pub async fn export_now(&self) -> Result<(), ExportError> {
+        let snapshot = match self.registry.read().await.snapshot() {
+            Ok(snapshot) => snapshot,
+            Err(error) => {
+                self.record_failure(true);
+                return Err(error.into());
+            }
+        };
+
+        let mut retry = 0;
+        loop {
+            let started = Instant::now();
+            let result = tokio::time::timeout(self.config.timeout, self.exporter.export(&snapshot))
+                .await
+                .unwrap_or(Err(ExportError::Timeout(self.config.timeout)));
+            if let Some(metrics) = &self.metrics {
+                metrics.duration.observe(started.elapsed().as_secs_f64());
+            }
+
+            let error = match result {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+            if !error.is_retryable() || retry >= self.config.max_retries {
+                self.record_failure(true);
+                return Err(error);
+            }
+            self.record_failure(false);
+            tokio::time::sleep(self.backoff(retry)).await;
+            retry += 1;
+        }
+    }
+
+    /// Register the self-metrics and start exporting in a background task.
+    ///
+    /// Must be called from within a tokio runtime. Fails if the interval is
+    /// zero or the self-metrics cannot be registered (e.g. the exporter name
+    /// is not a valid metric name fragment).
+    pub async fn spawn(mut self) -> Result<ExportHandle, SpawnError<B::Error>> {
+        if self.config.interval.is_zero() {
+            return Err(SpawnError::InvalidConfig(
+                "export interval must not be zero".to_string(),
+            ));
+        }
+        let metrics =
+            SelfMetrics::register(&mut *self.registry.write().await, self.exporter.name())
+                .map_err(SpawnError::Register)?;
+        self.metrics = Some(Arc::new(metrics));
+
+        let (shutdown, mut shutdown_rx) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            let period = self.config.interval;
+            let mut ticker = tokio::time::interval_at(Instant::now() + period, period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        let _ = self.export_now().await;
+                    }
+                    _ = &mut shutdown_rx => break,
+                }
+            }
+            let _ = self.export_now().await;
+            let _ = self.exporter.shutdown().await;
+        });
+
+        Ok(ExportHandle {
+            shutdown: Some(shutdown),
+            task,
+        })
+    }
+
+    fn record_failure(&self, dropped: bool) {
+        if let Some(metrics) = &self.metrics {
+            metrics.failures.inc();
+            if dropped {
+                metrics.dropped.inc();
+            }
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let base = self.config.base_backoff(retry);
+        if self.config.jitter == 0.0 {
+            return base;
+        }
+        // xorshift64: good enough to spread retries, no extra dependency.
+        let mut x = self.rng.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng.store(x, Ordering::Relaxed);
+        let unit = (x >> 11) as f64 / (1u64 << 53) as f64;
+        base.mul_f64(1.0 - self.config.jitter + 2.0 * self.config.jitter * unit)
+    }
+}
+
+/// Handle to a running [`ExportScheduler`].
+///
+/// Dropping the handle stops the scheduler without a final flush; call
+/// [`shutdown`](Self::shutdown) to flush.
+#[derive(Debug)]
+pub struct ExportHandle {
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl ExportHandle {
+    /// Stop the scheduler after one final export, and wait for it.
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for ExportHandle {
+    fn drop(&mut self) {
+        if self.shutdown.is_some() {
+            self.task.abort();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+    use super::*;
+    use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+    use crate::core::snapshot::Snapshot;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    #[derive(Default, Clone)]
+    struct Recording {
+        attempts: Arc<Mutex<Vec<(Instant, usize)>>>,
+        script: Arc<Mutex<VecDeque<Result<(), ExportError>>>>,
+        delay: Option<Duration>,
+        shut_down: Arc<Mutex<bool>>,
+    }
+
+    impl Recording {
+        fn scripted(results: Vec<Result<(), ExportError>>) -> Self {
+            Self {
+                script: Arc::new(Mutex::new(results.into())),
+                ..Self::default()
+            }
+        }
+
+        fn attempts(&self) -> Vec<(Instant, usize)> {
+            self.attempts.lock().unwrap().clone()
+        }
+    }
+
+    impl Exporter for Recording {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        async fn export(&self, snapshot: &Snapshot) -> Result<(), ExportError> {
+            self.attempts
+                .lock()
+                .unwrap()
+                .push((Instant::now(), snapshot.sample_count()));
+            if let Some(delay) = self.delay {
+                tokio::time::sleep(delay).await;
+            }
+            self.script.lock().unwrap().pop_front().unwrap_or(Ok(()))
+        }
+
+        async fn shutdown(&self) -> Result<(), ExportError> {
+            *self.shut_down.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    fn registry() -> SharedRegistry<PrometheusBackend> {
+        let mut registry = ObservabilityRegistry::new();
+        registry.counter("jobs", "Jobs").unwrap().inc();
+        Arc::new(RwLock::new(registry))
+    }
+
+    fn config() -> ExportConfig {
+        ExportConfig::default()
+            .interval(Duration::from_secs(10))
+            .backoff(Duration::from_secs(1), Duration::from_secs(4))
+            .jitter(0.0)
+    }
+
+    fn transient() -> Result<(), ExportError> {
+        Err(ExportError::transient("503 Service Unavailable"))
+    }
+
+    async fn render(registry: &SharedRegistry<PrometheusBackend>) -> String {
+        let registry = registry.read().await;
+        registry.render().unwrap().as_str().unwrap().to_string()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn exports_on_every_tick_and_flushes_on_shutdown() {
+        let exporter = Recording::default();
+        let handle = ExportScheduler::new(exporter.clone(), registry())
+            .with_config(config())
+            .spawn()
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        assert_eq!(exporter.attempts().len(), 2);
+
+        handle.shutdown().await;
+        assert_eq!(exporter.attempts().len(), 3);
+        assert!(*exporter.shut_down.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn zero_interval_is_rejected() {
+        let result = ExportScheduler::new(Recording::default(), registry())
+            .with_config(config().interval(Duration::ZERO))
+            .spawn()
+            .await;
+        assert!(matches!(result, Err(SpawnError::InvalidConfig(_))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transient_failures_are_retried_with_exponential_backoff() {
+        let registry = registry();
+        let exporter = Recording::scripted(vec![transient(), transient(), transient(), Ok(())]);
+        let scheduler = ExportScheduler::new(exporter.clone(), Arc::clone(&registry))
+            .with_config(config().max_retries(5));
+
+        scheduler.export_now().await.unwrap();
+
+        let attempts = exporter.attempts();
+        let gaps: Vec<u64> = attempts
+            .windows(2)
+            .map(|pair| (pair[1].0 - pair[0].0).as_secs())
+            .collect();
+        assert_eq!(gaps, vec![1, 2, 4]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_are_dropped_after_max_retries_or_permanent_errors() {
+        let registry = registry();
+        let exporter = Recording::scripted(vec![
+            transient(),
+            transient(),
+            Err(ExportError::permanent("400 Bad Request")),
+        ]);
+        let handle = ExportScheduler::new(exporter.clone(), Arc::clone(&registry))
+            .with_config(config().max_retries(1))
+            .spawn()
+            .await
+            .unwrap();
+
+        // First tick: two transient failures, dropped. Second: permanent.
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        handle.shutdown().await;
+
+        let text = render(&registry).await;
+        assert!(
+            text.contains("observe_rs_export_test_failures_total 3\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("observe_rs_export_test_dropped_batches_total 2\n"));
+        assert!(text.contains("observe_rs_export_test_duration_seconds_count 4\n"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_exports_time_out_and_are_retried() {
+        let exporter = Recording {
+            delay: Some(Duration::from_secs(60)),
+            ..Recording::default()
+        };
+        let scheduler = ExportScheduler::new(exporter.clone(), registry())
+            .with_config(config().timeout(Duration::from_secs(5)).max_retries(1));
+
+        let error = scheduler.export_now().await.unwrap_err();
+        assert!(matches!(error, ExportError::Timeout(_)));
+        assert_eq!(exporter.attempts().len(), 2);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let config = config();
+        let backoffs: Vec<u64> = (0..5).map(|i| config.base_backoff(i).as_secs()).collect();
+        assert_eq!(backoffs, vec![1, 2, 4, 4, 4]);
+    }
+
+    #[tokio::test]
+    async fn jitter_stays_within_bounds() {
+        let scheduler = ExportScheduler::new(Recording::default(), registry())
+            .with_config(config().jitter(0.5));
+        for _ in 0..100 {
+            let backoff = scheduler.backoff(1);
+            assert!(backoff >= Duration::from_secs(1) && backoff <= Duration::from_secs(3));
+        }
+    }
+}
@@ -0,0 +1,513 @@
+//! node_exporter textfile collector sink.
+//!
+//! Writes the registry to a `.prom` file that node_exporter's textfile
+//! collector (`--collector.textfile.directory`) picks up, for cron jobs and
+//! hosts where running an HTTP endpoint isn't an option:
+//!
+//! ```ignore
+//! let sink = TextfileExporter::builder("/var/lib/node_exporter/backup.prom")
+//!     .interval(Duration::from_secs(60))
+//!     .mode(0o644)
+//!     .build()?
+//!     .spawn(server.registry())
+//!     .await?;
+//! // ... written every minute, and once more when `sink` is dropped
+//! ```
+//!
+//! Every write goes to a temporary file in the same directory which is then
+//! renamed over the target, so node_exporter never reads a partial file.
+//! node_exporter parses the Prometheus text format and rejects files with
+//! timestamps or duplicate series, so snapshots are checked for both before
+//! anything is written.
+
+use super::{
+    ExportConfig, ExportError, ExportHandle, ExportScheduler, Exporter, SharedRegistry, SpawnError,
+};
+use crate::core::registry::MetricBackend;
+use crate::core::renderer::MetricsRenderer;
+use crate::core::snapshot::{Snapshot, SnapshotError};
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Distinguishes temporary files of concurrent writes within the process.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Default time between writes.
+pub const DEFAULT_TEXTFILE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Default permissions of the written file (`rw-r--r--`).
+pub const DEFAULT_TEXTFILE_MODE: u32 = 0o644;
+
+/// Error writing a textfile.
+#[derive(Debug, thiserror::Error)]
+pub enum TextfileError {
+    /// node_exporter only reads files ending in `.prom`.
+    #[error("textfile path {0:?} must have a file name ending in .prom")]
+    InvalidPath(PathBuf),
+
+    /// A sample carries a timestamp, which node_exporter rejects.
+    #[error("series {0} has a timestamp; node_exporter rejects timestamped textfile metrics")]
+    Timestamp(String),
+
+    /// The same series appears twice, which node_exporter rejects.
+    #[error("duplicate series {0}")]
+    DuplicateSeries(String),
+
+    /// The registry could not be snapshotted.
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
+
+    /// Writing or renaming the file failed.
+    #[error("failed to write {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl From<TextfileError> for ExportError {
+    fn from(error: TextfileError) -> Self {
+        match error {
+            // A full disk or a directory that doesn't exist yet may recover.
+            TextfileError::Io { .. } => ExportError::transient(error),
+            TextfileError::Snapshot(error) => ExportError::Snapshot(error),
+            error => ExportError::permanent(error),
+        }
+    }
+}
+
+/// Builder for [`TextfileExporter`].
+#[derive(Debug, Clone)]
+pub struct TextfileBuilder {
+    path: PathBuf,
+    interval: Duration,
+    mode: u32,
+}
+
+impl TextfileBuilder {
+    /// Set the time between writes when spawned; must not be zero. Default:
+    /// 15 seconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the Unix permissions of the file. Default: `0o644`. Ignored on
+    /// other platforms.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Validate the path and build the exporter.
+    pub fn build(self) -> Result<TextfileExporter, TextfileError> {
+        let valid = self
+            .path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(".prom") && name != ".prom");
+        if !valid {
+            return Err(TextfileError::InvalidPath(self.path));
+        }
+        Ok(TextfileExporter {
+            path: self.path,
+            interval: self.interval,
+            mode: self.mode,
+            closed: Arc::default(),
+        })
+    }
+}
+
+/// [`Exporter`] that writes snapshots to a node_exporter textfile.
+///
+/// Its self-metrics are named `observe_rs_export_textfile_*`.
+#[derive(Debug, Clone)]
+pub struct TextfileExporter {
+    path: PathBuf,
+    interval: Duration,
+    mode: u32,
+    /// Held during writes and set by the guard's final write, so a
+    /// periodic write still in flight can't replace the final file.
+    closed: Arc<Mutex<bool>>,
+}
+
+impl TextfileExporter {
+    /// Start configuring a sink writing to `path`, which must end in `.prom`.
+    pub fn builder(path: impl Into<PathBuf>) -> TextfileBuilder {
+        TextfileBuilder {
+            path: path.into(),
+            interval: DEFAULT_TEXTFILE_INTERVAL,
+            mode: DEFAULT_TEXTFILE_MODE,
+        }
+    }
+
+    /// The target file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Validate `snapshot` and atomically replace the file with it.
+    ///
+    /// Does nothing once the [`TextfileGuard`] of a spawned sink has made
+    /// its final write. This blocks on file I/O; async callers use the
+    /// blocking thread pool.
+    pub fn write(&self, snapshot: &Snapshot) -> Result<(), TextfileError> {
+        validate(snapshot)?;
+        self.replace_file(&snapshot.to_prometheus_text(), false)
+    }
+
+    /// Write `snapshot` and stop all further writes.
+    fn write_final(&self, snapshot: &Snapshot) -> Result<(), TextfileError> {
+        validate(snapshot)?;
+        self.replace_file(&snapshot.to_prometheus_text(), true)
+    }
+
+    /// [`write`](Self::write) with the file I/O on the blocking thread pool.
+    async fn write_async(&self, snapshot: &Snapshot) -> Result<(), TextfileError> {
+        validate(snapshot)?;
+        let text = snapshot.to_prometheus_text();
+        let exporter = self.clone();
+        tokio::task::spawn_blocking(move || exporter.replace_file(&text, false))
+            .await
+            .unwrap_or_else(|_| {
+                Err(TextfileError::Io {
+                    path: self.path.clone(),
+                    source: io::Error::other("textfile write panicked"),
+                })
+            })
+    }
+
+    /// Atomically replace the file with `text`, unless the final write was
+    /// made already. `last` makes this the final write.
+    fn replace_file(&self, text: &str, last: bool) -> Result<(), TextfileError> {
+        let mut closed = self.closed.lock().unwrap_or_else(|e| e.into_inner());
+        if *closed && !last {
+            return Ok(());
+        }
+        *closed = last || *closed;
+
+        let io_error = |source| TextfileError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        let file_name = self
+            .path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| TextfileError::InvalidPath(self.path.clone()))?;
+        // Doesn't end in `.prom`, so node_exporter skips it while it's
+        // being written.
+        let temp = self.path.with_file_name(format!(
+            ".{}.{}-{}.tmp",
+            file_name,
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = (|| {
+            let mut file = File::create(&temp)?;
+            file.write_all(text.as_bytes())?;
+            set_mode(&file, self.mode)?;
+            file.sync_all()?;
+            fs::rename(&temp, &self.path)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result.map_err(io_error)?;
+        // Make the rename itself durable.
+        sync_parent_dir(&self.path).map_err(io_error)
+    }
+
+    /// Start writing `registry` every [`interval`](TextfileBuilder::interval).
+    ///
+    /// The returned guard writes once more when dropped.
+    pub async fn spawn<B>(
+        self,
+        registry: SharedRegistry<B>,
+    ) -> Result<TextfileGuard<B>, SpawnError<B::Error>>
+    where
+        B: MetricBackend,
+        <B::Registry as MetricsRenderer>::Error: Display,
+    {
+        let handle = ExportScheduler::new(self.clone(), registry.clone())
+            .with_config(ExportConfig::default().interval(self.interval))
+            .spawn()
+            .await?;
+        Ok(TextfileGuard {
+            exporter: self,
+            registry,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Exporter for TextfileExporter {
+    fn name(&self) -> &str {
+        "textfile"
+    }
+
+    async fn export(&self, snapshot: &Snapshot) -> Result<(), ExportError> {
+        self.write_async(snapshot).await.map_err(ExportError::from)
+    }
+}
+
+/// Check `snapshot` for what node_exporter rejects: timestamps and
+/// duplicate series.
+pub fn validate(snapshot: &Snapshot) -> Result<(), TextfileError> {
+    let mut seen = HashSet::new();
+    for (_, sample) in snapshot.samples() {
+        let mut labels = sample.labels.clone();
+        labels.sort();
+        let series = format!(
+            "{}{{{}}}",
+            sample.name,
+            labels
+                .iter()
+                .map(|(name, value)| format!("{}={:?}", name, value))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        if sample.timestamp.is_some() {
+            return Err(TextfileError::Timestamp(series));
+        }
+        if !seen.insert(series.clone()) {
+            return Err(TextfileError::DuplicateSeries(series));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(file: &File, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    file.set_permissions(fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_file: &File, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Flush the directory entry of `path` to disk.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+/// Directories can't be opened for syncing on other platforms.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Running textfile sink. Created by [`TextfileExporter::spawn`].
+///
+/// Dropping the guard stops the periodic writes and writes the file one
+/// last time, blocking the current thread; [`shutdown`](Self::shutdown)
+/// does the same asynchronously.
+pub struct TextfileGuard<B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    exporter: TextfileExporter,
+    registry: SharedRegistry<B>,
+    handle: Option<ExportHandle>,
+}
+
+impl<B> TextfileGuard<B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    /// Stop the periodic writes after a final write.
+    pub async fn shutdown(mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.shutdown().await;
+        }
+    }
+
+    /// Write the current state of the registry now.
+    pub async fn write_now(&self) -> Result<(), TextfileError> {
+        let snapshot = self.registry.read().await.snapshot()?;
+        self.exporter.write_async(&snapshot).await
+    }
+
+    fn write_blocking(&self) -> Result<(), TextfileError> {
+        // `blocking_read` panics on a runtime thread, so read from a helper.
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let snapshot = self.registry.blocking_read().snapshot()?;
+                    self.exporter.write_final(&snapshot)
+                })
+                .join()
+                .unwrap_or_else(|_| {
+                    Err(TextfileError::Io {
+                        path: self.exporter.path.clone(),
+                        source: io::Error::other("textfile write panicked"),
+                    })
+                })
+        })
+    }
+}
+
+impl<B> fmt::Debug for TextfileGuard<B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TextfileGuard")
+            .field("exporter", &self.exporter)
+            .field("running", &self.handle.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B> Drop for TextfileGuard<B>
+where
+    B: MetricBackend,
+    <B::Registry as MetricsRenderer>::Error: Display,
+{
+    fn drop(&mut self) {
+        // `shutdown` already flushed.
+        if let Some(handle) = self.handle.take() {
+            drop(handle);
+            // There is nobody to report to from `drop`; the periodic writes
+            // have already surfaced persistent errors in the self-metrics.
+            let _ = self.write_blocking();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+    use super::*;
+    use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+    use crate::core::registry::ObservabilityRegistry;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "observe-rs-textfile-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn registry() -> SharedRegistry<PrometheusBackend> {
+        let mut registry = ObservabilityRegistry::new();
+        registry
+            .counter("backups", "Completed backups")
+            .unwrap()
+            .inc();
+        Arc::new(RwLock::new(registry))
+    }
+
+    #[test]
+    fn paths_must_end_in_prom() {
+        assert!(TextfileExporter::builder("/tmp/job.prom").build().is_ok());
+        for path in ["/tmp/job.txt", "/tmp/.prom", "/"] {
+            assert!(matches!(
+                TextfileExporter::builder(path).build(),
+                Err(TextfileError::InvalidPath(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn writes_prometheus_text_atomically_with_permissions() {
+        let dir = temp_dir("write");
+        let path = dir.join("job.prom");
+        let exporter = TextfileExporter::builder(&path)
+            .mode(0o600)
+            .build()
+            .unwrap();
+
+        let snapshot = Snapshot::parse("# TYPE a gauge\na{x=\"1\"} 2\n# EOF\n").unwrap();
+        exporter.write(&snapshot).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# TYPE a gauge\na{x=\"1\"} 2\n"
+        );
+        // Only the target remains; the temporary file was renamed.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn timestamps_and_duplicate_series_are_rejected() {
+        let dir = temp_dir("validate");
+        let path = dir.join("job.prom");
+        let exporter = TextfileExporter::builder(&path).build().unwrap();
+
+        let timestamped = Snapshot::parse("a 1 1700000000\n").unwrap();
+        assert!(matches!(
+            exporter.write(&timestamped),
+            Err(TextfileError::Timestamp(_))
+        ));
+
+        let duplicated = Snapshot::parse("a{x=\"1\",y=\"2\"} 1\na{y=\"2\",x=\"1\"} 2\n").unwrap();
+        let error = exporter.write(&duplicated).unwrap_err();
+        assert!(matches!(error, TextfileError::DuplicateSeries(_)));
+        assert!(!ExportError::from(error).is_retryable());
+
+        // Nothing was written.
+        assert!(!path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn spawned_sink_writes_periodically_and_on_drop() {
+        let dir = temp_dir("spawn");
+        let path = dir.join("job.prom");
+        let registry = registry();
+
+        let guard = TextfileExporter::builder(&path)
+            .interval(Duration::from_millis(20))
+            .build()
+            .unwrap()
+            .spawn(Arc::clone(&registry))
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(written.contains("backups_total 1\n"), "{}", written);
+        assert!(written.contains("# TYPE observe_rs_export_textfile_failures_total counter"));
+
+        registry
+            .write()
+            .await
+            .gauge("finished", "Job finished")
+            .unwrap()
+            .set(1);
+        drop(guard);
+        assert!(fs::read_to_string(&path).unwrap().contains("finished 1\n"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
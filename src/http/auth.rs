@@ -0,0 +1,678 @@
+//! Authentication for the standalone server's endpoints.
+//!
+//! An [`AuthPolicy`] combines an address allowlist with credentials: HTTP
+//! basic auth against bcrypt or Argon2 password hashes, and static bearer
+//! tokens. Policies are set per [`Endpoint`](super::standalone::Endpoint),
+//! so `/health` can stay open while `/metrics` is protected.
+//!
+//! A request must come from an allowed address, if there is an allowlist,
+//! and carry valid credentials, if any are configured. Requests from other
+//! addresses get `403 Forbidden`; missing or wrong credentials get
+//! `401 Unauthorized`.
+//!
+//! # Example
+//! ```ignore
+//! let server = StandaloneServer::<PrometheusBackend>::builder()
+//!     .auth(
+//!         Endpoint::Metrics,
+//!         AuthPolicy::new()
+//!             .basic_user("prometheus", "$2b$12$...")?
+//!             .bearer_tokens_file("/etc/metrics/tokens")?
+//!             .allow_cidr("10.0.0.0/8")?,
+//!     )
+//!     .build();
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use argon2::password_hash::{PasswordHash, PasswordVerifier};
+use axum::extract::{ConnectInfo, Request};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use ipnet::IpNet;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tokio::sync::Semaphore;
+
+use crate::core::metrics::Metric;
+use crate::core::registry::{MetricBackend, ObservabilityRegistry};
+
+/// Name of the counter of requests rejected for missing or wrong
+/// credentials.
+pub const AUTH_FAILURES_METRIC: &str = "observe_rs_server_auth_failures";
+
+/// Name of the counter of requests rejected by an address allowlist.
+pub const AUTH_DENIED_ADDRESSES_METRIC: &str = "observe_rs_server_auth_denied_addresses";
+
+/// Most successful basic-auth logins remembered per policy, so repeated
+/// scrapes skip the slow password hash.
+const VERIFIED_CACHE_CAPACITY: usize = 64;
+
+/// Most password hashes verified at once per policy. Further basic-auth
+/// attempts are turned away rather than queued on the blocking pool that
+/// renders share.
+const MAX_CONCURRENT_VERIFICATIONS: usize = 4;
+
+/// SHA-256 of a secret, compared instead of the secret itself so the
+/// comparison takes the same time whatever the secret's length.
+type SecretDigest = [u8; 32];
+
+/// HMAC-SHA256 of a `user:password` pair under a key random to this
+/// process, so the verified-login cache can't be used to test guesses
+/// offline.
+fn cache_key(login: &[u8]) -> SecretDigest {
+    static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+    let key = KEY.get_or_init(|| {
+        let mut key = [0; 32];
+        getrandom::fill(&mut key).expect("the OS random number generator is available");
+        key
+    });
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(login);
+    mac.finalize().into_bytes().into()
+}
+
+fn digest(secret: &[u8]) -> SecretDigest {
+    Sha256::digest(secret).into()
+}
+
+/// Errors from building an [`AuthPolicy`].
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    /// A token file couldn't be read.
+    #[error("failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A token file has no tokens.
+    #[error("no bearer tokens in {0}")]
+    NoTokens(PathBuf),
+
+    /// A password hash isn't a bcrypt or Argon2 hash.
+    #[error("unsupported password hash for user {user:?}: {reason}")]
+    InvalidHash { user: String, reason: String },
+
+    /// An allowlist entry isn't an address or CIDR block.
+    #[error("invalid CIDR {cidr:?}: {reason}")]
+    InvalidCidr { cidr: String, reason: String },
+}
+
+/// A stored password hash.
+#[derive(Clone)]
+enum PasswordHashKind {
+    Bcrypt(String),
+    Argon2(String),
+}
+
+impl PasswordHashKind {
+    fn parse(user: &str, hash: &str) -> Result<Self, AuthError> {
+        let invalid = |reason: String| AuthError::InvalidHash {
+            user: user.to_string(),
+            reason,
+        };
+        if hash.starts_with("$argon2") {
+            PasswordHash::new(hash).map_err(|e| invalid(e.to_string()))?;
+            Ok(Self::Argon2(hash.to_string()))
+        } else if hash.starts_with("$2") {
+            hash.parse::<bcrypt::HashParts>()
+                .map_err(|e| invalid(e.to_string()))?;
+            Ok(Self::Bcrypt(hash.to_string()))
+        } else {
+            Err(invalid(
+                "expected a bcrypt ($2b$...) or Argon2 ($argon2id$...) hash".to_string(),
+            ))
+        }
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        match self {
+            Self::Bcrypt(hash) => bcrypt::verify(password, hash).unwrap_or(false),
+            Self::Argon2(hash) => PasswordHash::new(hash).is_ok_and(|parsed| {
+                argon2::Argon2::default()
+                    .verify_password(password.as_bytes(), &parsed)
+                    .is_ok()
+            }),
+        }
+    }
+}
+
+/// Who may call an endpoint.
+///
+/// An empty policy lets everyone in.
+#[derive(Clone, Default)]
+pub struct AuthPolicy {
+    users: HashMap<String, PasswordHashKind>,
+    /// Verified for unknown users, so a miss costs as much as a wrong
+    /// password.
+    dummy_hash: Option<PasswordHashKind>,
+    tokens: Vec<SecretDigest>,
+    allowlist: Vec<IpNet>,
+    /// Keyed digests of `user:password` pairs that verified recently.
+    verified: Arc<Mutex<HashSet<SecretDigest>>>,
+    verifications: Verifications,
+}
+
+/// Permits to verify a password hash, shared by clones of a policy.
+#[derive(Clone)]
+struct Verifications(Arc<Semaphore>);
+
+impl Default for Verifications {
+    fn default() -> Self {
+        Self(Arc::new(Semaphore::new(MAX_CONCURRENT_VERIFICATIONS)))
+    }
+}
+
+/// A basic-auth login that isn't in the verified cache.
+pub(crate) struct Login {
+    user: String,
+    password: String,
+    key: SecretDigest,
+}
+
+impl AuthPolicy {
+    /// A policy that lets everyone in.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept HTTP basic auth as `user` with a password matching `hash`,
+    /// a bcrypt (`$2b$...`) or Argon2 (`$argon2id$...`) PHC string.
+    pub fn basic_user(mut self, user: impl Into<String>, hash: &str) -> Result<Self, AuthError> {
+        let user = user.into();
+        let hash = PasswordHashKind::parse(&user, hash)?;
+        self.dummy_hash.get_or_insert_with(|| hash.clone());
+        self.users.insert(user, hash);
+        // Clones share the cache; don't let them accept a replaced password.
+        self.verified = Arc::default();
+        Ok(self)
+    }
+
+    /// Accept `Authorization: Bearer <token>`.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.tokens.push(digest(token.into().as_bytes()));
+        self
+    }
+
+    /// Accept the bearer tokens in a file, one per line.
+    ///
+    /// Blank lines and lines starting with `#` are skipped. The file is
+    /// read once, when the policy is built.
+    pub fn bearer_tokens_file(mut self, path: impl AsRef<Path>) -> Result<Self, AuthError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| AuthError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let before = self.tokens.len();
+        self.tokens.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|token| digest(token.as_bytes())),
+        );
+        if self.tokens.len() == before {
+            return Err(AuthError::NoTokens(path.to_path_buf()));
+        }
+        Ok(self)
+    }
+
+    /// Only accept requests from addresses in `cidr`, e.g. `10.0.0.0/8`,
+    /// `::1/128` or a single address.
+    pub fn allow_cidr(mut self, cidr: &str) -> Result<Self, AuthError> {
+        let net = cidr
+            .parse::<IpNet>()
+            .or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from))
+            .map_err(|e| AuthError::InvalidCidr {
+                cidr: cidr.to_string(),
+                reason: e.to_string(),
+            })?;
+        self.allowlist.push(net);
+        Ok(self)
+    }
+
+    /// Whether the policy lets everyone in.
+    pub fn is_open(&self) -> bool {
+        self.users.is_empty() && self.tokens.is_empty() && self.allowlist.is_empty()
+    }
+
+    fn requires_credentials(&self) -> bool {
+        !self.users.is_empty() || !self.tokens.is_empty()
+    }
+
+    /// Check a request from `peer` with `headers` against the policy,
+    /// verifying its password in place.
+    #[cfg(test)]
+    pub(crate) fn check(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Result<(), Denial> {
+        match self.check_cheaply(peer, headers)? {
+            Some(login) => self.verify(&login),
+            None => Ok(()),
+        }
+    }
+
+    /// Check a request from `peer` with `headers` against everything but
+    /// password hashes: the address, bearer tokens and cached logins.
+    /// Returns the basic-auth login left to [`verify`](Self::verify), if
+    /// any.
+    pub(crate) fn check_cheaply(
+        &self,
+        peer: Option<IpAddr>,
+        headers: &HeaderMap,
+    ) -> Result<Option<Login>, Denial> {
+        if !self.allowlist.is_empty() {
+            // IPv4 clients of a dual-stack listener show up as mapped
+            // IPv6 addresses.
+            let peer = peer.map(|ip| ip.to_canonical());
+            if !peer.is_some_and(|ip| self.allowlist.iter().any(|net| net.contains(&ip))) {
+                return Err(Denial::Forbidden);
+            }
+        }
+        if !self.requires_credentials() {
+            return Ok(None);
+        }
+        let authorization = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        match authorization.split_once(' ') {
+            Some((scheme, token))
+                if scheme.eq_ignore_ascii_case("bearer") && self.check_token(token.trim()) =>
+            {
+                Ok(None)
+            }
+            Some((scheme, encoded))
+                if scheme.eq_ignore_ascii_case("basic") && !self.users.is_empty() =>
+            {
+                match self.basic_login(encoded.trim()) {
+                    Some(login) if self.verified().contains(&login.key) => Ok(None),
+                    Some(login) => Ok(Some(login)),
+                    None => Err(Denial::Unauthorized),
+                }
+            }
+            _ => Err(Denial::Unauthorized),
+        }
+    }
+
+    fn check_token(&self, presented: &str) -> bool {
+        let presented = digest(presented.as_bytes());
+        // Compare against every token so timing doesn't reveal which one
+        // nearly matched.
+        self.tokens.iter().fold(false, |found, token| {
+            found | bool::from(token.ct_eq(&presented))
+        })
+    }
+
+    fn basic_login(&self, encoded: &str) -> Option<Login> {
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let key = cache_key(decoded.as_bytes());
+        let (user, password) = decoded.split_once(':')?;
+        Some(Login {
+            user: user.to_string(),
+            password: password.to_string(),
+            key,
+        })
+    }
+
+    /// Verify `login` against its user's password hash, caching it if it
+    /// matches.
+    ///
+    /// Password hashes are slow to verify by design; call this off the
+    /// async runtime.
+    pub(crate) fn verify(&self, login: &Login) -> Result<(), Denial> {
+        let accepted = match self.users.get(&login.user) {
+            Some(hash) => hash.verify(&login.password),
+            None => {
+                // Take as long as for a known user so timing doesn't
+                // reveal which users exist.
+                if let Some(dummy) = &self.dummy_hash {
+                    dummy.verify(&login.password);
+                }
+                false
+            }
+        };
+        if !accepted {
+            return Err(Denial::Unauthorized);
+        }
+        let mut verified = self.verified();
+        if verified.len() >= VERIFIED_CACHE_CAPACITY {
+            verified.clear();
+        }
+        verified.insert(login.key);
+        Ok(())
+    }
+
+    fn verified(&self) -> MutexGuard<'_, HashSet<SecretDigest>> {
+        self.verified.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The `WWW-Authenticate` challenge for a rejected request.
+    pub(crate) fn challenge(&self) -> HeaderValue {
+        match (self.users.is_empty(), self.tokens.is_empty()) {
+            (false, _) => HeaderValue::from_static("Basic realm=\"metrics\", charset=\"UTF-8\""),
+            _ => HeaderValue::from_static("Bearer realm=\"metrics\""),
+        }
+    }
+}
+
+impl fmt::Debug for AuthPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut users: Vec<_> = self.users.keys().collect();
+        users.sort();
+        f.debug_struct("AuthPolicy")
+            .field("users", &users)
+            .field("tokens", &format_args!("[{} redacted]", self.tokens.len()))
+            .field("allowlist", &self.allowlist)
+            .finish()
+    }
+}
+
+/// Why a request was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Denial {
+    /// The peer address isn't allowed.
+    Forbidden,
+    /// Credentials are missing or wrong.
+    Unauthorized,
+    /// Too many passwords are being verified already.
+    Busy,
+}
+
+/// Self-metrics of rejected requests.
+pub(crate) struct AuthMetrics<B: MetricBackend> {
+    failures: Metric<B::Counter>,
+    denied_addresses: Metric<B::Counter>,
+}
+
+impl<B: MetricBackend> AuthMetrics<B> {
+    /// Register the counters in `registry`.
+    pub(crate) fn register(registry: &mut ObservabilityRegistry<B>) -> Result<Self, B::Error> {
+        Ok(Self {
+            failures: registry.counter(
+                AUTH_FAILURES_METRIC,
+                "Requests rejected for missing or wrong credentials",
+            )?,
+            denied_addresses: registry.counter(
+                AUTH_DENIED_ADDRESSES_METRIC,
+                "Requests rejected because of their client address",
+            )?,
+        })
+    }
+}
+
+/// Enforces a policy on one endpoint's requests.
+pub(crate) struct AuthGuard<B: MetricBackend> {
+    policy: AuthPolicy,
+    metrics: Arc<AuthMetrics<B>>,
+}
+
+impl<B: MetricBackend> AuthGuard<B> {
+    pub(crate) fn new(policy: AuthPolicy, metrics: Arc<AuthMetrics<B>>) -> Self {
+        Self { policy, metrics }
+    }
+
+    /// Pass `request` on if the policy allows it, or reject it.
+    pub(crate) async fn authorize(self: Arc<Self>, request: Request, next: Next) -> Response {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let checked = match self.policy.check_cheaply(peer, request.headers()) {
+            Ok(Some(login)) => self.verify(login).await,
+            checked => checked.map(|_| ()),
+        };
+
+        match checked {
+            Ok(()) => next.run(request).await,
+            Err(Denial::Forbidden) => {
+                self.metrics.denied_addresses.inc();
+                StatusCode::FORBIDDEN.into_response()
+            }
+            Err(Denial::Unauthorized) => {
+                self.metrics.failures.inc();
+                (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, self.policy.challenge())],
+                )
+                    .into_response()
+            }
+            Err(Denial::Busy) => {
+                self.metrics.failures.inc();
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(header::RETRY_AFTER, "1")],
+                )
+                    .into_response()
+            }
+        }
+    }
+
+    /// Verify `login` on the blocking pool, or turn it away if the policy
+    /// is verifying as many passwords as it may already.
+    async fn verify(self: &Arc<Self>, login: Login) -> Result<(), Denial> {
+        let Ok(permit) = Arc::clone(&self.policy.verifications.0).try_acquire_owned() else {
+            return Err(Denial::Busy);
+        };
+        let guard = Arc::clone(self);
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            guard.policy.verify(&login)
+        })
+        .await
+        .unwrap_or(Err(Denial::Unauthorized))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use argon2::password_hash::{PasswordHasher, SaltString};
+
+    fn basic(user: &str, password: &str) -> HeaderMap {
+        let encoded =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Basic {}", encoded).parse().unwrap(),
+        );
+        headers
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    fn argon2_hash(password: &str) -> String {
+        let salt = SaltString::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+        // Minimal cost parameters keep the test fast; verification reads
+        // them from the hash.
+        let params = argon2::Params::new(8, 1, 1, None).unwrap();
+        argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+            .hash_password(password.as_bytes(), &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn basic_auth_accepts_bcrypt_and_argon2_hashes() {
+        let policy = AuthPolicy::new()
+            .basic_user("prometheus", &bcrypt::hash("s3cret", 4).unwrap())
+            .unwrap()
+            .basic_user("grafana", &argon2_hash("hunter2"))
+            .unwrap();
+
+        assert_eq!(policy.check(None, &basic("prometheus", "s3cret")), Ok(()));
+        assert_eq!(policy.check(None, &basic("grafana", "hunter2")), Ok(()));
+        for rejected in [
+            basic("prometheus", "hunter2"),
+            basic("grafana", "s3cret"),
+            basic("nobody", "s3cret"),
+            bearer("s3cret"),
+            HeaderMap::new(),
+        ] {
+            assert_eq!(policy.check(None, &rejected), Err(Denial::Unauthorized));
+        }
+
+        assert!(matches!(
+            AuthPolicy::new().basic_user("prometheus", "plaintext"),
+            Err(AuthError::InvalidHash { .. })
+        ));
+        assert!(matches!(
+            AuthPolicy::new().basic_user("prometheus", "$2b$04$short"),
+            Err(AuthError::InvalidHash { .. })
+        ));
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test]
+    async fn verifications_beyond_the_limit_are_turned_away() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+
+        let policy = AuthPolicy::new()
+            .basic_user("prometheus", &bcrypt::hash("s3cret", 4).unwrap())
+            .unwrap();
+        let mut registry = ObservabilityRegistry::<PrometheusBackend>::new();
+        let metrics = Arc::new(AuthMetrics::register(&mut registry).unwrap());
+        let guard = Arc::new(AuthGuard::new(policy.clone(), metrics));
+        let login = || {
+            policy
+                .check_cheaply(None, &basic("prometheus", "s3cret"))
+                .unwrap()
+                .expect("not cached yet")
+        };
+
+        let busy = Arc::clone(&policy.verifications.0)
+            .try_acquire_many_owned(MAX_CONCURRENT_VERIFICATIONS as u32)
+            .unwrap();
+        assert_eq!(guard.verify(login()).await, Err(Denial::Busy));
+        assert!(policy.verified().is_empty());
+
+        drop(busy);
+        assert_eq!(guard.verify(login()).await, Ok(()));
+        assert!(matches!(
+            policy.check_cheaply(None, &basic("prometheus", "s3cret")),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn successful_logins_are_cached() {
+        let policy = AuthPolicy::new()
+            .basic_user("prometheus", &bcrypt::hash("s3cret", 4).unwrap())
+            .unwrap();
+
+        assert_eq!(
+            policy.check(None, &basic("prometheus", "wrong")),
+            Err(Denial::Unauthorized)
+        );
+        assert_eq!(
+            policy.check(None, &basic("nobody", "s3cret")),
+            Err(Denial::Unauthorized)
+        );
+        assert!(policy.verified().is_empty());
+
+        assert_eq!(policy.check(None, &basic("prometheus", "s3cret")), Ok(()));
+        assert!(policy.verified().contains(&cache_key(b"prometheus:s3cret")));
+        assert!(!policy.verified().contains(&digest(b"prometheus:s3cret")));
+        assert_eq!(policy.check(None, &basic("prometheus", "s3cret")), Ok(()));
+
+        // Replacing the hash forgets logins verified against the old one.
+        let replaced = policy
+            .clone()
+            .basic_user("prometheus", &bcrypt::hash("hunter2", 4).unwrap())
+            .unwrap();
+        assert_eq!(
+            replaced.check(None, &basic("prometheus", "s3cret")),
+            Err(Denial::Unauthorized)
+        );
+        assert_eq!(policy.check(None, &basic("prometheus", "s3cret")), Ok(()));
+    }
+
+    #[test]
+    fn bearer_tokens_are_read_from_files() {
+        let path = std::env::temp_dir().join(format!("observe-rs-tokens-{}", std::process::id()));
+        std::fs::write(&path, "# scrapers\ntoken-a\n\n  token-b  \n").unwrap();
+        let policy = AuthPolicy::new().bearer_tokens_file(&path).unwrap();
+
+        assert_eq!(policy.check(None, &bearer("token-a")), Ok(()));
+        assert_eq!(policy.check(None, &bearer("token-b")), Ok(()));
+        assert_eq!(
+            policy.check(None, &bearer("token-c")),
+            Err(Denial::Unauthorized)
+        );
+        assert_eq!(
+            policy.check(None, &bearer("# scrapers")),
+            Err(Denial::Unauthorized)
+        );
+
+        std::fs::write(&path, "# nothing here\n").unwrap();
+        assert!(matches!(
+            AuthPolicy::new().bearer_tokens_file(&path),
+            Err(AuthError::NoTokens(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            AuthPolicy::new().bearer_tokens_file(&path),
+            Err(AuthError::Io { .. })
+        ));
+        assert!(!format!("{:?}", policy).contains("token-a"));
+    }
+
+    #[test]
+    fn allowlists_are_checked_before_credentials() {
+        let policy = AuthPolicy::new()
+            .allow_cidr("10.0.0.0/8")
+            .unwrap()
+            .allow_cidr("::1")
+            .unwrap()
+            .bearer_token("token");
+        let inside: IpAddr = "10.1.2.3".parse().unwrap();
+        let mapped: IpAddr = "::ffff:10.1.2.3".parse().unwrap();
+        let outside: IpAddr = "192.168.0.1".parse().unwrap();
+
+        assert_eq!(policy.check(Some(inside), &bearer("token")), Ok(()));
+        assert_eq!(policy.check(Some(mapped), &bearer("token")), Ok(()));
+        assert_eq!(
+            policy.check(Some("::1".parse().unwrap()), &bearer("token")),
+            Ok(())
+        );
+        assert_eq!(
+            policy.check(Some(inside), &bearer("wrong")),
+            Err(Denial::Unauthorized)
+        );
+        assert_eq!(
+            policy.check(Some(outside), &bearer("token")),
+            Err(Denial::Forbidden)
+        );
+        assert_eq!(policy.check(None, &bearer("token")), Err(Denial::Forbidden));
+
+        let open_inside = AuthPolicy::new().allow_cidr("127.0.0.0/8").unwrap();
+        assert_eq!(
+            open_inside.check(Some("127.0.0.1".parse().unwrap()), &HeaderMap::new()),
+            Ok(())
+        );
+        assert!(AuthPolicy::new().is_open());
+        assert!(matches!(
+            AuthPolicy::new().allow_cidr("10.0.0.0/33"),
+            Err(AuthError::InvalidCidr { .. })
+        ));
+    }
+}
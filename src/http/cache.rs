@@ -0,0 +1,185 @@
+//! Render cache of the standalone server.
+//!
+//! With a cache configured, each exposition format is rendered at most
+//! once per max age, and scrapes that arrive while a render is running
+//! wait for it rather than starting their own.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::core::metrics::Metric;
+use crate::core::registry::{MetricBackend, ObservabilityRegistry};
+use crate::core::renderer::RenderedMetrics;
+use crate::core::unit::Unit;
+
+use super::standalone::Exposition;
+
+/// Name of the counter of scrapes served from the cache.
+pub const RENDER_CACHE_HITS_METRIC: &str = "observe_rs_server_render_cache_hits";
+
+/// Name of the counter of scrapes that rendered the registry.
+pub const RENDER_CACHE_MISSES_METRIC: &str = "observe_rs_server_render_cache_misses";
+
+/// Name of the histogram of render durations.
+pub const RENDER_DURATION_METRIC: &str = "observe_rs_server_render_duration_seconds";
+
+/// Buckets for the render duration self-metric, in seconds.
+const RENDER_DURATION_BUCKETS: &[f64] =
+    &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// Self-metrics of the render cache.
+struct CacheMetrics<B: MetricBackend> {
+    hits: Metric<B::Counter>,
+    misses: Metric<B::Counter>,
+    duration: Metric<B::Histogram>,
+}
+
+/// A finished render.
+struct Entry {
+    rendered: Arc<RenderedMetrics>,
+    finished: Instant,
+}
+
+/// The latest render of one format; the async lock is held while
+/// rendering, which is what queues concurrent scrapes behind it.
+type Slot = Arc<tokio::sync::Mutex<Option<Entry>>>;
+
+/// Renders shared between scrapes.
+pub(crate) struct RenderCache<B: MetricBackend> {
+    max_age: Duration,
+    slots: Mutex<HashMap<Exposition, Slot>>,
+    metrics: CacheMetrics<B>,
+}
+
+impl<B: MetricBackend> RenderCache<B> {
+    /// Create a cache, registering its self-metrics in `registry`.
+    pub(crate) fn register(
+        registry: &mut ObservabilityRegistry<B>,
+        max_age: Duration,
+    ) -> Result<Self, B::Error> {
+        Ok(Self {
+            max_age,
+            slots: Mutex::new(HashMap::new()),
+            metrics: CacheMetrics {
+                hits: registry.counter(
+                    RENDER_CACHE_HITS_METRIC,
+                    "Scrapes served from the render cache or an in-flight render",
+                )?,
+                misses: registry.counter(
+                    RENDER_CACHE_MISSES_METRIC,
+                    "Scrapes that rendered the registry",
+                )?,
+                duration: registry.histogram_with_unit(
+                    RENDER_DURATION_METRIC,
+                    "Duration of registry renders",
+                    RENDER_DURATION_BUCKETS.to_vec(),
+                    Unit::Seconds,
+                )?,
+            },
+        })
+    }
+
+    /// Return the render of `format`, running `render` on a blocking
+    /// thread if the cached one is too old.
+    ///
+    /// A render that finishes after this call was made is always reused,
+    /// even with a max age of zero. Failed renders aren't cached.
+    pub(crate) async fn get<F>(
+        &self,
+        format: Exposition,
+        render: F,
+    ) -> Result<Arc<RenderedMetrics>, String>
+    where
+        F: FnOnce() -> Result<RenderedMetrics, String> + Send + 'static,
+    {
+        let requested = Instant::now();
+        let slot = Arc::clone(
+            self.slots
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(format)
+                .or_default(),
+        );
+        let mut entry = slot.lock().await;
+        if let Some(cached) = entry.as_ref() {
+            if cached.finished >= requested || cached.finished.elapsed() <= self.max_age {
+                self.metrics.hits.inc();
+                return Ok(Arc::clone(&cached.rendered));
+            }
+        }
+
+        self.metrics.misses.inc();
+        let started = Instant::now();
+        let rendered = tokio::task::spawn_blocking(render)
+            .await
+            .map_err(|_| "render task panicked".to_string())??;
+        self.metrics
+            .duration
+            .observe(started.elapsed().as_secs_f64());
+
+        let rendered = Arc::new(rendered);
+        *entry = Some(Entry {
+            rendered: Arc::clone(&rendered),
+            finished: Instant::now(),
+        });
+        Ok(rendered)
+    }
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+    use super::*;
+    use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test(start_paused = true)]
+    async fn renders_are_reused_until_they_expire() {
+        let mut registry = ObservabilityRegistry::<PrometheusBackend>::new();
+        let cache = RenderCache::register(&mut registry, Duration::from_secs(10)).unwrap();
+        let renders = Arc::new(AtomicUsize::new(0));
+        let get = || {
+            let renders = Arc::clone(&renders);
+            cache.get(Exposition::Text, move || {
+                let n = renders.fetch_add(1, Ordering::SeqCst);
+                Ok(RenderedMetrics::new(
+                    "text/plain",
+                    n.to_string().into_bytes(),
+                ))
+            })
+        };
+
+        assert_eq!(get().await.unwrap().as_bytes(), b"0");
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(get().await.unwrap().as_bytes(), b"0");
+        tokio::time::advance(Duration::from_secs(6)).await;
+        assert_eq!(get().await.unwrap().as_bytes(), b"1");
+        assert_eq!(renders.load(Ordering::SeqCst), 2);
+
+        let text = registry.render().unwrap();
+        let text = text.as_str().unwrap();
+        assert!(text.contains("observe_rs_server_render_cache_hits_total 1\n"));
+        assert!(text.contains("observe_rs_server_render_cache_misses_total 2\n"));
+        assert!(text.contains("observe_rs_server_render_duration_seconds_count 2\n"));
+    }
+
+    #[tokio::test]
+    async fn failed_renders_are_not_cached() {
+        let mut registry = ObservabilityRegistry::<PrometheusBackend>::new();
+        let cache = RenderCache::register(&mut registry, Duration::from_secs(60)).unwrap();
+
+        let failed = cache
+            .get(Exposition::Text, || Err("broken".to_string()))
+            .await;
+        assert!(matches!(failed, Err(ref e) if e == "broken"));
+        let rendered = cache
+            .get(Exposition::Text, || {
+                Ok(RenderedMetrics::new("text/plain", b"ok".to_vec()))
+            })
+            .await
+            .unwrap();
+        assert_eq!(rendered.as_bytes(), b"ok");
+    }
+}
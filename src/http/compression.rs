@@ -0,0 +1,155 @@
+//! Response compression for the metrics endpoints.
+//!
+//! Responses are compressed with gzip or zstd when the client lists one in
+//! `Accept-Encoding` and the body is at least
+//! [`min_size`](CompressionConfig::min_size) bytes. Prometheus sends
+//! `Accept-Encoding: gzip`, which typically shrinks text scrapes by 90%.
+
+/// Default smallest body that is compressed, in bytes.
+pub const DEFAULT_MIN_COMPRESS_SIZE: usize = 1024;
+
+/// Default gzip level (0-9).
+pub const DEFAULT_GZIP_LEVEL: u32 = 6;
+
+/// Default zstd level (1-22).
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// Compression settings of the standalone server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Smallest body that is compressed, in bytes (default: 1024).
+    pub min_size: usize,
+    /// gzip level, 0 (none) to 9 (best) (default: 6).
+    pub gzip_level: u32,
+    /// zstd level, 1 (fastest) to 22 (best) (default: 3).
+    pub zstd_level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            min_size: DEFAULT_MIN_COMPRESS_SIZE,
+            gzip_level: DEFAULT_GZIP_LEVEL,
+            zstd_level: DEFAULT_ZSTD_LEVEL,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Set the smallest body that is compressed.
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Set the gzip level (0-9).
+    pub fn gzip_level(mut self, level: u32) -> Self {
+        self.gzip_level = level;
+        self
+    }
+
+    /// Set the zstd level (1-22).
+    pub fn zstd_level(mut self, level: i32) -> Self {
+        self.zstd_level = level;
+        self
+    }
+
+    /// Check the levels are in range.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.gzip_level > 9 {
+            return Err(format!(
+                "gzip level {} is out of range 0-9",
+                self.gzip_level
+            ));
+        }
+        if !(1..=22).contains(&self.zstd_level) {
+            return Err(format!(
+                "zstd level {} is out of range 1-22",
+                self.zstd_level
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A supported content coding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Gzip,
+    Zstd,
+}
+
+impl Encoding {
+    /// The `Content-Encoding` token.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// Pick a coding from an `Accept-Encoding` header.
+///
+/// The coding with the highest `q` wins, zstd on ties; `*` stands for
+/// codings not listed and `q=0` rules a coding out. `None` means identity.
+pub(crate) fn negotiate(accept_encoding: Option<&str>) -> Option<Encoding> {
+    let mut gzip = None;
+    let mut zstd = None;
+    let mut any = None;
+    for coding in accept_encoding.unwrap_or("").split(',') {
+        let mut parts = coding.split(';').map(str::trim);
+        let name = parts.next().unwrap_or("").to_ascii_lowercase();
+        let q = parts
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .map_or(1.0, |(_, q)| q.trim().parse::<f32>().unwrap_or(0.0));
+        match name.as_str() {
+            "gzip" | "x-gzip" => gzip = Some(q),
+            "zstd" => zstd = Some(q),
+            "*" => any = Some(q),
+            _ => {}
+        }
+    }
+    let gzip = gzip.or(any).unwrap_or(0.0);
+    let zstd = zstd.or(any).unwrap_or(0.0);
+    if zstd > 0.0 && zstd >= gzip {
+        Some(Encoding::Zstd)
+    } else if gzip > 0.0 {
+        Some(Encoding::Gzip)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation_follows_q_values() {
+        assert_eq!(negotiate(None), None);
+        assert_eq!(negotiate(Some("identity")), None);
+        assert_eq!(negotiate(Some("gzip")), Some(Encoding::Gzip));
+        assert_eq!(
+            negotiate(Some("gzip, deflate, br, zstd")),
+            Some(Encoding::Zstd)
+        );
+        assert_eq!(negotiate(Some("zstd;q=0.5, gzip")), Some(Encoding::Gzip));
+        assert_eq!(negotiate(Some("gzip;q=0, *")), Some(Encoding::Zstd));
+        assert_eq!(negotiate(Some("*;q=0")), None);
+    }
+
+    #[test]
+    fn levels_are_validated() {
+        assert!(CompressionConfig::default().validate().is_ok());
+        assert!(CompressionConfig::default()
+            .gzip_level(10)
+            .validate()
+            .is_err());
+        assert!(CompressionConfig::default()
+            .zstd_level(0)
+            .validate()
+            .is_err());
+    }
+}
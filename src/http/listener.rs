@@ -0,0 +1,255 @@
+//! Listeners of the standalone server.
+//!
+//! By default the server has one TCP listener on its `host` and `port`
+//! serving every endpoint. With [`ListenerConfig`]s it can instead listen
+//! on several TCP ports and Unix domain sockets at once, each serving a
+//! subset of the endpoints, e.g. health probes on their own port and
+//! metrics on a socket shared with a sidecar.
+//!
+//! # Example
+//! ```ignore
+//! let server = StandaloneServer::<PrometheusBackend>::builder()
+//!     .listener(ListenerConfig::unix("/var/run/metrics/metrics.sock").mode(0o660))
+//!     .listener(ListenerConfig::tcp("0.0.0.0", 8081).endpoints([Endpoint::Health, Endpoint::Ready]))
+//!     .build();
+//! ```
+
+use std::fmt;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use super::standalone::{Endpoint, ServerError};
+
+/// Where a listener accepts connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddress {
+    /// A TCP host and port.
+    Tcp { host: String, port: u16 },
+    /// A Unix domain socket path.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// One address the server listens on and the endpoints it serves there.
+#[derive(Debug, Clone)]
+pub struct ListenerConfig {
+    address: ListenAddress,
+    endpoints: Option<Vec<Endpoint>>,
+    #[cfg(unix)]
+    mode: Option<u32>,
+}
+
+impl ListenerConfig {
+    /// Listen on a TCP host and port.
+    pub fn tcp(host: impl Into<String>, port: u16) -> Self {
+        Self::new(ListenAddress::Tcp {
+            host: host.into(),
+            port,
+        })
+    }
+
+    /// Listen on a Unix domain socket.
+    ///
+    /// A stale socket left at `path` by a previous run is replaced, and
+    /// the socket is removed when the server stops. Connections over the
+    /// socket are plain HTTP, even with TLS configured, and have no client
+    /// address, so address allowlists reject them.
+    #[cfg(unix)]
+    pub fn unix(path: impl AsRef<Path>) -> Self {
+        Self::new(ListenAddress::Unix(path.as_ref().to_path_buf()))
+    }
+
+    fn new(address: ListenAddress) -> Self {
+        Self {
+            address,
+            endpoints: None,
+            #[cfg(unix)]
+            mode: None,
+        }
+    }
+
+    /// Set the file mode of a Unix socket, e.g. `0o660` (default: as
+    /// created under the process umask).
+    #[cfg(unix)]
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Only serve these endpoints (default: all of them).
+    pub fn endpoints(mut self, endpoints: impl IntoIterator<Item = Endpoint>) -> Self {
+        self.endpoints = Some(endpoints.into_iter().collect());
+        self
+    }
+
+    /// Where the listener accepts connections.
+    pub fn address(&self) -> &ListenAddress {
+        &self.address
+    }
+
+    /// Whether the listener serves `endpoint`.
+    pub fn serves(&self, endpoint: Endpoint) -> bool {
+        self.endpoints
+            .as_ref()
+            .is_none_or(|endpoints| endpoints.contains(&endpoint))
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.endpoints.as_ref().is_some_and(Vec::is_empty) {
+            return Err(format!("listener {} serves no endpoints", self.address));
+        }
+        Ok(())
+    }
+
+    /// Bind the listener.
+    pub(crate) async fn bind(&self) -> Result<BoundListener, ServerError> {
+        let bind_error =
+            |e: &dyn fmt::Display| ServerError::BindError(format!("{}: {}", self.address, e));
+        match &self.address {
+            ListenAddress::Tcp { host, port } => TcpListener::bind((host.as_str(), *port))
+                .await
+                .map(BoundListener::Tcp)
+                .map_err(|e| bind_error(&e)),
+            #[cfg(unix)]
+            ListenAddress::Unix(path) => {
+                remove_stale_socket(path).map_err(|e| bind_error(&e))?;
+                let listener = UnixListener::bind(path).map_err(|e| bind_error(&e))?;
+                let socket = SocketFile(path.clone());
+                if let Some(mode) = self.mode {
+                    use std::os::unix::fs::PermissionsExt;
+                    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                        .map_err(|e| bind_error(&e))?;
+                }
+                Ok(BoundListener::Unix(listener, socket))
+            }
+        }
+    }
+}
+
+/// A bound listener, ready to serve.
+pub(crate) enum BoundListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, SocketFile),
+}
+
+/// A Unix socket path, removed when dropped.
+#[cfg(unix)]
+pub(crate) struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Remove a socket at `path` that no process is listening on.
+///
+/// Anything else at `path`, including a live socket, is an error.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    use std::io::{Error, ErrorKind};
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            "path exists and is not a socket",
+        ));
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(Error::new(
+            ErrorKind::AddrInUse,
+            "another process is listening on the socket",
+        ));
+    }
+    std::fs::remove_file(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("observe-rs-{}-{}.sock", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn unix_sockets_get_their_mode_and_are_removed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = socket_path("mode");
+        let bound = ListenerConfig::unix(&path)
+            .mode(0o600)
+            .bind()
+            .await
+            .unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // A second server can't take over a live socket.
+        assert!(matches!(
+            ListenerConfig::unix(&path).bind().await,
+            Err(ServerError::BindError(_))
+        ));
+
+        drop(bound);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn stale_sockets_are_replaced() {
+        let path = socket_path("stale");
+        // A socket whose listener is gone, as after a crash.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let bound = ListenerConfig::unix(&path).bind().await.unwrap();
+        assert!(matches!(bound, BoundListener::Unix(..)));
+        drop(bound);
+
+        // Regular files are never removed.
+        std::fs::write(&path, "data").unwrap();
+        assert!(matches!(
+            ListenerConfig::unix(&path).bind().await,
+            Err(ServerError::BindError(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn endpoints_default_to_all() {
+        let all = ListenerConfig::tcp("127.0.0.1", 9090);
+        assert!(all.serves(Endpoint::Metrics) && all.serves(Endpoint::Health));
+        all.validate().unwrap();
+
+        let probes = ListenerConfig::tcp("127.0.0.1", 8081).endpoints([Endpoint::Health]);
+        assert!(probes.serves(Endpoint::Health));
+        assert!(!probes.serves(Endpoint::Metrics));
+        assert!(ListenerConfig::tcp("127.0.0.1", 8081)
+            .endpoints([])
+            .validate()
+            .is_err());
+    }
+}
@@ -1,14 +1,41 @@
 //! HTTP server and endpoint implementations.
 //!
 //! This module contains:
-//! - Standalone HTTP server (feature: `standalone`)
+//! - Standalone HTTP server (feature: `standalone`), on TCP ports and
+//!   Unix domain sockets
 //! - Health and readiness endpoints
-//! - Metrics endpoint handlers
+//! - Metrics endpoint handlers, streamed in chunks or served from a
+//!   render cache
+//! - Response compression (feature: `compression`)
+//! - HTTPS and mutual TLS (feature: `tls`)
+//! - Basic auth, bearer tokens and address allowlists (feature: `auth`)
 
+#[cfg(feature = "auth")]
+pub mod auth;
+#[cfg(feature = "standalone")]
+mod cache;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "standalone")]
+pub mod listener;
 #[cfg(feature = "standalone")]
 pub mod standalone;
+#[cfg(feature = "standalone")]
+mod stream;
+#[cfg(feature = "tls")]
+pub mod tls;
 
 pub mod health;
 
+#[cfg(feature = "auth")]
+pub use auth::{AuthError, AuthPolicy, AUTH_DENIED_ADDRESSES_METRIC, AUTH_FAILURES_METRIC};
+#[cfg(feature = "standalone")]
+pub use cache::{RENDER_CACHE_HITS_METRIC, RENDER_CACHE_MISSES_METRIC, RENDER_DURATION_METRIC};
+#[cfg(feature = "compression")]
+pub use compression::CompressionConfig;
+#[cfg(feature = "standalone")]
+pub use listener::{ListenAddress, ListenerConfig};
 #[cfg(feature = "standalone")]
 pub use standalone::*;
+#[cfg(feature = "tls")]
+pub use tls::{PemSource, TlsConfig, TlsError, TLS_RELOAD_FAILURES_METRIC};
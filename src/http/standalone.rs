@@ -1,7 +1,8 @@
 //! Standalone HTTP server for metrics exposure.
 //!
 //! This module provides a self-contained HTTP server that exposes
-//! `/metrics`, `/health`, and `/ready` endpoints.
+//! `/metrics`, `/health`, and `/ready` endpoints, plus `/metrics/json` with
+//! the `json` feature.
 //!
 //! # Example
 //!
@@ -25,21 +26,47 @@
 //! }
 //! ```
 
+#[cfg(feature = "tls")]
+use axum::extract::ConnectInfo;
+#[cfg(feature = "filter")]
+use axum::extract::RawQuery;
 use axum::{
     extract::State,
-    http::{header, StatusCode},
-    response::IntoResponse,
-    routing::get,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, MethodRouter},
     Router,
 };
+#[cfg(feature = "auth")]
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 
+use crate::core::build_info::BuildInfo;
+#[cfg(feature = "filter")]
+use crate::core::filter::MetricFilter;
+#[cfg(feature = "tls")]
+use crate::core::metrics::Metric;
 use crate::core::registry::{MetricBackend, ObservabilityRegistry};
-use crate::core::renderer::MetricsRenderer;
+#[cfg(feature = "relabel")]
+use crate::core::relabel::RelabelRules;
+use crate::core::renderer::{MetricsRenderer, RenderedMetrics};
 
+#[cfg(feature = "compression")]
+use super::compression::CompressionConfig;
+
+#[cfg(feature = "auth")]
+use super::auth::{AuthGuard, AuthMetrics, AuthPolicy};
+use super::cache::RenderCache;
 use super::health::{default_health_check, default_readiness_check};
+use super::listener::{BoundListener, ListenAddress, ListenerConfig};
+use super::stream::{stream_response, BodyCoding, BodyWriter};
+#[cfg(feature = "tls")]
+use super::tls::{TlsConfig, TlsState, TLS_RELOAD_FAILURES_METRIC};
 
 /// Configuration for the standalone server.
 #[derive(Debug, Clone)]
@@ -48,12 +75,34 @@ pub struct ServerConfig {
     pub port: u16,
     /// The host to bind to (default: "0.0.0.0")
     pub host: String,
+    /// Where to listen and what to serve there (default: none, one TCP
+    /// listener on `host` and `port` serving every endpoint)
+    pub listeners: Vec<ListenerConfig>,
     /// Path for the metrics endpoint (default: "/metrics")
     pub metrics_path: String,
     /// Path for the health endpoint (default: "/health")
     pub health_path: String,
     /// Path for the readiness endpoint (default: "/ready")
     pub ready_path: String,
+    /// Path for the JSON metrics endpoint (default: "/metrics/json")
+    #[cfg(feature = "json")]
+    pub json_path: String,
+    /// Compression of metrics responses (default: gzip or zstd for bodies
+    /// of 1 KiB or more, when the client accepts it)
+    #[cfg(feature = "compression")]
+    pub compression: CompressionConfig,
+    /// How long a render is reused for (default: none, every scrape
+    /// renders and streams the registry)
+    pub render_cache: Option<Duration>,
+    /// How long after it starts a streamed response may still wait for a
+    /// slow client before it is aborted (default: 10 seconds)
+    pub write_timeout: Duration,
+    /// Certificates to serve HTTPS with (default: none, plain HTTP)
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConfig>,
+    /// Who may call each endpoint (default: everyone)
+    #[cfg(feature = "auth")]
+    pub auth: HashMap<Endpoint, AuthPolicy>,
 }
 
 impl Default for ServerConfig {
@@ -61,16 +110,73 @@ impl Default for ServerConfig {
         Self {
             port: 9090,
             host: "0.0.0.0".to_string(),
+            listeners: Vec::new(),
             metrics_path: "/metrics".to_string(),
             health_path: "/health".to_string(),
             ready_path: "/ready".to_string(),
+            #[cfg(feature = "json")]
+            json_path: "/metrics/json".to_string(),
+            #[cfg(feature = "compression")]
+            compression: CompressionConfig::default(),
+            render_cache: None,
+            write_timeout: Duration::from_secs(10),
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "auth")]
+            auth: HashMap::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The listeners the server runs.
+    pub fn effective_listeners(&self) -> Vec<ListenerConfig> {
+        if self.listeners.is_empty() {
+            vec![ListenerConfig::tcp(self.host.clone(), self.port)]
+        } else {
+            self.listeners.clone()
         }
     }
 }
 
+/// Endpoints of the standalone server.
+///
+/// Used to pick the endpoints a [`ListenerConfig`] serves and, with the
+/// `auth` feature, the endpoint an auth policy protects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// The metrics endpoint (`/metrics`).
+    Metrics,
+    /// The JSON metrics endpoint (`/metrics/json`).
+    #[cfg(feature = "json")]
+    Json,
+    /// The health endpoint (`/health`).
+    Health,
+    /// The readiness endpoint (`/ready`).
+    Ready,
+    /// Routes added with [`route`](StandaloneServerBuilder::route).
+    Custom,
+}
+
+/// A route added with [`StandaloneServerBuilder::route`], whose handlers
+/// get the server's registry as their state.
+pub type CustomRoute<B> = MethodRouter<Arc<RwLock<ObservabilityRegistry<B>>>>;
+
+/// Name of the OpenMetrics target metadata metric.
+pub const TARGET_INFO_METRIC: &str = "target_info";
+
+/// Name of the build metadata metric.
+pub const BUILD_INFO_METRIC: &str = "build_info";
+
 /// Builder for creating a standalone server.
 pub struct StandaloneServerBuilder<B: MetricBackend> {
     config: ServerConfig,
+    const_labels: Vec<(String, String)>,
+    target_info: Vec<(String, String)>,
+    build_info: Option<BuildInfo>,
+    #[cfg(feature = "relabel")]
+    relabel: RelabelRules,
+    routes: Vec<(String, CustomRoute<B>)>,
     _marker: std::marker::PhantomData<B>,
 }
 
@@ -78,6 +184,12 @@ impl<B: MetricBackend> Default for StandaloneServerBuilder<B> {
     fn default() -> Self {
         Self {
             config: ServerConfig::default(),
+            const_labels: Vec::new(),
+            target_info: Vec::new(),
+            build_info: None,
+            #[cfg(feature = "relabel")]
+            relabel: RelabelRules::new(),
+            routes: Vec::new(),
             _marker: std::marker::PhantomData,
         }
     }
@@ -101,6 +213,15 @@ impl<B: MetricBackend> StandaloneServerBuilder<B> {
         self
     }
 
+    /// Add a listener.
+    ///
+    /// Once any listener is added, the server runs only the added ones and
+    /// `host` and `port` are ignored. TLS applies to TCP listeners only.
+    pub fn listener(mut self, listener: ListenerConfig) -> Self {
+        self.config.listeners.push(listener);
+        self
+    }
+
     /// Set the metrics endpoint path.
     pub fn metrics_path(mut self, path: impl Into<String>) -> Self {
         self.config.metrics_path = path.into();
@@ -119,24 +240,303 @@ impl<B: MetricBackend> StandaloneServerBuilder<B> {
         self
     }
 
+    /// Set the JSON metrics endpoint path.
+    #[cfg(feature = "json")]
+    pub fn json_path(mut self, path: impl Into<String>) -> Self {
+        self.config.json_path = path.into();
+        self
+    }
+
+    /// Set when and how metrics responses are compressed.
+    #[cfg(feature = "compression")]
+    pub fn compression(mut self, compression: CompressionConfig) -> Self {
+        self.config.compression = compression;
+        self
+    }
+
+    /// Reuse each render for up to `max_age`.
+    ///
+    /// Scrapes that arrive while a render is running wait for it instead
+    /// of rendering again, even with a zero max age. Cached renders are
+    /// held in memory in full, unlike the default streamed responses. The
+    /// server then publishes `observe_rs_server_render_cache_hits_total`,
+    /// `observe_rs_server_render_cache_misses_total` and
+    /// `observe_rs_server_render_duration_seconds`.
+    pub fn render_cache(mut self, max_age: Duration) -> Self {
+        self.config.render_cache = Some(max_age);
+        self
+    }
+
+    /// Abort a streamed response that still has to wait for the client
+    /// `timeout` after its render started (default: 10 seconds).
+    ///
+    /// The deadline covers the whole response, not each chunk. Without a
+    /// render cache the render holds the registry's read lock, so this
+    /// bounds how long a slow scraper can delay registrations (and, behind
+    /// a queued registration, other scrapes).
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.write_timeout = timeout;
+        self
+    }
+
+    /// Serve HTTPS, and require client certificates if `tls` has a client
+    /// CA.
+    ///
+    /// Certificates read from files are reloaded when the files change;
+    /// the server counts failed reloads in
+    /// `observe_rs_server_tls_reload_failures_total`.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.config.tls = Some(tls);
+        self
+    }
+
+    /// Restrict who may call `endpoint`.
+    ///
+    /// Endpoints without a policy stay open. Rejected requests are counted
+    /// in `observe_rs_server_auth_failures_total` (401) and
+    /// `observe_rs_server_auth_denied_addresses_total` (403).
+    #[cfg(feature = "auth")]
+    pub fn auth(mut self, endpoint: Endpoint, policy: AuthPolicy) -> Self {
+        self.config.auth.insert(endpoint, policy);
+        self
+    }
+
+    /// Serve an extra route, e.g. `/version`, next to the built-in ones.
+    ///
+    /// Handlers get the server's registry as their state, so they can
+    /// update or inspect metrics:
+    ///
+    /// ```ignore
+    /// type Shared = Arc<RwLock<PrometheusRegistry>>;
+    ///
+    /// let server = StandaloneServer::<PrometheusBackend>::builder()
+    ///     .route("/version", get(|| async { env!("CARGO_PKG_VERSION") }))
+    ///     .route("/debug/cardinality", get(|State(registry): State<Shared>| async move {
+    ///         format!("{:?}", registry.read().await.cardinality())
+    ///     }))
+    ///     .build();
+    /// ```
+    ///
+    /// Listeners and auth policies treat all custom routes as
+    /// [`Endpoint::Custom`]. [`try_build`](Self::try_build) fails if `path`
+    /// is taken by a built-in endpoint or another custom route.
+    pub fn route(mut self, path: impl Into<String>, route: CustomRoute<B>) -> Self {
+        self.routes.push((path.into(), route));
+        self
+    }
+
+    /// Add a constant label to every series exposed by the server.
+    ///
+    /// Typical candidates are `service`, `version`, `instance` and `env`.
+    /// Use [`target_info`](Self::target_info) instead to publish them once.
+    pub fn const_label(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.const_labels.push((name.into(), value.into()));
+        self
+    }
+
+    /// Add several constant labels to every series exposed by the server.
+    pub fn const_labels<K, V>(mut self, labels: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.const_labels
+            .extend(labels.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Publish target metadata once as a `target_info` series instead of
+    /// attaching it to every series.
+    ///
+    /// Join it onto other series at query time, e.g.
+    /// `rate(http_requests_total[5m]) * on(instance) group_left(service) target_info`.
+    pub fn target_info<K, V>(mut self, labels: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.target_info
+            .extend(labels.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Publish a `build_info` series with the given build metadata.
+    ///
+    /// Use the [`build_info!`](crate::build_info) macro to populate it from
+    /// the calling crate's version and the `GIT_SHA` environment variable.
+    pub fn build_info(mut self, info: BuildInfo) -> Self {
+        self.build_info = Some(info);
+        self
+    }
+
+    /// Relabel everything the server exposes with `rules`.
+    ///
+    /// See [`ObservabilityRegistry::with_relabel_rules`].
+    #[cfg(feature = "relabel")]
+    pub fn relabel_rules(mut self, rules: RelabelRules) -> Self {
+        self.relabel = rules;
+        self
+    }
+
     /// Build the standalone server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is invalid. Use
+    /// [`try_build`](Self::try_build) to handle the error instead.
     pub fn build(self) -> StandaloneServer<B> {
-        StandaloneServer {
-            config: self.config,
-            registry: Arc::new(RwLock::new(ObservabilityRegistry::<B>::new())),
+        match self.try_build() {
+            Ok(server) => server,
+            Err(e) => panic!("invalid standalone server configuration: {}", e),
         }
     }
+
+    /// Build the standalone server, returning an error if the configuration
+    /// is invalid (e.g. a constant or info label the backend rejects).
+    pub fn try_build(self) -> Result<StandaloneServer<B>, ServerError> {
+        #[cfg(feature = "compression")]
+        self.config
+            .compression
+            .validate()
+            .map_err(ServerError::InvalidConfig)?;
+
+        if self.config.write_timeout.is_zero() {
+            return Err(ServerError::InvalidConfig(
+                "write timeout must be non-zero".to_string(),
+            ));
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.config.tls {
+            tls.validate().map_err(ServerError::InvalidConfig)?;
+        }
+
+        let builtin = [
+            &self.config.metrics_path,
+            &self.config.health_path,
+            &self.config.ready_path,
+        ]
+        .into_iter();
+        #[cfg(feature = "json")]
+        let builtin = builtin.chain([&self.config.json_path]);
+        // axum panics on invalid or overlapping paths when the router is
+        // built, so check them all up front with the router it uses.
+        let mut paths = matchit::Router::new();
+        let routes = builtin
+            .map(|path| ("endpoint", path))
+            .chain(self.routes.iter().map(|(path, _)| ("custom route", path)));
+        for (kind, path) in routes {
+            validate_path(path)
+                .and_then(|()| paths.insert(path, ()).map_err(|e| e.to_string()))
+                .map_err(|reason| {
+                    ServerError::InvalidConfig(format!("{} {}: {}", kind, path, reason))
+                })?;
+        }
+
+        for (i, listener) in self.config.listeners.iter().enumerate() {
+            listener.validate().map_err(ServerError::InvalidConfig)?;
+            let address = listener.address();
+            // Port 0 binds a fresh ephemeral port each time.
+            let ephemeral = matches!(address, ListenAddress::Tcp { port: 0, .. });
+            if !ephemeral
+                && self.config.listeners[..i]
+                    .iter()
+                    .any(|l| l.address() == address)
+            {
+                return Err(ServerError::InvalidConfig(format!(
+                    "listener {} is configured twice",
+                    address
+                )));
+            }
+        }
+
+        let mut registry = if self.const_labels.is_empty() {
+            ObservabilityRegistry::<B>::new()
+        } else {
+            ObservabilityRegistry::<B>::with_const_labels(self.const_labels)
+                .map_err(|e| ServerError::InvalidConfig(e.to_string()))?
+        };
+        #[cfg(feature = "relabel")]
+        {
+            registry = registry.with_relabel_rules(self.relabel);
+        }
+
+        if !self.target_info.is_empty() {
+            registry
+                .info(TARGET_INFO_METRIC, "Target metadata", self.target_info)
+                .map_err(|e| ServerError::InvalidConfig(e.to_string()))?;
+        }
+
+        if let Some(info) = self.build_info {
+            registry
+                .info(
+                    BUILD_INFO_METRIC,
+                    "Build metadata of the running binary",
+                    info.labels(),
+                )
+                .map_err(|e| ServerError::InvalidConfig(e.to_string()))?;
+        }
+
+        let cache = match self.config.render_cache {
+            Some(max_age) => Some(Arc::new(
+                RenderCache::register(&mut registry, max_age)
+                    .map_err(|e| ServerError::InvalidConfig(e.to_string()))?,
+            )),
+            None => None,
+        };
+
+        #[cfg(feature = "tls")]
+        let tls = match &self.config.tls {
+            Some(config) => Some(Arc::new(ServerTls {
+                state: TlsState::load(config.clone())
+                    .map_err(|e| ServerError::InvalidConfig(e.to_string()))?,
+                reload_failures: registry
+                    .counter(
+                        TLS_RELOAD_FAILURES_METRIC,
+                        "Failed reloads of the TLS certificates",
+                    )
+                    .map_err(|e| ServerError::InvalidConfig(e.to_string()))?,
+            })),
+            None => None,
+        };
+
+        #[cfg(feature = "auth")]
+        let auth_metrics = if self.config.auth.values().all(AuthPolicy::is_open) {
+            None
+        } else {
+            Some(Arc::new(
+                AuthMetrics::register(&mut registry)
+                    .map_err(|e| ServerError::InvalidConfig(e.to_string()))?,
+            ))
+        };
+
+        Ok(StandaloneServer {
+            config: self.config,
+            registry: Arc::new(RwLock::new(registry)),
+            cache,
+            routes: self.routes,
+            #[cfg(feature = "tls")]
+            tls,
+            #[cfg(feature = "auth")]
+            auth_metrics,
+        })
+    }
 }
 
 /// Shared state for the HTTP handlers.
 struct AppState<B: MetricBackend> {
     registry: Arc<RwLock<ObservabilityRegistry<B>>>,
+    config: Arc<ServerConfig>,
+    cache: Option<Arc<RenderCache<B>>>,
 }
 
 impl<B: MetricBackend> Clone for AppState<B> {
     fn clone(&self) -> Self {
         Self {
             registry: Arc::clone(&self.registry),
+            config: Arc::clone(&self.config),
+            cache: self.cache.clone(),
         }
     }
 }
@@ -166,6 +566,12 @@ impl<B: MetricBackend> Clone for AppState<B> {
 pub struct StandaloneServer<B: MetricBackend> {
     config: ServerConfig,
     registry: Arc<RwLock<ObservabilityRegistry<B>>>,
+    cache: Option<Arc<RenderCache<B>>>,
+    routes: Vec<(String, CustomRoute<B>)>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<ServerTls<B>>>,
+    #[cfg(feature = "auth")]
+    auth_metrics: Option<Arc<AuthMetrics<B>>>,
 }
 
 impl<B: MetricBackend> StandaloneServer<B> {
@@ -194,43 +600,259 @@ impl<B: MetricBackend> StandaloneServer<B> {
     }
 
     /// Run the server (blocking).
+    ///
+    /// Every listener is bound before any is served. The server stops, and
+    /// removes its Unix sockets, when any listener fails.
     pub async fn run(&self) -> Result<(), ServerError>
     where
         B::Registry: MetricsRenderer<Error = std::fmt::Error>,
     {
-        let state = AppState {
-            registry: Arc::clone(&self.registry),
-        };
-
-        let app = self.create_router(state);
-        let addr = format!("{}:{}", self.config.host, self.config.port);
-
-        let listener = TcpListener::bind(&addr)
-            .await
-            .map_err(|e| ServerError::BindError(e.to_string()))?;
-
-        println!(
-            "Observability server listening on http://{}",
-            listener.local_addr().unwrap()
-        );
+        let mut bound = Vec::new();
+        for listener in self.config.effective_listeners() {
+            let socket = listener.bind().await?;
+            match &socket {
+                BoundListener::Tcp(tcp) => {
+                    #[cfg(feature = "tls")]
+                    let scheme = if self.tls.is_some() { "https" } else { "http" };
+                    #[cfg(not(feature = "tls"))]
+                    let scheme = "http";
+                    println!(
+                        "Observability server listening on {}://{}",
+                        scheme,
+                        tcp.local_addr().unwrap()
+                    );
+                }
+                #[cfg(unix)]
+                BoundListener::Unix(..) => {
+                    println!("Observability server listening on {}", listener.address());
+                }
+            }
+            bound.push((listener, socket));
+        }
 
-        axum::serve(listener, app)
-            .await
-            .map_err(|e| ServerError::ServeError(e.to_string()))?;
+        let mut serving = tokio::task::JoinSet::new();
+        for (listener, socket) in bound {
+            let app = self.create_router_for(|endpoint| listener.serves(endpoint));
+            match socket {
+                BoundListener::Tcp(tcp) => {
+                    #[cfg(feature = "tls")]
+                    if let Some(tls) = &self.tls {
+                        serving.spawn(serve_tls(tcp, app, Arc::clone(tls)));
+                        continue;
+                    }
+                    serving.spawn(serve_tcp(tcp, app));
+                }
+                #[cfg(unix)]
+                BoundListener::Unix(unix, file) => {
+                    serving.spawn(async move {
+                        let _file = file;
+                        axum::serve(unix, app)
+                            .await
+                            .map_err(|e| ServerError::ServeError(e.to_string()))
+                    });
+                }
+            }
+        }
 
-        Ok(())
+        // Dropping the set aborts the remaining listeners.
+        match serving.join_next().await {
+            Some(Ok(result)) => result,
+            Some(Err(e)) => Err(ServerError::ServeError(e.to_string())),
+            None => Ok(()),
+        }
     }
 
     /// Create the router with all endpoints.
-    fn create_router(&self, state: AppState<B>) -> Router
+    #[cfg(all(test, feature = "prometheus"))]
+    fn create_router(&self) -> Router
     where
         B::Registry: MetricsRenderer<Error = std::fmt::Error>,
     {
-        Router::new()
-            .route(&self.config.metrics_path, get(metrics_handler::<B>))
-            .route(&self.config.health_path, get(health_handler))
-            .route(&self.config.ready_path, get(ready_handler))
-            .with_state(state)
+        self.create_router_for(|_| true)
+    }
+
+    /// Create the router with the endpoints `serves` selects.
+    fn create_router_for(&self, serves: impl Fn(Endpoint) -> bool) -> Router
+    where
+        B::Registry: MetricsRenderer<Error = std::fmt::Error>,
+    {
+        let mut router = Router::new();
+        let routes = [
+            (
+                Endpoint::Metrics,
+                &self.config.metrics_path,
+                get(metrics_handler::<B>),
+            ),
+            (
+                Endpoint::Health,
+                &self.config.health_path,
+                get(health_handler),
+            ),
+            (Endpoint::Ready, &self.config.ready_path, get(ready_handler)),
+        ]
+        .into_iter();
+        #[cfg(feature = "json")]
+        let routes = routes.chain([(
+            Endpoint::Json,
+            &self.config.json_path,
+            get(json_handler::<B>),
+        )]);
+        for (endpoint, path, route) in routes {
+            if serves(endpoint) {
+                #[cfg(feature = "auth")]
+                let route = self.guard(endpoint, route);
+                router = router.route(path, route);
+            }
+        }
+        let router = router.with_state(AppState {
+            registry: Arc::clone(&self.registry),
+            config: Arc::new(self.config.clone()),
+            cache: self.cache.clone(),
+        });
+        if !serves(Endpoint::Custom) {
+            return router;
+        }
+        let mut custom = Router::new();
+        for (path, route) in &self.routes {
+            #[cfg(feature = "auth")]
+            let route = self.guard(Endpoint::Custom, route.clone());
+            #[cfg(not(feature = "auth"))]
+            let route = route.clone();
+            custom = custom.route(path, route);
+        }
+        router.merge(custom.with_state(Arc::clone(&self.registry)))
+    }
+
+    /// Enforce the auth policy of `endpoint`, if it has one, on `route`.
+    #[cfg(feature = "auth")]
+    fn guard<S>(&self, endpoint: Endpoint, route: MethodRouter<S>) -> MethodRouter<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        match (self.config.auth.get(&endpoint), &self.auth_metrics) {
+            (Some(policy), Some(metrics)) if !policy.is_open() => {
+                let guard = Arc::new(AuthGuard::new(policy.clone(), Arc::clone(metrics)));
+                route.route_layer(axum::middleware::from_fn(
+                    move |request: axum::extract::Request, next: axum::middleware::Next| {
+                        Arc::clone(&guard).authorize(request, next)
+                    },
+                ))
+            }
+            _ => route,
+        }
+    }
+}
+
+/// Reject paths axum refuses before they reach the path router: relative
+/// paths and axum 0.7 style `:capture`/`*wildcard` segments.
+fn validate_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') {
+        return Err("must start with `/`".to_string());
+    }
+    match path
+        .split('/')
+        .find(|segment| segment.starts_with(':') || segment.starts_with('*'))
+    {
+        Some(segment) => Err(format!(
+            "segment {} must be written as `{{capture}}` or `{{*wildcard}}`",
+            segment
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Serve `app` over plain HTTP on a TCP listener.
+async fn serve_tcp(listener: TcpListener, app: Router) -> Result<(), ServerError> {
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .map_err(|e| ServerError::ServeError(e.to_string()))
+}
+
+/// How long a client has to complete the TLS handshake.
+#[cfg(feature = "tls")]
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS configuration of a server and its reload self-metric.
+#[cfg(feature = "tls")]
+struct ServerTls<B: MetricBackend> {
+    state: TlsState,
+    reload_failures: Metric<B::Counter>,
+}
+
+#[cfg(feature = "tls")]
+impl<B: MetricBackend> ServerTls<B> {
+    /// Reload the certificates whenever their files change.
+    async fn watch(self: Arc<Self>) {
+        let Some(interval) = self.state.reload_interval() else {
+            return std::future::pending().await;
+        };
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let tls = Arc::clone(&self);
+            let reloaded = tokio::task::spawn_blocking(move || tls.state.reload_if_changed()).await;
+            if !matches!(reloaded, Ok(Ok(_))) {
+                self.reload_failures.inc();
+            }
+        }
+    }
+}
+
+/// Serve `app` over TLS, handshaking each connection on its own task.
+#[cfg(feature = "tls")]
+async fn serve_tls<B: MetricBackend>(
+    listener: TcpListener,
+    app: Router,
+    tls: Arc<ServerTls<B>>,
+) -> Result<(), ServerError> {
+    use hyper_util::rt::TokioIo;
+    use hyper_util::service::TowerToHyperService;
+
+    let accept = async {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    // Per-connection failures (e.g. a reset before accept)
+                    // are routine; back off on anything else, such as
+                    // running out of file descriptors.
+                    if !matches!(
+                        e.kind(),
+                        std::io::ErrorKind::ConnectionAborted
+                            | std::io::ErrorKind::ConnectionReset
+                            | std::io::ErrorKind::ConnectionRefused
+                    ) {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                    continue;
+                }
+            };
+            let acceptor = tokio_rustls::TlsAcceptor::from(tls.state.current());
+            // Expose the client address like `into_make_service_with_connect_info`.
+            let app = app.clone().layer(axum::Extension(ConnectInfo(peer)));
+            tokio::spawn(async move {
+                // Failed handshakes, e.g. without a trusted client
+                // certificate, just close the connection.
+                let Ok(Ok(stream)) =
+                    tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await
+                else {
+                    return;
+                };
+                let _ = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), TowerToHyperService::new(app))
+                    .await;
+            });
+        }
+    };
+
+    tokio::select! {
+        () = Arc::clone(&tls).watch() => Ok(()),
+        () = accept => Ok(()),
     }
 }
 
@@ -241,36 +863,303 @@ pub enum ServerError {
     BindError(String),
     #[error("Server error: {0}")]
     ServeError(String),
+    #[error("Invalid server configuration: {0}")]
+    InvalidConfig(String),
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
 // HTTP Handlers
 // ═══════════════════════════════════════════════════════════════════════════
 
-async fn metrics_handler<B: MetricBackend>(State(state): State<AppState<B>>) -> impl IntoResponse
+async fn metrics_handler<B: MetricBackend>(
+    State(state): State<AppState<B>>,
+    #[cfg(feature = "filter")] RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Response
 where
     B::Registry: MetricsRenderer<Error = std::fmt::Error>,
 {
-    let registry = state.registry.read().await;
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    let scrape = Scrape::new(
+        negotiate(accept),
+        #[cfg(feature = "filter")]
+        query.as_deref(),
+    );
+    match scrape {
+        Ok(scrape) => stream_metrics(state, &headers, scrape).await,
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
 
-    match registry.render() {
-        Ok(rendered) => {
-            let content_type = rendered.content_type.clone();
-            (
-                StatusCode::OK,
-                [(header::CONTENT_TYPE, content_type)],
-                rendered.into_bytes(),
+#[cfg(feature = "json")]
+async fn json_handler<B: MetricBackend>(
+    State(state): State<AppState<B>>,
+    #[cfg(feature = "filter")] RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Response
+where
+    B::Registry: MetricsRenderer<Error = std::fmt::Error>,
+{
+    let scrape = Scrape::new(
+        Exposition::Json,
+        #[cfg(feature = "filter")]
+        query.as_deref(),
+    );
+    match scrape {
+        Ok(scrape) => stream_metrics(state, &headers, scrape).await,
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+/// Render a scrape on a blocking thread and stream it as the response.
+///
+/// Without a render cache, the registry's read lock is held until the
+/// render returns, including while a slow client drains the last few
+/// chunks; scrapes don't block each other, but registering a metric waits
+/// for them. A render still waiting for the client once the write timeout
+/// has passed is aborted and releases the lock. Filtered scrapes always
+/// bypass the cache.
+async fn stream_metrics<B: MetricBackend>(
+    state: AppState<B>,
+    headers: &HeaderMap,
+    scrape: Scrape,
+) -> Response
+where
+    B::Registry: MetricsRenderer<Error = std::fmt::Error>,
+{
+    let coding = BodyCoding::negotiate(headers, &state.config);
+    let write_timeout = state.config.write_timeout;
+    if let (Some(cache), true) = (&state.cache, scrape.is_cacheable()) {
+        let registry = Arc::clone(&state.registry);
+        let format = scrape.format;
+        let rendered = cache
+            .get(format, move || format.render(&registry.blocking_read()))
+            .await;
+        return match rendered {
+            Ok(rendered) => {
+                stream_response(coding, write_timeout, move |writer| {
+                    writer.set_content_type(rendered.content_type.clone());
+                    writer
+                        .write_all(rendered.as_bytes())
+                        .map_err(|e| e.to_string())
+                })
+                .await
+            }
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to render metrics: {}", e),
             )
-                .into_response()
+                .into_response(),
+        };
+    }
+    stream_response(coding, write_timeout, move |writer| {
+        let registry = state.registry.blocking_read();
+        scrape.render_to(&registry, writer)
+    })
+    .await
+}
+
+/// What a scrape asked for.
+struct Scrape {
+    format: Exposition,
+    /// Families selected by the query string, if it has any filters.
+    #[cfg(feature = "filter")]
+    filter: Option<MetricFilter>,
+}
+
+impl Scrape {
+    /// A scrape of `format`, filtered by the `name[]`, `prefix` and
+    /// `regex` parameters of `query`.
+    fn new(
+        format: Exposition,
+        #[cfg(feature = "filter")] query: Option<&str>,
+    ) -> Result<Self, String> {
+        #[cfg(feature = "filter")]
+        let filter = match MetricFilter::from_query(query.unwrap_or("")) {
+            Ok(filter) if filter.is_empty() => None,
+            Ok(filter) => Some(filter),
+            Err(e) => return Err(e.to_string()),
+        };
+        Ok(Self {
+            format,
+            #[cfg(feature = "filter")]
+            filter,
+        })
+    }
+
+    /// Whether the render can be shared with other scrapes.
+    fn is_cacheable(&self) -> bool {
+        #[cfg(feature = "filter")]
+        if self.filter.is_some() {
+            return false;
         }
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to render metrics: {}", e),
-        )
-            .into_response(),
+        true
+    }
+
+    fn render_to<B: MetricBackend>(
+        &self,
+        registry: &ObservabilityRegistry<B>,
+        writer: &mut BodyWriter,
+    ) -> Result<(), String>
+    where
+        B::Registry: MetricsRenderer<Error = std::fmt::Error>,
+    {
+        #[cfg(feature = "filter")]
+        if let Some(filter) = &self.filter {
+            return self.format.render_filtered_to(registry, filter, writer);
+        }
+        self.format.render_to(registry, writer)
     }
 }
 
+/// Write `renderer`'s output to `writer`, setting the content type first.
+#[cfg(any(feature = "json", feature = "protobuf"))]
+fn write_rendered<R: MetricsRenderer>(renderer: &R, writer: &mut BodyWriter) -> Result<(), String>
+where
+    R::Error: std::fmt::Display,
+{
+    writer.set_content_type(renderer.content_type().map_err(|e| e.to_string())?);
+    renderer.render_to(writer).map_err(|e| e.to_string())
+}
+
+/// Exposition formats served by the metrics endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Exposition {
+    /// The backend's own text output (OpenMetrics for Prometheus).
+    Text,
+    /// Delimited `io.prometheus.client.MetricFamily` messages.
+    #[cfg(feature = "protobuf")]
+    Protobuf,
+    /// The JSON schema served on the JSON path.
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl Exposition {
+    /// Write the registry in this format, setting the content type first.
+    fn render_to<B: MetricBackend>(
+        self,
+        registry: &ObservabilityRegistry<B>,
+        writer: &mut BodyWriter,
+    ) -> Result<(), String>
+    where
+        B::Registry: MetricsRenderer<Error = std::fmt::Error>,
+    {
+        match self {
+            Self::Text => {
+                let content_type = registry.inner().content_type().map_err(|e| e.to_string())?;
+                writer.set_content_type(content_type);
+                registry.render_to(writer).map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "protobuf")]
+            Self::Protobuf => write_rendered(
+                &crate::core::protobuf::ProtobufMetrics::new(registry),
+                writer,
+            ),
+            #[cfg(feature = "json")]
+            Self::Json => write_rendered(&crate::core::json::JsonMetrics::new(registry), writer),
+        }
+    }
+
+    /// Write the families `filter` selects in this format.
+    #[cfg(feature = "filter")]
+    fn render_filtered_to<B: MetricBackend>(
+        self,
+        registry: &ObservabilityRegistry<B>,
+        filter: &MetricFilter,
+        writer: &mut BodyWriter,
+    ) -> Result<(), String>
+    where
+        B::Registry: MetricsRenderer<Error = std::fmt::Error>,
+    {
+        match self {
+            Self::Text => {
+                let content_type = registry.inner().content_type().map_err(|e| e.to_string())?;
+                writer.set_content_type(content_type);
+                registry
+                    .render_filtered_to(filter, writer)
+                    .map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "protobuf")]
+            Self::Protobuf => write_rendered(
+                &crate::core::protobuf::ProtobufMetrics::new(registry).filter(filter),
+                writer,
+            ),
+            #[cfg(feature = "json")]
+            Self::Json => write_rendered(
+                &crate::core::json::JsonMetrics::new(registry).filter(filter),
+                writer,
+            ),
+        }
+    }
+
+    /// Render the registry in this format into memory.
+    fn render<B: MetricBackend>(
+        self,
+        registry: &ObservabilityRegistry<B>,
+    ) -> Result<RenderedMetrics, String>
+    where
+        B::Registry: MetricsRenderer<Error = std::fmt::Error>,
+    {
+        match self {
+            Self::Text => registry.render().map_err(|e| e.to_string()),
+            #[cfg(feature = "protobuf")]
+            Self::Protobuf => crate::core::protobuf::ProtobufMetrics::new(registry)
+                .render()
+                .map_err(|e| e.to_string()),
+            #[cfg(feature = "json")]
+            Self::Json => crate::core::json::JsonMetrics::new(registry)
+                .render()
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Pick the exposition format from an `Accept` header.
+///
+/// The acceptable media range with the highest `q` wins, the first one on
+/// ties; text is served when nothing else is acceptable.
+fn negotiate(accept: Option<&str>) -> Exposition {
+    let mut best = (0.0, Exposition::Text);
+    for range in accept.unwrap_or("").split(',') {
+        let mut parts = range.split(';').map(str::trim);
+        let media_type = parts.next().unwrap_or("").to_ascii_lowercase();
+        let params: Vec<(String, &str)> = parts
+            .filter_map(|param| param.split_once('='))
+            .map(|(name, value)| {
+                (
+                    name.trim().to_ascii_lowercase(),
+                    value.trim().trim_matches('"'),
+                )
+            })
+            .collect();
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(param, _)| param == name)
+                .map(|(_, value)| *value)
+        };
+        let q = param("q").map_or(1.0, |q| q.parse().unwrap_or(0.0));
+        let format = match media_type.as_str() {
+            "application/openmetrics-text" | "text/plain" | "text/*" | "*/*" => Exposition::Text,
+            #[cfg(feature = "protobuf")]
+            "application/vnd.google.protobuf"
+                if param("proto") == Some("io.prometheus.client.MetricFamily")
+                    && param("encoding") == Some("delimited") =>
+            {
+                Exposition::Protobuf
+            }
+            _ => continue,
+        };
+        if q > best.0 {
+            best = (q, format);
+        }
+    }
+    best.1
+}
+
 async fn health_handler() -> (StatusCode, &'static str) {
     let status = default_health_check();
     let code = StatusCode::from_u16(status.status_code()).unwrap_or(StatusCode::OK);
@@ -295,6 +1184,8 @@ mod tests {
         assert_eq!(config.metrics_path, "/metrics");
         assert_eq!(config.health_path, "/health");
         assert_eq!(config.ready_path, "/ready");
+        assert_eq!(config.render_cache, None);
+        assert_eq!(config.write_timeout, Duration::from_secs(10));
     }
 
     #[cfg(feature = "prometheus")]
@@ -312,4 +1203,1135 @@ mod tests {
         assert_eq!(server.config().host, "127.0.0.1");
         assert_eq!(server.config().metrics_path, "/prometheus");
     }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test]
+    async fn test_builder_const_labels_and_info_metrics() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .const_label("service", "checkout")
+            .const_labels([("env", "prod")])
+            .target_info([("instance", "pod-1")])
+            .build_info(BuildInfo::new("1.2.3").git_sha("abc123"))
+            .build();
+
+        let registry = server.registry();
+        let mut registry = registry.write().await;
+        registry.counter("orders", "Orders placed").unwrap().inc();
+
+        let output = registry.render().unwrap();
+        let text = output.as_str().unwrap();
+        assert!(text.contains(r#"orders_total{service="checkout",env="prod"} 1"#));
+        assert!(text.contains("# TYPE target info\n"));
+        assert!(text.contains(r#"target_info{service="checkout",env="prod",instance="pod-1"} 1"#));
+        assert!(text.contains(
+            r#"build_info{service="checkout",env="prod",version="1.2.3",git_sha="abc123"} 1"#
+        ));
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test]
+    async fn test_custom_routes_share_the_registry() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::Body;
+        use axum::http::Request;
+        use axum::routing::post;
+        use tower::ServiceExt;
+
+        type Shared = Arc<RwLock<ObservabilityRegistry<PrometheusBackend>>>;
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .route("/version", get(|| async { "1.2.3" }))
+            .route(
+                "/debug/reset",
+                post(|State(registry): State<Shared>| async move {
+                    let resets = registry.write().await.counter("resets", "Debug resets");
+                    resets.unwrap().inc();
+                    StatusCode::NO_CONTENT
+                }),
+            )
+            .build();
+        let router = server.create_router();
+        let call = |request: Request<Body>| router.clone().oneshot(request);
+
+        let version = call(Request::get("/version").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(version.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(version.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"1.2.3");
+
+        let reset = call(Request::post("/debug/reset").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(reset.status(), StatusCode::NO_CONTENT);
+        let metrics = call(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(metrics.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("resets_total 1\n"));
+
+        // Listeners that don't serve custom routes leave them out.
+        let probes = server.create_router_for(|endpoint| endpoint != Endpoint::Custom);
+        let missing = probes
+            .oneshot(Request::get("/version").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        for (first, second) in [
+            ("/metrics", "/version"),
+            ("/health", "/version"),
+            ("version", "/debug"),
+            ("/version", "/version"),
+            ("/users/:id", "/debug"),
+            ("/files/*path", "/debug"),
+            ("/users/{a}", "/users/{b}"),
+            ("/{*rest}", "/{*path}"),
+            ("/{id", "/debug"),
+        ] {
+            let conflict = StandaloneServer::<PrometheusBackend>::builder()
+                .route(first, get(|| async { "" }))
+                .route(second, get(|| async { "" }))
+                .try_build();
+            assert!(
+                matches!(conflict, Err(ServerError::InvalidConfig(_))),
+                "{} and {}",
+                first,
+                second
+            );
+        }
+
+        let captures = StandaloneServer::<PrometheusBackend>::builder()
+            .route("/users/{id}", get(|| async { "" }))
+            .route("/users/{id}/posts", get(|| async { "" }))
+            .try_build()
+            .unwrap();
+        let posts = captures
+            .create_router()
+            .oneshot(Request::get("/users/7/posts").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(posts.status(), StatusCode::OK);
+    }
+
+    #[cfg(all(feature = "json", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_json_endpoint() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use crate::core::json::JsonMetricFamily;
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .json_path("/api/metrics")
+            .build();
+        server
+            .registry()
+            .write()
+            .await
+            .counter("orders", "Orders placed")
+            .unwrap()
+            .inc();
+        let router = server.create_router();
+
+        let response = router
+            .oneshot(Request::get("/api/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let families: Vec<JsonMetricFamily> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(families[0].name, "orders");
+
+        for path in ["/metrics", "/health", "/ready", "api"] {
+            let conflict = StandaloneServer::<PrometheusBackend>::builder()
+                .json_path(path)
+                .try_build();
+            assert!(
+                matches!(conflict, Err(ServerError::InvalidConfig(_))),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_negotiate_defaults_to_text() {
+        assert_eq!(negotiate(None), Exposition::Text);
+        assert_eq!(
+            negotiate(Some("text/plain;version=0.0.4")),
+            Exposition::Text
+        );
+        assert_eq!(negotiate(Some("application/json")), Exposition::Text);
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn test_negotiate_protobuf() {
+        const PROTOBUF: &str = "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited";
+
+        // What Prometheus sends with protobuf scraping enabled.
+        let prometheus = format!(
+            "{};q=0.6,application/openmetrics-text;version=1.0.0;q=0.5,text/plain;version=0.0.4;q=0.4,*/*;q=0.1",
+            PROTOBUF
+        );
+        assert_eq!(negotiate(Some(&prometheus)), Exposition::Protobuf);
+        assert_eq!(negotiate(Some(PROTOBUF)), Exposition::Protobuf);
+        assert_eq!(
+            negotiate(Some(&format!("text/plain;q=0.9,{};q=0.5", PROTOBUF))),
+            Exposition::Text
+        );
+        assert_eq!(
+            negotiate(Some(
+                "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=text"
+            )),
+            Exposition::Text
+        );
+    }
+
+    #[cfg(all(feature = "protobuf", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_metrics_endpoint_serves_protobuf_when_accepted() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use crate::core::protobuf::{proto, PROTOBUF_CONTENT_TYPE};
+        use axum::body::Body;
+        use axum::http::Request;
+        use prost::Message;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder().build();
+        server
+            .registry()
+            .write()
+            .await
+            .counter("orders", "Orders placed")
+            .unwrap()
+            .inc();
+        let router = server.create_router();
+
+        let request = Request::get("/metrics")
+            .header(header::ACCEPT, PROTOBUF_CONTENT_TYPE)
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            PROTOBUF_CONTENT_TYPE
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let family = proto::MetricFamily::decode_length_delimited(body.as_ref()).unwrap();
+        assert_eq!(family.name, "orders_total");
+        assert_eq!(family.metric[0].counter.as_ref().unwrap().value, 1.0);
+
+        let response = router
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("application/openmetrics-text"));
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test]
+    async fn test_metrics_endpoint_streams_large_registries_in_chunks() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::{Body, HttpBody};
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder().build();
+        {
+            let registry = server.registry();
+            let mut registry = registry.write().await;
+            for i in 0..5000 {
+                registry
+                    .counter(format!("jobs_{}", i), "Jobs processed by the worker pool")
+                    .unwrap()
+                    .inc_by(i);
+            }
+        }
+        let expected = server
+            .registry()
+            .read()
+            .await
+            .render()
+            .unwrap()
+            .into_bytes();
+        assert!(expected.len() > 256 * 1024);
+
+        let response = server
+            .create_router()
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body();
+        assert_eq!(body.size_hint().exact(), None);
+
+        let mut chunks = 0;
+        let mut streamed = Vec::new();
+        while let Some(frame) =
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_frame(cx)).await
+        {
+            streamed.extend_from_slice(&frame.unwrap().into_data().unwrap());
+            chunks += 1;
+        }
+        assert!(chunks > 1);
+        assert_eq!(streamed, expected);
+
+        // The render has finished, so the read lock is released.
+        assert!(server.registry().try_write().is_ok());
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_slow_clients_are_aborted_after_the_write_timeout() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::{Body, HttpBody};
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .write_timeout(Duration::from_millis(50))
+            .build();
+        {
+            let registry = server.registry();
+            let mut registry = registry.write().await;
+            for i in 0..5000 {
+                registry
+                    .counter(format!("jobs_{}", i), "Jobs processed by the worker pool")
+                    .unwrap()
+                    .inc_by(i);
+            }
+        }
+
+        let response = server
+            .create_router()
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The client reads nothing, so the render fills the channel, times
+        // out and releases the read lock.
+        let mut released = false;
+        for _ in 0..100 {
+            if server.registry().try_write().is_ok() {
+                released = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(released, "stalled scrape kept the read lock");
+
+        // The queued chunks are still delivered, then the body fails
+        // instead of ending early.
+        let mut body = response.into_body();
+        let mut failed = false;
+        while let Some(frame) =
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_frame(cx)).await
+        {
+            if frame.is_err() {
+                failed = true;
+                break;
+            }
+        }
+        assert!(failed, "aborted body ended without an error");
+
+        // A client that keeps reading, just slowly, is held to the same
+        // deadline for the whole response rather than per chunk.
+        let response = server
+            .create_router()
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let mut body = response.into_body();
+        let mut failed = false;
+        while let Some(frame) =
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_frame(cx)).await
+        {
+            if frame.is_err() {
+                failed = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(30)).await;
+        }
+        assert!(failed, "slow client outlived the write timeout");
+        assert!(server.registry().try_write().is_ok());
+
+        let invalid = StandaloneServer::<PrometheusBackend>::builder()
+            .write_timeout(Duration::ZERO)
+            .try_build();
+        assert!(matches!(invalid, Err(ServerError::InvalidConfig(_))));
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_render_cache_coalesces_concurrent_scrapes() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .render_cache(Duration::ZERO)
+            .build();
+        let router = server.create_router();
+        let registry = server.registry();
+        let counter = registry
+            .write()
+            .await
+            .counter("orders", "Orders placed")
+            .unwrap();
+
+        // Hold the registry so the first scrape's render can't finish
+        // until every scrape is waiting on it.
+        let guard = registry.write().await;
+        let scrapes: Vec<_> = (0..5)
+            .map(|_| {
+                let router = router.clone();
+                tokio::spawn(async move {
+                    let response = router
+                        .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+                        .await
+                        .unwrap();
+                    axum::body::to_bytes(response.into_body(), usize::MAX)
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        counter.inc();
+        drop(guard);
+
+        for scrape in scrapes {
+            let body = scrape.await.unwrap();
+            assert!(std::str::from_utf8(&body)
+                .unwrap()
+                .contains("orders_total 1\n"));
+        }
+
+        // A zero max age re-renders for scrapes that arrive afterwards; this
+        // one counts itself as a miss before rendering.
+        let response = router
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = std::str::from_utf8(&body).unwrap();
+        assert!(text.contains("observe_rs_server_render_cache_hits_total 4\n"));
+        assert!(text.contains("observe_rs_server_render_cache_misses_total 2\n"));
+        assert!(text.contains("observe_rs_server_render_duration_seconds_count 1\n"));
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test]
+    async fn test_render_cache_serves_stale_renders_within_max_age() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .render_cache(Duration::from_secs(60))
+            .build();
+        let router = server.create_router();
+        let counter = server
+            .registry()
+            .write()
+            .await
+            .counter("orders", "Orders placed")
+            .unwrap();
+
+        let scrape = || async {
+            let response = router
+                .clone()
+                .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert!(response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("application/openmetrics-text"));
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+        };
+        let first = scrape().await;
+        counter.inc();
+        assert_eq!(scrape().await, first);
+        assert!(std::str::from_utf8(&first)
+            .unwrap()
+            .contains("orders_total 0\n"));
+    }
+
+    #[cfg(all(feature = "relabel", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_metrics_endpoint_applies_relabel_rules() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .relabel_rules(
+                RelabelRules::new()
+                    .rename("legacy_(.*)", "app_$1")
+                    .unwrap()
+                    .drop_series(["path"], "/healthz")
+                    .unwrap()
+                    .redact("user")
+                    .unwrap(),
+            )
+            .build();
+        {
+            let registry = server.registry();
+            let mut registry = registry.write().await;
+            let requests = prometheus_client::metrics::family::Family::<
+                Vec<(String, String)>,
+                prometheus_client::metrics::counter::Counter,
+            >::default();
+            let series = |path: &str, user: &str| {
+                vec![
+                    ("path".to_string(), path.to_string()),
+                    ("user".to_string(), user.to_string()),
+                ]
+            };
+            requests.get_or_create(&series("/", "alice")).inc();
+            requests.get_or_create(&series("/healthz", "probe")).inc();
+            registry
+                .inner_mut()
+                .register("legacy_requests", "Requests served", requests);
+        }
+
+        let response = server
+            .create_router()
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("# TYPE app_requests counter\n"));
+        assert!(text.contains("app_requests_total{path=\"/\",user=\"[REDACTED]\"} 1\n"));
+        assert!(!text.contains("healthz"));
+        assert!(!text.contains("alice"));
+        assert!(!text.contains("legacy_"));
+    }
+
+    #[cfg(all(feature = "filter", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_metrics_endpoint_filters_families() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .render_cache(Duration::from_secs(60))
+            .build();
+        {
+            let registry = server.registry();
+            let mut registry = registry.write().await;
+            registry
+                .counter("http_requests", "Requests served")
+                .unwrap()
+                .inc();
+            registry
+                .gauge("http_in_flight", "Requests in flight")
+                .unwrap();
+            registry
+                .gauge("queue_depth", "Items waiting")
+                .unwrap()
+                .set(3);
+            registry.gauge("db_pool_size", "Open connections").unwrap();
+        }
+        let router = server.create_router();
+        let get = |uri: &'static str| {
+            let router = router.clone();
+            async move {
+                let response = router
+                    .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (status, text) = get("/metrics?name[]=http_requests_total&name[]=queue_depth").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(text.contains("http_requests_total 1\n"));
+        assert!(text.contains("queue_depth 3\n"));
+        assert!(!text.contains("http_in_flight"));
+        assert!(!text.contains("db_pool_size"));
+        assert!(text.ends_with("# EOF\n"));
+
+        let (_, text) = get("/metrics?prefix=http_&regex=db_.%2A").await;
+        assert!(text.contains("# TYPE http_requests counter"));
+        assert!(text.contains("# TYPE http_in_flight gauge"));
+        assert!(text.contains("# TYPE db_pool_size gauge"));
+        assert!(!text.contains("queue_depth"));
+
+        let (status, text) = get("/metrics?regex=%28").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(text.contains("invalid metric name regex"));
+
+        // Unfiltered scrapes still go through the cache; filtered ones don't.
+        let (_, text) = get("/metrics").await;
+        assert!(text.contains("queue_depth 3\n"));
+        assert!(text.contains("observe_rs_server_render_cache_misses_total 1\n"));
+
+        #[cfg(feature = "json")]
+        {
+            let (_, json) = get("/metrics/json?name=queue_depth").await;
+            let families: Vec<crate::core::json::JsonMetricFamily> =
+                serde_json::from_str(&json).unwrap();
+            assert_eq!(families.len(), 1);
+            assert_eq!(families[0].name, "queue_depth");
+        }
+    }
+
+    #[cfg(all(feature = "compression", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_large_bodies_are_compressed_as_they_stream() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::Body;
+        use axum::http::Request;
+        use std::io::Read;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder().build();
+        {
+            let registry = server.registry();
+            let mut registry = registry.write().await;
+            for i in 0..5000 {
+                registry
+                    .gauge(format!("queue_depth_{}", i), "Items waiting in the queue")
+                    .unwrap()
+                    .set(i);
+            }
+        }
+        let expected = server
+            .registry()
+            .read()
+            .await
+            .render()
+            .unwrap()
+            .into_bytes();
+
+        let response = server
+            .create_router()
+            .oneshot(
+                Request::get("/metrics")
+                    .header(header::ACCEPT_ENCODING, "gzip")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.len() < expected.len() / 4);
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, expected);
+    }
+
+    #[cfg(all(feature = "compression", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_metrics_endpoint_compresses_when_accepted() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::Body;
+        use axum::http::Request;
+        use std::io::Read;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .compression(CompressionConfig::default().min_size(256).zstd_level(19))
+            .build();
+        {
+            let registry = server.registry();
+            let mut registry = registry.write().await;
+            for i in 0..20 {
+                registry
+                    .counter(format!("jobs_{}", i), "Jobs processed")
+                    .unwrap()
+                    .inc_by(i);
+            }
+        }
+        let router = server.create_router();
+        let get = |accept_encoding: &'static str| {
+            let router = router.clone();
+            async move {
+                let response = router
+                    .oneshot(
+                        Request::get("/metrics")
+                            .header(header::ACCEPT_ENCODING, accept_encoding)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let encoding = response
+                    .headers()
+                    .get(header::CONTENT_ENCODING)
+                    .map(|value| value.to_str().unwrap().to_string());
+                assert_eq!(response.headers()[header::VARY], "accept-encoding");
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (encoding, body.to_vec())
+            }
+        };
+
+        let expected = server
+            .registry()
+            .read()
+            .await
+            .render()
+            .unwrap()
+            .into_bytes();
+
+        let (encoding, body) = get("identity").await;
+        assert_eq!(encoding, None);
+        assert_eq!(body, expected);
+
+        let (encoding, body) = get("gzip").await;
+        assert_eq!(encoding.as_deref(), Some("gzip"));
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(body.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, expected);
+        assert!(body.len() < expected.len());
+
+        let (encoding, body) = get("gzip, zstd").await;
+        assert_eq!(encoding.as_deref(), Some("zstd"));
+        assert_eq!(zstd::decode_all(body.as_slice()).unwrap(), expected);
+    }
+
+    #[cfg(all(feature = "compression", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_small_bodies_and_bad_levels() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::Body;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder().build();
+        let response = server
+            .create_router()
+            .oneshot(
+                Request::get("/metrics")
+                    .header(header::ACCEPT_ENCODING, "gzip")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+
+        let invalid = StandaloneServer::<PrometheusBackend>::builder()
+            .compression(CompressionConfig::default().gzip_level(12))
+            .try_build();
+        assert!(matches!(invalid, Err(ServerError::InvalidConfig(_))));
+    }
+
+    /// GET `path` over TLS from `addr`, returning the raw response, or the
+    /// I/O error of a failed handshake.
+    #[cfg(all(feature = "tls", feature = "prometheus"))]
+    async fn https_get(
+        addr: std::net::SocketAddr,
+        ca_cert: &str,
+        client_cert: Option<(&str, &str)>,
+        path: &str,
+    ) -> std::io::Result<String> {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_slice(ca_cert.as_bytes()).unwrap())
+            .unwrap();
+        let builder = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+        let config = match client_cert {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(
+                    vec![CertificateDer::from_pem_slice(cert.as_bytes()).unwrap()],
+                    PrivateKeyDer::from_pem_slice(key.as_bytes()).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+
+        let stream = tokio::net::TcpStream::connect(addr).await?;
+        let mut stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await?;
+        stream
+            .write_all(
+                format!(
+                    "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                    path
+                )
+                .as_bytes(),
+            )
+            .await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response)
+    }
+
+    /// Start `server` on an ephemeral local port.
+    #[cfg(all(feature = "tls", feature = "prometheus"))]
+    async fn spawn_server(
+        server: StandaloneServer<
+            crate::backends::prometheus::prometheus_backend::PrometheusBackend,
+        >,
+    ) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = server.create_router();
+        match server.tls.clone() {
+            Some(tls) => tokio::spawn(serve_tls(listener, app, tls)),
+            None => tokio::spawn(serve_tcp(listener, app)),
+        };
+        addr
+    }
+
+    #[cfg(all(feature = "tls", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_tls_serves_https() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use crate::http::tls::tests::TestPki;
+
+        let pki = TestPki::new("server CA");
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .tls(TlsConfig::from_pem(pki.cert.clone(), pki.key.clone()))
+            .build();
+        server
+            .registry()
+            .write()
+            .await
+            .counter("jobs", "Jobs run")
+            .unwrap()
+            .inc();
+        let addr = spawn_server(server).await;
+
+        let response = https_get(addr, &pki.ca_cert, None, "/metrics")
+            .await
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("jobs_total 1\n"));
+        assert!(response.contains("observe_rs_server_tls_reload_failures_total 0\n"));
+
+        // Clients that don't trust the server's CA refuse it.
+        let other = TestPki::new("other CA");
+        assert!(https_get(addr, &other.ca_cert, None, "/health")
+            .await
+            .is_err());
+    }
+
+    #[cfg(all(feature = "tls", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_mutual_tls_requires_trusted_client_certificates() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use crate::http::tls::tests::TestPki;
+
+        let server_pki = TestPki::new("server CA");
+        let client_pki = TestPki::new("client CA");
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .tls(
+                TlsConfig::from_pem(server_pki.cert.clone(), server_pki.key.clone())
+                    .client_ca_pem(client_pki.ca_cert.clone()),
+            )
+            .build();
+        let addr = spawn_server(server).await;
+
+        let (cert, key) = client_pki.leaf("scraper");
+        let response = https_get(addr, &server_pki.ca_cert, Some((&cert, &key)), "/health")
+            .await
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        // TLS 1.3 reports a rejected client certificate on the first read.
+        assert!(https_get(addr, &server_pki.ca_cert, None, "/health")
+            .await
+            .is_err());
+        let untrusted = TestPki::new("untrusted CA");
+        let (cert, key) = untrusted.leaf("scraper");
+        assert!(
+            https_get(addr, &server_pki.ca_cert, Some((&cert, &key)), "/health")
+                .await
+                .is_err()
+        );
+    }
+
+    #[cfg(all(feature = "tls", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_tls_certificates_reload_when_files_change() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use crate::http::tls::tests::TestPki;
+
+        let dir =
+            std::env::temp_dir().join(format!("observe-rs-tls-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert_path, key_path) = (dir.join("tls.crt"), dir.join("tls.key"));
+        let old = TestPki::new("old CA");
+        std::fs::write(&cert_path, &old.cert).unwrap();
+        std::fs::write(&key_path, &old.key).unwrap();
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .tls(
+                TlsConfig::from_pem_files(&cert_path, &key_path)
+                    .reload_interval(Duration::from_millis(20)),
+            )
+            .build();
+        let addr = spawn_server(server).await;
+        assert!(https_get(addr, &old.ca_cert, None, "/health").await.is_ok());
+
+        let new = TestPki::new("new CA");
+        std::fs::write(&cert_path, &new.cert).unwrap();
+        std::fs::write(&key_path, &new.key).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&key_path)
+            .unwrap()
+            .set_modified(std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap();
+
+        let mut reloaded = false;
+        for _ in 0..100 {
+            if https_get(addr, &new.ca_cert, None, "/health").await.is_ok() {
+                reloaded = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(reloaded, "server kept the old certificate");
+        assert!(https_get(addr, &old.ca_cert, None, "/health")
+            .await
+            .is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(all(feature = "tls", feature = "prometheus"))]
+    #[test]
+    fn test_invalid_tls_config_is_rejected() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+
+        let invalid = StandaloneServer::<PrometheusBackend>::builder()
+            .tls(TlsConfig::from_pem_files(
+                "/nonexistent/tls.crt",
+                "/nonexistent/tls.key",
+            ))
+            .try_build();
+        assert!(matches!(invalid, Err(ServerError::InvalidConfig(_))));
+
+        let zero_interval = StandaloneServer::<PrometheusBackend>::builder()
+            .tls(
+                TlsConfig::from_pem_files("/nonexistent/tls.crt", "/nonexistent/tls.key")
+                    .reload_interval(Duration::ZERO),
+            )
+            .try_build();
+        match zero_interval {
+            Err(ServerError::InvalidConfig(reason)) => assert!(reason.contains("reload interval")),
+            _ => panic!("zero reload interval was accepted"),
+        }
+    }
+
+    #[cfg(all(feature = "auth", feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_auth_policies_apply_per_endpoint() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+        use axum::body::Body;
+        use axum::extract::ConnectInfo;
+        use axum::http::Request;
+        use tower::ServiceExt;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .auth(
+                Endpoint::Metrics,
+                AuthPolicy::new()
+                    .basic_user("prometheus", &bcrypt::hash("s3cret", 4).unwrap())
+                    .unwrap()
+                    .bearer_token("scrape-token"),
+            )
+            .auth(
+                Endpoint::Ready,
+                AuthPolicy::new().allow_cidr("10.0.0.0/8").unwrap(),
+            )
+            .build();
+        let router = server.create_router();
+        let get = |uri: &'static str, peer: &'static str, authorization: Option<&'static str>| {
+            let router = router.clone();
+            async move {
+                let mut request =
+                    Request::get(uri).extension(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+                if let Some(authorization) = authorization {
+                    request = request.header(header::AUTHORIZATION, authorization);
+                }
+                router
+                    .oneshot(request.body(Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let open = get("/health", "192.168.0.1:5000", None).await;
+        assert_eq!(open.status(), StatusCode::OK);
+
+        let missing = get("/metrics", "10.0.0.1:5000", None).await;
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        assert!(missing.headers()[header::WWW_AUTHENTICATE]
+            .to_str()
+            .unwrap()
+            .starts_with("Basic realm="));
+        let wrong = get("/metrics", "10.0.0.1:5000", Some("Bearer guess")).await;
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+
+        let bearer = get("/metrics", "10.0.0.1:5000", Some("Bearer scrape-token")).await;
+        assert_eq!(bearer.status(), StatusCode::OK);
+        // prometheus:s3cret
+        let basic = get(
+            "/metrics",
+            "10.0.0.1:5000",
+            Some("Basic cHJvbWV0aGV1czpzM2NyZXQ="),
+        )
+        .await;
+        assert_eq!(basic.status(), StatusCode::OK);
+
+        let inside = get("/ready", "10.1.2.3:5000", None).await;
+        assert_eq!(inside.status(), StatusCode::OK);
+        let outside = get("/ready", "192.168.0.1:5000", None).await;
+        assert_eq!(outside.status(), StatusCode::FORBIDDEN);
+
+        let body = axum::body::to_bytes(bearer.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("observe_rs_server_auth_failures_total 2\n"));
+        assert!(text.contains("observe_rs_server_auth_denied_addresses_total 0\n"));
+        let text = server.registry().read().await.render().unwrap();
+        assert!(text
+            .as_str()
+            .unwrap()
+            .contains("observe_rs_server_auth_denied_addresses_total 1\n"));
+    }
+
+    /// GET `uri` over the Unix socket at `path`, returning the raw response.
+    #[cfg(all(unix, feature = "prometheus"))]
+    async fn unix_get(path: &std::path::Path, uri: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::UnixStream::connect(path).await.unwrap();
+        stream
+            .write_all(
+                format!(
+                    "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                    uri
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[cfg(all(unix, feature = "prometheus"))]
+    #[tokio::test]
+    async fn test_listeners_serve_their_endpoints() {
+        use crate::backends::prometheus::prometheus_backend::PrometheusBackend;
+
+        let socket = |name: &str| {
+            std::env::temp_dir().join(format!("observe-rs-{}-{}.sock", name, std::process::id()))
+        };
+        let (metrics, probes) = (socket("metrics"), socket("probes"));
+        let server = StandaloneServer::<PrometheusBackend>::builder()
+            .listener(ListenerConfig::unix(&metrics).endpoints([Endpoint::Metrics]))
+            .listener(ListenerConfig::unix(&probes).endpoints([Endpoint::Health, Endpoint::Ready]))
+            .build();
+        server
+            .registry()
+            .write()
+            .await
+            .counter("jobs", "Jobs run")
+            .unwrap()
+            .inc();
+        let running = tokio::spawn(async move { server.run().await });
+        for _ in 0..100 {
+            if metrics.exists() && probes.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let scrape = unix_get(&metrics, "/metrics").await;
+        assert!(scrape.starts_with("HTTP/1.1 200"), "{}", scrape);
+        assert!(scrape.contains("jobs_total 1"));
+        assert!(unix_get(&metrics, "/health")
+            .await
+            .starts_with("HTTP/1.1 404"));
+        assert!(unix_get(&probes, "/health")
+            .await
+            .starts_with("HTTP/1.1 200"));
+        assert!(unix_get(&probes, "/ready")
+            .await
+            .starts_with("HTTP/1.1 200"));
+        assert!(unix_get(&probes, "/metrics")
+            .await
+            .starts_with("HTTP/1.1 404"));
+
+        // Stopping the server removes its sockets.
+        running.abort();
+        let _ = running.await;
+        for _ in 0..100 {
+            if !metrics.exists() && !probes.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!metrics.exists() && !probes.exists());
+
+        let duplicate = StandaloneServer::<PrometheusBackend>::builder()
+            .listener(ListenerConfig::tcp("127.0.0.1", 9100))
+            .listener(ListenerConfig::tcp("127.0.0.1", 9100).endpoints([Endpoint::Health]))
+            .try_build();
+        assert!(matches!(duplicate, Err(ServerError::InvalidConfig(_))));
+    }
 }
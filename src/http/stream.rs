@@ -0,0 +1,420 @@
+//! Streaming response bodies for the metrics endpoints.
+//!
+//! A render runs on a blocking thread and writes into a [`BodyWriter`],
+//! which cuts the output into [`CHUNK_SIZE`] chunks and hands them to the
+//! connection over a bounded channel. At most [`CHANNEL_CHUNKS`] chunks are
+//! queued, so a scrape holds a fixed amount of memory however large the
+//! registry is, and a client that goes away stops the render. A render
+//! still waiting for the client once the server's write timeout has passed
+//! since it started is aborted too, so a slow client can hold the registry
+//! lock for at most that long, however it paces its reads.
+//!
+//! Headers can't change once the first chunk is sent, so the writer holds
+//! output back until it has a full chunk (or the compression threshold,
+//! if larger). Renders that fail before that still get a 500; later
+//! failures abort the response.
+
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use hyper::body::{Bytes, Frame};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::error::SendTimeoutError;
+use tokio::sync::{mpsc, oneshot};
+
+#[cfg(feature = "compression")]
+use super::compression::{CompressionConfig, Encoding};
+use super::standalone::ServerConfig;
+
+/// Size of the chunks sent to the client, in bytes.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Chunks queued between the render and the connection.
+const CHANNEL_CHUNKS: usize = 4;
+
+/// How the response starts, sent once the writer knows.
+enum Start {
+    /// The render failed before any output was sent.
+    Failed(String),
+    /// Headers for a body that follows on the chunk channel.
+    Body {
+        content_type: String,
+        content_encoding: Option<&'static str>,
+    },
+}
+
+/// Content coding chosen for a response, applied only to bodies of at
+/// least `min_size` bytes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BodyCoding {
+    #[cfg(feature = "compression")]
+    compression: Option<(Encoding, CompressionConfig)>,
+}
+
+impl BodyCoding {
+    /// Negotiate a coding from the request's `Accept-Encoding`.
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+    pub(crate) fn negotiate(headers: &HeaderMap, config: &ServerConfig) -> Self {
+        #[cfg(feature = "compression")]
+        {
+            let accept_encoding = headers
+                .get(header::ACCEPT_ENCODING)
+                .and_then(|value| value.to_str().ok());
+            Self {
+                compression: super::compression::negotiate(accept_encoding)
+                    .map(|encoding| (encoding, config.compression.clone())),
+            }
+        }
+        #[cfg(not(feature = "compression"))]
+        Self {}
+    }
+
+    /// Output held back before the headers are sent.
+    fn threshold(&self) -> usize {
+        #[cfg(feature = "compression")]
+        if let Some((_, config)) = &self.compression {
+            return CHUNK_SIZE.max(config.min_size);
+        }
+        CHUNK_SIZE
+    }
+
+    /// The encoder for a body that starts with `len` held-back bytes.
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+    fn encoder(&self, len: usize, writer: ChunkWriter) -> io::Result<Encoder> {
+        #[cfg(feature = "compression")]
+        if let Some((encoding, config)) = &self.compression {
+            if len >= config.min_size {
+                return Ok(match encoding {
+                    Encoding::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                        writer,
+                        flate2::Compression::new(config.gzip_level),
+                    )),
+                    Encoding::Zstd => Encoder::Zstd(zstd::Encoder::new(writer, config.zstd_level)?),
+                });
+            }
+        }
+        Ok(Encoder::Identity(writer))
+    }
+}
+
+/// Render on a blocking thread and stream the output as the response.
+///
+/// `render` returns the content type and writes the body; its error is
+/// reported as a 500 if nothing has been sent yet. Writes that have to
+/// wait for the client fail once `write_timeout` has passed since the
+/// render was started.
+pub(crate) async fn stream_response<F>(
+    coding: BodyCoding,
+    write_timeout: Duration,
+    render: F,
+) -> Response
+where
+    F: FnOnce(&mut BodyWriter) -> Result<(), String> + Send + 'static,
+{
+    let (start_tx, start_rx) = oneshot::channel();
+    let (chunk_tx, chunk_rx) = mpsc::channel(CHANNEL_CHUNKS);
+    let complete = Arc::new(AtomicBool::new(false));
+    let mut writer = BodyWriter {
+        coding,
+        content_type: None,
+        state: State::Buffering(Vec::new()),
+        start: Some(start_tx),
+        chunks: ChunkSender {
+            tx: chunk_tx,
+            runtime: Handle::current(),
+            deadline: Instant::now() + write_timeout,
+        },
+        complete: Arc::clone(&complete),
+    };
+    tokio::task::spawn_blocking(move || {
+        let result = render(&mut writer);
+        writer.finish(result);
+    });
+
+    match start_rx.await {
+        Ok(Start::Body {
+            content_type,
+            content_encoding,
+        }) => {
+            let mut headers = HeaderMap::new();
+            if let Ok(value) = content_type.parse() {
+                headers.insert(header::CONTENT_TYPE, value);
+            }
+            #[cfg(feature = "compression")]
+            {
+                headers.insert(header::VARY, "accept-encoding".parse().unwrap());
+                if let Some(encoding) = content_encoding {
+                    headers.insert(header::CONTENT_ENCODING, encoding.parse().unwrap());
+                }
+            }
+            #[cfg(not(feature = "compression"))]
+            let _ = content_encoding;
+            (
+                StatusCode::OK,
+                headers,
+                Body::new(ChunkBody {
+                    chunks: chunk_rx,
+                    complete,
+                }),
+            )
+                .into_response()
+        }
+        Ok(Start::Failed(e)) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to render metrics: {}", e),
+        )
+            .into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to render metrics: render task panicked",
+        )
+            .into_response(),
+    }
+}
+
+/// Where a render writes its output.
+pub(crate) struct BodyWriter {
+    coding: BodyCoding,
+    content_type: Option<String>,
+    state: State,
+    start: Option<oneshot::Sender<Start>>,
+    chunks: ChunkSender,
+    /// Set once the whole body has been queued.
+    complete: Arc<AtomicBool>,
+}
+
+enum State {
+    /// Holding output back until the headers can be decided.
+    Buffering(Vec<u8>),
+    /// Headers sent; output goes straight to the encoder.
+    Streaming(Encoder),
+    /// The client went away or an encoder failed.
+    Closed,
+}
+
+impl BodyWriter {
+    /// Set the response's content type; call before writing.
+    pub(crate) fn set_content_type(&mut self, content_type: String) {
+        self.content_type = Some(content_type);
+    }
+
+    /// Send the headers and switch to streaming the held-back output.
+    fn start_body(&mut self) -> io::Result<()> {
+        let held = match &mut self.state {
+            State::Buffering(held) => std::mem::take(held),
+            _ => return Ok(()),
+        };
+        self.state = State::Closed;
+        let writer = ChunkWriter {
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            chunks: self.chunks.clone(),
+        };
+        let mut encoder = self.coding.encoder(held.len(), writer)?;
+        let start = Start::Body {
+            content_type: self.content_type.take().unwrap_or_default(),
+            content_encoding: encoder.content_encoding(),
+        };
+        if let Some(tx) = self.start.take() {
+            tx.send(start).map_err(|_| closed())?;
+        }
+        encoder.write_all(&held)?;
+        self.state = State::Streaming(encoder);
+        Ok(())
+    }
+
+    /// End the response with the render's result.
+    fn finish(mut self, result: Result<(), String>) {
+        match result {
+            Ok(()) => {
+                let finished = self.start_body().and_then(|()| {
+                    match std::mem::replace(&mut self.state, State::Closed) {
+                        State::Streaming(encoder) => encoder.finish()?.flush(),
+                        _ => Ok(()),
+                    }
+                });
+                match finished {
+                    Ok(()) => self.complete.store(true, Ordering::Release),
+                    Err(e) => {
+                        let _ = self.chunks.send(Err(e));
+                    }
+                }
+            }
+            Err(e) => match self.start.take() {
+                Some(tx) => {
+                    let _ = tx.send(Start::Failed(e));
+                }
+                // Too late for a status code; fail the body instead.
+                None => {
+                    let _ = self.chunks.send(Err(io::Error::other(e)));
+                }
+            },
+        }
+    }
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match &mut self.state {
+            State::Buffering(held) => {
+                held.extend_from_slice(data);
+                if held.len() >= self.coding.threshold() {
+                    self.start_body()?;
+                }
+                Ok(data.len())
+            }
+            State::Streaming(encoder) => encoder.write(data),
+            State::Closed => Err(closed()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The content coding applied on top of the chunk writer.
+enum Encoder {
+    Identity(ChunkWriter),
+    #[cfg(feature = "compression")]
+    Gzip(flate2::write::GzEncoder<ChunkWriter>),
+    #[cfg(feature = "compression")]
+    Zstd(zstd::Encoder<'static, ChunkWriter>),
+}
+
+impl Encoder {
+    fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            Self::Identity(_) => None,
+            #[cfg(feature = "compression")]
+            Self::Gzip(_) => Some(Encoding::Gzip.as_str()),
+            #[cfg(feature = "compression")]
+            Self::Zstd(_) => Some(Encoding::Zstd.as_str()),
+        }
+    }
+
+    /// Write the coding's trailer and return the chunk writer.
+    fn finish(self) -> io::Result<ChunkWriter> {
+        match self {
+            Self::Identity(writer) => Ok(writer),
+            #[cfg(feature = "compression")]
+            Self::Gzip(encoder) => encoder.finish(),
+            #[cfg(feature = "compression")]
+            Self::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Identity(writer) => writer.write(data),
+            #[cfg(feature = "compression")]
+            Self::Gzip(encoder) => encoder.write(data),
+            #[cfg(feature = "compression")]
+            Self::Zstd(encoder) => encoder.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Identity(writer) => writer.flush(),
+            #[cfg(feature = "compression")]
+            Self::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "compression")]
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// The render's end of the chunk channel.
+#[derive(Clone)]
+struct ChunkSender {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    runtime: Handle,
+    /// When waiting for the client stops; a chunk the channel has room for
+    /// is still queued after it.
+    deadline: Instant,
+}
+
+impl ChunkSender {
+    /// Queue `chunk`, blocking while the channel is full until the
+    /// deadline at most.
+    fn send(&self, chunk: io::Result<Bytes>) -> io::Result<()> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        self.runtime
+            .block_on(self.tx.send_timeout(chunk, remaining))
+            .map_err(|e| match e {
+                SendTimeoutError::Timeout(_) => io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "client too slow to read the response before the write timeout",
+                ),
+                SendTimeoutError::Closed(_) => closed(),
+            })
+    }
+}
+
+/// Collects output into chunks and sends each one to the connection.
+struct ChunkWriter {
+    buf: Vec<u8>,
+    chunks: ChunkSender,
+}
+
+impl ChunkWriter {
+    fn send(&mut self) -> io::Result<()> {
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.chunks.send(Ok(Bytes::from(chunk)))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.send()
+    }
+}
+
+/// The client stopped reading the response.
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "client closed the connection")
+}
+
+/// Response body fed by the chunk channel.
+struct ChunkBody {
+    chunks: mpsc::Receiver<io::Result<Bytes>>,
+    /// Whether the render queued the whole body; a channel that closes
+    /// before that fails the response instead of truncating it.
+    complete: Arc<AtomicBool>,
+}
+
+impl hyper::body::Body for ChunkBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        self.chunks.poll_recv(cx).map(|chunk| match chunk {
+            Some(chunk) => Some(chunk.map(Frame::data)),
+            None if self.complete.load(Ordering::Acquire) => None,
+            None => Some(Err(io::Error::other("metrics render was aborted"))),
+        })
+    }
+}
@@ -0,0 +1,419 @@
+//! TLS and mutual TLS for the standalone server.
+//!
+//! A [`TlsConfig`] names a certificate chain and private key, as PEM files
+//! or in-memory PEM, and optionally the CA bundle client certificates must
+//! chain to. Files are checked for changes every
+//! [`reload_interval`](TlsConfig::reload_interval) and new connections
+//! use the new certificates; if a reload fails, the previous ones stay in
+//! use.
+//!
+//! # Example
+//! ```ignore
+//! let server = StandaloneServer::<PrometheusBackend>::builder()
+//!     .tls(
+//!         TlsConfig::from_pem_files("/etc/metrics/tls.crt", "/etc/metrics/tls.key")
+//!             .client_ca_file("/etc/metrics/clients-ca.crt"),
+//!     )
+//!     .build();
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+
+/// Default interval between checks of the PEM files for changes.
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Name of the counter of failed certificate reloads.
+pub const TLS_RELOAD_FAILURES_METRIC: &str = "observe_rs_server_tls_reload_failures";
+
+/// Errors from loading TLS certificates and keys.
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    /// A PEM file couldn't be read.
+    #[error("failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A PEM source has no usable contents.
+    #[error("invalid {what} PEM: {reason}")]
+    Pem { what: &'static str, reason: String },
+
+    /// rustls rejected the certificates or key.
+    #[error("invalid TLS configuration: {0}")]
+    Rustls(#[from] rustls::Error),
+
+    /// The client CA bundle can't be used to verify clients.
+    #[error("invalid client CA: {0}")]
+    ClientCa(String),
+}
+
+/// Where PEM data comes from.
+#[derive(Clone)]
+pub enum PemSource {
+    /// A file, re-read when it changes.
+    File(PathBuf),
+    /// PEM held in memory.
+    Memory(Vec<u8>),
+}
+
+impl PemSource {
+    fn read(&self) -> Result<Vec<u8>, TlsError> {
+        match self {
+            Self::File(path) => std::fs::read(path).map_err(|source| TlsError::Io {
+                path: path.clone(),
+                source,
+            }),
+            Self::Memory(pem) => Ok(pem.clone()),
+        }
+    }
+
+    /// Modification time of a file source.
+    fn modified(&self) -> Option<SystemTime> {
+        match self {
+            Self::File(path) => std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            Self::Memory(_) => None,
+        }
+    }
+
+    fn is_file(&self) -> bool {
+        matches!(self, Self::File(_))
+    }
+}
+
+impl fmt::Debug for PemSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+            Self::Memory(pem) => write!(f, "Memory({} bytes)", pem.len()),
+        }
+    }
+}
+
+/// Certificates, key and client CA of the standalone server.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    cert: PemSource,
+    key: PemSource,
+    client_ca: Option<PemSource>,
+    reload_interval: Duration,
+}
+
+impl TlsConfig {
+    /// Serve the certificate chain and private key in these PEM files.
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Self {
+        Self::new(
+            PemSource::File(cert.as_ref().to_path_buf()),
+            PemSource::File(key.as_ref().to_path_buf()),
+        )
+    }
+
+    /// Serve this PEM certificate chain and private key.
+    pub fn from_pem(cert: impl Into<Vec<u8>>, key: impl Into<Vec<u8>>) -> Self {
+        Self::new(
+            PemSource::Memory(cert.into()),
+            PemSource::Memory(key.into()),
+        )
+    }
+
+    /// Serve the certificate chain and private key from these sources.
+    pub fn new(cert: PemSource, key: PemSource) -> Self {
+        Self {
+            cert,
+            key,
+            client_ca: None,
+            reload_interval: DEFAULT_RELOAD_INTERVAL,
+        }
+    }
+
+    /// Require client certificates issued by a CA in this PEM file.
+    pub fn client_ca_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.client_ca = Some(PemSource::File(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Require client certificates issued by a CA in this PEM bundle.
+    pub fn client_ca_pem(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.client_ca = Some(PemSource::Memory(pem.into()));
+        self
+    }
+
+    /// How often PEM files are checked for changes (default: 30 seconds).
+    ///
+    /// Must be non-zero.
+    pub fn reload_interval(mut self, interval: Duration) -> Self {
+        self.reload_interval = interval;
+        self
+    }
+
+    /// Check the settings that don't depend on the PEM contents.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.reload_interval.is_zero() {
+            return Err("TLS reload interval must be non-zero".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether clients must present a certificate.
+    pub fn requires_client_cert(&self) -> bool {
+        self.client_ca.is_some()
+    }
+
+    fn sources(&self) -> impl Iterator<Item = &PemSource> {
+        [&self.cert, &self.key].into_iter().chain(&self.client_ca)
+    }
+
+    /// Whether any source is a file, i.e. there is anything to reload.
+    fn watches_files(&self) -> bool {
+        self.sources().any(PemSource::is_file)
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        self.sources().map(PemSource::modified).collect()
+    }
+
+    /// Load the sources into a rustls server configuration.
+    pub fn server_config(&self) -> Result<rustls::ServerConfig, TlsError> {
+        let certs = CertificateDer::pem_slice_iter(&self.cert.read()?)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| pem_error("certificate", e))?;
+        if certs.is_empty() {
+            return Err(TlsError::Pem {
+                what: "certificate",
+                reason: "no certificates found".to_string(),
+            });
+        }
+        let key = PrivateKeyDer::from_pem_slice(&self.key.read()?)
+            .map_err(|e| pem_error("private key", e))?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()?;
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                builder.with_client_cert_verifier(client_verifier(&client_ca.read()?, provider)?)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_single_cert(certs, key)?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+fn pem_error(what: &'static str, error: rustls::pki_types::pem::Error) -> TlsError {
+    TlsError::Pem {
+        what,
+        reason: error.to_string(),
+    }
+}
+
+/// A verifier requiring client certificates that chain to a CA in `pem`.
+fn client_verifier(
+    pem: &[u8],
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<dyn rustls::server::danger::ClientCertVerifier>, TlsError> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(pem) {
+        roots
+            .add(cert.map_err(|e| pem_error("client CA", e))?)
+            .map_err(|e| TlsError::ClientCa(e.to_string()))?;
+    }
+    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+        .build()
+        .map_err(|e| TlsError::ClientCa(e.to_string()))
+}
+
+/// The server's current TLS configuration, reloaded as its files change.
+pub(crate) struct TlsState {
+    config: TlsConfig,
+    current: RwLock<Arc<rustls::ServerConfig>>,
+    modified: Mutex<Vec<Option<SystemTime>>>,
+}
+
+impl TlsState {
+    /// Load `config`, failing if its certificates can't be used.
+    pub(crate) fn load(config: TlsConfig) -> Result<Self, TlsError> {
+        let modified = config.modified();
+        let current = config.server_config()?;
+        Ok(Self {
+            config,
+            current: RwLock::new(Arc::new(current)),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// The configuration for the next connection.
+    pub(crate) fn current(&self) -> Arc<rustls::ServerConfig> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// How often to call [`reload_if_changed`](Self::reload_if_changed),
+    /// or `None` if nothing is read from files.
+    pub(crate) fn reload_interval(&self) -> Option<Duration> {
+        self.config
+            .watches_files()
+            .then_some(self.config.reload_interval)
+    }
+
+    /// Reload the configuration if a file changed since the last attempt,
+    /// returning whether it was replaced.
+    ///
+    /// A failed reload keeps the current configuration and isn't retried
+    /// until the files change again.
+    pub(crate) fn reload_if_changed(&self) -> Result<bool, TlsError> {
+        let modified = self.config.modified();
+        {
+            let mut last = self.modified.lock().unwrap_or_else(|e| e.into_inner());
+            if *last == modified {
+                return Ok(false);
+            }
+            *last = modified;
+        }
+        let reloaded = self.config.server_config()?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(reloaded);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+
+    /// A CA and a certificate it issued for `localhost`, as PEM.
+    pub(crate) struct TestPki {
+        pub(crate) ca_cert: String,
+        pub(crate) cert: String,
+        pub(crate) key: String,
+        issuer: Issuer<'static, KeyPair>,
+    }
+
+    impl TestPki {
+        pub(crate) fn new(ca_name: &str) -> Self {
+            let ca_key = KeyPair::generate().unwrap();
+            let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            ca_params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, ca_name);
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+            let issuer = Issuer::new(ca_params, ca_key);
+            let (cert, key) = Self::issue(&issuer, "localhost");
+            Self {
+                ca_cert: ca_cert.pem(),
+                cert,
+                key,
+                issuer,
+            }
+        }
+
+        /// A certificate and key for `name`, signed by this CA.
+        pub(crate) fn leaf(&self, name: &str) -> (String, String) {
+            Self::issue(&self.issuer, name)
+        }
+
+        fn issue(issuer: &Issuer<'static, KeyPair>, name: &str) -> (String, String) {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![name.to_string()])
+                .unwrap()
+                .signed_by(&key, issuer)
+                .unwrap();
+            (cert.pem(), key.serialize_pem())
+        }
+    }
+
+    #[test]
+    fn pem_certificates_and_keys_are_loaded() {
+        let pki = TestPki::new("test CA");
+        let config = TlsConfig::from_pem(pki.cert.clone(), pki.key.clone());
+        assert!(!config.requires_client_cert());
+        assert_eq!(
+            config.server_config().unwrap().alpn_protocols,
+            [b"http/1.1".to_vec()]
+        );
+
+        let mtls = config.client_ca_pem(pki.ca_cert.clone());
+        assert!(mtls.requires_client_cert());
+        mtls.server_config().unwrap();
+
+        assert!(matches!(
+            TlsConfig::from_pem("", pki.key.clone()).server_config(),
+            Err(TlsError::Pem {
+                what: "certificate",
+                ..
+            })
+        ));
+        assert!(matches!(
+            TlsConfig::from_pem(pki.cert.clone(), pki.cert.clone()).server_config(),
+            Err(TlsError::Pem {
+                what: "private key",
+                ..
+            })
+        ));
+        assert!(matches!(
+            TlsConfig::from_pem_files("/nonexistent/tls.crt", "/nonexistent/tls.key")
+                .server_config(),
+            Err(TlsError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn in_memory_keys_are_not_printed() {
+        let config = TlsConfig::from_pem("cert", "secret key material");
+        assert!(!format!("{:?}", config).contains("secret"));
+    }
+
+    #[test]
+    fn changed_files_are_reloaded() {
+        let dir = std::env::temp_dir().join(format!("observe-rs-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert_path, key_path) = (dir.join("tls.crt"), dir.join("tls.key"));
+        let pki = TestPki::new("test CA");
+        std::fs::write(&cert_path, &pki.cert).unwrap();
+        std::fs::write(&key_path, &pki.key).unwrap();
+
+        let state = TlsState::load(TlsConfig::from_pem_files(&cert_path, &key_path)).unwrap();
+        assert_eq!(state.reload_interval(), Some(DEFAULT_RELOAD_INTERVAL));
+        assert!(!state.reload_if_changed().unwrap());
+        let before = state.current();
+
+        // A broken write keeps the old certificate in use.
+        std::fs::write(&key_path, "not a key").unwrap();
+        set_modified(&key_path, 1);
+        assert!(state.reload_if_changed().is_err());
+        assert!(Arc::ptr_eq(&before, &state.current()));
+
+        let (cert, key) = pki.leaf("localhost");
+        std::fs::write(&cert_path, cert).unwrap();
+        std::fs::write(&key_path, key).unwrap();
+        set_modified(&key_path, 2);
+        assert!(state.reload_if_changed().unwrap());
+        assert!(!Arc::ptr_eq(&before, &state.current()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        let in_memory = TlsState::load(TlsConfig::from_pem(pki.cert, pki.key)).unwrap();
+        assert_eq!(in_memory.reload_interval(), None);
+    }
+
+    /// Give `path` a distinct modification time, as filesystem timestamps
+    /// may be too coarse to tell quick successive writes apart.
+    fn set_modified(path: &Path, seconds: u64) {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+}
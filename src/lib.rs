@@ -60,7 +60,22 @@
 //! | `prometheus` | Prometheus metrics backend | ✓ |
 //! | `otlp` | OpenTelemetry/OTLP backend | |
 //! | `standalone` | Standalone HTTP server | ✓ |
+//! | `json` | JSON renderer and `/metrics/json` endpoint | |
+//! | `protobuf` | Prometheus protobuf exposition, negotiated on `/metrics` | |
+//! | `compression` | gzip/zstd compression of metrics responses (implies `standalone`) | |
+//! | `filter` | Metric name filters (`?name[]=`, `?prefix=`, `?regex=`) for partial scrapes | |
+//! | `relabel` | Render-time relabel rules (rename, drop/keep, hash/redact, labelmap/labeldrop) | |
+//! | `tls` | HTTPS and mutual TLS with hot-reloaded certificates via rustls (implies `standalone`) | |
+//! | `auth` | Basic auth, bearer tokens and CIDR allowlists per endpoint (implies `standalone`) | |
 //! | `axum-integration` | Axum middleware integration | |
+//! | `export` | `Exporter` trait and scheduler for push backends | |
+//! | `remote-write` | Prometheus remote-write v1 exporter (implies `export`) | |
+//! | `pushgateway` | Pushgateway client for batch jobs (implies `export`) | |
+//! | `textfile` | node_exporter textfile collector sink (implies `export`) | |
+//! | `influx` | InfluxDB line protocol renderer and exporter (implies `export`) | |
+//! | `graphite` | Graphite plaintext/pickle exporter over TCP (implies `export`) | |
+//! | `tracing-integration` | `tracing` layer for span/event metrics, span exemplars | |
+//! | `metrics-integration` | `metrics` crate recorder (implies `prometheus`) | |
 //! | `mock` | Mock backend for testing | |
 //! | `json-config` | JSON configuration support | |
 //! | `yaml-config` | YAML configuration support | |
@@ -74,9 +89,25 @@ pub mod backends;
 #[cfg(feature = "standalone")]
 pub mod http;
 
+#[cfg(feature = "export")]
+pub mod export;
+
+#[cfg(feature = "tracing-integration")]
+pub mod tracing_integration;
+
+#[cfg(feature = "metrics-integration")]
+pub mod metrics_integration;
+
 // Prelude for convenient imports
 pub mod prelude {
-    pub use crate::core::metrics::{CounterTrait, GaugeTrait, HistogramTrait, Metric};
+    pub use crate::core::build_info::BuildInfo;
+    pub use crate::core::cardinality::{CardinalityLimits, OverflowPolicy};
+    pub use crate::core::labels::LabelValuePolicy;
+    pub use crate::core::metrics::{
+        CounterTrait, ExemplarCounterTrait, ExemplarHistogramTrait, GaugeTrait, HistogramTrait,
+        Metric,
+    };
+    pub use crate::core::unit::{Bytes, Ratio, Seconds, Unit, Unitless};
 
     #[cfg(feature = "prometheus")]
     pub use crate::backends::prometheus::prometheus_backend::{
@@ -93,14 +124,26 @@ pub mod prelude {
         labeled_histogram,
         labeled_histogram_for_bytes,
         labeled_histogram_for_latency,
+        managed_counter,
+        managed_gauge,
+        // Types
+        DynamicMetrics,
         EncodeLabelSet,
         Family,
+        LabelSet,
         LabeledCounter,
+        LabeledFamily,
         LabeledGauge,
         LabeledHistogram,
-        // Types
+        ManagedCounter,
+        ManagedExemplarCounter,
+        ManagedExemplarHistogram,
+        ManagedGauge,
+        ManagedHistogram,
         PrometheusBackend,
         PrometheusCounter,
+        PrometheusExemplarCounter,
+        PrometheusExemplarHistogram,
         PrometheusGauge,
         PrometheusHistogram,
         PrometheusRegistry,
@@ -110,6 +153,18 @@ pub mod prelude {
         DEFAULT_SIZE_BUCKETS,
     };
 
+    #[cfg(feature = "export")]
+    pub use crate::export::{ExportConfig, ExportError, ExportScheduler, Exporter};
+
+    #[cfg(feature = "remote-write")]
+    pub use crate::export::RemoteWriteExporter;
+
+    #[cfg(all(feature = "tracing-integration", feature = "prometheus"))]
+    pub use crate::tracing_integration::MetricsLayer;
+
+    #[cfg(feature = "metrics-integration")]
+    pub use crate::metrics_integration::{MetricsRecorder, NameMatcher};
+
     #[cfg(feature = "mock")]
     pub use crate::backends::mock::{
         test_counter, test_gauge, test_histogram, MockCounter, MockGauge, MockHistogram,
@@ -122,12 +177,16 @@ pub mod prelude {
     // Deserialization support (feature-gated)
     #[cfg(any(feature = "json-config", feature = "yaml-config"))]
     pub use crate::core::deserialise::{
-        ConfiguredRegistry, DeserializeError, MetricConfig, RegistryConfig,
+        ConfiguredRegistry, DeserializeError, MetricConfig, RegistryConfig, RegistryDocument,
     };
 
     #[cfg(feature = "json-config")]
-    pub use crate::core::deserialise::{load_json_file, load_json_str};
+    pub use crate::core::deserialise::{
+        load_json_document_file, load_json_document_str, load_json_file, load_json_str,
+    };
 
     #[cfg(feature = "yaml-config")]
-    pub use crate::core::deserialise::{load_yaml_file, load_yaml_str};
+    pub use crate::core::deserialise::{
+        load_yaml_document_file, load_yaml_document_str, load_yaml_file, load_yaml_str,
+    };
 }
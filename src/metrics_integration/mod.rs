@@ -0,0 +1,11 @@
+//! Integration with the `metrics` crate.
+//!
+//! Enabled by the `metrics-integration` feature. [`MetricsRecorder`] is a
+//! `metrics::Recorder` that records into an
+//! [`ObservabilityRegistry`](crate::core::registry::ObservabilityRegistry),
+//! so metrics emitted by third-party libraries through the `counter!`,
+//! `gauge!` and `histogram!` macros are exposed next to your own.
+
+pub mod recorder;
+
+pub use recorder::{MetricsRecorder, MetricsRecorderBuilder, NameMatcher};
@@ -0,0 +1,420 @@
+//! `metrics::Recorder` backed by a Prometheus registry.
+//!
+//! Key names and label keys are mapped onto Prometheus names by replacing
+//! invalid characters with `_` (`http.requests` becomes `http_requests`).
+//! Counter names lose a trailing `_total`, which the exposition adds back.
+//! Units passed to `describe_*` are not exposed, since the Prometheus
+//! backend requires the unit suffix to be part of the name.
+
+use crate::backends::prometheus::dynamic::{DynamicLabels, DynamicMetrics};
+use crate::backends::prometheus::prometheus_backend::{
+    validate_histogram_buckets, ExemplarLabels, PrometheusBackend, PrometheusError,
+};
+use crate::core::metrics::HistogramTrait;
+use crate::core::registry::{ObservabilityRegistry, DEFAULT_LATENCY_BUCKETS};
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SetRecorderError, SharedString, Unit,
+};
+use prometheus_client::metrics::counter::Counter as PrometheusCounter;
+use prometheus_client::metrics::exemplar::HistogramWithExemplars;
+use prometheus_client::metrics::gauge::Gauge as PrometheusGauge;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Selects metrics by key name, for per-name histogram buckets.
+///
+/// Matches the key name as the library emits it (e.g. `http.request.duration`),
+/// before it is mapped onto a Prometheus name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameMatcher {
+    /// The whole name.
+    Exact(String),
+    /// Names starting with the given prefix.
+    Prefix(String),
+    /// Names ending with the given suffix.
+    Suffix(String),
+}
+
+impl NameMatcher {
+    /// Whether `name` is selected.
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Exact(exact) => name == exact,
+            Self::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            Self::Suffix(suffix) => name.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+/// Builder for [`MetricsRecorder`].
+#[derive(Debug, Clone)]
+pub struct MetricsRecorderBuilder {
+    buckets: Vec<f64>,
+    bucket_overrides: Vec<(NameMatcher, Vec<f64>)>,
+}
+
+impl Default for MetricsRecorderBuilder {
+    fn default() -> Self {
+        Self {
+            buckets: DEFAULT_LATENCY_BUCKETS.to_vec(),
+            bucket_overrides: Vec::new(),
+        }
+    }
+}
+
+impl MetricsRecorderBuilder {
+    /// Create a builder with default latency buckets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the buckets of histograms no matcher selects (default
+    /// [`DEFAULT_LATENCY_BUCKETS`]).
+    pub fn buckets(mut self, buckets: Vec<f64>) -> Self {
+        self.buckets = buckets;
+        self
+    }
+
+    /// Use `buckets` for histograms selected by `matcher`.
+    ///
+    /// Matchers are tried in the order they were added; the first match wins.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let recorder = MetricsRecorder::builder()
+    ///     .buckets_for(NameMatcher::Suffix("_bytes".into()), DEFAULT_SIZE_BUCKETS.to_vec())
+    ///     .buckets_for(NameMatcher::Prefix("db.".into()), vec![0.001, 0.01, 0.1, 1.0])
+    ///     .build(&mut registry)?;
+    /// ```
+    pub fn buckets_for(mut self, matcher: NameMatcher, buckets: Vec<f64>) -> Self {
+        self.bucket_overrides.push((matcher, buckets));
+        self
+    }
+
+    /// Build the recorder, exporting its metrics through `registry`.
+    ///
+    /// Fails if a bucket set is invalid.
+    pub fn build(
+        self,
+        registry: &mut ObservabilityRegistry<PrometheusBackend>,
+    ) -> Result<MetricsRecorder, PrometheusError> {
+        validate_histogram_buckets(&self.buckets)?;
+        for (_, buckets) in &self.bucket_overrides {
+            validate_histogram_buckets(buckets)?;
+        }
+        Ok(MetricsRecorder {
+            metrics: registry.dynamic_metrics(),
+            buckets: self.buckets,
+            bucket_overrides: self.bucket_overrides,
+            descriptions: RwLock::new(HashMap::new()),
+        })
+    }
+}
+
+/// A `metrics::Recorder` recording into an [`ObservabilityRegistry`].
+///
+/// Metrics whose name or labels are rejected (label value policy,
+/// cardinality limits, a name already used by another metric type) are
+/// recorded into no-op handles, as a recorder cannot report errors.
+///
+/// # Example
+/// ```ignore
+/// let server = StandaloneServer::<PrometheusBackend>::builder().build();
+/// MetricsRecorder::builder()
+///     .build(&mut *server.registry().write().await)?
+///     .install()?;
+///
+/// // Anywhere, including in dependencies:
+/// metrics::counter!("cache.hits", "cache" => "users").increment(1);
+/// // cache_hits_total{cache="users"} 1
+/// ```
+#[derive(Debug)]
+pub struct MetricsRecorder {
+    metrics: DynamicMetrics,
+    buckets: Vec<f64>,
+    bucket_overrides: Vec<(NameMatcher, Vec<f64>)>,
+    descriptions: RwLock<HashMap<String, String>>,
+}
+
+impl MetricsRecorder {
+    /// Create a builder.
+    pub fn builder() -> MetricsRecorderBuilder {
+        MetricsRecorderBuilder::new()
+    }
+
+    /// The families created by the recorder so far.
+    pub fn metrics(&self) -> &DynamicMetrics {
+        &self.metrics
+    }
+
+    /// Install as the global `metrics` recorder.
+    ///
+    /// Fails if a global recorder was already installed.
+    pub fn install(self) -> Result<(), SetRecorderError<Self>> {
+        metrics::set_global_recorder(self)
+    }
+
+    fn describe(&self, name: String, description: SharedString) {
+        self.metrics.describe(&name, &description);
+        self.descriptions
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(name, description.into_owned());
+    }
+
+    fn help(&self, name: &str, key: &Key) -> String {
+        self.descriptions
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| key.name().to_string())
+    }
+
+    fn buckets_for(&self, key_name: &str) -> Vec<f64> {
+        self.bucket_overrides
+            .iter()
+            .find(|(matcher, _)| matcher.matches(key_name))
+            .map(|(_, buckets)| buckets)
+            .unwrap_or(&self.buckets)
+            .clone()
+    }
+}
+
+impl Recorder for MetricsRecorder {
+    fn describe_counter(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        self.describe(counter_name(key.as_str()), description);
+    }
+
+    fn describe_gauge(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        self.describe(metric_name(key.as_str()), description);
+    }
+
+    fn describe_histogram(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        self.describe(metric_name(key.as_str()), description);
+    }
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        let name = counter_name(key.name());
+        self.metrics
+            .counter(&name, &self.help(&name, key))
+            .and_then(|family| family.try_get_or_create(&labels(key)))
+            .map(|counter| Counter::from_arc(Arc::new(CounterHandle(counter))))
+            .unwrap_or_else(|_| Counter::noop())
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        let name = metric_name(key.name());
+        self.metrics
+            .gauge(&name, &self.help(&name, key))
+            .and_then(|family| family.try_get_or_create(&labels(key)))
+            .map(|gauge| Gauge::from_arc(Arc::new(GaugeHandle(gauge))))
+            .unwrap_or_else(|_| Gauge::noop())
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let name = metric_name(key.name());
+        self.metrics
+            .histogram(&name, &self.help(&name, key), || {
+                self.buckets_for(key.name())
+            })
+            .and_then(|family| family.try_get_or_create(&labels(key)))
+            .map(|histogram| Histogram::from_arc(Arc::new(HistogramHandle(histogram))))
+            .unwrap_or_else(|_| Histogram::noop())
+    }
+}
+
+struct CounterHandle(PrometheusCounter<u64>);
+
+impl CounterFn for CounterHandle {
+    fn increment(&self, value: u64) {
+        self.0.inc_by(value);
+    }
+
+    fn absolute(&self, value: u64) {
+        // Counters never go down; a lower absolute value is ignored.
+        self.0.inner().fetch_max(value, Ordering::Relaxed);
+    }
+}
+
+struct GaugeHandle(PrometheusGauge<f64, AtomicU64>);
+
+impl GaugeFn for GaugeHandle {
+    fn increment(&self, value: f64) {
+        self.0.inc_by(value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.0.dec_by(value);
+    }
+
+    fn set(&self, value: f64) {
+        self.0.set(value);
+    }
+}
+
+struct HistogramHandle(HistogramWithExemplars<ExemplarLabels>);
+
+impl HistogramFn for HistogramHandle {
+    fn record(&self, value: f64) {
+        HistogramTrait::observe(&self.0, value);
+    }
+}
+
+/// Map a key name onto `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+fn metric_name(name: &str) -> String {
+    sanitize(name, true)
+}
+
+/// Like [`metric_name`], without a trailing `_total` (the encoder adds it).
+fn counter_name(name: &str) -> String {
+    let name = metric_name(name);
+    match name.strip_suffix("_total") {
+        Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+        _ => name,
+    }
+}
+
+fn labels(key: &Key) -> DynamicLabels {
+    key.labels()
+        .map(|label| (sanitize(label.key(), false), label.value().to_string()))
+        .collect()
+}
+
+fn sanitize(name: &str, allow_colon: bool) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || (allow_colon && c == ':') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.is_empty() || sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::prometheus::prometheus_backend::PrometheusRegistry;
+
+    fn render(registry: &PrometheusRegistry) -> String {
+        registry.render().unwrap().as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn names_and_label_keys_are_sanitized() {
+        assert_eq!(metric_name("http.server.duration"), "http_server_duration");
+        assert_eq!(metric_name("2xx-responses"), "_2xx_responses");
+        assert_eq!(metric_name("ns:requests"), "ns:requests");
+        assert_eq!(counter_name("requests_total"), "requests");
+        assert_eq!(counter_name("_total"), "_total");
+        assert_eq!(sanitize("http.method", false), "http_method");
+    }
+
+    #[test]
+    fn macros_record_into_the_registry() {
+        let mut registry = PrometheusRegistry::new();
+        let recorder = MetricsRecorder::builder()
+            .buckets(vec![0.1, 1.0])
+            .build(&mut registry)
+            .unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            metrics::describe_counter!("cache.hits", "Cache hits");
+            metrics::counter!("cache.hits", "cache" => "users").increment(2);
+            metrics::counter!("cache.hits", "cache" => "users").increment(1);
+            metrics::gauge!("pool.size").set(4.0);
+            metrics::gauge!("pool.size").decrement(1.5);
+            metrics::histogram!("db.query.seconds", "db.table" => "orders").record(0.05);
+        });
+
+        let text = render(&registry);
+        assert!(text.contains("# HELP cache_hits Cache hits.\n"), "{}", text);
+        assert!(text.contains(r#"cache_hits_total{cache="users"} 3"#));
+        assert!(text.contains("pool_size{} 2.5\n"));
+        assert!(text.contains(r#"db_query_seconds_bucket{le="0.1",db_table="orders"} 1"#));
+    }
+
+    #[test]
+    fn absolute_counters_never_decrease() {
+        let mut registry = PrometheusRegistry::new();
+        let recorder = MetricsRecorder::builder().build(&mut registry).unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            metrics::counter!("bytes_read_total").absolute(10);
+            metrics::counter!("bytes_read_total").absolute(7);
+        });
+
+        assert!(render(&registry).contains("bytes_read_total{} 10\n"));
+    }
+
+    #[test]
+    fn bucket_matchers_select_by_key_name() {
+        let mut registry = PrometheusRegistry::new();
+        let recorder = MetricsRecorder::builder()
+            .buckets_for(NameMatcher::Suffix(".bytes".into()), vec![1024.0])
+            .buckets_for(NameMatcher::Prefix("db.".into()), vec![0.5])
+            .build(&mut registry)
+            .unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            metrics::histogram!("db.row.bytes").record(10.0);
+            metrics::histogram!("db.query").record(0.1);
+            metrics::histogram!("other").record(0.1);
+        });
+
+        let text = render(&registry);
+        assert!(text.contains(r#"db_row_bytes_bucket{le="1024.0"} 1"#));
+        assert!(text.contains(r#"db_query_bucket{le="0.5"} 1"#));
+        assert!(text.contains(r#"other_bucket{le="0.005"} 0"#));
+    }
+
+    #[test]
+    fn conflicting_types_get_noop_handles() {
+        let mut registry = PrometheusRegistry::new();
+        let recorder = MetricsRecorder::builder().build(&mut registry).unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            metrics::counter!("jobs").increment(1);
+            metrics::gauge!("jobs").set(5.0);
+        });
+
+        let text = render(&registry);
+        assert!(text.contains("jobs_total{} 1\n"));
+        assert!(!text.contains("jobs{} 5"));
+    }
+
+    #[cfg(feature = "standalone")]
+    #[tokio::test]
+    async fn library_metrics_reach_the_standalone_server_registry() {
+        use crate::http::standalone::StandaloneServer;
+
+        let server = StandaloneServer::<PrometheusBackend>::builder().build();
+        let recorder = MetricsRecorder::builder()
+            .build(&mut *server.registry().write().await)
+            .unwrap();
+        metrics::with_local_recorder(&recorder, || {
+            metrics::counter!("lib.calls", "op" => "get").increment(1);
+        });
+
+        let registry = server.registry();
+        let registry = registry.read().await;
+        assert!(render(&registry).contains(r#"lib_calls_total{op="get"} 1"#));
+    }
+
+    #[test]
+    fn matchers() {
+        assert!(NameMatcher::Exact("a.b".into()).matches("a.b"));
+        assert!(!NameMatcher::Exact("a.b".into()).matches("a.bc"));
+        assert!(NameMatcher::Prefix("a.".into()).matches("a.bc"));
+        assert!(NameMatcher::Suffix(".bytes".into()).matches("io.bytes"));
+    }
+}
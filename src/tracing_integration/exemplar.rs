@@ -0,0 +1,145 @@
+//! Exemplar labels from the current `tracing` span.
+
+use tracing::Span;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+use tracing_subscriber::Registry;
+
+/// Span field, and exemplar label, carrying a span id of the trace.
+///
+/// Like [`TRACE_ID_LABEL`], this is the id of the span in the distributed
+/// trace (e.g. the parent id of an incoming `traceparent` header), not the
+/// subscriber's internal span id, which is reused and meaningless outside
+/// the process.
+pub const SPAN_ID_LABEL: &str = "span_id";
+
+/// Span field, and exemplar label, carrying a trace id.
+///
+/// A span created with a `trace_id` field (e.g. copied from an incoming
+/// `traceparent` header) passes it on to exemplars recorded inside it and
+/// its children, provided [`MetricsLayer`](super::MetricsLayer) is
+/// installed to remember it.
+pub const TRACE_ID_LABEL: &str = "trace_id";
+
+/// Trace context fields recorded on a span, stored in the span's extensions.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TraceContext {
+    pub(crate) trace_id: Option<String>,
+    pub(crate) span_id: Option<String>,
+}
+
+impl TraceContext {
+    /// Overwrite the fields set in `other`.
+    pub(crate) fn merge(&mut self, other: TraceContext) {
+        if other.trace_id.is_some() {
+            self.trace_id = other.trace_id;
+        }
+        if other.span_id.is_some() {
+            self.span_id = other.span_id;
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.trace_id.is_none() && self.span_id.is_none()
+    }
+
+    /// Exemplar labels for the context: `trace_id` then `span_id`, each
+    /// only when recorded.
+    pub(crate) fn exemplar(self) -> Vec<(String, String)> {
+        let mut labels = Vec::with_capacity(2);
+        if let Some(trace_id) = self.trace_id {
+            labels.push((TRACE_ID_LABEL.to_string(), trace_id));
+        }
+        if let Some(span_id) = self.span_id {
+            labels.push((SPAN_ID_LABEL.to_string(), span_id));
+        }
+        labels
+    }
+}
+
+/// Exemplar labels for the current span.
+///
+/// Returns the `trace_id` and `span_id` recorded on the span or its closest
+/// ancestors, each only when one was recorded. Returns no labels when there
+/// is no trace context (no subscriber, outside any span, or no ids
+/// recorded), in which case observations are recorded without an exemplar.
+///
+/// # Example
+/// ```ignore
+/// let span = tracing::info_span!("checkout", trace_id = %trace_id, span_id = %parent_id);
+/// let _guard = span.enter();
+/// latency.observe_with_span_exemplar(0.42); // exemplar {trace_id="...",span_id="..."}
+/// ```
+pub fn current_span_exemplar() -> Vec<(String, String)> {
+    let Some(id) = Span::current().id() else {
+        return Vec::new();
+    };
+    tracing::dispatcher::get_default(|dispatch| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        Some(trace_context_in_scope(registry.span(&id)?))
+    })
+    .map(TraceContext::exemplar)
+    .unwrap_or_default()
+}
+
+/// The trace context of `span`: each id is taken from the closest span in
+/// its scope that recorded one.
+pub(crate) fn trace_context_in_scope<'a, R: LookupSpan<'a>>(span: SpanRef<'a, R>) -> TraceContext {
+    let mut context = TraceContext::default();
+    for span in span.scope() {
+        if let Some(recorded) = span.extensions().get::<TraceContext>() {
+            if context.trace_id.is_none() {
+                context.trace_id = recorded.trace_id.clone();
+            }
+            if context.span_id.is_none() {
+                context.span_id = recorded.span_id.clone();
+            }
+        }
+        if context.trace_id.is_some() && context.span_id.is_some() {
+            break;
+        }
+    }
+    context
+}
+
+/// Borrow owned label pairs in the shape the exemplar traits take.
+pub(crate) fn as_pairs(labels: &[(String, String)]) -> Vec<(&str, &str)> {
+    labels
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_span_means_no_exemplar() {
+        assert!(current_span_exemplar().is_empty());
+    }
+
+    #[test]
+    fn subscriber_span_ids_are_not_exemplars() {
+        let subscriber = tracing_subscriber::registry();
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("request").entered();
+            assert!(current_span_exemplar().is_empty());
+        });
+    }
+
+    #[test]
+    fn recorded_ids_become_labels() {
+        let context = TraceContext {
+            trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".into()),
+            span_id: None,
+        };
+        assert_eq!(
+            context.exemplar(),
+            vec![(
+                TRACE_ID_LABEL.to_string(),
+                "4bf92f3577b34da6a3ce929d0e0e4736".to_string()
+            )]
+        );
+        assert!(TraceContext::default().is_empty());
+    }
+}
@@ -0,0 +1,559 @@
+//! A `tracing_subscriber` layer that turns spans and events into metrics.
+//!
+//! Spans opt in declaratively through attributes:
+//!
+//! | Field | Meaning |
+//! |-------|---------|
+//! | `metric.histogram = "db_query_seconds"` | Record the span's duration, in seconds, into this histogram |
+//! | `metric.labels = "table,op"` | Span fields to use as labels (`.` in a field name becomes `_`) |
+//! | `metric.help = "..."` | Help text, used when the histogram is first created |
+//!
+//! ```ignore
+//! let span = tracing::info_span!(
+//!     "query",
+//!     metric.histogram = "db_query_seconds",
+//!     metric.labels = "table,op",
+//!     table = "users",
+//!     op = "select",
+//! );
+//! ```
+//!
+//! Every event is also counted by level and target
+//! (`tracing_events_total{level="warn",target="my_app::db"}`). Durations are
+//! recorded with a `trace_id`/`span_id` exemplar when the span or one of its
+//! parents recorded those fields.
+
+use super::exemplar::{trace_context_in_scope, TraceContext, SPAN_ID_LABEL, TRACE_ID_LABEL};
+use crate::backends::prometheus::dynamic::{DynamicCounter, DynamicLabels, DynamicMetrics};
+use crate::backends::prometheus::prometheus_backend::{
+    validate_histogram_buckets, PrometheusBackend, PrometheusError,
+};
+use crate::core::clock::{Clock, SystemClock};
+use crate::core::metrics::ExemplarHistogramTrait;
+use crate::core::registry::{ObservabilityRegistry, DEFAULT_LATENCY_BUCKETS};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Span field naming the histogram that records the span's duration.
+pub const HISTOGRAM_FIELD: &str = "metric.histogram";
+
+/// Span field listing, comma separated, the span fields used as labels.
+pub const LABELS_FIELD: &str = "metric.labels";
+
+/// Span field holding the histogram's help text.
+pub const HELP_FIELD: &str = "metric.help";
+
+/// Default name of the event counter.
+pub const DEFAULT_EVENTS_METRIC: &str = "tracing_events";
+
+/// Builder for [`MetricsLayer`].
+#[derive(Debug, Clone)]
+pub struct MetricsLayerBuilder {
+    buckets: Vec<f64>,
+    histogram_buckets: HashMap<String, Vec<f64>>,
+    events_metric: Option<String>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for MetricsLayerBuilder {
+    fn default() -> Self {
+        Self {
+            buckets: DEFAULT_LATENCY_BUCKETS.to_vec(),
+            histogram_buckets: HashMap::new(),
+            events_metric: Some(DEFAULT_EVENTS_METRIC.to_string()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl MetricsLayerBuilder {
+    /// Create a builder with default latency buckets and event counting on.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the buckets of span duration histograms (default
+    /// [`DEFAULT_LATENCY_BUCKETS`]).
+    pub fn buckets(mut self, buckets: Vec<f64>) -> Self {
+        self.buckets = buckets;
+        self
+    }
+
+    /// Set the buckets of one histogram, overriding [`buckets`](Self::buckets).
+    pub fn histogram_buckets(mut self, name: impl Into<String>, buckets: Vec<f64>) -> Self {
+        self.histogram_buckets.insert(name.into(), buckets);
+        self
+    }
+
+    /// Set the name of the event counter (default `tracing_events`).
+    pub fn events_metric(mut self, name: impl Into<String>) -> Self {
+        self.events_metric = Some(name.into());
+        self
+    }
+
+    /// Don't count events.
+    pub fn without_events(mut self) -> Self {
+        self.events_metric = None;
+        self
+    }
+
+    /// Use `clock` to time spans (for tests; defaults to [`SystemClock`]).
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Build the layer, exporting its metrics through `registry`.
+    ///
+    /// Fails if a bucket set or the event counter name is invalid.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use tracing_subscriber::prelude::*;
+    ///
+    /// let server = StandaloneServer::<PrometheusBackend>::builder().build();
+    /// let layer = MetricsLayer::builder().build(&mut *server.registry().write().await)?;
+    /// tracing_subscriber::registry().with(layer).init();
+    /// ```
+    pub fn build(
+        self,
+        registry: &mut ObservabilityRegistry<PrometheusBackend>,
+    ) -> Result<MetricsLayer, PrometheusError> {
+        validate_histogram_buckets(&self.buckets)?;
+        for buckets in self.histogram_buckets.values() {
+            validate_histogram_buckets(buckets)?;
+        }
+
+        let metrics = registry.dynamic_metrics();
+        let events = self
+            .events_metric
+            .map(|name| metrics.counter(&name, "Tracing events by level and target"))
+            .transpose()?;
+
+        Ok(MetricsLayer {
+            metrics,
+            events,
+            buckets: self.buckets,
+            histogram_buckets: self.histogram_buckets,
+            clock: self.clock,
+        })
+    }
+}
+
+/// Layer recording span durations and event counts into a registry.
+///
+/// See the [module documentation](self) for the span attributes it reads.
+/// Samples whose labels are rejected (invalid names, label value policy,
+/// cardinality limits) are dropped rather than reported, since a layer has
+/// nowhere to return an error to.
+#[derive(Debug, Clone)]
+pub struct MetricsLayer {
+    metrics: DynamicMetrics,
+    events: Option<DynamicCounter>,
+    buckets: Vec<f64>,
+    histogram_buckets: HashMap<String, Vec<f64>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MetricsLayer {
+    /// Create a builder.
+    pub fn builder() -> MetricsLayerBuilder {
+        MetricsLayerBuilder::new()
+    }
+
+    /// The families created by the layer so far.
+    pub fn metrics(&self) -> &DynamicMetrics {
+        &self.metrics
+    }
+
+    fn record_duration(&self, timing: &SpanTiming, context: TraceContext) {
+        let help = timing
+            .help
+            .clone()
+            .unwrap_or_else(|| format!("Duration of {} spans in seconds", timing.span_name));
+        let Ok(histogram) = self.metrics.histogram(&timing.histogram, &help, || {
+            self.histogram_buckets
+                .get(&timing.histogram)
+                .unwrap_or(&self.buckets)
+                .clone()
+        }) else {
+            return;
+        };
+        let Ok(series) = histogram.try_get_or_create(&timing.labels()) else {
+            return;
+        };
+
+        let elapsed = self.clock.now().saturating_duration_since(timing.start);
+        // Without a trace context the labels are empty and no exemplar is
+        // recorded.
+        let exemplar = context.exemplar();
+        series.observe_with_exemplar(elapsed.as_secs_f64(), &super::as_pairs(&exemplar));
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = FieldRecorder::default();
+        attrs.record(&mut fields);
+
+        let mut extensions = span.extensions_mut();
+        let context = std::mem::take(&mut fields.context);
+        if !context.is_empty() {
+            extensions.insert(context);
+        }
+        if let Some(histogram) = fields.histogram.take() {
+            extensions.insert(SpanTiming {
+                span_name: span.name(),
+                histogram,
+                help: fields.help.take(),
+                label_fields: fields.label_fields.take().unwrap_or_default(),
+                values: fields.values,
+                start: self.clock.now(),
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = FieldRecorder::default();
+        values.record(&mut fields);
+
+        let mut extensions = span.extensions_mut();
+        let context = std::mem::take(&mut fields.context);
+        if !context.is_empty() {
+            match extensions.get_mut::<TraceContext>() {
+                Some(recorded) => recorded.merge(context),
+                None => extensions.insert(context),
+            }
+        }
+        if let Some(timing) = extensions.get_mut::<SpanTiming>() {
+            for (name, value) in fields.values {
+                upsert(&mut timing.values, name, value);
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        let metadata = event.metadata();
+        let labels = vec![
+            (
+                "level".to_string(),
+                level_label(metadata.level()).to_string(),
+            ),
+            ("target".to_string(), metadata.target().to_string()),
+        ];
+        if let Ok(counter) = events.try_get_or_create(&labels) {
+            counter.inc();
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(timing) = span.extensions_mut().remove::<SpanTiming>() else {
+            return;
+        };
+        let context = trace_context_in_scope(span);
+        self.record_duration(&timing, context);
+    }
+}
+
+fn level_label(level: &Level) -> &'static str {
+    match *level {
+        Level::TRACE => "trace",
+        Level::DEBUG => "debug",
+        Level::INFO => "info",
+        Level::WARN => "warn",
+        Level::ERROR => "error",
+    }
+}
+
+/// Timing state of a span with a `metric.histogram` attribute.
+#[derive(Debug)]
+struct SpanTiming {
+    span_name: &'static str,
+    histogram: String,
+    help: Option<String>,
+    label_fields: Vec<String>,
+    values: Vec<(String, String)>,
+    start: Instant,
+}
+
+impl SpanTiming {
+    /// Labels in `metric.labels` order; fields never recorded are empty.
+    fn labels(&self) -> DynamicLabels {
+        self.label_fields
+            .iter()
+            .map(|field| {
+                let value = self
+                    .values
+                    .iter()
+                    .find(|(name, _)| name == field)
+                    .map(|(_, value)| value.clone())
+                    .unwrap_or_default();
+                (field.replace('.', "_"), value)
+            })
+            .collect()
+    }
+}
+
+/// Visitor splitting span fields into `metric.*` attributes, the trace
+/// context and plain values.
+#[derive(Debug, Default)]
+struct FieldRecorder {
+    histogram: Option<String>,
+    help: Option<String>,
+    label_fields: Option<Vec<String>>,
+    context: TraceContext,
+    values: Vec<(String, String)>,
+}
+
+impl FieldRecorder {
+    fn record(&mut self, name: &str, value: String) {
+        match name {
+            HISTOGRAM_FIELD => self.histogram = Some(value),
+            HELP_FIELD => self.help = Some(value),
+            LABELS_FIELD => {
+                self.label_fields = Some(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|field| !field.is_empty())
+                        .map(String::from)
+                        .collect(),
+                )
+            }
+            _ => {
+                match name {
+                    TRACE_ID_LABEL => self.context.trace_id = Some(value.clone()),
+                    SPAN_ID_LABEL => self.context.span_id = Some(value.clone()),
+                    _ => {}
+                }
+                upsert(&mut self.values, name.to_string(), value);
+            }
+        }
+    }
+}
+
+impl Visit for FieldRecorder {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field.name(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record(field.name(), format!("{:?}", value));
+    }
+}
+
+fn upsert(values: &mut Vec<(String, String)>, name: String, value: String) {
+    match values.iter_mut().find(|(existing, _)| *existing == name) {
+        Some((_, existing)) => *existing = value,
+        None => values.push((name, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::prometheus::prometheus_backend::PrometheusRegistry;
+    use crate::core::clock::ManualClock;
+    use std::time::Duration;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn layer(registry: &mut PrometheusRegistry, clock: &ManualClock) -> MetricsLayer {
+        MetricsLayer::builder()
+            .buckets(vec![0.1, 1.0])
+            .with_clock(Arc::new(clock.clone()))
+            .build(registry)
+            .unwrap()
+    }
+
+    fn render(registry: &PrometheusRegistry) -> String {
+        registry.render().unwrap().as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn span_duration_is_recorded_with_field_labels() {
+        let mut registry = PrometheusRegistry::new();
+        let clock = ManualClock::new();
+        let subscriber = tracing_subscriber::registry().with(layer(&mut registry, &clock));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "query",
+                metric.histogram = "db_query_seconds",
+                metric.labels = "table,db.op",
+                table = "users",
+                db.op = "select",
+                rows = 3,
+            );
+            clock.advance(Duration::from_millis(250));
+            drop(span);
+        });
+
+        let text = render(&registry);
+        assert!(text.contains("# HELP db_query_seconds Duration of query spans in seconds.\n"));
+        assert!(
+            text.contains(r#"db_query_seconds_sum{table="users",db_op="select"} 0.25"#),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains(r#"db_query_seconds_bucket{le="0.1",table="users",db_op="select"} 0"#)
+        );
+        // No trace context was recorded, so there is no exemplar.
+        assert!(text
+            .contains("db_query_seconds_bucket{le=\"1.0\",table=\"users\",db_op=\"select\"} 1\n"));
+    }
+
+    #[test]
+    fn fields_recorded_later_become_labels() {
+        let mut registry = PrometheusRegistry::new();
+        let clock = ManualClock::new();
+        let subscriber = tracing_subscriber::registry().with(layer(&mut registry, &clock));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "handler",
+                metric.histogram = "handler_seconds",
+                metric.help = "Handler latency",
+                metric.labels = "status",
+                status = tracing::field::Empty,
+            );
+            span.record("status", 404);
+        });
+
+        let text = render(&registry);
+        assert!(text.contains("# HELP handler_seconds Handler latency.\n"));
+        assert!(
+            text.contains(r#"handler_seconds_count{status="404"} 1"#),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn spans_without_attributes_are_ignored() {
+        let mut registry = PrometheusRegistry::new();
+        let clock = ManualClock::new();
+        let layer = layer(&mut registry, &clock);
+        let metrics = layer.metrics().clone();
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("plain", table = "users").entered();
+        });
+
+        assert_eq!(metrics.names(), vec![DEFAULT_EVENTS_METRIC.to_string()]);
+    }
+
+    #[test]
+    fn events_are_counted_by_level_and_target() {
+        let mut registry = PrometheusRegistry::new();
+        let clock = ManualClock::new();
+        let subscriber = tracing_subscriber::registry().with(layer(&mut registry, &clock));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "app::db", "connected");
+            tracing::warn!(target: "app::db", "slow");
+            tracing::warn!(target: "app::db", "slow again");
+        });
+
+        let text = render(&registry);
+        assert!(text.contains(r#"tracing_events_total{level="info",target="app::db"} 1"#));
+        assert!(text.contains(r#"tracing_events_total{level="warn",target="app::db"} 2"#));
+    }
+
+    #[test]
+    fn event_counting_can_be_disabled() {
+        let mut registry = PrometheusRegistry::new();
+        let layer = MetricsLayer::builder()
+            .without_events()
+            .build(&mut registry)
+            .unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || tracing::info!("ignored"));
+
+        assert!(!render(&registry).contains("tracing_events"));
+    }
+
+    #[test]
+    fn trace_context_is_inherited_by_exemplars() {
+        let mut registry = PrometheusRegistry::new();
+        let clock = ManualClock::new();
+        let subscriber = tracing_subscriber::registry().with(layer(&mut registry, &clock));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!(
+                "request",
+                trace_id = "4bf92f3577b34da6",
+                span_id = tracing::field::Empty
+            );
+            request.record(SPAN_ID_LABEL, "00f067aa0ba902b7");
+            let _request = request.enter();
+            let query = tracing::info_span!("query", metric.histogram = "query_seconds");
+            let _query = query.enter();
+
+            assert_eq!(
+                super::super::current_span_exemplar(),
+                vec![
+                    (TRACE_ID_LABEL.to_string(), "4bf92f3577b34da6".to_string()),
+                    (SPAN_ID_LABEL.to_string(), "00f067aa0ba902b7".to_string()),
+                ]
+            );
+        });
+
+        assert!(render(&registry)
+            .contains(r#"# {trace_id="4bf92f3577b34da6",span_id="00f067aa0ba902b7"}"#));
+    }
+
+    #[test]
+    fn per_histogram_buckets_override_the_default() {
+        let mut registry = PrometheusRegistry::new();
+        let layer = MetricsLayer::builder()
+            .histogram_buckets("cache_seconds", vec![0.001])
+            .build(&mut registry)
+            .unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("get", metric.histogram = "cache_seconds").entered();
+        });
+
+        let text = render(&registry);
+        assert!(text.contains(r#"cache_seconds_bucket{le="0.001"}"#));
+        assert!(!text.contains(r#"cache_seconds_bucket{le="0.005"}"#));
+    }
+
+    #[test]
+    fn invalid_buckets_fail_the_build() {
+        let mut registry = PrometheusRegistry::new();
+        assert!(matches!(
+            MetricsLayer::builder()
+                .buckets(vec![1.0, 0.5])
+                .build(&mut registry),
+            Err(PrometheusError::InvalidHistogramBuckets(_))
+        ));
+    }
+}
@@ -0,0 +1,19 @@
+//! Integration with the `tracing` ecosystem.
+//!
+//! Enabled by the `tracing-integration` feature:
+//!
+//! - [`MetricsLayer`] (with `prometheus`) turns spans and events into
+//!   metrics, configured by attributes on the spans themselves.
+//! - [`current_span_exemplar`] supplies exemplar labels from the current
+//!   span, so a sample on the `/metrics` endpoint points back at the trace
+//!   that produced it.
+
+pub mod exemplar;
+#[cfg(feature = "prometheus")]
+pub mod layer;
+
+pub use exemplar::{current_span_exemplar, SPAN_ID_LABEL, TRACE_ID_LABEL};
+#[cfg(feature = "prometheus")]
+pub use layer::{MetricsLayer, MetricsLayerBuilder};
+
+pub(crate) use exemplar::as_pairs;
@@ -0,0 +1,126 @@
+//! A local hyper server that stands in for the push and export targets.
+//!
+//! It records every request verbatim and answers with scripted statuses;
+//! each test file decodes the recorded bodies for its own protocol.
+
+#![allow(dead_code)]
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::service::service_fn;
+use hyper::{HeaderMap, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+/// A request as seen by the stand-in.
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    pub path_and_query: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl Recorded {
+    /// The request path without its query string.
+    pub fn path(&self) -> &str {
+        self.path_and_query
+            .split_once('?')
+            .map_or(self.path_and_query.as_str(), |(path, _)| path)
+    }
+
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.headers
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+}
+
+#[derive(Clone)]
+pub struct StandIn {
+    received: Arc<Mutex<Vec<Recorded>>>,
+    statuses: Arc<Mutex<VecDeque<u16>>>,
+    status: u16,
+    reply: Bytes,
+}
+
+impl StandIn {
+    /// A stand-in answering every request with `status` and an empty body.
+    pub fn new(status: u16) -> Self {
+        Self {
+            received: Arc::default(),
+            statuses: Arc::default(),
+            status,
+            reply: Bytes::new(),
+        }
+    }
+
+    /// Answer the first requests with `statuses`, in order, before falling
+    /// back to the default status.
+    pub fn script(self, statuses: impl IntoIterator<Item = u16>) -> Self {
+        self.statuses.lock().unwrap().extend(statuses);
+        self
+    }
+
+    /// Send `body` with every response.
+    pub fn reply(mut self, body: &'static [u8]) -> Self {
+        self.reply = Bytes::from_static(body);
+        self
+    }
+
+    /// Start serving on a local port; returns the stand-in and its base URL.
+    pub async fn start(self) -> (Self, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let server = server.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| {
+                        let server = server.clone();
+                        async move { server.handle(request).await }
+                    });
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        (self, url)
+    }
+
+    async fn handle(
+        &self,
+        request: Request<Incoming>,
+    ) -> Result<Response<Full<Bytes>>, hyper::Error> {
+        let (parts, body) = request.into_parts();
+        let body = body.collect().await?.to_bytes();
+        self.received.lock().unwrap().push(Recorded {
+            method: parts.method.to_string(),
+            path_and_query: parts.uri.path_and_query().unwrap().to_string(),
+            headers: parts.headers,
+            body,
+        });
+
+        let status = self
+            .statuses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(self.status);
+        Ok(Response::builder()
+            .status(StatusCode::from_u16(status).unwrap())
+            .body(Full::new(self.reply.clone()))
+            .unwrap())
+    }
+
+    /// Every request received so far, oldest first.
+    pub fn received(&self) -> Vec<Recorded> {
+        self.received.lock().unwrap().clone()
+    }
+}
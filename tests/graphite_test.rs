@@ -0,0 +1,141 @@
+//! Integration tests for the Graphite exporter.
+//!
+//! A local `TcpListener` stands in for carbon and collects what each
+//! accepted connection sends.
+
+#[cfg(all(feature = "graphite", feature = "prometheus"))]
+mod graphite_tests {
+    use observe_rs::backends::prometheus::prometheus_backend::PrometheusBackend;
+    use observe_rs::core::registry::ObservabilityRegistry;
+    use observe_rs::export::{
+        ExportConfig, ExportScheduler, GraphiteExporter, GraphiteProtocol, SharedRegistry,
+    };
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::RwLock;
+
+    fn registry() -> SharedRegistry<PrometheusBackend> {
+        let mut registry = ObservabilityRegistry::new();
+        registry
+            .counter("jobs", "Jobs processed")
+            .unwrap()
+            .inc_by(3);
+        registry.gauge("queue_depth", "Queued jobs").unwrap().set(7);
+        Arc::new(RwLock::new(registry))
+    }
+
+    async fn carbon() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        (listener, address)
+    }
+
+    /// Read from `stream` until `len` bytes have arrived.
+    async fn read(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut buf))
+            .await
+            .expect("carbon stand-in timed out")
+            .unwrap();
+        buf
+    }
+
+    /// Read plaintext lines from `stream` until `count` have arrived.
+    async fn read_lines(stream: &mut TcpStream, count: usize) -> Vec<String> {
+        let mut text = String::new();
+        while text.matches('\n').count() < count {
+            let byte = read(stream, 1).await;
+            text.push(byte[0] as char);
+        }
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn sends_plaintext_lines_over_one_connection() {
+        let (listener, address) = carbon().await;
+        let exporter = GraphiteExporter::builder(address)
+            .prefix("app")
+            .build()
+            .unwrap();
+        let scheduler = ExportScheduler::new(exporter, registry());
+
+        scheduler.export_now().await.unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let lines = read_lines(&mut stream, 2).await;
+
+        let fields: Vec<Vec<&str>> = lines.iter().map(|line| line.split(' ').collect()).collect();
+        assert_eq!(fields[0][..2], ["app.jobs_total", "3"]);
+        assert_eq!(fields[1][..2], ["app.queue_depth", "7"]);
+        assert!(fields[0][2].parse::<u64>().unwrap() > 1_600_000_000);
+
+        // The next export reuses the connection.
+        scheduler.export_now().await.unwrap();
+        assert_eq!(read_lines(&mut stream, 2).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_carbon_closes_the_connection() {
+        let (listener, address) = carbon().await;
+        let scheduler = ExportScheduler::new(
+            GraphiteExporter::builder(address).build().unwrap(),
+            registry(),
+        )
+        .with_config(
+            ExportConfig::default()
+                .backoff(Duration::from_millis(10), Duration::from_millis(50))
+                .jitter(0.0),
+        );
+
+        scheduler.export_now().await.unwrap();
+        let (mut first, _) = listener.accept().await.unwrap();
+        read_lines(&mut first, 2).await;
+        drop(first);
+        // Let the close reach the exporter's socket.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        scheduler.export_now().await.unwrap();
+        let (mut second, _) = listener.accept().await.unwrap();
+        let lines = read_lines(&mut second, 2).await;
+        assert!(lines[0].starts_with("jobs_total 3 "), "{:?}", lines);
+    }
+
+    #[tokio::test]
+    async fn unreachable_carbon_is_a_retryable_failure() {
+        let (listener, address) = carbon().await;
+        drop(listener);
+        let scheduler = ExportScheduler::new(
+            GraphiteExporter::builder(address).build().unwrap(),
+            registry(),
+        )
+        .with_config(ExportConfig::default().max_retries(0));
+
+        let error = scheduler.export_now().await.unwrap_err();
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn sends_length_prefixed_pickles() {
+        let (listener, address) = carbon().await;
+        let exporter = GraphiteExporter::builder(address)
+            .protocol(GraphiteProtocol::Pickle)
+            .build()
+            .unwrap();
+
+        ExportScheduler::new(exporter, registry())
+            .export_now()
+            .await
+            .unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let len = u32::from_be_bytes(read(&mut stream, 4).await.try_into().unwrap());
+        let body = read(&mut stream, len as usize).await;
+
+        assert_eq!(&body[..2], [0x80, 2]);
+        assert!(body.ends_with(b"e."));
+        let contains = |needle: &[u8]| body.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"\x0a\x00\x00\x00jobs_total"));
+        assert!(contains(&[&b"G"[..], &3f64.to_be_bytes()].concat()));
+        assert!(contains(b"queue_depth"));
+    }
+}
@@ -0,0 +1,141 @@
+//! Integration tests for the InfluxDB exporter.
+//!
+//! A local stand-in plays InfluxDB's `/api/v2/write` endpoint; each
+//! recorded body is gunzipped back into line protocol.
+
+#[cfg(all(feature = "influx", feature = "prometheus"))]
+mod common;
+
+#[cfg(all(feature = "influx", feature = "prometheus"))]
+mod influx_tests {
+    use super::common::StandIn;
+    use flate2::read::GzDecoder;
+    use observe_rs::backends::prometheus::prometheus_backend::PrometheusBackend;
+    use observe_rs::core::registry::ObservabilityRegistry;
+    use observe_rs::export::{ExportError, ExportScheduler, InfluxExporter, SharedRegistry};
+    use std::io::Read;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[derive(Debug, Clone)]
+    struct Received {
+        path_and_query: String,
+        authorization: Option<String>,
+        content_encoding: Option<String>,
+        body: String,
+    }
+
+    /// Start a stand-in answering every write with `status`; returns its
+    /// base URL.
+    async fn start(status: u16) -> (StandIn, String) {
+        StandIn::new(status)
+            .reply(b"{\"code\":\"invalid\"}")
+            .start()
+            .await
+    }
+
+    fn received(stand_in: &StandIn) -> Vec<Received> {
+        stand_in
+            .received()
+            .into_iter()
+            .map(|request| {
+                let content_encoding = request.header("content-encoding");
+                let body = if content_encoding.as_deref() == Some("gzip") {
+                    let mut body = String::new();
+                    GzDecoder::new(request.body.as_ref())
+                        .read_to_string(&mut body)
+                        .unwrap();
+                    body
+                } else {
+                    String::from_utf8(request.body.to_vec()).unwrap()
+                };
+                Received {
+                    path_and_query: request.path_and_query.clone(),
+                    authorization: request.header("authorization"),
+                    content_encoding,
+                    body,
+                }
+            })
+            .collect()
+    }
+
+    fn registry() -> SharedRegistry<PrometheusBackend> {
+        let mut registry = ObservabilityRegistry::new();
+        registry
+            .counter("jobs", "Jobs processed")
+            .unwrap()
+            .inc_by(3);
+        registry.gauge("queue_depth", "Queued jobs").unwrap().set(7);
+        Arc::new(RwLock::new(registry))
+    }
+
+    #[tokio::test]
+    async fn writes_gzipped_line_protocol_with_token() {
+        let (stand_in, url) = start(204).await;
+        let exporter = InfluxExporter::builder(url, "acme", "metrics")
+            .token("s3cret")
+            .build()
+            .unwrap();
+
+        ExportScheduler::new(exporter, registry())
+            .export_now()
+            .await
+            .unwrap();
+
+        let received = received(&stand_in);
+        assert_eq!(received.len(), 1);
+        let request = &received[0];
+        assert_eq!(
+            request.path_and_query,
+            "/api/v2/write?org=acme&bucket=metrics&precision=ns"
+        );
+        assert_eq!(request.authorization.as_deref(), Some("Token s3cret"));
+        assert_eq!(request.content_encoding.as_deref(), Some("gzip"));
+
+        let lines: Vec<&str> = request.body.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", request.body);
+        assert!(lines.iter().any(|line| line.starts_with("jobs counter=3 ")));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("queue_depth gauge=7 ")));
+        // Nanosecond precision: 19 digits for any current timestamp.
+        assert!(lines
+            .iter()
+            .all(|line| line.rsplit(' ').next().unwrap().len() == 19));
+    }
+
+    #[tokio::test]
+    async fn uncompressed_bodies_can_be_requested() {
+        let (stand_in, url) = start(204).await;
+        let exporter = InfluxExporter::builder(url, "acme", "metrics")
+            .gzip(false)
+            .build()
+            .unwrap();
+
+        ExportScheduler::new(exporter, registry())
+            .export_now()
+            .await
+            .unwrap();
+
+        let received = received(&stand_in);
+        assert_eq!(received[0].content_encoding, None);
+        assert!(received[0].body.contains("jobs counter=3 "));
+    }
+
+    #[tokio::test]
+    async fn rejected_writes_are_permanent_failures() {
+        let (stand_in, url) = start(400).await;
+        let exporter = InfluxExporter::builder(url, "acme", "metrics")
+            .build()
+            .unwrap();
+
+        let error = ExportScheduler::new(exporter, registry())
+            .export_now()
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, ExportError::Permanent(_)));
+        assert!(error.to_string().contains("invalid"), "{}", error);
+        assert_eq!(received(&stand_in).len(), 1);
+    }
+}
@@ -0,0 +1,167 @@
+//! Integration tests for the Pushgateway client.
+//!
+//! A local stand-in plays the Pushgateway and records the method, path
+//! and body of every request.
+
+#[cfg(all(feature = "pushgateway", feature = "prometheus"))]
+mod common;
+
+#[cfg(all(feature = "pushgateway", feature = "prometheus"))]
+mod pushgateway_tests {
+    use super::common::StandIn;
+    use observe_rs::backends::prometheus::prometheus_backend::PrometheusBackend;
+    use observe_rs::core::registry::ObservabilityRegistry;
+    use observe_rs::export::{
+        delete_from_gateway, push_add_to_gateway, push_to_gateway, PushError, PushMethod,
+        Pushgateway,
+    };
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::RwLock;
+
+    #[derive(Debug, Clone)]
+    struct Received {
+        method: String,
+        path: String,
+        content_type: Option<String>,
+        body: String,
+    }
+
+    /// Start a stand-in answering every request with `status`; returns its
+    /// base URL.
+    async fn start(status: u16) -> (StandIn, String) {
+        StandIn::new(status).start().await
+    }
+
+    fn received(stand_in: &StandIn) -> Vec<Received> {
+        stand_in
+            .received()
+            .into_iter()
+            .map(|request| Received {
+                method: request.method.clone(),
+                path: request.path().to_string(),
+                content_type: request.header("content-type"),
+                body: String::from_utf8(request.body.to_vec()).unwrap(),
+            })
+            .collect()
+    }
+
+    fn registry() -> ObservabilityRegistry<PrometheusBackend> {
+        let mut registry = ObservabilityRegistry::new();
+        registry
+            .counter("backup_files", "Files backed up")
+            .unwrap()
+            .inc_by(42);
+        registry
+    }
+
+    #[tokio::test]
+    async fn push_replaces_and_push_add_merges_the_group() {
+        let (stand_in, url) = start(200).await;
+        let registry = registry();
+
+        push_to_gateway(&url, "backup", &[("instance", "db-1")], &registry)
+            .await
+            .unwrap();
+        push_add_to_gateway(&url, "backup", &[("instance", "db-1")], &registry)
+            .await
+            .unwrap();
+
+        let received = received(&stand_in);
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].method, "PUT");
+        assert_eq!(received[1].method, "POST");
+        for request in &received {
+            assert_eq!(request.path, "/metrics/job/backup/instance/db-1");
+            assert_eq!(
+                request.content_type.as_deref(),
+                Some("text/plain; version=0.0.4; charset=utf-8")
+            );
+            assert!(request
+                .body
+                .contains("# TYPE backup_files_total counter\nbackup_files_total 42\n"));
+            assert!(!request.body.contains("# EOF"));
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_removes_the_group() {
+        let (stand_in, url) = start(202).await;
+
+        delete_from_gateway(&url, "backup", &[("path", "/srv/data")])
+            .await
+            .unwrap();
+
+        let received = received(&stand_in);
+        assert_eq!(received[0].method, "DELETE");
+        assert_eq!(
+            received[0].path,
+            "/metrics/job/backup/path@base64/L3Nydi9kYXRh"
+        );
+        assert!(received[0].body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejected_pushes_are_reported() {
+        let (_stand_in, url) = start(400).await;
+
+        let error = push_to_gateway(&url, "backup", &[], &registry())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, PushError::Export(ref e) if !e.is_retryable()));
+    }
+
+    // Dropping the guard blocks the dropping thread, so the stand-in must
+    // run on another worker.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn guard_pushes_when_dropped() {
+        let (stand_in, url) = start(200).await;
+        let registry = Arc::new(RwLock::new(registry()));
+        let gateway = Pushgateway::builder(&url).build().unwrap();
+
+        {
+            let _guard = gateway.push_on_exit(
+                PushMethod::Put,
+                "backup",
+                &[("instance", "db-1")],
+                Arc::clone(&registry),
+            );
+            assert!(received(&stand_in).is_empty());
+        }
+
+        let pushed = received(&stand_in);
+        assert_eq!(pushed.len(), 1);
+        assert_eq!(pushed[0].method, "PUT");
+        assert!(pushed[0].body.contains("backup_files_total 42"));
+
+        gateway
+            .push_on_exit(PushMethod::Put, "backup", &[], registry)
+            .disarm();
+        assert_eq!(received(&stand_in).len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn guard_reports_failures_on_drop() {
+        let (_stand_in, url) = start(503).await;
+        let registry = Arc::new(RwLock::new(registry()));
+        let gateway = Pushgateway::builder(&url).build().unwrap();
+        let failures = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = Arc::clone(&failures);
+        drop(
+            gateway
+                .push_on_exit(PushMethod::Post, "backup", &[], Arc::clone(&registry))
+                .on_error(move |error| recorded.lock().unwrap().push(error.to_string())),
+        );
+
+        let failures = failures.lock().unwrap().clone();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].contains("503"), "{}", failures[0]);
+
+        let result = gateway
+            .push_on_exit(PushMethod::Post, "backup", &[], registry)
+            .finish()
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Integration tests for the remote-write exporter.
+//!
+//! A local stand-in plays the remote-write receiver; each recorded
+//! snappy-compressed body is decoded back into a `WriteRequest`.
+
+#[cfg(all(feature = "remote-write", feature = "prometheus"))]
+mod common;
+
+#[cfg(all(feature = "remote-write", feature = "prometheus"))]
+mod remote_write_tests {
+    use super::common::StandIn;
+    use observe_rs::backends::prometheus::prometheus_backend::PrometheusBackend;
+    use observe_rs::core::registry::ObservabilityRegistry;
+    use observe_rs::export::remote_write::proto::WriteRequest;
+    use observe_rs::export::{
+        ExportConfig, ExportError, ExportScheduler, RemoteWriteExporter, SharedRegistry,
+    };
+    use prost::Message;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::RwLock;
+
+    /// A write request as seen by the stand-in receiver.
+    struct Received {
+        authorization: Option<String>,
+        content_encoding: Option<String>,
+        version: Option<String>,
+        body: WriteRequest,
+    }
+
+    /// Start a receiver answering with `statuses`, then 204; returns its
+    /// write URL.
+    async fn start(statuses: Vec<u16>) -> (StandIn, String) {
+        let (stand_in, url) = StandIn::new(204)
+            .script(statuses)
+            .reply(b"stand-in says no")
+            .start()
+            .await;
+        (stand_in, format!("{url}/api/v1/write"))
+    }
+
+    fn received(stand_in: &StandIn) -> Vec<Received> {
+        stand_in
+            .received()
+            .into_iter()
+            .map(|request| {
+                let decompressed = snap::raw::Decoder::new()
+                    .decompress_vec(&request.body)
+                    .unwrap();
+                Received {
+                    authorization: request.header("authorization"),
+                    content_encoding: request.header("content-encoding"),
+                    version: request.header("x-prometheus-remote-write-version"),
+                    body: WriteRequest::decode(decompressed.as_slice()).unwrap(),
+                }
+            })
+            .collect()
+    }
+
+    fn registry() -> SharedRegistry<PrometheusBackend> {
+        let mut registry = ObservabilityRegistry::new();
+        registry
+            .counter("jobs", "Jobs processed")
+            .unwrap()
+            .inc_by(3);
+        registry.gauge("queue_depth", "Queued jobs").unwrap().set(7);
+        Arc::new(RwLock::new(registry))
+    }
+
+    fn config() -> ExportConfig {
+        ExportConfig::default()
+            .backoff(Duration::from_millis(10), Duration::from_millis(50))
+            .jitter(0.0)
+    }
+
+    fn label<'a>(
+        series: &'a observe_rs::export::remote_write::proto::TimeSeries,
+        name: &str,
+    ) -> Option<&'a str> {
+        series
+            .labels
+            .iter()
+            .find(|label| label.name == name)
+            .map(|label| label.value.as_str())
+    }
+
+    #[tokio::test]
+    async fn payload_is_decodable_with_auth_and_external_labels() {
+        let (stand_in, url) = start(vec![]).await;
+        let exporter = RemoteWriteExporter::builder(url)
+            .bearer_token("s3cret")
+            .external_label("cluster", "test")
+            .build()
+            .unwrap();
+
+        ExportScheduler::new(exporter, registry())
+            .export_now()
+            .await
+            .unwrap();
+
+        let received = received(&stand_in);
+        assert_eq!(received.len(), 1);
+        let request = &received[0];
+        assert_eq!(request.authorization.as_deref(), Some("Bearer s3cret"));
+        assert_eq!(request.content_encoding.as_deref(), Some("snappy"));
+        assert_eq!(request.version.as_deref(), Some("0.1.0"));
+
+        let jobs = request
+            .body
+            .timeseries
+            .iter()
+            .find(|series| label(series, "__name__") == Some("jobs_total"))
+            .expect("jobs_total series");
+        assert_eq!(label(jobs, "cluster"), Some("test"));
+        assert_eq!(jobs.samples[0].value, 3.0);
+        assert!(request
+            .body
+            .metadata
+            .iter()
+            .any(|metadata| metadata.metric_family_name == "queue_depth"));
+    }
+
+    #[tokio::test]
+    async fn server_errors_and_throttling_are_retried() {
+        let (stand_in, url) = start(vec![503, 429]).await;
+        let exporter = RemoteWriteExporter::builder(url)
+            .basic_auth("user", "pass")
+            .build()
+            .unwrap();
+
+        ExportScheduler::new(exporter, registry())
+            .with_config(config())
+            .export_now()
+            .await
+            .unwrap();
+
+        let received = received(&stand_in);
+        assert_eq!(received.len(), 3);
+        assert_eq!(
+            received[0].authorization.as_deref(),
+            Some("Basic dXNlcjpwYXNz")
+        );
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (stand_in, url) = start(vec![400]).await;
+        let exporter = RemoteWriteExporter::builder(url).build().unwrap();
+
+        let error = ExportScheduler::new(exporter, registry())
+            .with_config(config())
+            .export_now()
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, ExportError::Permanent(_)));
+        assert!(error
+            .to_string()
+            .contains("400 Bad Request: stand-in says no"));
+        assert_eq!(received(&stand_in).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn snapshots_are_batched_by_series_count() {
+        let (stand_in, url) = start(vec![]).await;
+        let exporter = RemoteWriteExporter::builder(url)
+            .max_series_per_request(1)
+            .build()
+            .unwrap();
+
+        ExportScheduler::new(exporter, registry())
+            .export_now()
+            .await
+            .unwrap();
+
+        let received = received(&stand_in);
+        assert_eq!(received.len(), 2);
+        assert!(received
+            .iter()
+            .all(|request| request.body.timeseries.len() == 1));
+        assert!(!received[0].body.metadata.is_empty());
+        assert!(received[1].body.metadata.is_empty());
+    }
+}